@@ -0,0 +1,63 @@
+//! What a daemon is told by the process that spawns it. Daemons are started by re-executing the
+//! current binary with a different `argv[0]`, so they can't take arguments like the cli does.
+
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::error;
+
+use crate::transport::Transport;
+
+/// The environment variable the bootstrap is passed in.
+const VAR: &str = "CLI_DAEMON_BOOTSTRAP";
+
+/// The bootstrap [`take_bootstrap`] took out of the environment.
+static TAKEN: OnceLock<Option<String>> = OnceLock::new();
+
+/// Takes the bootstrap this process was spawned with out of the environment, so that the token
+/// of the transport isn't passed on to whatever the daemon runs. Changing the environment isn't
+/// safe once other threads are running, so this must be called at the start of `main`, before
+/// the async runtime is started. Without it the variable is read but left in place.
+pub fn take_bootstrap() {
+    TAKEN.get_or_init(|| {
+        let var = std::env::var(VAR).ok();
+        if var.is_some() {
+            std::env::remove_var(VAR);
+        }
+        var
+    });
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Bootstrap {
+    /// The namespace of the socket the daemon must listen on.
+    pub socket_namespace: Option<String>,
+    /// How the daemon must listen, see [`crate::Daemon::set_transport`].
+    #[serde(default)]
+    pub transport: Option<Transport>,
+    /// What the spawner gave with [`crate::Daemon::set_bootstrap`].
+    pub payload: Option<Value>,
+}
+
+impl Bootstrap {
+    /// The bootstrap this process was spawned with, if any, see [`take_bootstrap`].
+    pub fn from_env() -> Self {
+        let var = match TAKEN.get() {
+            Some(taken) => taken.clone(),
+            None => std::env::var(VAR).ok(),
+        };
+        let Some(var) = var else {
+            return Self::default();
+        };
+        serde_json::from_str(&var).unwrap_or_else(|e| {
+            error!(?e, "invalid daemon bootstrap");
+            Self::default()
+        })
+    }
+
+    /// Passes the bootstrap to a daemon that is about to be spawned by `cmd`.
+    pub fn pass_to(&self, cmd: &mut std::process::Command) {
+        cmd.env(VAR, serde_json::to_string(self).unwrap());
+    }
+}
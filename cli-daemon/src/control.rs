@@ -0,0 +1,65 @@
+//! Messages every daemon answers by itself, before its handler sees them, so that a daemon can be
+//! checked on or stopped without knowing what messages it takes.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::metrics::Metrics;
+
+/// Sent as `{"cli-daemon": kind}`, which no message of a handler is expected to look like.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Control {
+    #[serde(rename = "cli-daemon")]
+    pub kind: ControlKind,
+}
+
+/// The version of how cli-daemon itself frames messages, to be bumped whenever old daemons can't
+/// understand new clients or the other way around, whatever their [`crate::Daemon::protocol`].
+pub(crate) const WIRE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Hello {
+    /// The [`crate::Daemon::protocol`], if it has one.
+    pub protocol: Option<u32>,
+    /// The [`WIRE_VERSION`], which daemons from before it existed don't send.
+    #[serde(default)]
+    pub wire: u32,
+}
+
+impl Hello {
+    pub(crate) fn new(protocol: Option<u32>) -> Self {
+        Self {
+            protocol,
+            wire: WIRE_VERSION,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum ControlKind {
+    /// Sent first by clients when they connect, carrying their versions.
+    Hello(Hello),
+    Ping,
+    Version,
+    Metrics,
+    Shutdown,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum ControlResponse {
+    /// The versions of the daemon.
+    Hello(Hello),
+    Pong(Pong),
+    Version(Option<String>),
+    Metrics(Metrics),
+    ShuttingDown,
+}
+
+/// What a daemon answers to [`crate::Daemon::ping`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pong {
+    pub pid: u32,
+    /// How long the daemon has been running for.
+    pub uptime: Duration,
+}
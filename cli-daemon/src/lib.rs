@@ -1,5 +1,18 @@
+mod bootstrap;
+mod control;
 mod link;
+mod log;
+mod metrics;
+mod mux;
 mod process;
+mod transport;
+mod watch;
+
+pub use bootstrap::take_bootstrap;
+pub use control::Pong;
+pub use metrics::{MessageCount, Metrics, Recorder};
+pub use process::DaemonProcess;
+pub use transport::Transport;
 
 use std::{
     any::Any,
@@ -14,14 +27,18 @@ use std::{
     time::Duration,
 };
 
+use bootstrap::Bootstrap;
+use control::{ControlKind, ControlResponse, Hello};
 use futures_util::Stream;
 use link::DaemonLink;
-use process::DaemonProcess;
+use mux::MuxLink;
 use serde::{de::DeserializeOwned, Serialize};
 use tokio::sync::{Mutex, OnceCell};
-use tracing::error;
+use tracing::{error, warn};
+use transport::Endpoint;
+use watch::SocketWatch;
 
-type ArcDaemonLink<M, R, E> = Arc<Mutex<DaemonLink<M, R, E>>>;
+type ArcMuxLink<M, R, E> = Arc<MuxLink<M, R, E>>;
 
 /// The idea of a daemon. Instances of this struct can be used to
 /// - talk to an existing daemon
@@ -32,9 +49,13 @@ type ArcDaemonLink<M, R, E> = Arc<Mutex<DaemonLink<M, R, E>>>;
 pub struct Daemon<M, R, E = Infallible> {
     start_daemon: AtomicBool,
     name: &'static str,
-    socket_namespace: Option<String>,
-    channels: Mutex<Option<ArcDaemonLink<M, R, E>>>,
+    socket_namespace: std::sync::Mutex<Option<String>>,
+    channels: Mutex<Option<ArcMuxLink<M, R, E>>>,
     socket_path: OnceCell<PathBuf>,
+    bootstrap: std::sync::Mutex<Option<serde_json::Value>>,
+    version: Option<&'static str>,
+    protocol: Option<u32>,
+    transport: std::sync::Mutex<Transport>,
 }
 
 impl<M, R, E> Daemon<M, R, E> {
@@ -42,16 +63,59 @@ impl<M, R, E> Daemon<M, R, E> {
         Daemon {
             start_daemon: AtomicBool::new(false),
             name,
-            socket_namespace: None,
+            socket_namespace: std::sync::Mutex::new(None),
             channels: Mutex::const_new(None),
             socket_path: OnceCell::const_new(),
+            bootstrap: std::sync::Mutex::new(None),
+            version: None,
+            protocol: None,
+            transport: std::sync::Mutex::new(Transport::Unix),
         }
     }
 
+    /// The version the daemon reports, see [`Daemon::version`].
+    pub const fn versioned(mut self, version: &'static str) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// The version of the messages, to be bumped whenever old daemons can't understand new
+    /// clients or the other way around. Clients check it when they connect and, if it doesn't
+    /// match, restart the daemon if they are allowed to start it or refuse to talk to it
+    /// otherwise.
+    pub const fn protocol(mut self, protocol: u32) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
     async fn socket_path(&self) -> &Path {
+        let namespace = self.socket_namespace();
+        self.socket_path_in(namespace.as_deref()).await
+    }
+
+    fn socket_namespace(&self) -> Option<String> {
+        self.socket_namespace.lock().unwrap().clone()
+    }
+
+    /// Put the socket in `namespace`, so that daemons in different namespaces don't see each
+    /// other. Has to be set before the daemon is first talked to.
+    pub fn set_socket_namespace(&self, namespace: String) {
+        *self.socket_namespace.lock().unwrap() = Some(namespace);
+    }
+
+    fn transport(&self) -> Transport {
+        self.transport.lock().unwrap().clone()
+    }
+
+    async fn endpoint(&self) -> Endpoint {
+        Endpoint::new(self.transport(), self.socket_path().await.into())
+    }
+
+    /// The socket path, which is in `namespace` if it wasn't already decided.
+    async fn socket_path_in(&self, namespace: Option<&str>) -> &Path {
         self.socket_path
             .get_or_init(|| async {
-                let (path, e) = match &self.socket_namespace {
+                let (path, e) = match namespace {
                     None => namespaced_tmp::async_impl::in_user_tmp(self.name).await,
                     Some(ns) => namespaced_tmp::async_impl::in_tmp(ns, self.name).await,
                 };
@@ -67,21 +131,36 @@ impl<M, R, E> Daemon<M, R, E> {
         Self {
             start_daemon: AtomicBool::new(self.start_daemon.load(Ordering::Relaxed)),
             name: self.name,
-            socket_namespace: Some(new_namepsace),
+            socket_namespace: std::sync::Mutex::new(Some(new_namepsace)),
             channels: Mutex::const_new(None),
             socket_path: OnceCell::const_new(),
+            bootstrap: std::sync::Mutex::new(self.bootstrap.lock().unwrap().clone()),
+            version: self.version,
+            protocol: self.protocol,
+            transport: std::sync::Mutex::new(self.transport()),
         }
     }
 
+    /// Set what is given to the daemon when it's started by this process, see
+    /// [`DaemonProcess::bootstrap`].
+    pub fn set_bootstrap<B: Serialize>(&self, payload: &B) {
+        *self.bootstrap.lock().unwrap() =
+            Some(serde_json::to_value(payload).expect("bootstrap payload must be valid json"));
+    }
+
+    /// Set how the daemon is reached, which is also how it listens if it's started by this
+    /// process. Has to be set before the daemon is first talked to.
+    pub fn set_transport(&self, transport: Transport) {
+        *self.transport.lock().unwrap() = transport;
+    }
+
     pub async fn wait_for_daemon_to_spawn(&self) {
         // reset the socket. If we are doing this we expect to not have a valid socket setup.
         *self.channels.lock().await = None;
-        // TODO: make this smarter with ifnotify things
-        loop {
-            if self.channels().await.is_ok() {
-                break;
-            }
-            tokio::time::sleep(Duration::from_secs(1)).await;
+        // watching starts before the first attempt, so that the socket can't be missed in between
+        let mut watch = SocketWatch::new(&self.endpoint().await);
+        while self.channels().await.is_err() {
+            watch.changed().await;
         }
     }
 
@@ -94,22 +173,131 @@ impl<M, R, E> Daemon<M, R, E> {
         }
     }
 
-    async fn channels(&self) -> io::Result<Arc<Mutex<DaemonLink<M, R, E>>>> {
+    async fn channels(&self) -> io::Result<ArcMuxLink<M, R, E>> {
         let mut channels = self.channels.lock().await;
         match &*channels {
-            Some(ch) => Ok(ch.clone()),
-            None => Ok(channels
-                .insert(Arc::new(Mutex::new(
-                    DaemonLink::new(
-                        self.name,
-                        self.socket_path().await,
-                        self.start_daemon.load(Ordering::SeqCst),
-                    )
-                    .await?,
-                )))
+            // a daemon that went away, e.g. because it was restarted, is connected to again
+            Some(ch) if !ch.is_closed() => Ok(ch.clone()),
+            _ => Ok(channels
+                .insert(Arc::new(self.connect().await?.multiplex()))
                 .clone()),
         }
     }
+
+    /// Connects to the daemon, starting it if allowed, and checks that it speaks our protocol.
+    async fn connect(&self) -> io::Result<DaemonLink<M, R, E>> {
+        let auto_start = self.start_daemon.load(Ordering::SeqCst).then(|| Bootstrap {
+            socket_namespace: self.socket_namespace(),
+            transport: Some(self.transport()),
+            payload: self.bootstrap.lock().unwrap().clone(),
+        });
+        let endpoint = self.endpoint().await;
+        let ours = Hello::new(self.protocol);
+        let mut link = DaemonLink::new(self.name, &endpoint, auto_start.clone()).await?;
+        let theirs = link.handshake(self.protocol).await?;
+        if theirs == Some(ours) {
+            return Ok(link);
+        }
+        // daemons from before the handshake don't know how to shut down
+        let (Some(_), Some(auto_start)) = (theirs, auto_start) else {
+            return Err(incompatible(self.name, theirs, ours));
+        };
+        warn!(
+            name = self.name,
+            ?theirs,
+            ?ours,
+            "restarting the daemon, it speaks another protocol"
+        );
+        link.control(ControlKind::Shutdown).await?;
+        drop(link);
+        for _ in 0..10 {
+            if endpoint.connect().await.is_err() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        let mut link = DaemonLink::new(self.name, &endpoint, Some(auto_start)).await?;
+        match link.handshake(self.protocol).await? {
+            Some(theirs) if theirs == ours => Ok(link),
+            theirs => Err(incompatible(self.name, theirs, ours)),
+        }
+    }
+}
+
+impl<M, R, E> Daemon<M, R, E> {
+    /// Sends a control message on a new connection, which doesn't start the daemon if it isn't
+    /// running.
+    async fn control(&self, kind: ControlKind) -> io::Result<ControlResponse> {
+        let mut link = DaemonLink::<M, R, E>::new(self.name, &self.endpoint().await, None).await?;
+        link.control(kind).await
+    }
+
+    /// Checks that the daemon is running and answering.
+    pub async fn ping(&self) -> io::Result<Pong> {
+        match self.control(ControlKind::Ping).await? {
+            ControlResponse::Pong(pong) => Ok(pong),
+            r => Err(unexpected(r)),
+        }
+    }
+
+    /// The version of the running daemon, as given to [`Daemon::versioned`].
+    pub async fn version(&self) -> io::Result<Option<String>> {
+        match self.control(ControlKind::Version).await? {
+            ControlResponse::Version(version) => Ok(version),
+            r => Err(unexpected(r)),
+        }
+    }
+
+    /// What the daemon has done since it started.
+    pub async fn metrics(&self) -> io::Result<Metrics> {
+        match self.control(ControlKind::Metrics).await? {
+            ControlResponse::Metrics(metrics) => Ok(metrics),
+            r => Err(unexpected(r)),
+        }
+    }
+
+    /// The last `lines` lines the daemon logged, if it was started by a client.
+    pub async fn log_tail(&self, lines: usize) -> io::Result<Vec<String>> {
+        let path = self.endpoint().await.log_path();
+        tokio::task::spawn_blocking(move || log::tail(&path, lines))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    /// Makes the daemon exit, like it does when it's sent SIGTERM.
+    pub async fn shutdown(&self) -> io::Result<()> {
+        match self.control(ControlKind::Shutdown).await? {
+            ControlResponse::ShuttingDown => {
+                *self.channels.lock().await = None;
+                Ok(())
+            }
+            r => Err(unexpected(r)),
+        }
+    }
+}
+
+fn incompatible(name: &str, theirs: Option<Hello>, ours: Hello) -> io::Error {
+    let message = match theirs {
+        Some(theirs) if theirs.wire != ours.wire => format!(
+            "the {name} daemon frames messages with version {} but this is version {}, stop it and try again",
+            theirs.wire, ours.wire
+        ),
+        Some(theirs) => format!(
+            "the {name} daemon speaks protocol version {:?} but this is version {:?}, stop it and try again",
+            theirs.protocol, ours.protocol
+        ),
+        None => format!(
+            "the {name} daemon is from before protocol versions, kill it and try again"
+        ),
+    };
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn unexpected(response: ControlResponse) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected response from the daemon: {response:?}"),
+    )
 }
 
 impl<M, R, E> Daemon<M, R, E>
@@ -118,9 +306,7 @@ where
     R: DeserializeOwned,
 {
     pub async fn exchange(&self, message: M) -> io::Result<R> {
-        let channels = self.channels().await?;
-        let mut channels = channels.lock().await;
-        channels.exchange(message).await
+        self.channels().await?.exchange(message).await
     }
 }
 
@@ -132,8 +318,6 @@ where
     pub async fn subscribe(&self) -> Result<impl Stream<Item = io::Result<E>>, io::Error> {
         tracing::debug!("getting channels");
         let ch = self.channels().await?;
-        tracing::debug!("getting channels lock");
-        let ch = ch.lock().await;
         tracing::debug!("cloning channels");
         let ch = ch.try_clone().await?;
         tracing::debug!("subscribing");
@@ -1,31 +1,32 @@
 use std::{
-    any::Any,
     convert::Infallible,
-    fmt::Debug,
+    fs::File,
     io,
     marker::PhantomData,
     os::unix::prelude::CommandExt,
-    path::{Path, PathBuf},
-    process::Command,
+    process::{Command, Stdio},
     time::Duration,
 };
 
 use futures_util::{stream, Stream};
+use raii_flock::FileLock;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter},
-    net::{
-        unix::{OwnedReadHalf, OwnedWriteHalf},
-        UnixStream,
-    },
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tracing::{debug, warn};
+
+use crate::{
+    bootstrap::Bootstrap,
+    control::{Control, ControlKind, ControlResponse, Hello},
+    log,
+    mux::MuxLink,
+    transport::{Endpoint, Reader, Writer},
 };
-use tracing::debug;
 
 #[derive(Debug)]
 pub struct DaemonLink<M, R, E = Infallible> {
-    reader: BufReader<OwnedReadHalf>,
-    writer: BufWriter<OwnedWriteHalf>,
-    socket_path: PathBuf,
+    reader: Reader,
+    writer: Writer,
+    endpoint: Endpoint,
     name: String,
     _marker: PhantomData<(M, R, E)>,
 }
@@ -33,60 +34,44 @@ pub struct DaemonLink<M, R, E = Infallible> {
 impl<M, R, E> DaemonLink<M, R, E> {
     /// Try to connect to the daemon.
     ///
-    /// If the daemon isn't running and `auto_start` is `true`. It will attempt to start the daemon
-    /// and connect to it.
-    pub async fn new(name: &str, socket_path: &Path, auto_start: bool) -> io::Result<Self> {
+    /// If the daemon isn't running and `auto_start` is `Some`. It will attempt to start the daemon,
+    /// with that bootstrap, and connect to it.
+    pub async fn new(
+        name: &str,
+        endpoint: &Endpoint,
+        auto_start: Option<Bootstrap>,
+    ) -> io::Result<Self> {
         let try_connect = || async {
-            debug!(?socket_path, "attempt to connect");
-            UnixStream::connect(socket_path).await.map(|sock| {
-                let (reader, writer) = sock.into_split();
-                DaemonLink {
-                    reader: BufReader::new(reader),
-                    writer: BufWriter::new(writer),
-                    socket_path: socket_path.into(),
-                    name: name.into(),
-                    _marker: PhantomData,
-                }
+            debug!(?endpoint, "attempt to connect");
+            endpoint.connect().await.map(|(reader, writer)| DaemonLink {
+                reader,
+                writer,
+                endpoint: endpoint.clone(),
+                name: name.into(),
+                _marker: PhantomData,
             })
         };
 
-        match try_connect().await {
+        let bootstrap = match try_connect().await {
             Ok(link) => return Ok(link),
-            Err(e) if !auto_start => return Err(e),
-            _ => {}
-        }
-
-        debug!(?name, ?socket_path, "starting the daemon");
-        Command::new(std::env::current_exe()?).arg0(name).spawn()?;
+            Err(e) => match auto_start {
+                Some(bootstrap) => bootstrap,
+                None => return Err(e),
+            },
+        };
 
-        debug!(?name, ?socket_path, "establishing connection to daemon");
-        for i in 1..=5 {
-            tokio::time::sleep(Duration::from_millis(100 * i)).await;
-            if let Ok(link) = try_connect().await {
-                return Ok(link);
-            }
-        }
+        debug!(?name, ?endpoint, ?bootstrap, "starting the daemon");
+        let spawning = {
+            let (name, endpoint) = (name.to_owned(), endpoint.clone());
+            let runtime = tokio::runtime::Handle::current();
+            tokio::task::spawn_blocking(move || spawn(&name, &endpoint, &bootstrap, &runtime))
+        };
+        spawning.await??;
         try_connect().await
     }
 
-    /// Try to clone this link and make a new independent one.
-    pub async fn try_clone(&self) -> io::Result<Self> {
-        Self::new(&self.name, &self.socket_path, false).await
-    }
-}
-
-impl<M, R, E> DaemonLink<M, R, E>
-where
-    M: Serialize + Any + Debug,
-    R: DeserializeOwned,
-{
-    pub async fn exchange(&mut self, message: M) -> Result<R, io::Error> {
-        debug!(
-            ?message,
-            "sending message to daemon, type: {}",
-            std::any::type_name::<M>()
-        );
-        let message = serde_json::to_vec(&message).unwrap();
+    async fn request<T: Serialize, U: DeserializeOwned>(&mut self, message: &T) -> io::Result<U> {
+        let message = serde_json::to_vec(message).unwrap();
         self.writer.write_all(&message).await?;
         self.writer.write_all(b"\n").await?;
         self.writer.flush().await?;
@@ -97,11 +82,78 @@ where
         debug!(?response, "got");
         Ok(serde_json::from_str(&response)?)
     }
+
+    pub(crate) async fn control(&mut self, kind: ControlKind) -> io::Result<ControlResponse> {
+        debug!(?kind, "sending control message to daemon");
+        self.request(&Control { kind }).await
+    }
+
+    /// Tells the daemon the versions this side speaks, returning the ones it speaks. Daemons from
+    /// before the handshake answer with an error, which is taken as `None`.
+    pub(crate) async fn handshake(&mut self, protocol: Option<u32>) -> io::Result<Option<Hello>> {
+        let response = self
+            .request::<_, serde_json::Value>(&Control {
+                kind: ControlKind::Hello(Hello::new(protocol)),
+            })
+            .await?;
+        match serde_json::from_value(response) {
+            Ok(ControlResponse::Hello(hello)) => Ok(Some(hello)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Hands the connection over to a [`MuxLink`], after which requests no longer wait for each
+    /// other.
+    pub(crate) fn multiplex(self) -> MuxLink<M, R, E> {
+        MuxLink::new(self.reader, self.writer, self.name, self.endpoint)
+    }
+}
+
+/// Starts the daemon, unless another client did while this one waited for the spawn lock, and
+/// waits a little for it to be listening. Blocks, to hold the lock.
+fn spawn(
+    name: &str,
+    endpoint: &Endpoint,
+    bootstrap: &Bootstrap,
+    runtime: &tokio::runtime::Handle,
+) -> io::Result<()> {
+    let lock_file = File::create(endpoint.spawn_lock_path())?;
+    let _lock = FileLock::wrap_exclusive(&lock_file);
+    if runtime.block_on(endpoint.connect()).is_ok() {
+        debug!(?name, "another client started the daemon");
+        return Ok(());
+    }
+    let mut daemon = Command::new(std::env::current_exe()?);
+    bootstrap.pass_to(daemon.arg0(name));
+    daemon.stdin(Stdio::null());
+    match log::rotate(&endpoint.log_path()) {
+        Ok(log) => {
+            daemon.stdout(log.try_clone()?).stderr(log);
+        }
+        Err(e) => warn!(?e, "failed to open the log of the daemon"),
+    }
+    daemon.spawn()?;
+
+    debug!(?name, ?endpoint, "establishing connection to daemon");
+    for i in 1..=5 {
+        std::thread::sleep(Duration::from_millis(100 * i));
+        if runtime.block_on(endpoint.connect()).is_ok() {
+            break;
+        }
+    }
+    Ok(())
 }
 
 #[derive(Deserialize, Serialize)]
 pub(crate) struct EventSubscription;
 
+/// A request or its response, tagged with an id so that many can be in flight on one connection.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct Tagged<T> {
+    pub id: u64,
+    pub body: T,
+}
+
 impl<M, R, E> DaemonLink<M, R, E>
 where
     E: DeserializeOwned,
@@ -0,0 +1,86 @@
+//! The log of a daemon started by a client. Daemons would otherwise write to the stderr of whatever
+//! started them, which is usually gone by the time anything interesting is logged.
+
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// How many logs are kept, the current one and the ones of previous runs.
+const KEEP: usize = 3;
+
+/// How big a log can grow before it's emptied, see [`cap`].
+const MAX_SIZE: u64 = 8 * 1024 * 1024;
+
+/// How often [`cap`] checks the size of the log.
+const CHECK_EVERY: Duration = Duration::from_secs(60);
+
+/// How much of the end of a log [`tail`] looks at.
+const TAIL_BYTES: u64 = 64 * 1024;
+
+/// Opens the log for a daemon that is about to start, moving the logs of previous runs aside as
+/// `path.1`, `path.2`, etc. Only one client can be doing this at a time, or they could move each
+/// other's logs.
+///
+/// The log is opened for appending, so that [`cap`] can empty it while the daemon writes to it.
+pub(crate) fn rotate(path: &Path) -> io::Result<File> {
+    for i in (1..KEEP).rev() {
+        let from = if i == 1 {
+            path.to_owned()
+        } else {
+            numbered(path, i - 1)
+        };
+        match fs::rename(from, numbered(path, i)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    File::options().create(true).append(true).open(path)
+}
+
+/// Empties the log at `path` whenever it grows past [`MAX_SIZE`], for as long as the daemon runs.
+pub(crate) async fn cap(path: PathBuf) {
+    loop {
+        tokio::time::sleep(CHECK_EVERY).await;
+        let too_big = tokio::fs::metadata(&path)
+            .await
+            .is_ok_and(|m| m.len() > MAX_SIZE);
+        if !too_big {
+            continue;
+        }
+        let emptied = File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|log| log.set_len(0));
+        match emptied {
+            Ok(()) => tracing::info!("emptied the log, it was over {MAX_SIZE} bytes"),
+            Err(e) => tracing::warn!(?e, "failed to empty the log"),
+        }
+    }
+}
+
+fn numbered(path: &Path, i: usize) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{i}"));
+    path.into()
+}
+
+/// The last `lines` lines of the log at `path`.
+pub(crate) fn tail(path: &Path, lines: usize) -> io::Result<Vec<String>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(TAIL_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    let text = String::from_utf8_lossy(&buf);
+    let mut tail = text.lines().collect::<Vec<_>>();
+    if start > 0 {
+        // the first line was cut in half
+        tail.remove(0);
+    }
+    let skip = tail.len().saturating_sub(lines);
+    Ok(tail[skip..].iter().map(|l| l.to_string()).collect())
+}
@@ -0,0 +1,85 @@
+//! Counting what a daemon does, see [`crate::Daemon::metrics`].
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// What a daemon has done since it started.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metrics {
+    pub uptime: Duration,
+    /// How many clients connected.
+    pub connections: u64,
+    /// How many messages couldn't be understood.
+    pub malformed: u64,
+    /// By kind of message, as named by [`crate::DaemonProcess::with_metrics`].
+    pub messages: BTreeMap<String, MessageCount>,
+}
+
+impl Metrics {
+    /// How many messages were handled and how many of those failed, of all kinds.
+    pub fn total(&self) -> MessageCount {
+        self.messages
+            .values()
+            .fold(MessageCount::default(), |acc, c| MessageCount {
+                handled: acc.handled + c.handled,
+                failed: acc.failed + c.failed,
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageCount {
+    pub handled: u64,
+    pub failed: u64,
+}
+
+/// Where the connections of a daemon count what they do. Clones count into the same metrics.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    started: Instant,
+    metrics: Arc<Mutex<Metrics>>,
+}
+
+impl Recorder {
+    pub(crate) fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            metrics: Default::default(),
+        }
+    }
+
+    pub(crate) fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// The metrics so far.
+    pub fn snapshot(&self) -> Metrics {
+        Metrics {
+            uptime: self.uptime(),
+            ..self.metrics.lock().unwrap().clone()
+        }
+    }
+
+    pub(crate) fn connected(&self) {
+        self.metrics.lock().unwrap().connections += 1;
+    }
+
+    pub(crate) fn malformed(&self) {
+        self.metrics.lock().unwrap().malformed += 1;
+    }
+
+    pub(crate) fn handled(&self, kind: &str, failed: bool) {
+        let mut metrics = self.metrics.lock().unwrap();
+        let count = match metrics.messages.get_mut(kind) {
+            Some(count) => count,
+            None => metrics.messages.entry(kind.to_owned()).or_default(),
+        };
+        count.handled += 1;
+        count.failed += u64::from(failed);
+    }
+}
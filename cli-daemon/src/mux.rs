@@ -0,0 +1,154 @@
+//! Many requests in flight over one connection to the daemon. Each one is [`Tagged`] with an id
+//! and a background task hands the responses, in whatever order they come, to who's waiting for
+//! them.
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    convert::Infallible,
+    fmt::Debug,
+    io,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt},
+    sync::{oneshot, Mutex},
+    task::JoinHandle,
+};
+use tracing::{debug, error};
+
+use crate::{
+    link::{DaemonLink, Tagged},
+    transport::{Endpoint, Reader, Writer},
+};
+
+#[derive(Debug, Default)]
+struct Pending {
+    waiting: HashMap<u64, oneshot::Sender<serde_json::Value>>,
+    /// Set once the daemon stops answering, after which nothing more is waited on.
+    closed: bool,
+}
+
+#[derive(Debug)]
+pub struct MuxLink<M, R, E = Infallible> {
+    writer: Mutex<Writer>,
+    pending: Arc<std::sync::Mutex<Pending>>,
+    next_id: AtomicU64,
+    dispatcher: JoinHandle<()>,
+    endpoint: Endpoint,
+    name: String,
+    _marker: PhantomData<(M, R, E)>,
+}
+
+impl<M, R, E> MuxLink<M, R, E> {
+    pub(crate) fn new(reader: Reader, writer: Writer, name: String, endpoint: Endpoint) -> Self {
+        let pending = Arc::new(std::sync::Mutex::new(Pending::default()));
+        Self {
+            writer: Mutex::new(writer),
+            dispatcher: tokio::spawn(dispatch(reader, pending.clone())),
+            pending,
+            next_id: AtomicU64::new(0),
+            endpoint,
+            name,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Whether the daemon closed the connection, after which every request fails.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.pending.lock().unwrap().closed
+    }
+
+    /// Try to make a new independent link to the same daemon.
+    pub async fn try_clone(&self) -> io::Result<DaemonLink<M, R, E>> {
+        DaemonLink::new(&self.name, &self.endpoint, None).await
+    }
+}
+
+impl<M, R, E> MuxLink<M, R, E>
+where
+    M: Serialize + Any + Debug,
+    R: DeserializeOwned,
+{
+    pub async fn exchange(&self, message: M) -> io::Result<R> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        debug!(
+            id,
+            ?message,
+            "sending message to daemon, type: {}",
+            std::any::type_name::<M>()
+        );
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.closed {
+                return Err(disconnected());
+            }
+            pending.waiting.insert(id, tx);
+        }
+        let message = serde_json::to_vec(&Tagged { id, body: message }).unwrap();
+        let sent = async {
+            let mut writer = self.writer.lock().await;
+            writer.write_all(&message).await?;
+            writer.write_all(b"\n").await?;
+            writer.flush().await
+        };
+        if let Err(e) = sent.await {
+            self.pending.lock().unwrap().waiting.remove(&id);
+            return Err(e);
+        }
+        let response = rx.await.map_err(|_| disconnected())?;
+        Ok(serde_json::from_value(response)?)
+    }
+}
+
+impl<M, R, E> Drop for MuxLink<M, R, E> {
+    fn drop(&mut self) {
+        self.dispatcher.abort();
+    }
+}
+
+/// Reads the responses of the daemon and hands each one to the request with its id.
+async fn dispatch(mut reader: Reader, pending: Arc<std::sync::Mutex<Pending>>) {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.read_line(&mut line).await {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                error!(?e, "failed to read response from daemon");
+                break;
+            }
+        }
+        debug!(response = ?line.trim_end(), "got");
+        match serde_json::from_str::<Tagged<serde_json::Value>>(&line) {
+            Ok(Tagged { id, body }) => {
+                let waiting = pending.lock().unwrap().waiting.remove(&id);
+                match waiting {
+                    // the receiver is gone if the request was cancelled
+                    Some(tx) => drop(tx.send(body)),
+                    None => debug!(id, "response to a request no one is waiting for"),
+                }
+            }
+            Err(e) => error!(?e, ?line, "response from daemon without an id"),
+        }
+    }
+    let mut pending = pending.lock().unwrap();
+    pending.closed = true;
+    // dropping the senders wakes up who was waiting on them
+    pending.waiting.clear();
+}
+
+fn disconnected() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "the daemon closed the connection",
+    )
+}
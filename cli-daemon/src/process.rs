@@ -7,38 +7,90 @@ use std::{
     io,
     marker::PhantomData,
     path::Path,
+    sync::Arc,
 };
 
-use futures_util::{future::OptionFuture, stream, Stream, StreamExt};
+use futures_util::{
+    future::OptionFuture,
+    stream::{self, FuturesUnordered},
+    Stream, StreamExt,
+};
 use serde::{de::DeserializeOwned, Serialize};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter},
-    net::{unix::WriteHalf, UnixListener, UnixStream},
+    io::{AsyncBufReadExt, AsyncWriteExt},
     signal::{
         unix::SignalKind,
         unix::{signal, Signal},
     },
-    sync::oneshot,
+    sync::{oneshot, Notify},
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    bootstrap::Bootstrap,
+    control::{Control, ControlKind, ControlResponse, Hello, Pong},
+    link::{EventSubscription, Tagged},
+    log,
+    metrics::Recorder,
+    transport::{self, Endpoint, Reader, Transport, Writer},
+    Daemon,
 };
-use tracing::{debug, error, info};
 
-use crate::{link::EventSubscription, Daemon};
+/// How messages are named, and which responses are errors, see [`DaemonProcess::with_metrics`].
+type MetricsNaming<M, R> = (fn(&M) -> &'static str, fn(&R) -> bool);
 
 /// A builder for a daemon process.
 pub struct DaemonProcess<'s, M, R, E = Infallible> {
     socket_path: &'s Path,
+    namespace: Option<String>,
+    transport: Transport,
     shutdown: Option<oneshot::Receiver<()>>,
+    bootstrap: Option<serde_json::Value>,
+    version: Option<&'static str>,
+    protocol: Option<u32>,
+    recorder: Recorder,
+    message_kind: fn(&M) -> &'static str,
+    is_error: fn(&R) -> bool,
     _marker: PhantomData<(M, R, E)>,
 }
 
 impl<'s, M, R, E> DaemonProcess<'s, M, R, E> {
+    /// Build the daemon process, listening where and with what the process that spawned it said.
     pub async fn new(daemon: &'s Daemon<M, R, E>) -> DaemonProcess<'s, M, R, E> {
+        let Bootstrap {
+            socket_namespace,
+            transport,
+            payload,
+        } = Bootstrap::from_env();
+        let namespace = socket_namespace.or_else(|| daemon.socket_namespace());
         Self {
-            socket_path: daemon.socket_path().await,
+            socket_path: daemon.socket_path_in(namespace.as_deref()).await,
+            namespace,
+            transport: transport.unwrap_or_else(|| daemon.transport()),
             shutdown: None,
+            bootstrap: payload,
+            version: daemon.version,
+            protocol: daemon.protocol,
+            recorder: Recorder::new(),
+            message_kind: |_| "message",
+            is_error: |_| false,
             _marker: PhantomData,
         }
     }
+
+    /// What the process that started the daemon gave with [`Daemon::set_bootstrap`], if it was
+    /// started by one.
+    pub fn bootstrap<B: DeserializeOwned>(&self) -> serde_json::Result<Option<B>> {
+        self.bootstrap
+            .clone()
+            .map(serde_json::from_value)
+            .transpose()
+    }
+
+    /// The namespace the socket is in, see [`Daemon::set_socket_namespace`].
+    pub fn socket_namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
 }
 
 impl<'s, M, R, E> DaemonProcess<'s, M, R, E> {
@@ -51,6 +103,26 @@ impl<'s, M, R, E> DaemonProcess<'s, M, R, E> {
         }
     }
 
+    /// How to tell messages apart, and which responses are errors, in [`Daemon::metrics`].
+    /// Without it every message is a `message` and nothing fails.
+    pub fn with_metrics(
+        self,
+        message_kind: fn(&M) -> &'static str,
+        is_error: fn(&R) -> bool,
+    ) -> Self {
+        Self {
+            message_kind,
+            is_error,
+            ..self
+        }
+    }
+
+    /// Where the daemon counts what it does, to expose it some other way than
+    /// [`Daemon::metrics`].
+    pub fn metrics(&self) -> Recorder {
+        self.recorder.clone()
+    }
+
     /// Start the daemon process with a handler. This functions returns error if initialization
     /// fails. If initialization does not fail this function never returns.
     pub async fn run<H, Fut>(self, handler: H) -> io::Result<Infallible>
@@ -62,12 +134,28 @@ impl<'s, M, R, E> DaemonProcess<'s, M, R, E> {
     {
         let DaemonProcess {
             socket_path,
+            namespace,
+            transport,
             shutdown,
+            bootstrap,
+            version,
+            protocol,
+            recorder,
+            message_kind,
+            is_error,
             ..
         } = self;
         DaemonProcess {
             socket_path,
+            namespace,
+            transport,
             shutdown,
+            bootstrap,
+            version,
+            protocol,
+            recorder,
+            message_kind,
+            is_error,
             _marker: PhantomData::<(M, R, ())>,
         }
         .run_with_events(handler, || async { stream::iter([]) })
@@ -95,9 +183,10 @@ where
         H: FnMut(M) -> Fut + Clone + Send + 'static,
         Fut: Future<Output = R> + Send + 'static,
     {
-        let _ = tokio::fs::remove_file(&self.socket_path).await;
-        let socket = UnixListener::bind(self.socket_path)?;
-        debug!(socket_path = ?self.socket_path, "listening on");
+        let endpoint = Endpoint::new(self.transport.clone(), self.socket_path.into());
+        let socket = endpoint.listen().await?;
+        debug!(?endpoint, "listening on");
+        tokio::spawn(log::cap(endpoint.log_path()));
 
         let mut term = signal(SignalKind::terminate()).ok();
         let mut ctrc = signal(SignalKind::interrupt()).ok();
@@ -109,16 +198,32 @@ where
             }
         };
         tokio::pin!(shutdown);
+        let control = ControlHandler {
+            version: self.version,
+            protocol: self.protocol,
+            recorder: self.recorder.clone(),
+            shutdown: Arc::new(Notify::new()),
+        };
 
         loop {
             tokio::select! {
                 Some(_) = recv_signal(term.as_mut()) => break,
                 Some(_) = recv_signal(ctrc.as_mut()) => break,
                 Ok(_) = &mut shutdown => break,
+                _ = control.shutdown.notified() => break,
                 accept = socket.accept() => match accept {
-                    Ok((stream, addr)) => {
-                        info!("got a new connection from {:?}", addr);
-                        tokio::spawn(handle_task(stream, handler.clone(), events.clone()));
+                    Ok((reader, writer, addr)) => {
+                        info!("got a new connection from {}", addr);
+                        self.recorder.connected();
+                        tokio::spawn(handle_task(
+                            reader,
+                            writer,
+                            socket.token(),
+                            (self.message_kind, self.is_error),
+                            handler.clone(),
+                            events.clone(),
+                            control.clone(),
+                        ));
                     },
                     Err(e) => {
                         error!("failed to accept connection: {:?}", e);
@@ -126,7 +231,7 @@ where
                 }
             }
         }
-        let _ = tokio::fs::remove_file(&self.socket_path).await;
+        endpoint.remove().await;
         info!("daemon exiting");
         std::process::exit(0);
 
@@ -136,8 +241,46 @@ where
     }
 }
 
-async fn handle_task<M, H, Fut, E, EFut>(mut stream: UnixStream, mut handler: H, events: E)
-where
+/// Answers the [`Control`] messages.
+#[derive(Clone)]
+struct ControlHandler {
+    version: Option<&'static str>,
+    protocol: Option<u32>,
+    recorder: Recorder,
+    /// Notified to make the daemon exit.
+    shutdown: Arc<Notify>,
+}
+
+impl ControlHandler {
+    fn answer(&self, kind: ControlKind) -> ControlResponse {
+        match kind {
+            ControlKind::Hello(theirs) => {
+                let ours = Hello::new(self.protocol);
+                if theirs != ours {
+                    info!(?theirs, ?ours, "client speaks another protocol");
+                }
+                ControlResponse::Hello(ours)
+            }
+            ControlKind::Ping => ControlResponse::Pong(Pong {
+                pid: std::process::id(),
+                uptime: self.recorder.uptime(),
+            }),
+            ControlKind::Metrics => ControlResponse::Metrics(self.recorder.snapshot()),
+            ControlKind::Version => ControlResponse::Version(self.version.map(String::from)),
+            ControlKind::Shutdown => ControlResponse::ShuttingDown,
+        }
+    }
+}
+
+async fn handle_task<M, H, Fut, E, EFut>(
+    mut recv: Reader,
+    mut send: Writer,
+    token: Option<Arc<str>>,
+    (message_kind, is_error): MetricsNaming<M, Fut::Output>,
+    mut handler: H,
+    events: E,
+    control: ControlHandler,
+) where
     E: FnOnce() -> EFut,
     EFut: Future,
     EFut::Output: Stream,
@@ -147,15 +290,26 @@ where
     M: DeserializeOwned,
     Fut::Output: Serialize,
 {
-    let (recv, send) = stream.split();
-    let mut lines = BufReader::new(recv).lines();
-    let mut send = BufWriter::new(send);
+    if let Err(e) = transport::admit(&mut recv, token.as_deref()).await {
+        warn!(?e, "refusing client");
+        return;
+    }
+    let mut lines = recv.lines();
+    // requests are answered as they finish, not in the order they came in
+    let mut in_flight = FuturesUnordered::new();
     loop {
-        match lines.next_line().await {
-            Ok(Some(line)) => {
-                debug!(?line, "received message");
-                match serde_json::from_str(&line) {
-                    Ok(EventSubscription) => {
+        tokio::select! {
+            Some((kind, response)) = in_flight.next() => {
+                let response: Tagged<Fut::Output> = response;
+                control.recorder.handled(kind, is_error(&response.body));
+                if let Err(e) = send_msg(&mut send, &response).await {
+                    error!(?e, "failed to respond to client");
+                }
+            }
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => {
+                    debug!(?line, "received message");
+                    if let Ok(EventSubscription) = serde_json::from_str(&line) {
                         let stream = events().await;
                         tokio::pin!(stream);
                         while let Some(e) = stream.next().await {
@@ -166,26 +320,60 @@ where
                         }
                         break;
                     }
-                    Err(_) => {
-                        let e = match serde_json::from_str(&line) {
-                            Ok(m) => send_msg(&mut send, &handler(m).await).await,
-                            Err(e) => send_msg(&mut send, &e.to_string()).await,
-                        };
-                        if let Err(e) = e {
+                    if let Ok(Control { kind }) = serde_json::from_str(&line) {
+                        debug!(?kind, "received control message");
+                        if let Err(e) = send_msg(&mut send, &control.answer(kind)).await {
                             error!(?e, "failed to respond to client");
                         }
+                        if kind == ControlKind::Shutdown {
+                            // only once the client was answered, since the process exits
+                            control.shutdown.notify_one();
+                            break;
+                        }
+                        continue;
+                    }
+                    let e = match serde_json::from_str::<Tagged<serde_json::Value>>(&line) {
+                        Ok(Tagged { id, body }) => match serde_json::from_value(body) {
+                            Ok(m) => {
+                                let kind = message_kind(&m);
+                                let response = handler(m);
+                                in_flight.push(async move {
+                                    let body = response.await;
+                                    (kind, Tagged { id, body })
+                                });
+                                continue;
+                            }
+                            Err(e) => {
+                                control.recorder.malformed();
+                                send_msg(
+                                    &mut send,
+                                    &Tagged {
+                                        id,
+                                        body: e.to_string(),
+                                    },
+                                )
+                                .await
+                            }
+                        },
+                        Err(e) => {
+                            control.recorder.malformed();
+                            send_msg(&mut send, &e.to_string()).await
+                        }
+                    };
+                    if let Err(e) = e {
+                        error!(?e, "failed to respond to client");
                     }
                 }
-            }
-            Ok(None) => break,
-            Err(e) => {
-                error!(?e, "error reading line from client");
-                break;
+                Ok(None) => break,
+                Err(e) => {
+                    error!(?e, "error reading line from client");
+                    break;
+                }
             }
         }
     }
 
-    async fn send_msg<M: Serialize>(sink: &mut BufWriter<WriteHalf<'_>>, m: &M) -> io::Result<()> {
+    async fn send_msg<M: Serialize>(sink: &mut Writer, m: &M) -> io::Result<()> {
         let response = serde_json::to_string(m).unwrap();
         debug!(?response, "sending response");
         sink.write_all(response.as_bytes()).await?;
@@ -0,0 +1,264 @@
+//! How clients reach a daemon. By default it's a unix socket in tmp, but that isn't shared with
+//! containers or flatpaks, which can use a socket in linux's abstract namespace (shared by all
+//! processes in the same network namespace) or TCP.
+
+use std::{
+    fmt::Debug,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{
+        AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter,
+    },
+    net::{TcpListener, TcpStream, UnixListener, UnixStream},
+};
+
+/// How long a client has to send its token.
+const ADMIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The longest token a client can send, so that one that never sends a newline can't make the
+/// daemon buffer forever.
+const MAX_TOKEN_LEN: u64 = 4096;
+
+/// How a daemon is reached, see [`crate::Daemon::set_transport`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Transport {
+    /// A unix socket in the tmp directory.
+    #[default]
+    Unix,
+    /// A unix socket in linux's abstract namespace, named like the one in tmp would be. It has no
+    /// permissions, so only clients of the same user are let in.
+    Abstract,
+    /// TCP on `address` (e.g. `127.0.0.1:4242`), where clients have to send `token`, which can't
+    /// be empty, before anything else. Nothing is encrypted, it's meant for reaching daemons on the
+    /// same machine.
+    Tcp { address: String, token: String },
+}
+
+pub(crate) trait ReadHalf: AsyncRead + Send + Unpin + Debug {}
+impl<T: AsyncRead + Send + Unpin + Debug> ReadHalf for T {}
+
+pub(crate) trait WriteHalf: AsyncWrite + Send + Unpin + Debug {}
+impl<T: AsyncWrite + Send + Unpin + Debug> WriteHalf for T {}
+
+pub(crate) type Reader = BufReader<Box<dyn ReadHalf>>;
+pub(crate) type Writer = BufWriter<Box<dyn WriteHalf>>;
+
+/// Where exactly a daemon is, its [`Transport`] together with the path its socket would have.
+#[derive(Debug, Clone)]
+pub(crate) struct Endpoint {
+    transport: Transport,
+    socket_path: PathBuf,
+}
+
+impl Endpoint {
+    pub(crate) fn new(transport: Transport, socket_path: PathBuf) -> Self {
+        Self {
+            transport,
+            socket_path,
+        }
+    }
+
+    /// The socket in the filesystem, if the daemon is reached through one.
+    pub(crate) fn path(&self) -> Option<&Path> {
+        match self.transport {
+            Transport::Unix => Some(&self.socket_path),
+            _ => None,
+        }
+    }
+
+    pub(crate) async fn connect(&self) -> io::Result<(Reader, Writer)> {
+        match &self.transport {
+            Transport::Unix => Ok(split_unix(UnixStream::connect(&self.socket_path).await?)),
+            Transport::Abstract => Ok(split_unix(abstract_socket::connect(&self.socket_path)?)),
+            Transport::Tcp { address, token } => {
+                let (reader, mut writer) = split_tcp(TcpStream::connect(address).await?);
+                writer.write_all(token.as_bytes()).await?;
+                writer.write_all(b"\n").await?;
+                writer.flush().await?;
+                Ok((reader, writer))
+            }
+        }
+    }
+
+    pub(crate) async fn listen(&self) -> io::Result<Listener> {
+        match &self.transport {
+            Transport::Unix => {
+                let _ = tokio::fs::remove_file(&self.socket_path).await;
+                Ok(Listener::Unix(UnixListener::bind(&self.socket_path)?))
+            }
+            Transport::Abstract => Ok(Listener::Abstract(abstract_socket::bind(
+                &self.socket_path,
+            )?)),
+            Transport::Tcp { token, .. } if token.is_empty() => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "refusing to listen on TCP with an empty token",
+            )),
+            Transport::Tcp { address, token } => Ok(Listener::Tcp(
+                TcpListener::bind(address).await?,
+                token.as_str().into(),
+            )),
+        }
+    }
+
+    /// Where the daemon logs to when a client starts it, next to where its socket would be.
+    pub(crate) fn log_path(&self) -> PathBuf {
+        self.socket_path.with_extension("log")
+    }
+
+    /// Held by a client while it starts the daemon, so that only one does.
+    pub(crate) fn spawn_lock_path(&self) -> PathBuf {
+        self.socket_path.with_extension("spawn.lock")
+    }
+
+    /// Cleans up after the daemon stops listening.
+    pub(crate) async fn remove(&self) {
+        if let Some(path) = self.path() {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+    }
+}
+
+pub(crate) enum Listener {
+    Unix(UnixListener),
+    Abstract(UnixListener),
+    Tcp(TcpListener, Arc<str>),
+}
+
+impl Listener {
+    /// Accepts a connection, returning where it came from.
+    pub(crate) async fn accept(&self) -> io::Result<(Reader, Writer, String)> {
+        match self {
+            Self::Unix(listener) => {
+                let (stream, addr) = listener.accept().await?;
+                let (reader, writer) = split_unix(stream);
+                Ok((reader, writer, format!("{addr:?}")))
+            }
+            Self::Abstract(listener) => {
+                let (stream, addr) = listener.accept().await?;
+                if !abstract_socket::same_user(&stream)? {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "connection from another user",
+                    ));
+                }
+                let (reader, writer) = split_unix(stream);
+                Ok((reader, writer, format!("{addr:?}")))
+            }
+            Self::Tcp(listener, _) => {
+                let (stream, addr) = listener.accept().await?;
+                let (reader, writer) = split_tcp(stream);
+                Ok((reader, writer, addr.to_string()))
+            }
+        }
+    }
+
+    /// What clients have to send before anything else, see [`admit`].
+    pub(crate) fn token(&self) -> Option<Arc<str>> {
+        match self {
+            Self::Unix(_) | Self::Abstract(_) => None,
+            Self::Tcp(_, token) => Some(token.clone()),
+        }
+    }
+}
+
+/// Checks that a client is allowed in, which is reading its `token` if one is needed.
+pub(crate) async fn admit(reader: &mut Reader, token: Option<&str>) -> io::Result<()> {
+    let Some(token) = token else {
+        return Ok(());
+    };
+    let mut line = String::new();
+    let read = (&mut *reader).take(MAX_TOKEN_LEN).read_line(&mut line);
+    tokio::time::timeout(ADMIT_TIMEOUT, read)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "client didn't send a token"))??;
+    match line.strip_suffix('\n') {
+        Some(given) if same_token(given.as_bytes(), token.as_bytes()) => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "client sent the wrong token",
+        )),
+    }
+}
+
+/// Compares tokens in a time that doesn't depend on how much of them matches.
+fn same_token(given: &[u8], token: &[u8]) -> bool {
+    given.len() == token.len() && given.iter().zip(token).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn split_unix(stream: UnixStream) -> (Reader, Writer) {
+    let (reader, writer) = stream.into_split();
+    (
+        BufReader::new(Box::new(reader)),
+        BufWriter::new(Box::new(writer)),
+    )
+}
+
+fn split_tcp(stream: TcpStream) -> (Reader, Writer) {
+    let (reader, writer) = stream.into_split();
+    (
+        BufReader::new(Box::new(reader)),
+        BufWriter::new(Box::new(writer)),
+    )
+}
+
+#[cfg(target_os = "linux")]
+mod abstract_socket {
+    use std::{
+        io,
+        os::{
+            linux::net::SocketAddrExt,
+            unix::{ffi::OsStrExt, fs::MetadataExt, net::SocketAddr},
+        },
+        path::Path,
+    };
+
+    use tokio::net::{UnixListener, UnixStream};
+
+    fn addr(name: &Path) -> io::Result<SocketAddr> {
+        SocketAddr::from_abstract_name(name.as_os_str().as_bytes())
+    }
+
+    pub(super) fn connect(name: &Path) -> io::Result<UnixStream> {
+        let stream = std::os::unix::net::UnixStream::connect_addr(&addr(name)?)?;
+        stream.set_nonblocking(true)?;
+        UnixStream::from_std(stream)
+    }
+
+    pub(super) fn bind(name: &Path) -> io::Result<UnixListener> {
+        let listener = std::os::unix::net::UnixListener::bind_addr(&addr(name)?)?;
+        listener.set_nonblocking(true)?;
+        UnixListener::from_std(listener)
+    }
+
+    /// Whether the other end of `stream` runs as the same user as this process.
+    pub(super) fn same_user(stream: &UnixStream) -> io::Result<bool> {
+        let ours = std::fs::metadata("/proc/self")?.uid();
+        Ok(stream.peer_cred()?.uid() == ours)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod abstract_socket {
+    use std::{io, path::Path};
+
+    use tokio::net::{UnixListener, UnixStream};
+
+    pub(super) fn connect(_: &Path) -> io::Result<UnixStream> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub(super) fn bind(_: &Path) -> io::Result<UnixListener> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub(super) fn same_user(_: &UnixStream) -> io::Result<bool> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
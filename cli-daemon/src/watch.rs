@@ -0,0 +1,89 @@
+//! Noticing when the socket of a daemon shows up, to connect to it as soon as it's listening.
+
+use std::{ffi::OsString, path::Path, time::Duration};
+
+use futures_util::StreamExt;
+use tracing::debug;
+
+use crate::transport::Endpoint;
+
+/// How long to wait for the socket before trying to connect again anyway, which is all there is
+/// to it where the socket directory can't be watched.
+const POLL: Duration = Duration::from_secs(1);
+
+#[cfg(target_os = "linux")]
+type Events = inotify::EventStream<[u8; 1024]>;
+
+#[cfg(not(target_os = "linux"))]
+type Events = futures_util::stream::Pending<std::io::Result<Event>>;
+
+#[cfg(not(target_os = "linux"))]
+struct Event {
+    name: Option<OsString>,
+}
+
+pub(crate) struct SocketWatch {
+    events: Option<Events>,
+    name: Option<OsString>,
+}
+
+impl SocketWatch {
+    /// Starts watching the directory of the socket of `endpoint`, falling back to polling if it
+    /// can't or it has none.
+    pub(crate) fn new(endpoint: &Endpoint) -> Self {
+        let Some(socket_path) = endpoint.path() else {
+            return Self {
+                events: None,
+                name: None,
+            };
+        };
+        let dir = match socket_path.parent() {
+            Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+            Some(dir) => dir,
+            None => Path::new("/"),
+        };
+        Self {
+            events: watch(dir)
+                .inspect_err(|e| debug!(?e, "failed to watch the socket directory, polling"))
+                .ok(),
+            name: socket_path.file_name().map(ToOwned::to_owned),
+        }
+    }
+
+    /// Waits for the next chance of the socket being there, which is when it's created or, at
+    /// most, [`POLL`] from now.
+    pub(crate) async fn changed(&mut self) {
+        let Some(events) = &mut self.events else {
+            return tokio::time::sleep(POLL).await;
+        };
+        let created = async {
+            loop {
+                match events.next().await {
+                    Some(Ok(event)) if event.name == self.name => return true,
+                    Some(Ok(_)) => {}
+                    Some(Err(_)) | None => return false,
+                }
+            }
+        };
+        if let Ok(false) = tokio::time::timeout(POLL, created).await {
+            debug!("stopped watching the socket directory, polling");
+            self.events = None;
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn watch(dir: &Path) -> std::io::Result<Events> {
+    use inotify::{Inotify, WatchMask};
+
+    let inotify = Inotify::init()?;
+    inotify
+        .watches()
+        .add(dir, WatchMask::CREATE | WatchMask::MOVED_TO)?;
+    inotify.into_event_stream([0; 1024])
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch(_: &Path) -> std::io::Result<Events> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
@@ -0,0 +1,41 @@
+//! A tiny now playing widget, prints a line every time the song changes or is (un)paused.
+
+use futures_util::StreamExt;
+use mlib::{
+    players::{self, event::OwnedLibMpvEvent},
+    prelude::*,
+};
+
+async fn status(player: &PlayerLink) -> Result<String, Error> {
+    let title = player.media_title().await?;
+    let icon = if player.is_paused().await? {
+        "⏸"
+    } else {
+        "▶"
+    };
+    let percent = player.percent_position().await?;
+    Ok(format!("{icon} {title} ({percent:.0}%)"))
+}
+
+async fn print_status(player: &PlayerLink) {
+    match status(player).await {
+        Ok(status) => println!("{status}"),
+        Err(_) => println!("nothing playing"),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    players::start_daemon_if_running_as_daemon(|()| {}).await?;
+    let player = PlayerLink::current();
+    print_status(player).await;
+    let mut events = std::pin::pin!(player.subscribe().await?);
+    while let Some(event) = events.next().await {
+        if let OwnedLibMpvEvent::PropertyChange { name, .. } = event?.event {
+            if name == "media-title" || name == "pause" {
+                print_status(player).await;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,18 @@
+//! Prints the queue of the current player, marking the song that is playing.
+
+use mlib::{players, prelude::*};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    players::start_daemon_if_running_as_daemon(|()| {}).await?;
+    let queue = Queue::load_full(PlayerLink::current()).await?;
+    for song in queue.before() {
+        println!("   {:3} {}", song.index, song.item);
+    }
+    let current = queue.current_song();
+    println!("=> {:3} {}", current.index, current.item);
+    for song in queue.after() {
+        println!("   {:3} {}", song.index, song.item);
+    }
+    Ok(())
+}
@@ -1,24 +1,38 @@
+#[cfg(feature = "statistics")]
+pub mod budget;
+pub mod manager;
+pub mod manifest;
+pub mod policy;
+pub mod thumbnails;
+
 use std::{
+    collections::HashSet,
     ffi::{OsStr, OsString},
     io,
-    os::unix::ffi::OsStringExt,
+    os::unix::ffi::{OsStrExt, OsStringExt},
     path::{Path, PathBuf},
     process::Stdio,
 };
 
 use futures_util::{Stream, TryStreamExt};
 use glob::Paths;
-use tokio::{fs, process::Command};
+use tokio::{
+    fs,
+    io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+    process::Command,
+};
 use tokio_stream::wrappers::ReadDirStream;
 
+use self::policy::{DownloadPolicy, OutputTemplate};
 use crate::{
-    item::{id_from_path, link::VideoLink},
-    playlist::{self, PlaylistIds},
+    item::{clean_up_path, id_from_path, link::VideoLink},
+    playlist::{self, Playlist, PlaylistIds},
     queue::Item,
-    ytdl::YtdlError,
+    ytdl::{self, YtdlError},
     Error,
 };
 use derive_more::derive::From;
+use serde::{Deserialize, Serialize};
 
 pub async fn clean_downloads<P: AsRef<Path>>(
     dl_dir: P,
@@ -40,33 +54,98 @@ pub async fn clean_downloads<P: AsRef<Path>>(
     )
 }
 
+/// Downloaded files that don't belong to any song of the playlist.
+pub async fn orphans(dl_dir: &Path, playlist: &Playlist) -> Result<Vec<PathBuf>, Error> {
+    let ids = playlist
+        .songs
+        .iter()
+        .map(|s| s.link.id())
+        .collect::<HashSet<_>>();
+    let mut files = match fs::read_dir(dl_dir).await {
+        Ok(files) => files,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut orphans = Vec::new();
+    while let Some(f) = files.next_entry().await? {
+        if !f.file_type().await?.is_file() {
+            continue;
+        }
+        let fname = f.file_name();
+        if fname.as_bytes().starts_with(b".") {
+            continue;
+        }
+        if !id_from_path(&fname).is_some_and(|id| ids.contains(id)) {
+            orphans.push(f.path());
+        }
+    }
+    orphans.sort();
+    Ok(orphans)
+}
+
 #[must_use]
 pub enum CheckCacheDecision {
     Download(VideoLink),
     Skip,
 }
 
-pub async fn is_in_cache(dl_dir: &Path, link: &VideoLink) -> bool {
+/// Files youtube-dl is still writing to, or left behind when it was interrupted.
+fn is_partial(path: &Path) -> bool {
+    let Some(name) = path.file_name().map(OsStr::as_bytes) else {
+        return false;
+    };
+    name.ends_with(b".part")
+        || name.ends_with(b".ytdl")
+        || name.windows(10).any(|w| w == b".part-Frag")
+}
+
+fn cache_glob(dl_dir: &Path, link: &VideoLink) -> String {
     let mut s = dl_dir.to_string_lossy().into_owned();
-    s.push_str("/*=");
-    s.push_str(link.id());
-    s.push_str("=m.*");
+    s.push('/');
+    s.push_str(&OutputTemplate::glob(link.id()));
+    s
+}
+
+/// Whether the song was downloaded, interrupted downloads don't count.
+pub async fn is_in_cache(dl_dir: &Path, link: &VideoLink) -> bool {
+    matches!(search_cache_for(dl_dir, link).await, Ok(Some(_)))
+}
+
+/// The partial file of an interrupted download of this song, if there is one.
+pub async fn partial_download(dl_dir: &Path, link: &VideoLink) -> Option<PathBuf> {
+    let s = cache_glob(dl_dir, link);
     tokio::task::spawn_blocking(move || {
-        tracing::debug!("searching cache using glob: {:?}", s);
-        let file = glob::glob(&s).map(Paths::collect::<Vec<_>>);
-        let mut files = match file {
-            Ok(files) => files,
-            Err(e) => {
-                tracing::error!("parsing glob pattern {:?}: {:?}", s, e);
-                return false;
-            }
-        };
-        matches!(files.pop(), Some(Ok(_)) if files.is_empty())
+        glob::glob(&s)
+            .ok()?
+            .filter_map(Result::ok)
+            .find(|p| is_partial(p))
     })
     .await
     .unwrap()
 }
 
+/// The downloaded songs whose name contains all of `words`, ignoring case.
+pub async fn search_by_name(dl_dir: &Path, words: &[&str]) -> Result<Vec<PathBuf>, Error> {
+    let words = words.iter().map(|w| w.to_lowercase()).collect::<Vec<_>>();
+    let mut files = fs::read_dir(dl_dir).await?;
+    let mut found = Vec::new();
+    while let Some(f) = files.next_entry().await? {
+        let path = f.path();
+        if is_partial(&path) || !f.metadata().await?.is_file() {
+            continue;
+        }
+        let Some(name) = clean_up_path(&path) else {
+            continue;
+        };
+        let name = name.to_lowercase();
+        if words.iter().all(|w| name.contains(w.as_str())) {
+            found.push(path);
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
 #[derive(Debug, From)]
 pub enum GlobLibError {
     Iter(glob::GlobError),
@@ -77,10 +156,7 @@ pub async fn search_cache_for(
     dl_dir: &Path,
     link: &VideoLink,
 ) -> Result<Option<PathBuf>, GlobLibError> {
-    let mut s = dl_dir.to_string_lossy().into_owned();
-    s.push_str("/*=");
-    s.push_str(link.id());
-    s.push_str("=m.*");
+    let s = cache_glob(dl_dir, link);
     tokio::task::spawn_blocking(move || {
         tracing::debug!("searching cache using glob: {:?}", s);
         let file = glob::glob(&s).map(Paths::collect::<Vec<_>>);
@@ -88,6 +164,7 @@ pub async fn search_cache_for(
             Ok(files) => files,
             Err(e) => return Err(e.into()),
         };
+        files.retain(|f| !f.as_ref().is_ok_and(|p| is_partial(p)));
         let file = match files.pop() {
             Some(Ok(file)) if files.is_empty() => file,
             None => return Ok(None),
@@ -151,14 +228,14 @@ pub struct GetDlPath<'v> {
 impl GetDlPath<'_> {
     pub async fn get(&self) -> Result<PathBuf, Error> {
         let o = OsStr::new;
-        let mut output = Command::new("youtube-dl")
+        let mut output = ytdl::command()
+            .await
             .args([
                 o("-o"),
                 self.output_format.as_os_str(),
                 o(self.link.as_str()),
-                o("--print"),
-                o("filename"),
             ])
+            .args(ytdl::backend().print("filename"))
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()?
@@ -170,54 +247,210 @@ impl GetDlPath<'_> {
             }
             Ok(PathBuf::from(OsString::from_vec(output.stdout)))
         } else {
-            Err(YtdlError::NonZeroStatus {
-                status_code: output.status,
-                stderr: String::from_utf8(output.stderr)
+            Err(YtdlError::failed(
+                output.status,
+                String::from_utf8(output.stderr)
                     .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()),
-            }
+            )
             .into())
         }
     }
 }
 
-pub async fn download(
+/// How far along a download is, as reported by youtube-dl.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+    pub percent: f32,
+    pub size: Option<String>,
+    pub speed: Option<String>,
+    pub eta: Option<String>,
+}
+
+impl Progress {
+    /// Parses youtube-dl's progress lines, e.g.
+    /// `[download]  45.3% of ~  3.50MiB at  1.20MiB/s ETA 00:02 (frag 3/10)`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.strip_prefix("[download]")?.split_whitespace();
+        let percent = words.next()?.strip_suffix('%')?.parse().ok()?;
+        let mut progress = Self {
+            percent,
+            ..Default::default()
+        };
+        while let Some(word) = words.next() {
+            let field = match word {
+                "of" => &mut progress.size,
+                "at" => &mut progress.speed,
+                "ETA" => &mut progress.eta,
+                _ => continue,
+            };
+            *field = words.find(|w| *w != "~").map(String::from);
+        }
+        Some(progress)
+    }
+}
+
+pub async fn download<'l>(
     dl_dir: PathBuf,
-    link: &VideoLink,
+    link: &'l VideoLink,
+    just_audio: bool,
+    policy: &DownloadPolicy,
+) -> Result<GetDlPath<'l>, Error> {
+    download_with_progress(dl_dir, link, just_audio, policy, |_| {}).await
+}
+
+/// Checks that a downloaded file can actually be played by probing it with ffprobe. If ffprobe
+/// isn't installed files are assumed to be fine.
+pub async fn verify(path: &Path) -> Result<(), Error> {
+    let output = match Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "csv=p=0",
+        ])
+        .arg(path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            tracing::warn!("ffprobe not found, not verifying {}", path.display());
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let duration = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<f64>()
+        .ok();
+    if output.status.success() && duration.is_some_and(|d| d > 0.) {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(YtdlError::Unplayable {
+            path: path.to_owned(),
+            reason: match stderr.trim() {
+                "" => "no duration".into(),
+                e => e.into(),
+            },
+        }
+        .into())
+    }
+}
+
+/// Downloads a song, calling `on_progress` every time youtube-dl reports progress.
+///
+/// Interrupted downloads are resumed and the downloaded file is [verified](verify), if it's not
+/// playable it's deleted.
+pub async fn download_with_progress<'l>(
+    dl_dir: PathBuf,
+    link: &'l VideoLink,
     just_audio: bool,
-) -> Result<GetDlPath<'_>, Error> {
+    policy: &DownloadPolicy,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<GetDlPath<'l>, Error> {
     tokio::fs::create_dir_all(&dl_dir).await?;
-    let mut output_format = dl_dir;
-    output_format.push("%(title)s=%(id)s=m.%(ext)s");
-    let mut cmd = Command::new("youtube-dl");
-    if just_audio {
-        cmd.arg("-x");
+    let mut cmd = ytdl::command().await;
+    cmd.args(policy.ytdl_args(just_audio));
+    if let Some(partial) = partial_download(&dl_dir, link).await {
+        tracing::info!("resuming download from {}", partial.display());
+        cmd.arg("--continue");
+    }
+    if ytdl::backend().embed_chapters {
+        cmd.arg("--embed-chapters");
     }
+    let mut output_format = dl_dir.clone();
+    output_format.push(policy.name.ytdl());
     let o = OsStr::new;
     tracing::info!("downloading {}", link.as_str());
-    let output = cmd
+    let mut child = cmd
         .args([
             o("-o"),
             output_format.as_os_str(),
             o("--add-metadata"),
-            o("--embed-chapters"),
+            o("--newline"),
             o(link.as_str()),
         ])
-        .stdout(Stdio::null())
+        .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .spawn()?
-        .wait_with_output()
-        .await?;
-    if output.status.success() {
+        .kill_on_drop(true)
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let progress = async {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Some(line) = lines.next_line().await? {
+            if let Some(progress) = Progress::parse(&line) {
+                on_progress(progress);
+            }
+        }
+        Ok::<_, io::Error>(())
+    };
+    let mut error_output = Vec::new();
+    let (progress, read_stderr) = tokio::join!(progress, stderr.read_to_end(&mut error_output));
+    progress?;
+    read_stderr?;
+    let status = child.wait().await?;
+    if status.success() {
+        match search_cache_for(&dl_dir, link).await {
+            Ok(Some(file)) => {
+                if let Err(e) = verify(&file).await {
+                    if let Err(error) = fs::remove_file(&file).await {
+                        tracing::error!(?error, "failed to delete {}", file.display());
+                    }
+                    return Err(e);
+                }
+            }
+            Ok(None) => tracing::warn!("can't find the downloaded file to verify it"),
+            Err(error) => tracing::error!(?error, "failed to search for the downloaded file"),
+        }
         Ok(GetDlPath {
             output_format,
             link,
         })
     } else {
-        Err(YtdlError::NonZeroStatus {
-            status_code: output.status,
-            stderr: String::from_utf8(output.stderr)
+        Err(YtdlError::failed(
+            status,
+            String::from_utf8(error_output)
                 .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()),
-        }
+        )
         .into())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_progress_lines() {
+        let p =
+            Progress::parse("[download]  45.3% of ~  3.50MiB at  1.20MiB/s ETA 00:02 (frag 3/10)")
+                .unwrap();
+        assert_eq!(p.percent, 45.3);
+        assert_eq!(p.size.as_deref(), Some("3.50MiB"));
+        assert_eq!(p.speed.as_deref(), Some("1.20MiB/s"));
+        assert_eq!(p.eta.as_deref(), Some("00:02"));
+
+        let p = Progress::parse("[download] 100% of    3.50MiB in 00:00:02 at 1.52MiB/s").unwrap();
+        assert_eq!(p.percent, 100.);
+        assert_eq!(p.eta, None);
+
+        assert_eq!(Progress::parse("[download] Destination: song.webm"), None);
+        assert_eq!(Progress::parse("[youtube] abc: Downloading webpage"), None);
+    }
+
+    #[test]
+    fn partial_files() {
+        let partial = |p: &str| is_partial(Path::new(p));
+        assert!(partial("/m/Song=abc=m.webm.part"));
+        assert!(partial("/m/Song=abc=m.webm.ytdl"));
+        assert!(partial("/m/Song=abc=m.f251.webm.part-Frag3"));
+        assert!(!partial("/m/Song=abc=m.webm"));
+        assert!(!partial("/m/Party=abc=m.opus"));
+    }
+}
@@ -0,0 +1,160 @@
+//! Keeping the download dir under a size budget by deleting the songs that haven't been used in
+//! the longest time. Their thumbnails count towards the budget and go with them.
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use tokio::fs;
+
+use super::{is_partial, thumbnails};
+use crate::{item::id_from_path, statistics, Error, VideoId};
+
+/// A downloaded song, or thumbnail, that can be evicted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedFile {
+    pub path: PathBuf,
+    pub size: u64,
+    /// When the song was last played or, if it was downloaded after that, downloaded.
+    pub last_used: SystemTime,
+}
+
+/// The files to delete, least recently used first, so that the rest add up to at most
+/// `max_bytes`.
+pub fn select_evictions(mut files: Vec<CachedFile>, max_bytes: u64) -> Vec<CachedFile> {
+    let mut total = files.iter().map(|f| f.size).sum::<u64>();
+    files.sort_by_key(|f| f.last_used);
+    files
+        .into_iter()
+        .take_while(|f| {
+            let over = total > max_bytes;
+            total = total.saturating_sub(f.size);
+            over
+        })
+        .collect()
+}
+
+async fn cached_files(
+    dl_dir: &Path,
+    last_played: &HashMap<Box<VideoId>, statistics::Plays>,
+) -> Result<Vec<CachedFile>, Error> {
+    let mut files = Vec::new();
+    files_in(dl_dir, last_played, &mut files).await?;
+    files_in(&thumbnails::dir(dl_dir), last_played, &mut files).await?;
+    Ok(files)
+}
+
+async fn files_in(
+    dir: &Path,
+    last_played: &HashMap<Box<VideoId>, statistics::Plays>,
+    files: &mut Vec<CachedFile>,
+) -> Result<(), Error> {
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        // interrupted downloads will be resumed, anything else isn't a song
+        if is_partial(&path) {
+            continue;
+        }
+        let Some(id) = id_from_path(&path) else {
+            continue;
+        };
+        let metadata = entry.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let played = last_played
+            .get(id)
+            .and_then(|p| p.last)
+            .map(SystemTime::from);
+        let downloaded = metadata.modified().ok();
+        files.push(CachedFile {
+            path,
+            size: metadata.len(),
+            last_used: played.max(downloaded).unwrap_or(SystemTime::UNIX_EPOCH),
+        });
+    }
+    Ok(())
+}
+
+async fn remove(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Deletes the least recently used songs and thumbnails until the download dir takes up at most
+/// `max_bytes`, returning what was deleted. The thumbnails of deleted songs are deleted too.
+pub async fn enforce_budget(dl_dir: &Path, max_bytes: u64) -> Result<Vec<CachedFile>, Error> {
+    let plays = statistics::plays().await?;
+    let mut evicted = select_evictions(cached_files(dl_dir, &plays).await?, max_bytes);
+    let thumbnails_dir = thumbnails::dir(dl_dir);
+    let mut orphaned = Vec::new();
+    for file in &evicted {
+        tracing::info!(path = %file.path.display(), "evicting to stay under the cache budget");
+        remove(&file.path).await?;
+        if file.path.starts_with(&thumbnails_dir) {
+            continue;
+        }
+        let Some(id) = id_from_path(&file.path) else {
+            continue;
+        };
+        if let Some(thumbnail) = thumbnails::get(dl_dir, id).await? {
+            if evicted.iter().any(|f| f.path == thumbnail) {
+                continue;
+            }
+            let size = fs::metadata(&thumbnail).await.map_or(0, |m| m.len());
+            remove(&thumbnail).await?;
+            orphaned.push(CachedFile {
+                path: thumbnail,
+                size,
+                last_used: file.last_used,
+            });
+        }
+    }
+    evicted.extend(orphaned);
+    Ok(evicted)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn file(name: &str, size: u64, used: u64) -> CachedFile {
+        CachedFile {
+            path: name.into(),
+            size,
+            last_used: SystemTime::UNIX_EPOCH + Duration::from_secs(used),
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let files = vec![
+            file("new", 10, 30),
+            file("old", 10, 10),
+            file("mid", 10, 20),
+        ];
+        let names = |max| {
+            select_evictions(files.clone(), max)
+                .into_iter()
+                .map(|f| f.path)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(30), Vec::<PathBuf>::new());
+        assert_eq!(names(25), [PathBuf::from("old")]);
+        assert_eq!(names(20), [PathBuf::from("old")]);
+        assert_eq!(names(15), [PathBuf::from("old"), PathBuf::from("mid")]);
+        assert_eq!(names(0).len(), 3);
+    }
+}
@@ -0,0 +1,90 @@
+use std::{num::NonZeroUsize, path::PathBuf};
+
+use futures_util::{stream, StreamExt};
+use tokio::sync::mpsc;
+
+use super::{download_with_progress, policy::DownloadPolicy, Progress};
+use crate::{item::link::VideoLink, Error};
+
+/// What happened to one of the links given to [`DownloadManager::run`], identified by its
+/// position.
+#[derive(Debug)]
+pub enum Event {
+    Started(usize),
+    Progress(usize, Progress),
+    Finished(usize, Result<(), Error>),
+    /// Sent last, once every download finished.
+    Done(Summary),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub downloaded: usize,
+    pub failed: usize,
+}
+
+/// Runs several youtube-dl processes at once.
+#[derive(Debug, Clone)]
+pub struct DownloadManager {
+    dl_dir: PathBuf,
+    just_audio: bool,
+    policy: DownloadPolicy,
+    jobs: NonZeroUsize,
+}
+
+impl DownloadManager {
+    pub fn new(
+        dl_dir: PathBuf,
+        just_audio: bool,
+        policy: DownloadPolicy,
+        jobs: NonZeroUsize,
+    ) -> Self {
+        Self {
+            dl_dir,
+            just_audio,
+            policy,
+            jobs,
+        }
+    }
+
+    /// Downloads every link, at most `jobs` at a time, reporting progress through the returned
+    /// channel.
+    pub fn run(self, links: Vec<VideoLink>) -> mpsc::UnboundedReceiver<Event> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let summary = stream::iter(links.into_iter().enumerate())
+                .map(|(i, link)| {
+                    let tx = tx.clone();
+                    let dl_dir = self.dl_dir.clone();
+                    let (just_audio, policy) = (self.just_audio, &self.policy);
+                    async move {
+                        let _ = tx.send(Event::Started(i));
+                        let result =
+                            download_with_progress(dl_dir, &link, just_audio, policy, |progress| {
+                                let _ = tx.send(Event::Progress(i, progress));
+                            })
+                            .await
+                            .map(|_| ());
+                        if let Err(error) = &result {
+                            tracing::error!(?error, %link, "failed to download");
+                        }
+                        let ok = result.is_ok();
+                        let _ = tx.send(Event::Finished(i, result));
+                        ok
+                    }
+                })
+                .buffer_unordered(self.jobs.get())
+                .fold(Summary::default(), |mut summary, ok| async move {
+                    if ok {
+                        summary.downloaded += 1;
+                    } else {
+                        summary.failed += 1;
+                    }
+                    summary
+                })
+                .await;
+            let _ = tx.send(Event::Done(summary));
+        });
+        rx
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+use crate::item::id_from_path;
+
+/// Name of the file, inside the download dir, where the manifest is saved so that other machines
+/// can fetch it.
+pub const FILE_NAME: &str = ".manifest";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub id: String,
+    pub sha256: String,
+    pub size: u64,
+    pub file_name: String,
+}
+
+/// A listing of the songs in the download dir, used to share the cache between machines.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: Vec<Entry>,
+}
+
+impl Manifest {
+    /// Hashes every downloaded song in `dl_dir`.
+    pub async fn generate(dl_dir: &Path) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        for (id, path) in cached_files(dl_dir).await? {
+            let Some(file_name) = path.file_name().and_then(|f| f.to_str()) else {
+                tracing::warn!(?path, "skipping file with non utf8 name");
+                continue;
+            };
+            entries.push(Entry {
+                id,
+                file_name: file_name.to_string(),
+                size: fs::metadata(&path).await?.len(),
+                sha256: hash_file(path).await?,
+            });
+        }
+        entries.sort_unstable_by(|a, b| a.file_name.cmp(&b.file_name));
+        Ok(Self { entries })
+    }
+
+    pub async fn load(path: &Path) -> io::Result<Self> {
+        fs::read_to_string(path).await?.parse()
+    }
+
+    pub async fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string()).await
+    }
+
+    /// The entries whose video isn't downloaded in `dl_dir`.
+    pub async fn missing_in(&self, dl_dir: &Path) -> io::Result<Vec<&Entry>> {
+        let local = cached_files(dl_dir)
+            .await?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<HashSet<_>>();
+        Ok(self
+            .entries
+            .iter()
+            .filter(|e| !local.contains(&e.id))
+            .collect())
+    }
+}
+
+impl Entry {
+    /// Checks that the file in `dl_dir` matches this entry.
+    pub async fn verify(&self, dl_dir: &Path) -> io::Result<bool> {
+        let path = dl_dir.join(&self.file_name);
+        if fs::metadata(&path).await?.len() != self.size {
+            return Ok(false);
+        }
+        Ok(hash_file(path).await? == self.sha256)
+    }
+}
+
+impl Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for e in &self.entries {
+            writeln!(f, "{}\t{}\t{}\t{}", e.id, e.sha256, e.size, e.file_name)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Manifest {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid manifest line: {line:?}"),
+            )
+        };
+        let entries = s
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|line| {
+                let mut fields = line.splitn(4, '\t');
+                let mut next = || fields.next().ok_or_else(|| invalid(line));
+                Ok(Entry {
+                    id: next()?.to_string(),
+                    sha256: next()?.to_string(),
+                    size: next()?.parse().map_err(|_| invalid(line))?,
+                    file_name: next()?.to_string(),
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self { entries })
+    }
+}
+
+async fn cached_files(dl_dir: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    let mut dir = fs::read_dir(dl_dir).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        if !entry.file_type().await?.is_file() {
+            continue;
+        }
+        if let Some(id) = id_from_path(&entry.file_name()) {
+            files.push((id.to_string(), entry.path()));
+        }
+    }
+    Ok(files)
+}
+
+/// The hex encoded sha256 of a file.
+pub async fn hash_file(path: PathBuf) -> io::Result<String> {
+    tokio::task::spawn_blocking(move || {
+        let mut hasher = Sha256::new();
+        io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+        Ok(hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect())
+    })
+    .await
+    .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let manifest = Manifest {
+            entries: vec![Entry {
+                id: "jNQXAC9IVRw".into(),
+                sha256: "ab".repeat(32),
+                size: 42,
+                file_name: "Me at the zoo\twith a tab=jNQXAC9IVRw=m.webm".into(),
+            }],
+        };
+        assert_eq!(manifest, manifest.to_string().parse().unwrap());
+    }
+}
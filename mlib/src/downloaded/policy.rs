@@ -0,0 +1,184 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::item::{FILE_ID_SEPARATOR as SEP, FILE_MARKER};
+
+/// The youtube-dl output template for the name of downloaded files, `%(title)s` by default.
+///
+/// The id is always appended, as in `<name>=<id>=m.<ext>`, so that whatever the name is files can
+/// be matched to their songs.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct OutputTemplate(String);
+
+impl Default for OutputTemplate {
+    fn default() -> Self {
+        Self("%(title)s".into())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid output template {0:?}, it can't be empty or contain '/'")]
+pub struct InvalidTemplate(String);
+
+impl OutputTemplate {
+    pub fn new(name: String) -> Result<Self, InvalidTemplate> {
+        if name.is_empty() || name.contains('/') {
+            Err(InvalidTemplate(name))
+        } else {
+            Ok(Self(name))
+        }
+    }
+
+    /// The template given to youtube-dl's `-o`.
+    pub(crate) fn ytdl(&self) -> String {
+        format!("{}{SEP}%(id)s{SEP}{FILE_MARKER}.%(ext)s", self.0)
+    }
+
+    /// A glob matching the files of a song, whatever template they were downloaded with.
+    pub(crate) fn glob(id: &str) -> String {
+        format!("*{SEP}{id}{SEP}{FILE_MARKER}.*")
+    }
+
+    /// The name youtube-dl gives a file, once the template is filled in.
+    pub fn file_name(name: &str, id: &str, ext: &str) -> String {
+        format!("{name}{SEP}{id}{SEP}{FILE_MARKER}.{ext}")
+    }
+}
+
+impl TryFrom<String> for OutputTemplate {
+    type Error = InvalidTemplate;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::new(s)
+    }
+}
+
+impl From<OutputTemplate> for String {
+    fn from(t: OutputTemplate) -> Self {
+        t.0
+    }
+}
+
+/// Codecs youtube-dl can convert audio to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioCodec {
+    Best,
+    Aac,
+    Flac,
+    M4a,
+    Mp3,
+    Opus,
+    Vorbis,
+    Wav,
+}
+
+/// Containers youtube-dl can merge video and audio into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    Mkv,
+    Mp4,
+    Ogg,
+    Webm,
+    Flv,
+}
+
+macro_rules! lowercase_display {
+    ($($t:ty),*) => {$(
+        impl fmt::Display for $t {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&format!("{self:?}").to_lowercase())
+            }
+        }
+    )*};
+}
+
+lowercase_display!(AudioCodec, Container);
+
+/// How songs are downloaded and named. Unset options are left to youtube-dl.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadPolicy {
+    /// What to convert the audio to when only downloading audio.
+    pub audio_codec: Option<AudioCodec>,
+    /// When only downloading audio, a VBR quality from 0 (best) to 9 (worst), or a bitrate like
+    /// `128K`.
+    pub audio_quality: Option<String>,
+    /// What to put the video in when downloading video.
+    pub video_container: Option<Container>,
+    pub name: OutputTemplate,
+    /// The youtube-dl format to pick, as in mpv's `ytdl-format`. Only set for the songs
+    /// downloaded ahead for players, so they get the quality the player streams.
+    #[serde(skip)]
+    pub format: Option<String>,
+}
+
+impl DownloadPolicy {
+    pub(crate) fn ytdl_args(&self, just_audio: bool) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(format) = &self.format {
+            args.extend(["-f".into(), format.clone()]);
+        }
+        if just_audio {
+            args.push("-x".into());
+            if let Some(codec) = self.audio_codec {
+                args.extend(["--audio-format".into(), codec.to_string()]);
+            }
+            if let Some(quality) = &self.audio_quality {
+                args.extend(["--audio-quality".into(), quality.clone()]);
+            }
+        } else if let Some(container) = self.video_container {
+            args.extend(["--merge-output-format".into(), container.to_string()]);
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::item::id_from_path;
+
+    #[test]
+    fn file_names_round_trip_through_the_id() {
+        let name = OutputTemplate::file_name("Artist - A=B", "dQw4w9WgXcQ", "opus");
+        assert_eq!(
+            id_from_path(&name).map(|id| id.as_str()),
+            Some("dQw4w9WgXcQ")
+        );
+        assert_eq!(
+            OutputTemplate::new("%(artist)s - %(title)s".into())
+                .unwrap()
+                .ytdl(),
+            "%(artist)s - %(title)s=%(id)s=m.%(ext)s"
+        );
+        assert!(glob::Pattern::new(&OutputTemplate::glob("dQw4w9WgXcQ"))
+            .unwrap()
+            .matches(&name));
+        assert!(OutputTemplate::new("%(uploader)s/%(title)s".into()).is_err());
+    }
+
+    #[test]
+    fn ytdl_args() {
+        let policy = DownloadPolicy {
+            audio_codec: Some(AudioCodec::Opus),
+            audio_quality: Some("160K".into()),
+            video_container: Some(Container::Mkv),
+            name: Default::default(),
+            format: None,
+        };
+        assert_eq!(
+            policy.ytdl_args(true),
+            ["-x", "--audio-format", "opus", "--audio-quality", "160K"]
+        );
+        assert_eq!(policy.ytdl_args(false), ["--merge-output-format", "mkv"]);
+        assert!(DownloadPolicy::default().ytdl_args(false).is_empty());
+        let policy = DownloadPolicy {
+            format: Some("bestaudio/best".into()),
+            ..Default::default()
+        };
+        assert_eq!(policy.ytdl_args(false), ["-f", "bestaudio/best"]);
+    }
+}
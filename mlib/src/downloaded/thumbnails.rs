@@ -0,0 +1,79 @@
+//! Thumbnails of songs, kept in a hidden directory of the download dir so that they are only
+//! fetched once.
+
+use std::{
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use tokio::fs;
+
+use super::is_partial;
+use crate::{
+    item::link::VideoLink,
+    ytdl::{self, YtdlError},
+    Error, VideoId,
+};
+
+const DIR: &str = ".thumbnails";
+
+/// Where the thumbnails are kept, dot prefixed so that it isn't mistaken for a song.
+pub fn dir(dl_dir: &Path) -> PathBuf {
+    dl_dir.join(DIR)
+}
+
+/// The cached thumbnail of `id`, whatever image format it was downloaded in.
+pub async fn get(dl_dir: &Path, id: &VideoId) -> Result<Option<PathBuf>, Error> {
+    let mut entries = match fs::read_dir(dir(dl_dir)).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.file_stem() == Some(OsStr::new(id.as_str())) && !is_partial(&path) {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+/// The thumbnail of `id`, downloading it first if it isn't cached yet.
+pub async fn get_or_fetch(dl_dir: &Path, id: &VideoId) -> Result<PathBuf, Error> {
+    if let Some(path) = get(dl_dir, id).await? {
+        return Ok(path);
+    }
+    let dir = dir(dl_dir);
+    fs::create_dir_all(&dir).await?;
+    let output = ytdl::command()
+        .await
+        .args([
+            "--skip-download",
+            "--write-thumbnail",
+            "--no-warnings",
+            "-o",
+        ])
+        .arg(dir.join(format!("{}.%(ext)s", id.as_str())))
+        .arg(VideoLink::from_id(id).as_str())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(YtdlError::failed(
+            output.status,
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+        .into());
+    }
+    get(dl_dir, id).await?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("youtube-dl didn't write a thumbnail for {}", id.as_str()),
+        )
+        .into()
+    })
+}
@@ -0,0 +1,160 @@
+//! At rest encryption of the files that keep track of what was listened to.
+//!
+//! Encryption is enabled by the existence of a key file. Encrypted files start with [`MAGIC`]
+//! followed by a random nonce and the XChaCha20-Poly1305 ciphertext, anything else is read as
+//! plain text, so files written before the key was created can still be read.
+
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+
+const MAGIC: &[u8] = b"m-encrypted-v1\n";
+const NONCE_LEN: usize = 24;
+
+#[derive(Clone)]
+pub struct Key(chacha20poly1305::Key);
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+/// Where the key is kept: `$XDG_CONFIG_HOME/m/statistics.key`.
+pub fn key_path() -> io::Result<PathBuf> {
+    let Some(mut path) = dirs::config_dir() else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "failed to get config dir",
+        ));
+    };
+    path.push("m");
+    path.push("statistics.key");
+    Ok(path)
+}
+
+impl Key {
+    /// Loads the key, if encryption is enabled.
+    pub fn load() -> io::Result<Option<Self>> {
+        let bytes = match fs::read(key_path()?) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if bytes.len() != 32 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the statistics key should be 32 bytes long",
+            ));
+        }
+        Ok(Some(Self(*chacha20poly1305::Key::from_slice(&bytes))))
+    }
+
+    /// Creates a new key file, readable only by the current user. Fails if there already is one.
+    pub fn generate() -> io::Result<Self> {
+        let path = key_path()?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+        options.open(&path)?.write_all(&key)?;
+        Ok(Self(key))
+    }
+
+    /// Deletes the key file, after which nothing encrypted with it can be read.
+    pub fn forget() -> io::Result<()> {
+        match fs::remove_file(key_path()?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Decrypts the contents of a file with the key in [`key_path`], if they were encrypted.
+pub(crate) fn decode(data: Vec<u8>) -> io::Result<Vec<u8>> {
+    open(Key::load()?.as_ref(), data)
+}
+
+/// Encrypts the contents of a file with the key in [`key_path`], if there is one.
+pub(crate) fn encode(plain: Vec<u8>) -> io::Result<Vec<u8>> {
+    seal(Key::load()?.as_ref(), plain)
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encrypts `plain` with `key`, or leaves it as is if there is no key.
+pub fn seal(key: Option<&Key>, plain: Vec<u8>) -> io::Result<Vec<u8>> {
+    let Some(Key(key)) = key else {
+        return Ok(plain);
+    };
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = XChaCha20Poly1305::new(key)
+        .encrypt(&nonce, plain.as_slice())
+        .map_err(|_| io::Error::other("failed to encrypt"))?;
+    let mut data = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&ciphertext);
+    Ok(data)
+}
+
+/// Decrypts `data` if it was encrypted, fails if it was but there is no key or it's the wrong
+/// one.
+pub fn open(key: Option<&Key>, data: Vec<u8>) -> io::Result<Vec<u8>> {
+    let Some(rest) = data.strip_prefix(MAGIC) else {
+        return Ok(data);
+    };
+    let Some(Key(key)) = key else {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "file is encrypted but there is no key",
+        ));
+    };
+    if rest.len() < NONCE_LEN {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    XChaCha20Poly1305::new(key)
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "failed to decrypt, wrong key or corrupted file",
+            )
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let key = Key(XChaCha20Poly1305::generate_key(&mut OsRng));
+        let plain = br#"{"a":1}"#.to_vec();
+
+        let sealed = seal(Some(&key), plain.clone()).unwrap();
+        assert!(is_encrypted(&sealed));
+        assert_eq!(open(Some(&key), sealed.clone()).unwrap(), plain);
+
+        let other = Key(XChaCha20Poly1305::generate_key(&mut OsRng));
+        assert!(open(Some(&other), sealed.clone()).is_err());
+        assert!(open(None, sealed).is_err());
+
+        // plain text files are still readable, with or without a key
+        assert_eq!(open(Some(&key), plain.clone()).unwrap(), plain);
+        assert_eq!(seal(None, plain.clone()).unwrap(), plain);
+    }
+}
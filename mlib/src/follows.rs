@@ -0,0 +1,192 @@
+//! Playlists and channels that are followed, so that the videos uploaded to them can be added
+//! to the playlist as they come out.
+
+use std::{collections::BTreeSet, io, path::PathBuf, time::SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    item::link::{PlaylistLink, VideoLink},
+    playlist::Category,
+    ytdl::expand,
+    Error, Link, VideoId,
+};
+
+/// A followed playlist or channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Follow {
+    pub link: Link,
+    /// The categories its videos are added to the playlist with.
+    pub categories: Vec<Category>,
+    /// Every video it had when it was synced, the ones missing from here are new.
+    #[serde(default)]
+    seen: BTreeSet<String>,
+}
+
+impl Follow {
+    /// The ones of `ids` that weren't seen before.
+    fn unseen(&self, ids: Vec<Box<VideoId>>) -> Vec<VideoLink> {
+        ids.into_iter()
+            .filter(|id| !self.seen.contains(id.as_str()))
+            .map(|id| VideoLink::from_id(&id))
+            .collect()
+    }
+
+    fn see(&mut self, id: &VideoId) {
+        self.seen.insert(id.as_str().to_owned());
+    }
+}
+
+/// The videos of a followed playlist or channel that are new since the last sync.
+#[derive(Debug, Clone)]
+pub struct Uploads {
+    pub from: Link,
+    pub categories: Vec<Category>,
+    pub videos: Vec<VideoLink>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Follows {
+    pub follows: Vec<Follow>,
+    /// When the followed playlists and channels were last synced.
+    #[serde(default)]
+    pub last_sync: Option<SystemTime>,
+}
+
+fn follows_path() -> io::Result<PathBuf> {
+    let Some(mut path) = dirs::data_dir() else {
+        tracing::error!("failed to get data dir for the followed playlists");
+        return Err(io::ErrorKind::NotFound.into());
+    };
+    path.push("m");
+    path.push("follows.json");
+    Ok(path)
+}
+
+/// The same playlist can be linked from any of its videos, so playlists are stored by their id
+/// alone to be recognized when they are followed again.
+fn normalize(link: Link) -> io::Result<Link> {
+    match link {
+        Link::Playlist(l) => Ok(PlaylistLink::from_id(l.id()).into()),
+        Link::Channel(c) => Ok(c.into()),
+        l => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{l} is not a playlist or a channel"),
+        )),
+    }
+}
+
+impl Follows {
+    /// Loads the followed playlists and channels, none if nothing was ever followed.
+    pub async fn load() -> io::Result<Self> {
+        match tokio::fs::read(follows_path()?).await {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn save(&self) -> io::Result<()> {
+        let bytes = serde_json::to_vec_pretty(self)?;
+        crate::update_file(follows_path()?, move |_| Ok(bytes)).await
+    }
+
+    /// Starts following a playlist or channel. The videos it already has are not considered
+    /// new. Following it again only changes its categories.
+    pub async fn follow(&mut self, link: Link, categories: Vec<Category>) -> Result<(), Error> {
+        let link = normalize(link)?;
+        if let Some(follow) = self.follows.iter_mut().find(|f| f.link == link) {
+            follow.categories = categories;
+            return Ok(());
+        }
+        let mut follow = Follow {
+            seen: BTreeSet::new(),
+            link,
+            categories,
+        };
+        for id in expand::videos(&follow.link).await? {
+            follow.see(&id);
+        }
+        self.follows.push(follow);
+        Ok(())
+    }
+
+    /// Stops following a playlist or channel, returning whether it was followed.
+    pub fn unfollow(&mut self, link: Link) -> io::Result<bool> {
+        let link = normalize(link)?;
+        let before = self.follows.len();
+        self.follows.retain(|f| f.link != link);
+        Ok(self.follows.len() != before)
+    }
+
+    /// Asks yt-dlp for the videos of every followed playlist and channel, returning the ones
+    /// that are new. They stay new until they are marked as [seen](Self::see).
+    pub async fn sync(&mut self) -> Vec<Uploads> {
+        let mut uploads = Vec::new();
+        for follow in &mut self.follows {
+            let ids = match expand::videos(&follow.link).await {
+                Ok(ids) => ids,
+                Err(e) => {
+                    tracing::error!(error = ?e, link = %follow.link, "failed to sync follow");
+                    continue;
+                }
+            };
+            let videos = follow.unseen(ids);
+            if !videos.is_empty() {
+                uploads.push(Uploads {
+                    from: follow.link.clone(),
+                    categories: follow.categories.clone(),
+                    videos,
+                });
+            }
+        }
+        self.last_sync = Some(SystemTime::now());
+        uploads
+    }
+
+    /// Remembers that `video`, uploaded to the followed `from`, was taken care of, so that it
+    /// isn't new anymore. It is only remembered once these follows are saved.
+    pub fn see(&mut self, from: &Link, video: &VideoLink) {
+        if let Some(follow) = self.follows.iter_mut().find(|f| &f.link == from) {
+            follow.see(video.id());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::item::link::Id;
+
+    #[test]
+    fn only_unseen_videos_are_new() {
+        let link = Link::try_from(
+            "https://www.youtube.com/watch?v=UpIBKNxSeZU&list=PL17PSucW5L7nEPyX3tqEzq_wmyYk2IkXr"
+                .to_owned(),
+        )
+        .unwrap();
+        let mut follow = Follow {
+            link: normalize(link).unwrap(),
+            categories: vec![],
+            seen: BTreeSet::new(),
+        };
+        let ids = |ids: &[&str]| ids.iter().map(|id| VideoId::new(id).boxed()).collect();
+        assert_eq!(follow.unseen(ids(&["UpIBKNxSeZU", "dQw4w9WgXcQ"])).len(), 2);
+        assert_eq!(
+            follow.unseen(ids(&["UpIBKNxSeZU", "dQw4w9WgXcQ"])).len(),
+            2,
+            "unseen marked them as seen"
+        );
+        follow.see(VideoId::new("UpIBKNxSeZU"));
+        follow.see(VideoId::new("dQw4w9WgXcQ"));
+        assert_eq!(
+            follow.unseen(ids(&["UpIBKNxSeZU", "dQw4w9WgXcQ", "9bZkp7q1_ew"])),
+            [VideoLink::from_id(VideoId::new("9bZkp7q1_ew"))]
+        );
+        assert!(follow.unseen(ids(&["dQw4w9WgXcQ"])).is_empty());
+        assert_eq!(
+            follow.link.as_str(),
+            "https://youtube.com/playlist?list=PL17PSucW5L7nEPyX3tqEzq_wmyYk2IkXr"
+        );
+    }
+}
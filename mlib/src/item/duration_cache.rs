@@ -0,0 +1,27 @@
+use std::{io, path::PathBuf, time::Duration};
+
+use super::VideoId;
+
+async fn cache_path_for(id: &VideoId) -> PathBuf {
+    let (path, _error) =
+        namespaced_tmp::async_impl::in_user_tmp(&format!("m_duration_cache/{}", id.as_str())).await;
+    path
+}
+
+pub async fn get(id: &VideoId) -> io::Result<Option<Duration>> {
+    match tokio::fs::read_to_string(cache_path_for(id).await).await {
+        Ok(secs) => secs
+            .trim()
+            .parse()
+            .map(|secs| Some(Duration::from_secs(secs)))
+            .map_err(io::Error::other),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn put(id: &VideoId, duration: Duration) -> io::Result<()> {
+    let path = cache_path_for(id).await;
+    tokio::fs::create_dir_all(&path.parent().unwrap()).await?;
+    tokio::fs::write(path, duration.as_secs().to_string()).await
+}
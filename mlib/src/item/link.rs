@@ -1,4 +1,6 @@
 use derive_more::derive::From;
+use once_cell::sync::Lazy;
+use regex::Regex;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 use std::{borrow::Cow, ops::Deref};
@@ -271,6 +273,40 @@ impl VideoLink {
             }
         }
     }
+
+    /// Find out how long the video is, from the playlist, the duration cache or, as a last
+    /// resort, by querying youtube.
+    #[cfg(all(feature = "ytdl", feature = "playlist"))]
+    #[tracing::instrument(fields(self = self.as_str()))]
+    pub async fn resolve_duration(&self) -> Option<std::time::Duration> {
+        use crate::{item::duration_cache, playlist, ytdl::YtdlBuilder};
+
+        match playlist::find_song(self.id()).await {
+            Ok(Some(song)) if song.time > 0 => {
+                return Some(std::time::Duration::from_secs(song.time))
+            }
+            Ok(_) => {}
+            Err(e) => tracing::debug!("failed to find link in playlist: {e:?}"),
+        }
+        match duration_cache::get(self.id()).await {
+            Ok(Some(duration)) => return Some(duration),
+            Ok(None) => {}
+            Err(e) => tracing::warn!(error = ?e, "failed to fetch from duration cache"),
+        }
+        match YtdlBuilder::new(self).get_duration().request().await {
+            Ok(r) => {
+                let duration = r.duration();
+                if let Err(e) = duration_cache::put(self.id(), duration).await {
+                    tracing::warn!(error = ?e, "failed to cache duration");
+                }
+                Some(duration)
+            }
+            Err(e) => {
+                tracing::warn!("failed to get duration using yt dl: {e:?}");
+                None
+            }
+        }
+    }
 }
 
 impl AsRef<str> for VideoLink {
@@ -310,6 +346,21 @@ impl VideoId {
             .then(|| url.path().trim_start_matches('/'))
             .map(VideoId::new)
     }
+
+    /// Finds the id in any youtube url (`watch?v=`, `youtu.be/` or `shorts/`), or takes `s` to be
+    /// an id if it looks like one, which is how mpv reports the filename of youtube videos.
+    pub fn from_any_url(s: &str) -> Option<&Self> {
+        static IN_URL: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"(?:[?&]v=|youtu\.be/|/shorts/)(?P<id>[A-Za-z0-9_\-]{11})").unwrap()
+        });
+        Self::bare(s).or_else(|| Some(Self::new(IN_URL.captures(s)?.name("id")?.as_str())))
+    }
+
+    /// `s`, if it's nothing but an id.
+    pub fn bare(s: &str) -> Option<&Self> {
+        static BARE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z0-9_\-]{11}$").unwrap());
+        BARE.is_match(s).then(|| Self::new(s))
+    }
 }
 
 impl Deref for VideoId {
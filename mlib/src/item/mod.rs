@@ -1,6 +1,8 @@
+#[cfg(all(feature = "ytdl", feature = "playlist"))]
+pub mod duration_cache;
 pub mod link;
 #[cfg(all(feature = "ytdl", feature = "playlist"))]
-mod title_cache;
+pub mod title_cache;
 
 use std::{
     ffi::OsStr,
@@ -8,7 +10,7 @@ use std::{
     ops::Range,
     os::unix::{ffi::OsStrExt, prelude::OsStringExt},
     path::{Path, PathBuf},
-    str::Utf8Error,
+    str::{FromStr, Utf8Error},
     string::FromUtf8Error,
 };
 
@@ -29,6 +31,15 @@ pub enum Item {
 }
 
 impl Item {
+    /// Turns a filename reported by mpv back into an item. mpv reports youtube videos by their id
+    /// alone, which would otherwise be mistaken for a file.
+    pub fn from_mpv_filename(filename: String) -> Self {
+        match VideoId::bare(&filename) {
+            Some(id) => Item::Link(Link::from_video_id(id)),
+            None => Item::from(filename),
+        }
+    }
+
     pub fn id(&self) -> Option<&VideoId> {
         match self {
             Item::Link(l) => l.video_id(),
@@ -45,6 +56,12 @@ impl Item {
         }
     }
 
+    /// The duration of the item, if it's a video or the download of one.
+    #[cfg(all(feature = "ytdl", feature = "playlist"))]
+    pub async fn fetch_item_duration(&self) -> Option<std::time::Duration> {
+        VideoLink::from_id(self.id()?).resolve_duration().await
+    }
+
     #[cfg(all(feature = "ytdl", feature = "playlist"))]
     pub async fn fetch_item_title(&self) -> String {
         use crate::ytdl::YtdlBuilder;
@@ -53,9 +70,16 @@ impl Item {
                 Some(l) => l.resolve_link().await,
                 None => l.to_string(),
             },
-            Item::File(f) => clean_up_path(&f)
-                .map(ToString::to_string)
-                .unwrap_or_else(|| f.to_string_lossy().into_owned()),
+            Item::File(f) => {
+                if let Some(title) = title_cache::get_by_path(f) {
+                    return title;
+                }
+                let title = clean_up_path(&f)
+                    .map(ToString::to_string)
+                    .unwrap_or_else(|| f.to_string_lossy().into_owned());
+                title_cache::put_by_path(f, &title);
+                title
+            }
             Item::Search(s) => {
                 tracing::debug!("fetching title of search {s:?}");
                 match title_cache::get_by_search(s).await {
@@ -85,6 +109,63 @@ impl Item {
     }
 }
 
+/// The titles of `items`, in the same order, fetching a few at a time and each repeated item
+/// only once.
+#[cfg(all(feature = "ytdl", feature = "playlist"))]
+pub async fn resolve_titles(items: &[Item]) -> Vec<String> {
+    use futures_util::StreamExt;
+
+    stream_titles(items).collect().await
+}
+
+/// Like [`resolve_titles`], but each title comes out as soon as it and the ones before it are
+/// known.
+#[cfg(all(feature = "ytdl", feature = "playlist"))]
+pub fn stream_titles(items: &[Item]) -> impl futures_util::Stream<Item = String> + '_ {
+    titles_with(items, Item::fetch_item_title)
+}
+
+#[cfg(all(feature = "ytdl", feature = "playlist"))]
+fn titles_with<'i, F, Fut>(
+    items: &'i [Item],
+    fetch: F,
+) -> impl futures_util::Stream<Item = String> + 'i
+where
+    F: FnMut(&'i Item) -> Fut + 'i,
+    Fut: std::future::Future<Output = String> + 'i,
+{
+    use futures_util::{stream, StreamExt};
+    use std::collections::HashMap;
+
+    const CONCURRENT: usize = 8;
+
+    let mut unique = Vec::new();
+    let mut seen = HashMap::new();
+    let positions = items
+        .iter()
+        .map(|item| {
+            *seen.entry(item).or_insert_with(|| {
+                unique.push(item);
+                unique.len() - 1
+            })
+        })
+        .collect::<Vec<_>>();
+    let fetched = stream::iter(unique).map(fetch).buffered(CONCURRENT);
+    // an item is first seen after every item seen before it, so the titles are fetched in the
+    // order they are needed
+    stream::unfold(
+        (positions.into_iter(), Box::pin(fetched), Vec::new()),
+        |(mut positions, mut fetched, mut titles)| async move {
+            let i = positions.next()?;
+            while titles.len() <= i {
+                titles.push(fetched.next().await?);
+            }
+            let title = titles[i].clone();
+            Some((title, (positions, fetched, titles)))
+        },
+    )
+}
+
 impl<'s> TryFrom<&'s Item> for &'s str {
     type Error = Utf8Error;
 
@@ -136,7 +217,7 @@ impl Display for Item {
 
 impl From<String> for Item {
     fn from(s: String) -> Self {
-        if s.starts_with(Search::PREFIX) {
+        if Search::is_search(&s) {
             Item::Search(Search(s))
         } else {
             match Link::try_from(s) {
@@ -147,9 +228,22 @@ impl From<String> for Item {
     }
 }
 
+/// Downloaded songs are named `<name>=<id>=m.<ext>`, the id is what ties a file to its song. The
+/// names given to downloads (see `downloaded::policy::OutputTemplate`) and reading the id back
+/// from a file name share these.
+pub(crate) const FILE_ID_SEPARATOR: char = '=';
+pub(crate) const FILE_MARKER: &str = "m";
+/// Files named by older versions.
+const LEGACY_FILE_MARKER: &str = "mart";
+
 pub(crate) fn id_range(s: &str) -> Option<Range<usize>> {
-    let front_striped = s.strip_suffix("=m").or_else(|| s.strip_suffix("=mart"))?;
-    let start_idx = front_striped.char_indices().rfind(|(_, c)| *c == '=')?.0;
+    let front_striped = [FILE_MARKER, LEGACY_FILE_MARKER]
+        .into_iter()
+        .find_map(|marker| s.strip_suffix(marker)?.strip_suffix(FILE_ID_SEPARATOR))?;
+    let start_idx = front_striped
+        .char_indices()
+        .rfind(|(_, c)| *c == FILE_ID_SEPARATOR)?
+        .0;
     if front_striped.len() == start_idx {
         return None;
     }
@@ -157,27 +251,119 @@ pub(crate) fn id_range(s: &str) -> Option<Range<usize>> {
 }
 
 pub(crate) fn id_from_path<P: AsRef<Path>>(p: &P) -> Option<&VideoId> {
-    // format: [name]=[id]=m.ext
     let name = p.as_ref().file_stem()?.to_str()?;
     let range = id_range(name)?;
     Some(VideoId::new(&name[range]))
 }
 
+/// Where a [`Search`] looks for songs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum SearchProvider {
+    #[default]
+    YouTube,
+    SoundCloud,
+    /// The downloaded songs, which yt-dlp can't search.
+    Local,
+}
+
+impl SearchProvider {
+    const ALL: [Self; 3] = [Self::YouTube, Self::SoundCloud, Self::Local];
+
+    /// yt-dlp's prefix for searches in this provider.
+    pub fn ytdl_prefix(self) -> Option<&'static str> {
+        match self {
+            Self::YouTube => Some("ytsearch"),
+            Self::SoundCloud => Some("scsearch"),
+            Self::Local => None,
+        }
+    }
+}
+
+impl Display for SearchProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::YouTube => "youtube",
+            Self::SoundCloud => "soundcloud",
+            Self::Local => "local",
+        })
+    }
+}
+
+impl FromStr for SearchProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "yt" => Ok(Self::YouTube),
+            "sc" => Ok(Self::SoundCloud),
+            _ => Self::ALL
+                .into_iter()
+                .find(|p| p.to_string() == s)
+                .ok_or_else(|| format!("expected youtube, soundcloud or local but got {s}")),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 #[repr(transparent)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Search(String);
 
 impl Search {
-    const PREFIX: &'static str = "ytdl://ytsearch";
-    pub fn new(mut s: String) -> Self {
-        s.insert(0, ':');
-        s.insert_str(0, Self::PREFIX);
-        Self(s)
+    const SCHEME: &'static str = "ytdl://";
+
+    /// A youtube search for the first result of `s`.
+    pub fn new(s: String) -> Self {
+        Self::on(SearchProvider::YouTube, s).expect("youtube can be searched by yt-dlp")
     }
 
+    /// A youtube search for the first `limit` results of `s`.
     pub fn multiple(s: String, limit: usize) -> Self {
-        Self(format!("{}{}:{}", Self::PREFIX, limit, s))
+        Self::multiple_on(SearchProvider::YouTube, s, limit)
+            .expect("youtube can be searched by yt-dlp")
+    }
+
+    /// A search for `s` in `provider`, or `None` if yt-dlp can't search it.
+    pub fn on(provider: SearchProvider, s: String) -> Option<Self> {
+        Some(Self(format!(
+            "{}{}:{s}",
+            Self::SCHEME,
+            provider.ytdl_prefix()?
+        )))
+    }
+
+    pub fn multiple_on(provider: SearchProvider, s: String, limit: usize) -> Option<Self> {
+        Some(Self(format!(
+            "{}{}{limit}:{s}",
+            Self::SCHEME,
+            provider.ytdl_prefix()?
+        )))
+    }
+
+    /// Whether `s` is a search made by [`Search::on`] or [`Search::multiple_on`].
+    fn is_search(s: &str) -> bool {
+        let Some(rest) = s.strip_prefix(Self::SCHEME) else {
+            return false;
+        };
+        SearchProvider::ALL
+            .into_iter()
+            .filter_map(SearchProvider::ytdl_prefix)
+            .filter_map(|prefix| rest.strip_prefix(prefix))
+            .filter_map(|rest| rest.split_once(':'))
+            .any(|(limit, _)| limit.bytes().all(|b| b.is_ascii_digit()))
+    }
+
+    pub fn provider(&self) -> SearchProvider {
+        let rest = self.0.trim_start_matches(Self::SCHEME);
+        SearchProvider::ALL
+            .into_iter()
+            .find(|p| {
+                p.ytdl_prefix()
+                    .is_some_and(|prefix| rest.starts_with(prefix))
+            })
+            .unwrap_or_default()
     }
 
     pub fn as_str(&self) -> &str {
@@ -204,6 +390,52 @@ mod test {
     use super::*;
     use std::path::PathBuf;
 
+    #[cfg(all(feature = "ytdl", feature = "playlist"))]
+    #[tokio::test]
+    async fn titles_are_in_order_and_fetched_once() {
+        use futures_util::StreamExt;
+        use std::sync::Mutex;
+
+        let items = ["a", "b", "a", "c", "b"].map(|f| Item::File(PathBuf::from(f)));
+        let fetched = Mutex::new(Vec::new());
+        let titles = titles_with(&items, |item| {
+            fetched.lock().unwrap().push(item.clone());
+            let title = item.to_string().to_uppercase();
+            async move { title }
+        })
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(titles, ["A", "B", "A", "C", "B"]);
+        let [a, b, _, c, _] = items;
+        assert_eq!(fetched.into_inner().unwrap(), [a, b, c]);
+    }
+
+    #[test]
+    fn mpv_filenames() {
+        let rick = VideoId::new("dQw4w9WgXcQ");
+        for filename in [
+            "dQw4w9WgXcQ",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://youtu.be/dQw4w9WgXcQ",
+            "https://www.youtube.com/watch?list=RDdQw4w9WgXcQ&v=dQw4w9WgXcQ",
+        ] {
+            let item = Item::from_mpv_filename(filename.into());
+            assert!(matches!(item, Item::Link(_)), "{filename}: {item:?}");
+            assert_eq!(item.id(), Some(rick), "{filename}");
+        }
+        assert_eq!(
+            VideoId::from_any_url("https://www.youtube.com/shorts/dQw4w9WgXcQ"),
+            Some(rick)
+        );
+        let downloaded = Item::from_mpv_filename("/music/Rick Astley=dQw4w9WgXcQ=m.mp3".into());
+        assert!(matches!(downloaded, Item::File(_)));
+        assert_eq!(downloaded.id(), Some(rick));
+        assert_eq!(
+            Item::from_mpv_filename("/music/song.mp3".into()),
+            Item::File("/music/song.mp3".into())
+        );
+    }
+
     #[test]
     fn trivial() {
         assert_eq!(
@@ -235,4 +467,21 @@ mod test {
             id_from_path(&Path::new("Song Name 😎=AAA=mart.jpg"))
         )
     }
+
+    #[test]
+    fn searches_round_trip_through_strings() {
+        let search = Search::multiple_on(SearchProvider::SoundCloud, "lofi".into(), 5).unwrap();
+        assert_eq!(search.as_str(), "ytdl://scsearch5:lofi");
+        assert_eq!(search.provider(), SearchProvider::SoundCloud);
+        assert_eq!(Item::from(search.as_str().to_owned()), Item::Search(search));
+        assert_eq!(
+            Search::new("lofi".into()).provider(),
+            SearchProvider::YouTube
+        );
+        assert!(Search::on(SearchProvider::Local, "lofi".into()).is_none());
+        assert!(matches!(
+            Item::from("ytdl://ytsearchx:lofi".to_owned()),
+            Item::File(_)
+        ));
+    }
 }
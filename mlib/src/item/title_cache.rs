@@ -1,12 +1,83 @@
+//! Titles of items, remembered for a while by the process that resolved them and kept on disk
+//! for videos and searches, so that they aren't asked of youtube again.
+
 use std::{
+    collections::HashMap,
     io,
     path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant, SystemTime},
 };
 
 use base64::{engine::GeneralPurpose, Engine};
 
 use super::{Search, VideoId};
 
+/// What a title is remembered by.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Video(String),
+    Search(String),
+    File(PathBuf),
+}
+
+/// How long a title is remembered by a process before it's looked up again.
+const REMEMBER_FOR: Duration = Duration::from_secs(60 * 10);
+
+/// The titles this process resolved recently.
+#[derive(Debug, Default)]
+struct Memory {
+    titles: HashMap<Key, (Instant, String)>,
+    /// When the forgotten titles were last dropped, which is done at most once per
+    /// [`REMEMBER_FOR`].
+    pruned_at: Option<Instant>,
+}
+
+impl Memory {
+    fn recall(&mut self, key: &Key, now: Instant) -> Option<String> {
+        match self.titles.get(key) {
+            Some((at, title)) if now.saturating_duration_since(*at) < REMEMBER_FOR => {
+                Some(title.clone())
+            }
+            Some(_) => {
+                self.titles.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn remember(&mut self, key: Key, title: &str, now: Instant) {
+        let forgotten = |at: Instant| now.saturating_duration_since(at) >= REMEMBER_FOR;
+        if self.pruned_at.map_or(true, forgotten) {
+            self.titles.retain(|_, (at, _)| !forgotten(*at));
+            self.pruned_at = Some(now);
+        }
+        self.titles.insert(key, (now, title.to_owned()));
+    }
+}
+
+fn memory() -> &'static Mutex<Memory> {
+    static MEMORY: OnceLock<Mutex<Memory>> = OnceLock::new();
+    MEMORY.get_or_init(Default::default)
+}
+
+fn recall(key: &Key) -> Option<String> {
+    memory().lock().unwrap().recall(key, Instant::now())
+}
+
+fn remember(key: Key, title: &str) {
+    memory()
+        .lock()
+        .unwrap()
+        .remember(key, title, Instant::now())
+}
+
+async fn cache_dir() -> PathBuf {
+    let (path, _error) = namespaced_tmp::async_impl::in_user_tmp("m_title_cache").await;
+    path
+}
+
 async fn cache_path_for<S: AsRef<str> + ?Sized>(url: &S) -> PathBuf {
     let (path, _error) =
         namespaced_tmp::async_impl::in_user_tmp(&format!("m_title_cache/{}", url.as_ref())).await;
@@ -14,11 +85,16 @@ async fn cache_path_for<S: AsRef<str> + ?Sized>(url: &S) -> PathBuf {
 }
 
 pub async fn get_by_vid_id(id: &VideoId) -> io::Result<Option<String>> {
+    let key = Key::Video(id.as_str().to_owned());
+    if let Some(title) = recall(&key) {
+        return Ok(Some(title));
+    }
     let path = cache_path_for(id).await;
-    get_inner(&path).await
+    get_inner(key, &path).await
 }
 
 pub async fn put_by_vid_id(id: &VideoId, title: &str) -> io::Result<()> {
+    remember(Key::Video(id.as_str().to_owned()), title);
     let path = cache_path_for(id).await;
     put_inner(&path, title).await
 }
@@ -26,20 +102,42 @@ pub async fn put_by_vid_id(id: &VideoId, title: &str) -> io::Result<()> {
 const BASE64: GeneralPurpose = base64::engine::general_purpose::URL_SAFE;
 
 pub async fn get_by_search(id: &Search) -> io::Result<Option<String>> {
+    let key = Key::Search(id.as_str().to_owned());
+    if let Some(title) = recall(&key) {
+        return Ok(Some(title));
+    }
     let id = BASE64.encode(id.as_str());
     let path = cache_path_for(&id).await;
-    get_inner(&path).await
+    get_inner(key, &path).await
 }
 
 pub async fn put_by_search(id: &Search, title: &str) -> io::Result<()> {
+    remember(Key::Search(id.as_str().to_owned()), title);
     let id = BASE64.encode(id.as_str());
     let path = cache_path_for(&id).await;
     put_inner(&path, title).await
 }
 
-async fn get_inner(path: &Path) -> io::Result<Option<String>> {
+/// The title of a file, which is only remembered by this process since it's quick to work out
+/// again.
+pub fn get_by_path(path: &Path) -> Option<String> {
+    recall(&Key::File(path.to_owned()))
+}
+
+pub fn put_by_path(path: &Path, title: &str) {
+    remember(Key::File(path.to_owned()), title)
+}
+
+async fn get_inner(key: Key, path: &Path) -> io::Result<Option<String>> {
     match tokio::fs::read(path).await {
-        Ok(title) => String::from_utf8(title).map(Some).map_err(io::Error::other),
+        Ok(title) => {
+            if let Err(e) = touch(path).await {
+                tracing::debug!(error = ?e, ?path, "failed to mark title cache entry as used");
+            }
+            let title = String::from_utf8(title).map_err(io::Error::other)?;
+            remember(key, &title);
+            Ok(Some(title))
+        }
         Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
         Err(e) => Err(e),
     }
@@ -49,3 +147,77 @@ async fn put_inner(path: &Path, title: &str) -> io::Result<()> {
     tokio::fs::create_dir_all(&path.parent().unwrap()).await?;
     tokio::fs::write(path, title).await
 }
+
+/// Marks an entry as recently used.
+async fn touch(path: &Path) -> io::Result<()> {
+    let file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .open(path)
+        .await?
+        .into_std()
+        .await;
+    tokio::task::spawn_blocking(move || file.set_modified(SystemTime::now()))
+        .await
+        .unwrap()
+}
+
+/// How long an entry can go unused before it can be pruned.
+pub const PRUNE_AFTER: Duration = Duration::from_secs(60 * 60 * 24 * 30);
+
+/// Removes the entries that `keep` rejects and that haven't been used for `unused_for`.
+///
+/// `keep` is given the video id for entries of videos, search entries are only kept if they were
+/// used recently. Returns how many entries were removed.
+pub async fn prune(keep: impl Fn(&str) -> bool, unused_for: Duration) -> io::Result<usize> {
+    let mut dir = match tokio::fs::read_dir(cache_dir().await).await {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut removed = 0;
+    while let Some(entry) = dir.next_entry().await? {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if keep(&name) {
+            continue;
+        }
+        let metadata = entry.metadata().await?;
+        let last_used = metadata.modified()?.elapsed().unwrap_or_default();
+        if metadata.is_file() && last_used > unused_for {
+            tokio::fs::remove_file(entry.path()).await?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn titles_are_forgotten_after_a_while() {
+        let start = Instant::now();
+        let key = |s: &str| Key::Video(s.into());
+        let mut memory = Memory::default();
+        memory.remember(key("a"), "A", start);
+        assert_eq!(memory.recall(&key("a"), start).as_deref(), Some("A"));
+        let later = start + REMEMBER_FOR - Duration::from_secs(1);
+        assert_eq!(memory.recall(&key("a"), later).as_deref(), Some("A"));
+        memory.remember(key("b"), "B", later);
+        assert_eq!(
+            memory.titles.len(),
+            2,
+            "pruned before a whole while went by"
+        );
+        assert_eq!(memory.recall(&key("a"), start + REMEMBER_FOR), None);
+        assert_eq!(memory.titles.len(), 1);
+        memory.remember(key("c"), "C", later + REMEMBER_FOR);
+        assert_eq!(
+            memory.titles.keys().collect::<Vec<_>>(),
+            [&key("c")],
+            "b wasn't pruned"
+        );
+    }
+}
@@ -3,13 +3,26 @@
 
 #[cfg(feature = "downloads")]
 pub mod downloaded;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+#[cfg(feature = "follows")]
+pub mod follows;
 pub mod item;
+#[cfg(feature = "library")]
+pub mod library;
+#[cfg(feature = "lyrics")]
+pub mod lyrics;
 #[cfg(feature = "player-connection")]
 pub mod players;
 #[cfg(feature = "playlist")]
 pub mod playlist;
+pub mod prelude;
 #[cfg(feature = "queue")]
 pub mod queue;
+#[cfg(feature = "scrobble")]
+pub mod scrobble;
+#[cfg(feature = "sponsorblock")]
+pub mod sponsorblock;
 #[cfg(feature = "statistics")]
 pub mod statistics;
 #[cfg(feature = "ytdl")]
@@ -47,6 +60,68 @@ pub enum Error {
     #[cfg(feature = "playlist")]
     #[error("playlist file not found at: {0}")]
     PlaylistFileNotFound(std::path::PathBuf),
+
+    #[cfg(feature = "playlist")]
+    #[error("{0} is being written by another process, try again later")]
+    PlaylistLocked(std::path::PathBuf),
+
+    #[cfg(feature = "playlist")]
+    #[error("{song} is from {}, which is only ever read, change it there", path.display())]
+    IncludedSong {
+        song: String,
+        path: std::path::PathBuf,
+    },
+}
+
+/// Decrypts the contents of a file that says what was listened to, if they were encrypted. See
+/// [`encryption`].
+#[cfg(any(feature = "statistics", feature = "scrobble", feature = "player"))]
+pub(crate) fn decode_history(bytes: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    #[cfg(feature = "encryption")]
+    return encryption::decode(bytes);
+    #[cfg(not(feature = "encryption"))]
+    Ok(bytes)
+}
+
+/// Encrypts the contents of a file that says what was listened to, if there is a key.
+#[cfg(any(feature = "statistics", feature = "scrobble", feature = "player"))]
+pub(crate) fn encode_history(bytes: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    #[cfg(feature = "encryption")]
+    return encryption::encode(bytes);
+    #[cfg(not(feature = "encryption"))]
+    Ok(bytes)
+}
+
+/// Replaces the contents of the file at `path` with what `f` makes of them, a file that doesn't
+/// exist yet being empty. Concurrent updates wait for each other and readers see either the old or
+/// the new contents, never half of them.
+#[cfg(any(feature = "statistics", feature = "player", feature = "follows"))]
+pub(crate) async fn update_file<F>(path: std::path::PathBuf, f: F) -> std::io::Result<()>
+where
+    F: FnOnce(Vec<u8>) -> std::io::Result<Vec<u8>> + Send + 'static,
+{
+    use std::{fs, io, io::Write};
+
+    tokio::task::spawn_blocking(move || {
+        let dir = path.parent().unwrap();
+        fs::create_dir_all(dir)?;
+        // the file itself is replaced on every update, so the lock has to be held on another one
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        let lock_file = fs::File::create(lock_path)?;
+        let _lock = raii_flock::FileLock::wrap_exclusive(&lock_file);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let bytes = f(bytes)?;
+        let (mut file, temp_path) = tempfile::NamedTempFile::new_in(dir)?.into_parts();
+        file.write_all(&bytes)?;
+        temp_path.persist(&path).map_err(|e| e.error)?;
+        Ok(())
+    })
+    .await?
 }
 
 #[cfg(feature = "player-connection")]
@@ -0,0 +1,304 @@
+//! An index of a local music directory, so songs that are already on disk can be found by name
+//! without asking youtube.
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::SystemTime,
+};
+
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+/// Extensions of the files that are indexed.
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "aac", "aiff", "alac", "flac", "m4a", "mka", "mp3", "ogg", "opus", "wav", "webm", "wma",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Track {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// When the file was last modified, to know if it has to be probed again.
+    modified: SystemTime,
+}
+
+impl Track {
+    /// The title from the tags, or the file name if it has none.
+    pub fn name(&self) -> String {
+        match &self.title {
+            Some(title) => title.clone(),
+            None => self
+                .path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+
+    fn haystack(&self) -> String {
+        [&self.artist, &self.title, &self.album]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .chain(self.path.file_name().and_then(|n| n.to_str()))
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Library {
+    pub root: PathBuf,
+    pub tracks: Vec<Track>,
+}
+
+fn index_path() -> io::Result<PathBuf> {
+    let Some(mut path) = dirs::cache_dir() else {
+        tracing::error!("failed to get cache dir for the library index");
+        return Err(io::ErrorKind::NotFound.into());
+    };
+    path.push("m");
+    path.push("library.json");
+    Ok(path)
+}
+
+impl Library {
+    /// Loads the index of `root` that was last saved, which is empty if `root` was never indexed.
+    pub async fn load(root: &Path) -> io::Result<Self> {
+        let bytes = match tokio::fs::read(index_path()?).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::empty(root)),
+            Err(e) => return Err(e),
+        };
+        let library = serde_json::from_slice::<Self>(&bytes)?;
+        if library.root == root {
+            Ok(library)
+        } else {
+            Ok(Self::empty(root))
+        }
+    }
+
+    fn empty(root: &Path) -> Self {
+        Self {
+            root: root.to_owned(),
+            tracks: vec![],
+        }
+    }
+
+    pub async fn save(&self) -> io::Result<()> {
+        let path = index_path()?;
+        tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+        tokio::fs::write(path, serde_json::to_vec(self)?).await
+    }
+
+    /// Walks `root` and reads the tags of the files that are new or changed since the last time it
+    /// was indexed, then saves the index.
+    pub async fn index(root: &Path) -> io::Result<Self> {
+        let mut known = Self::load(root)
+            .await?
+            .tracks
+            .into_iter()
+            .map(|t| (t.path.clone(), t))
+            .collect::<HashMap<_, _>>();
+        let files = walk(root).await?;
+        let mut tracks = stream::iter(files)
+            .map(|(path, modified)| {
+                let unchanged = known.remove(&path).filter(|t| t.modified == modified);
+                async move {
+                    match unchanged {
+                        Some(track) => track,
+                        None => probe(path, modified).await,
+                    }
+                }
+            })
+            .buffer_unordered(8)
+            .collect::<Vec<_>>()
+            .await;
+        tracks.sort_by(|a, b| a.path.cmp(&b.path));
+        let library = Self {
+            root: root.to_owned(),
+            tracks,
+        };
+        library.save().await?;
+        Ok(library)
+    }
+
+    /// The tracks that fuzzily match all of `words`, best matches first.
+    pub fn search(&self, words: &[&str]) -> Vec<&Track> {
+        self.matching(words, 1)
+    }
+
+    /// The best match for `words`, if all of them are in it and not just their letters.
+    pub fn find(&self, words: &[&str]) -> Option<&Track> {
+        self.matching(words, 2).into_iter().next()
+    }
+
+    fn matching(&self, words: &[&str], min_score: u32) -> Vec<&Track> {
+        let words = words.iter().map(|w| w.to_lowercase()).collect::<Vec<_>>();
+        let mut found = self
+            .tracks
+            .iter()
+            .filter_map(|t| {
+                let haystack = t.haystack();
+                words
+                    .iter()
+                    .map(|w| score(&haystack, w).filter(|s| *s >= min_score))
+                    .sum::<Option<u32>>()
+                    .map(|score| (score, t))
+            })
+            .collect::<Vec<_>>();
+        found.sort_by(|(a, _), (b, _)| b.cmp(a));
+        found.into_iter().map(|(_, t)| t).collect()
+    }
+}
+
+/// How well `word` matches `haystack`: better if a word starts with it, worse if its letters are
+/// only there in order. Both are expected to be lowercase.
+pub fn score(haystack: &str, word: &str) -> Option<u32> {
+    if haystack
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|w| w.starts_with(word))
+    {
+        Some(3)
+    } else if haystack.contains(word) {
+        Some(2)
+    } else {
+        let mut letters = haystack.chars();
+        word.chars().all(|c| letters.any(|h| h == c)).then_some(1)
+    }
+}
+
+/// The audio files under `root`. Directories under it that can't be read are skipped, so one bad
+/// folder doesn't keep the rest of the library from being indexed.
+async fn walk(root: &Path) -> io::Result<Vec<(PathBuf, SystemTime)>> {
+    let mut files = vec![];
+    let mut dirs = vec![];
+    read_dir(root, &mut dirs, &mut files).await?;
+    while let Some(dir) = dirs.pop() {
+        if let Err(e) = read_dir(&dir, &mut dirs, &mut files).await {
+            tracing::warn!(error = ?e, dir = %dir.display(), "skipping unreadable directory");
+        }
+    }
+    Ok(files)
+}
+
+/// Adds the subdirectories of `dir` to `dirs` and the audio files in it to `files`.
+async fn read_dir(
+    dir: &Path,
+    dirs: &mut Vec<PathBuf>,
+    files: &mut Vec<(PathBuf, SystemTime)>,
+) -> io::Result<()> {
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let metadata = match entry.metadata().await {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                tracing::warn!(error = ?e, path = %path.display(), "skipping unreadable file");
+                continue;
+            }
+        };
+        if metadata.is_dir() {
+            dirs.push(path);
+        } else if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        {
+            files.push((path, metadata.modified()?));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct Probe {
+    format: ProbeFormat,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+/// Reads the tags of a file with ffprobe. Files it can't read are indexed by their name only.
+async fn probe(path: PathBuf, modified: SystemTime) -> Track {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format_tags", "-of", "json"])
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .await;
+    let mut tags = match output {
+        Ok(output) if output.status.success() => serde_json::from_slice::<Probe>(&output.stdout)
+            .map(|p| p.format.tags)
+            .unwrap_or_default(),
+        Ok(_) => Default::default(),
+        Err(e) => {
+            tracing::warn!(error = ?e, path = %path.display(), "failed to run ffprobe");
+            Default::default()
+        }
+    };
+    // vorbis comments are usually upper case, id3 tags lower case.
+    let mut tag = |name: &str| {
+        let key = tags.keys().find(|k| k.eq_ignore_ascii_case(name))?.clone();
+        tags.remove(&key)
+    };
+    Track {
+        title: tag("title"),
+        artist: tag("artist"),
+        album: tag("album"),
+        path,
+        modified,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn better_matches_come_first() {
+        let track = |artist: &str, title: &str| Track {
+            path: format!("/music/{artist} - {title}.flac").into(),
+            title: Some(title.into()),
+            artist: Some(artist.into()),
+            album: None,
+            modified: SystemTime::UNIX_EPOCH,
+        };
+        let library = Library {
+            root: "/music".into(),
+            tracks: vec![
+                track("Daft Punk", "Around the World"),
+                track("Dire Straits", "Sultans of Swing"),
+                track("Aphex Twin", "Windowlicker"),
+            ],
+        };
+        let names = |words: &[&str]| {
+            library
+                .search(words)
+                .into_iter()
+                .map(Track::name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&["daft", "world"]), ["Around the World"]);
+        assert_eq!(names(&["win"]), ["Windowlicker", "Sultans of Swing"]);
+        assert!(names(&["beatles"]).is_empty());
+        assert_eq!(names(&["wlckr"]), ["Windowlicker"]);
+        assert_eq!(library.find(&["wlckr"]), None);
+        assert_eq!(
+            library.find(&["swing"]).map(Track::name).unwrap(),
+            "Sultans of Swing"
+        );
+    }
+}
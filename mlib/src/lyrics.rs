@@ -0,0 +1,462 @@
+//! Finds the lyrics of songs, synced to the music when possible, and keeps them in the cache dir.
+
+use std::{fmt, io, path::PathBuf, sync::OnceLock, time::Duration};
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+const LRCLIB_URL: &str = "https://lrclib.net/api";
+const GENIUS_URL: &str = "https://genius.com";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("http: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("unexpected response from {0}")]
+    BadResponse(&'static str),
+}
+
+/// What song to look for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub artist: Option<String>,
+    pub title: String,
+    pub duration: Option<Duration>,
+}
+
+impl Query {
+    /// Splits titles like `Artist - Song`, otherwise the whole title is searched for.
+    pub fn from_media_title(media_title: &str, duration: Option<Duration>) -> Self {
+        let (artist, title) = match media_title.split_once(" - ") {
+            Some((artist, title)) if !artist.trim().is_empty() && !title.trim().is_empty() => {
+                (Some(artist.trim().to_owned()), title.trim())
+            }
+            _ => (None, media_title.trim()),
+        };
+        Self {
+            artist,
+            title: title.to_owned(),
+            duration,
+        }
+    }
+
+    fn search_terms(&self) -> String {
+        match &self.artist {
+            Some(artist) => format!("{artist} {}", self.title),
+            None => self.title.clone(),
+        }
+    }
+
+    fn cache_key(&self) -> String {
+        self.search_terms()
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub at: Duration,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lyrics {
+    Plain(String),
+    Synced(Vec<Line>),
+}
+
+impl Lyrics {
+    /// Parses the contents of an `.lrc` file, `[mm:ss.xx]` timestamps followed by the line.
+    /// Returns `None` if nothing in it has a timestamp.
+    pub fn parse_lrc(lrc: &str) -> Option<Self> {
+        let mut lines = vec![];
+        for line in lrc.lines() {
+            let mut rest = line.trim();
+            let mut stamps = vec![];
+            while let Some((stamp, after)) = rest.strip_prefix('[').and_then(|r| r.split_once(']'))
+            {
+                match parse_timestamp(stamp) {
+                    Some(at) => stamps.push(at),
+                    // metadata tags like [ar: Artist]
+                    None => break,
+                }
+                rest = after;
+            }
+            lines.extend(stamps.into_iter().map(|at| Line {
+                at,
+                text: rest.trim().to_owned(),
+            }));
+        }
+        if lines.is_empty() {
+            return None;
+        }
+        lines.sort_by_key(|l| l.at);
+        Some(Self::Synced(lines))
+    }
+
+    fn to_lrc(&self) -> String {
+        match self {
+            Self::Plain(text) => text.clone(),
+            Self::Synced(lines) => lines
+                .iter()
+                .map(|l| {
+                    let secs = l.at.as_secs();
+                    let centis = l.at.subsec_millis() / 10;
+                    format!(
+                        "[{:02}:{:02}.{centis:02}] {}\n",
+                        secs / 60,
+                        secs % 60,
+                        l.text
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// The index of the line being sung `at` some point in the song.
+    pub fn line_at(&self, at: Duration) -> Option<usize> {
+        match self {
+            Self::Plain(_) => None,
+            Self::Synced(lines) => lines.partition_point(|l| l.at <= at).checked_sub(1),
+        }
+    }
+}
+
+impl fmt::Display for Lyrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Plain(text) => f.write_str(text),
+            Self::Synced(lines) => {
+                for l in lines {
+                    writeln!(f, "{}", l.text)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn parse_timestamp(stamp: &str) -> Option<Duration> {
+    let (minutes, seconds) = stamp.split_once(':')?;
+    let minutes = minutes.trim().parse::<u64>().ok()?;
+    let seconds = seconds.trim().parse::<f64>().ok()?;
+    Some(Duration::from_secs(minutes * 60) + Duration::try_from_secs_f64(seconds).ok()?)
+}
+
+/// Somewhere lyrics can be found.
+pub trait Provider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn fetch<'q>(&'q self, query: &'q Query) -> BoxFuture<'q, Result<Option<Lyrics>, Error>>;
+}
+
+/// The providers that can be picked in the config, in the order they are tried by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    Lrclib,
+    Genius,
+}
+
+impl ProviderKind {
+    pub const ALL: [Self; 2] = [Self::Lrclib, Self::Genius];
+
+    pub fn provider(self) -> &'static dyn Provider {
+        match self {
+            Self::Lrclib => &Lrclib,
+            Self::Genius => &Genius,
+        }
+    }
+}
+
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("m/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("failed to build http client")
+    })
+}
+
+/// <https://lrclib.net>, which often has synced lyrics.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Lrclib;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LrclibTrack {
+    plain_lyrics: Option<String>,
+    synced_lyrics: Option<String>,
+}
+
+impl LrclibTrack {
+    fn into_lyrics(self) -> Option<Lyrics> {
+        self.synced_lyrics
+            .as_deref()
+            .and_then(Lyrics::parse_lrc)
+            .or_else(|| self.plain_lyrics.map(Lyrics::Plain))
+    }
+}
+
+impl Lrclib {
+    async fn get(&self, query: &Query) -> Result<Option<Lyrics>, Error> {
+        let request = match &query.artist {
+            Some(artist) => {
+                let mut params = vec![
+                    ("artist_name", artist.clone()),
+                    ("track_name", query.title.clone()),
+                ];
+                if let Some(d) = query.duration {
+                    params.push(("duration", d.as_secs().to_string()));
+                }
+                client().get(format!("{LRCLIB_URL}/get")).query(&params)
+            }
+            None => client()
+                .get(format!("{LRCLIB_URL}/search"))
+                .query(&[("q", &query.title)]),
+        };
+        let response = request.send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
+        let track = if query.artist.is_some() {
+            response.json::<LrclibTrack>().await?
+        } else {
+            match response
+                .json::<Vec<LrclibTrack>>()
+                .await?
+                .into_iter()
+                .next()
+            {
+                Some(track) => track,
+                None => return Ok(None),
+            }
+        };
+        Ok(track.into_lyrics())
+    }
+}
+
+impl Provider for Lrclib {
+    fn name(&self) -> &'static str {
+        "lrclib"
+    }
+
+    fn fetch<'q>(&'q self, query: &'q Query) -> BoxFuture<'q, Result<Option<Lyrics>, Error>> {
+        Box::pin(self.get(query))
+    }
+}
+
+/// <https://genius.com>, scraped from the song's page since the api doesn't give out lyrics.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Genius;
+
+#[derive(Deserialize)]
+struct GeniusSearch {
+    response: GeniusSections,
+}
+
+#[derive(Deserialize)]
+struct GeniusSections {
+    sections: Vec<GeniusSection>,
+}
+
+#[derive(Deserialize)]
+struct GeniusSection {
+    hits: Vec<GeniusHit>,
+}
+
+#[derive(Deserialize)]
+struct GeniusHit {
+    result: GeniusSong,
+}
+
+#[derive(Deserialize)]
+struct GeniusSong {
+    url: String,
+}
+
+impl Genius {
+    async fn get(&self, query: &Query) -> Result<Option<Lyrics>, Error> {
+        let search = client()
+            .get(format!("{GENIUS_URL}/api/search/song"))
+            .query(&[("q", query.search_terms())])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<GeniusSearch>()
+            .await?;
+        let Some(url) = search
+            .response
+            .sections
+            .into_iter()
+            .flat_map(|s| s.hits)
+            .map(|h| h.result.url)
+            .next()
+        else {
+            return Ok(None);
+        };
+        let page = client()
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        match scrape_genius(&page) {
+            Some(text) => Ok(Some(Lyrics::Plain(text))),
+            None => Err(Error::BadResponse("genius")),
+        }
+    }
+}
+
+impl Provider for Genius {
+    fn name(&self) -> &'static str {
+        "genius"
+    }
+
+    fn fetch<'q>(&'q self, query: &'q Query) -> BoxFuture<'q, Result<Option<Lyrics>, Error>> {
+        Box::pin(self.get(query))
+    }
+}
+
+/// Pulls the text out of the `data-lyrics-container` divs of a genius song page.
+fn scrape_genius(page: &str) -> Option<String> {
+    const CONTAINER: &str = "data-lyrics-container=\"true\"";
+    let mut text = String::new();
+    let mut rest = page;
+    while let Some(start) = rest.find(CONTAINER) {
+        rest = &rest[start..];
+        rest = &rest[rest.find('>')? + 1..];
+        let mut depth = 1;
+        while depth > 0 {
+            let tag_start = rest.find('<')?;
+            text.push_str(&decode_entities(&rest[..tag_start]));
+            rest = &rest[tag_start..];
+            let tag_end = rest.find('>')?;
+            let tag = &rest[1..tag_end];
+            if tag.starts_with("br") {
+                text.push('\n');
+            } else if tag.starts_with("div") {
+                depth += 1;
+            } else if tag.starts_with("/div") {
+                depth -= 1;
+            }
+            rest = &rest[tag_end + 1..];
+        }
+        text.push('\n');
+    }
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+fn decode_entities(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+fn cache_dir() -> io::Result<PathBuf> {
+    let Some(mut path) = dirs::cache_dir() else {
+        return Err(io::ErrorKind::NotFound.into());
+    };
+    path.push("m");
+    path.push("lyrics");
+    Ok(path)
+}
+
+async fn cached(query: &Query) -> io::Result<Option<Lyrics>> {
+    let mut path = cache_dir()?;
+    path.push(query.cache_key());
+    for (extension, synced) in [("lrc", true), ("txt", false)] {
+        match tokio::fs::read_to_string(path.with_extension(extension)).await {
+            Ok(text) if synced => return Ok(Lyrics::parse_lrc(&text)),
+            Ok(text) => return Ok(Some(Lyrics::Plain(text))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(None)
+}
+
+async fn cache(query: &Query, lyrics: &Lyrics) -> io::Result<()> {
+    let mut path = cache_dir()?;
+    tokio::fs::create_dir_all(&path).await?;
+    path.push(query.cache_key());
+    path.set_extension(match lyrics {
+        Lyrics::Plain(_) => "txt",
+        Lyrics::Synced(_) => "lrc",
+    });
+    tokio::fs::write(path, lyrics.to_lrc()).await
+}
+
+/// Looks for the lyrics in the cache and then in each provider, in order, until one has them.
+pub async fn fetch(query: &Query, providers: &[&dyn Provider]) -> Result<Option<Lyrics>, Error> {
+    match cached(query).await {
+        Ok(Some(lyrics)) => return Ok(Some(lyrics)),
+        Ok(None) => {}
+        Err(e) => tracing::warn!(error = ?e, "failed to read cached lyrics"),
+    }
+    let mut last_error = None;
+    for provider in providers {
+        match provider.fetch(query).await {
+            Ok(Some(lyrics)) => {
+                if let Err(e) = cache(query, &lyrics).await {
+                    tracing::warn!(error = ?e, "failed to cache lyrics");
+                }
+                return Ok(Some(lyrics));
+            }
+            Ok(None) => tracing::debug!(provider = provider.name(), "no lyrics found"),
+            Err(e) => {
+                tracing::warn!(provider = provider.name(), error = ?e, "failed to fetch lyrics");
+                last_error = Some(e);
+            }
+        }
+    }
+    match last_error {
+        Some(e) => Err(e),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_lrc() {
+        let lrc = "[ar: Rick Astley]\n[00:18.50]We're no strangers to love\n[00:22.10][01:40.00]You know the rules\n";
+        let lyrics = Lyrics::parse_lrc(lrc).unwrap();
+        let Lyrics::Synced(lines) = &lyrics else {
+            panic!("not synced: {lyrics:?}")
+        };
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1].at, Duration::from_millis(22_100));
+        assert_eq!(lines[2].text, "You know the rules");
+        assert_eq!(lyrics.line_at(Duration::from_secs(10)), None);
+        assert_eq!(lyrics.line_at(Duration::from_secs(20)), Some(0));
+        assert_eq!(lyrics.line_at(Duration::from_secs(200)), Some(2));
+        assert_eq!(Lyrics::parse_lrc(&lyrics.to_lrc()), Some(lyrics));
+        assert_eq!(Lyrics::parse_lrc("just words"), None);
+    }
+
+    #[test]
+    fn scrapes_genius_pages() {
+        let page = r#"<div class="x"><div data-lyrics-container="true" class="Lyrics">[Verse 1]<br/>We&#x27;re no <a href="/x"><span>strangers</span></a> to love<br/><div class="ad"></div>You know</div></div>
+<div data-lyrics-container="true">the rules &amp; so do I</div>"#;
+        assert_eq!(
+            scrape_genius(page).unwrap(),
+            "[Verse 1]\nWe're no strangers to love\nYou know\nthe rules & so do I"
+        );
+    }
+}
@@ -3,4 +3,6 @@ use cli_daemon::Daemon;
 use super::{error::MpvResult, event::PlayerEvent, Message, Response};
 
 pub(super) type PlayersDaemonLink = Daemon<Message, MpvResult<Response>, PlayerEvent>;
-pub(super) static PLAYERS: PlayersDaemonLink = Daemon::new("m-players");
+pub(super) static PLAYERS: PlayersDaemonLink = Daemon::new("m-players")
+    .versioned(env!("CARGO_PKG_VERSION"))
+    .protocol(super::PROTOCOL_VERSION);
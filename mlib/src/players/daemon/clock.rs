@@ -0,0 +1,83 @@
+//! The daemon's source of time, so that timeouts can be tested without waiting for them.
+
+use std::{
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use futures_util::future::BoxFuture;
+use tokio::time::Instant;
+
+pub trait Clock: Debug + Send + Sync {
+    /// Wall clock time, for timestamps that are shown or stored.
+    fn now(&self) -> SystemTime;
+
+    /// Monotonic time, for deadlines.
+    fn instant(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// A clock that only moves when [`MockClock::advance`] is called.
+#[cfg(test)]
+#[derive(Debug)]
+pub struct MockClock {
+    start: SystemTime,
+    start_instant: Instant,
+    elapsed: tokio::sync::watch::Sender<Duration>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            start: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            start_instant: Instant::now(),
+            elapsed: tokio::sync::watch::channel(Duration::ZERO).0,
+        })
+    }
+
+    /// Moves time forward, waking up everything sleeping until then.
+    pub fn advance(&self, by: Duration) {
+        self.elapsed.send_modify(|elapsed| *elapsed += by);
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        self.start + *self.elapsed.borrow()
+    }
+
+    fn instant(&self) -> Instant {
+        self.start_instant + *self.elapsed.borrow()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let mut elapsed = self.elapsed.subscribe();
+        let until = *elapsed.borrow() + duration;
+        Box::pin(async move {
+            let _ = elapsed.wait_for(|elapsed| *elapsed >= until).await;
+        })
+    }
+}
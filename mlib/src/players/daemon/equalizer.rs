@@ -0,0 +1,54 @@
+//! The equalizer, kept as a labeled entry in mpv's audio filter chain.
+
+use crate::players::EQUALIZER_BANDS;
+
+/// The label of the equalizer's entry in the audio filter chain.
+pub(super) const LABEL: &str = "m-equalizer";
+
+/// How much a band can be boosted or cut, in dB.
+const MAX_GAIN: f32 = 20.;
+
+/// The audio filter for the gains in `bands`, `None` if they are all flat.
+pub(super) fn filter(bands: &[f32]) -> Result<Option<String>, String> {
+    if bands.len() > EQUALIZER_BANDS.len() {
+        return Err(format!(
+            "the equalizer has {} bands, got {}",
+            EQUALIZER_BANDS.len(),
+            bands.len()
+        ));
+    }
+    if let Some(gain) = bands.iter().find(|g| !(-MAX_GAIN..=MAX_GAIN).contains(*g)) {
+        return Err(format!(
+            "{gain}dB is out of range, gains go from -{MAX_GAIN} to {MAX_GAIN}"
+        ));
+    }
+    let graph = EQUALIZER_BANDS
+        .iter()
+        .zip(bands)
+        .filter(|(_, gain)| **gain != 0.)
+        .map(|(freq, gain)| format!("equalizer=f={freq}:t=o:w=1:g={gain}"))
+        .collect::<Vec<_>>();
+    if graph.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(format!("lavfi=[{}]", graph.join(","))))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_boosted_or_cut_bands_are_filtered() {
+        assert_eq!(
+            filter(&[3., 0., -1.5]).unwrap().unwrap(),
+            "lavfi=[equalizer=f=31:t=o:w=1:g=3,equalizer=f=125:t=o:w=1:g=-1.5]"
+        );
+        assert_eq!(filter(&[0., 0.]).unwrap(), None);
+        assert_eq!(filter(&[]).unwrap(), None);
+        assert!(filter(&[21.]).is_err());
+        assert!(filter(&[f32::NAN]).is_err());
+        assert!(filter(&[0.; 11]).is_err());
+    }
+}
@@ -1,31 +1,38 @@
+mod clock;
+mod equalizer;
+mod raw_property;
 mod tasks;
+mod track_metadata;
 
-use std::{
-    any::type_name,
-    num::TryFromIntError,
-    path::PathBuf,
-    sync::{Arc, OnceLock},
-    time::{Duration, SystemTime},
-};
+use std::{any::type_name, io, num::TryFromIntError, path::PathBuf, sync::Arc, time::Duration};
 
 use futures_util::{join, stream, Stream, StreamExt};
 use libmpv::{FileState, GetData, Mpv, MpvNode};
-use regex::Regex;
-use tokio::sync::{broadcast, watch, Mutex};
+use serde::de::DeserializeOwned;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    watch, Mutex,
+};
 
 use crate::players::event::event_listener;
 use crate::{
+    item::VideoLink,
     players::{error::MpvError, legacy_socket_for, MessageKind},
-    Item,
+    Item, VideoId,
 };
 
 use super::libmpv_parsing;
 use super::{
     error::{MpvErrorCode, MpvResult},
-    event::{self, PlayerEvent},
-    Direction, LoopStatus, Message, Metadata, PlayerIndex, QueueItem, Response,
+    event::{self, OwnedLibMpvEvent, PlayerEvent, QueueChange},
+    Broadcast, DaemonSettings, Direction, EndOfQueuePolicy, FailedItem, Focus, HistoryEntry,
+    LoopStatus, Message, Metadata, PlayerIndex, PlayerProfile, PrecachePolicy, QueueItem, Radio,
+    RadioSeed, Response, SkippedItem, SleepTimer, StopOrPause, TrackMetadata, VolumeCurve,
 };
 
+#[cfg(feature = "encryption")]
+pub(crate) use tasks::resume::store_path as resume_positions_path;
+
 // make fields mod private
 use players::Players;
 mod players {
@@ -67,6 +74,9 @@ mod players {
 
 pub(super) struct PlayersDaemon {
     current_default: watch::Sender<Option<usize>>,
+    fade: watch::Sender<Option<Duration>>,
+    clock: clock::SharedClock,
+    settings: &'static DaemonSettings,
     players: Players,
 }
 
@@ -79,13 +89,46 @@ impl PlayersDaemon {
             .and_then(|i| self.players.get(i))
             .map(|p| p.subscribe())
     }
+
+    /// The current player's values of [`REPLAYED_PROPERTIES`], as if they had just changed.
+    fn current_state(&self) -> Vec<PlayerEvent> {
+        let Some((index, player)) = self
+            .current_default
+            .borrow()
+            .and_then(|i| Some((i, self.players.get(i)?)))
+        else {
+            return Vec::new();
+        };
+        REPLAYED_PROPERTIES
+            .iter()
+            .filter_map(|name| {
+                let change = player.get_property::<MpvNode>(name).ok()?;
+                Some(PlayerEvent {
+                    player_index: index,
+                    event: OwnedLibMpvEvent::PropertyChange {
+                        name: name.to_string(),
+                        change: change.into(),
+                        reply_userdata: 0,
+                    },
+                })
+            })
+            .collect()
+    }
 }
 
-impl Default for PlayersDaemon {
-    fn default() -> Self {
+/// What new subscribers are told about before any change, so that they don't have to wait for
+/// one to show something.
+const REPLAYED_PROPERTIES: &[&str] = &["pause", "volume", "media-title", "time-pos"];
+
+impl PlayersDaemon {
+    fn new(settings: &'static DaemonSettings) -> Self {
         let (current_default, _) = watch::channel(None);
+        let (fade, _) = watch::channel(None);
         Self {
             current_default,
+            fade,
+            clock: Arc::new(clock::SystemClock),
+            settings,
             players: Default::default(),
         }
     }
@@ -94,6 +137,7 @@ impl Default for PlayersDaemon {
 use player::{MpvExt, Player};
 mod player {
     use super::*;
+    use crate::ytdl::chapters::Chapter;
     use libmpv::MpvNodeArrayIter;
     use std::ops::Deref;
     use tasks::preemptive_dl::PreemptiveDownload;
@@ -101,43 +145,164 @@ mod player {
     pub struct Player {
         handle: Arc<Mpv>,
         events: event::EventSubscriber,
-        last_queue: parking_lot::Mutex<Option<(usize, SystemTime)>>,
-        pre_cacher: OnceLock<tasks::preemptive_dl::PreemptiveDownload>,
+        clock: clock::SharedClock,
+        last_queue: parking_lot::Mutex<tasks::last_queue_monitor::LastQueue>,
+        pre_cacher: PreemptiveDownload,
+        virtual_chapters: parking_lot::Mutex<Option<(String, Arc<[Chapter]>)>>,
+        sleep_timer: parking_lot::Mutex<Option<tasks::sleep_timer::Timer>>,
+        fader: tasks::fade::Fader,
+        history: parking_lot::Mutex<tasks::history::History>,
+        failed: parking_lot::Mutex<tasks::failed::Failed>,
+        end_of_queue: parking_lot::Mutex<EndOfQueuePolicy>,
+        name: parking_lot::Mutex<Option<String>>,
+        focus: parking_lot::Mutex<Option<tasks::focus::FocusMode>>,
+        radio: watch::Sender<Option<Radio>>,
+        equalizer: parking_lot::Mutex<Vec<f32>>,
+        resume: parking_lot::Mutex<bool>,
+        sponsorblock: watch::Sender<bool>,
+        idle: parking_lot::Mutex<tasks::idle::Idle>,
     }
 
     impl Player {
-        pub fn new(handle: Arc<Mpv>, events: event::EventSubscriber) -> Self {
+        pub fn new(
+            handle: Arc<Mpv>,
+            events: event::EventSubscriber,
+            fade: watch::Receiver<Option<Duration>>,
+            clock: clock::SharedClock,
+            settings: &DaemonSettings,
+        ) -> Self {
             Self {
+                fader: tasks::fade::Fader::new(Arc::downgrade(&handle), fade),
+                pre_cacher: PreemptiveDownload::new(Arc::downgrade(&handle), settings.precache),
                 handle,
                 events,
-                last_queue: parking_lot::Mutex::new(None),
-                pre_cacher: OnceLock::new(),
+                last_queue: parking_lot::Mutex::new(tasks::last_queue_monitor::LastQueue::new(
+                    clock.clone(),
+                )),
+                clock,
+                virtual_chapters: parking_lot::Mutex::new(None),
+                sleep_timer: parking_lot::Mutex::new(None),
+                history: Default::default(),
+                failed: Default::default(),
+                end_of_queue: Default::default(),
+                name: parking_lot::Mutex::new(None),
+                focus: parking_lot::Mutex::new(None),
+                radio: watch::Sender::new(None),
+                equalizer: Default::default(),
+                resume: parking_lot::Mutex::new(false),
+                #[cfg(feature = "sponsorblock")]
+                sponsorblock: watch::Sender::new(settings.sponsorblock.is_some()),
+                #[cfg(not(feature = "sponsorblock"))]
+                sponsorblock: watch::Sender::new(false),
+                idle: Default::default(),
             }
         }
 
         pub fn get_last_queue(&self) -> Option<usize> {
-            const THREE_HOURS: Duration = Duration::from_secs(60 * 60 * 3);
-
-            let mut last_queue = self.last_queue.lock();
-            match &*last_queue {
-                Some((idx, set)) => {
-                    if set.elapsed().unwrap_or_default() > THREE_HOURS {
-                        *last_queue = None;
-                        None
-                    } else {
-                        Some(*idx)
-                    }
-                }
-                None => None,
-            }
+            self.last_queue.lock().get()
         }
 
         pub fn set_last_queue(&self, index: usize) {
-            *self.last_queue.lock() = Some((index, SystemTime::now()));
+            self.last_queue.lock().set(index)
         }
 
         pub fn clear_last_queue(&self) {
-            *self.last_queue.lock() = None;
+            self.last_queue.lock().clear()
+        }
+
+        pub fn shift_last_queue(&self, inserted_at: usize) {
+            self.last_queue.lock().shift(inserted_at)
+        }
+
+        pub fn clock(&self) -> &clock::SharedClock {
+            &self.clock
+        }
+
+        /// Chapters extracted from the description of the currently playing file, only present
+        /// when the file has no chapters of its own.
+        pub fn virtual_chapters(&self) -> Option<Arc<[Chapter]>> {
+            let path = self.handle.simple_prop::<String>("path").ok()?;
+            self.virtual_chapters
+                .lock()
+                .as_ref()
+                .filter(|(p, _)| *p == path)
+                .map(|(_, chapters)| chapters.clone())
+        }
+
+        pub fn set_virtual_chapters(&self, path: String, chapters: Vec<Chapter>) {
+            *self.virtual_chapters.lock() = Some((path, chapters.into()));
+        }
+
+        pub fn clear_virtual_chapters(&self) {
+            *self.virtual_chapters.lock() = None;
+        }
+
+        /// Starts a sleep timer, replacing the previous one if it existed.
+        pub fn set_sleep_timer(&self, after: Duration, action: StopOrPause) {
+            let player = Arc::downgrade(&self.handle);
+            *self.sleep_timer.lock() = Some(tasks::sleep_timer::Timer::new(
+                after,
+                action,
+                self.clock.clone(),
+                move |action| tasks::sleep_timer::expire(action, player),
+            ));
+        }
+
+        pub fn cancel_sleep_timer(&self) -> bool {
+            self.sleep_timer
+                .lock()
+                .take()
+                .is_some_and(|t| t.status().is_some())
+        }
+
+        pub fn sleep_timer(&self) -> Option<SleepTimer> {
+            self.sleep_timer.lock().as_ref().and_then(|t| t.status())
+        }
+
+        pub fn idle(&self) -> &parking_lot::Mutex<tasks::idle::Idle> {
+            &self.idle
+        }
+
+        pub fn history(&self) -> &parking_lot::Mutex<tasks::history::History> {
+            &self.history
+        }
+
+        pub fn failed(&self) -> &parking_lot::Mutex<tasks::failed::Failed> {
+            &self.failed
+        }
+
+        pub fn end_of_queue(&self) -> &parking_lot::Mutex<EndOfQueuePolicy> {
+            &self.end_of_queue
+        }
+
+        pub fn name(&self) -> &parking_lot::Mutex<Option<String>> {
+            &self.name
+        }
+
+        pub fn focus(&self) -> &parking_lot::Mutex<Option<tasks::focus::FocusMode>> {
+            &self.focus
+        }
+
+        pub fn radio(&self) -> &watch::Sender<Option<Radio>> {
+            &self.radio
+        }
+
+        pub fn equalizer(&self) -> &parking_lot::Mutex<Vec<f32>> {
+            &self.equalizer
+        }
+
+        /// Whether files continue from where they were stopped last time.
+        pub fn resume(&self) -> &parking_lot::Mutex<bool> {
+            &self.resume
+        }
+
+        /// Whether SponsorBlock segments are skipped.
+        pub fn sponsorblock(&self) -> &watch::Sender<bool> {
+            &self.sponsorblock
+        }
+
+        pub fn fader(&self) -> &tasks::fade::Fader {
+            &self.fader
         }
 
         pub fn handle(&self) -> &Mpv {
@@ -148,9 +313,22 @@ mod player {
             self.events.subscribe()
         }
 
+        /// Sends an event that mpv doesn't have to the subscribers, for changes the daemon made.
+        pub fn notify(&self, event: OwnedLibMpvEvent) {
+            self.events.send(event)
+        }
+
+        pub fn queue_changed(&self, change: QueueChange) {
+            self.notify(OwnedLibMpvEvent::QueueChanged(change))
+        }
+
+        /// The playlist entry id of the file at `pos` in the queue.
+        pub fn entry_id(&self, pos: i64) -> MpvResult<usize> {
+            Ok(self.simple_prop::<i64>(&format!("playlist/{pos}/id"))? as usize)
+        }
+
         pub fn preemptive_download(&self) -> &PreemptiveDownload {
-            self.pre_cacher
-                .get_or_init(|| PreemptiveDownload::new(Arc::downgrade(&self.handle)))
+            &self.pre_cacher
         }
     }
 
@@ -165,6 +343,9 @@ mod player {
         fn simple_prop<T: GetData>(&self, prop: &str) -> MpvResult<T>;
         fn playlist(&self) -> MpvResult<PlaylistIntoIter>;
         fn playlist_move_fixed(&self, from: usize, to: usize) -> MpvResult<()>;
+        /// Replaces the entry labeled `label` in the audio filter chain with `filter`, or removes
+        /// it if `None`, leaving the other filters alone.
+        fn set_labeled_af(&self, label: &str, filter: Option<&str>) -> MpvResult<()>;
     }
 
     impl MpvExt for Mpv {
@@ -201,6 +382,25 @@ mod player {
             let (from, to) = indices.split_once(' ').unwrap();
             Ok(self.command("playlist-move", &[from, to])?)
         }
+
+        fn set_labeled_af(&self, label: &str, filter: Option<&str>) -> MpvResult<()> {
+            let label = format!("@{label}");
+            let chain = self.simple_prop::<String>("af")?;
+            if chain.contains(&format!("{label}:")) {
+                self.command("af", &["remove", &label])?;
+            }
+            let Some(filter) = filter else {
+                return Ok(());
+            };
+            if let Err(e) = self.command("af", &["add", &format!("{label}:{filter}")]) {
+                // don't leave the chain without the entry that was there
+                if let Err(e) = self.set_property("af", chain.as_str()) {
+                    tracing::error!(error = ?e, chain, "failed to restore the audio filters");
+                }
+                return Err(e.into());
+            }
+            Ok(())
+        }
     }
 
     pub struct PlaylistIntoIter {
@@ -235,9 +435,22 @@ impl PlayersDaemon {
         this: Arc<Mutex<Self>>,
         items: Vec<Item>,
         with_video: bool,
+        profile: PlayerProfile,
+        background: bool,
+        name: Option<String>,
     ) -> MpvResult<PlayerIndex> {
+        for option in profile.mpv_options.keys() {
+            raw_property::check_startup_option(option)?;
+        }
         let this_ref = this.clone();
         let mut this_ref = this_ref.lock().await;
+        if let Some(name) = &name {
+            if let Some(i) = this_ref.resolve_name(name) {
+                return Err(MpvError::FailedToExecute {
+                    reason: format!("player {i} is already called {name}"),
+                });
+            }
+        }
         let index = this_ref
             .players
             .iter()
@@ -264,9 +477,29 @@ impl PlayersDaemon {
                 mpv.set_property("msg-level", "all=debug")?;
                 mpv.set_property("log-file", format!("{legacy_socket}.log"))?;
             }
-            mpv.set_property("geometry", "820x466")?;
+            mpv.set_property("geometry", profile.geometry.as_deref().unwrap_or("820x466"))?;
             mpv.set_property("input-ipc-server", legacy_socket)?;
-            mpv.set_property("osc", true)?;
+            mpv.set_property("osc", profile.osc.unwrap_or(true))?;
+            if let Some(volume) = profile.volume {
+                mpv.set_property("volume", i64::from(volume))?;
+            }
+            if let Some(config) = crate::ytdl::config() {
+                // so that mpv's ytdl hook can play the same videos m can download
+                if let Some(cookies) = &config.cookies_file {
+                    mpv.set_property("ytdl-raw-options", format!("cookies={}", cookies.display()))?;
+                }
+                if config.backend != Default::default() {
+                    mpv.set_property(
+                        "script-opts",
+                        format!("ytdl_hook-ytdl_path={}", config.backend.binary.display()),
+                    )?;
+                }
+            }
+            for (option, value) in &profile.mpv_options {
+                if let Err(e) = mpv.set_property(option, value.as_str()) {
+                    tracing::error!(error = ?e, %option, %value, "failed to set mpv option");
+                }
+            }
 
             Ok(())
         })?);
@@ -283,19 +516,59 @@ impl PlayersDaemon {
             }
         });
 
-        let player = Arc::new(Player::new(mpv, events));
+        let player = Arc::new(Player::new(
+            mpv,
+            events,
+            this_ref.fade.subscribe(),
+            this_ref.clock.clone(),
+            this_ref.settings,
+        ));
+        *player.name().lock() = name;
 
         tokio::spawn(tasks::last_queue_monitor::reset(Arc::downgrade(&player)));
+        tokio::spawn(tasks::fade::fade_on_unpause(Arc::downgrade(&player)));
+        tokio::spawn(tasks::history::record(Arc::downgrade(&player)));
+        tokio::spawn(tasks::failed::record(Arc::downgrade(&player)));
+        tokio::spawn(tasks::end_of_queue::watch(Arc::downgrade(&player)));
+        tokio::spawn(tasks::virtual_chapters::fetch(Arc::downgrade(&player)));
+        tokio::spawn(tasks::focus::skip_unfocused(Arc::downgrade(&player)));
+        tokio::spawn(tasks::radio::keep_topped_up(Arc::downgrade(&player)));
+        tokio::spawn(tasks::resume::record(Arc::downgrade(&player)));
+        #[cfg(feature = "sponsorblock")]
+        tokio::spawn(tasks::sponsorblock::skip_segments(
+            Arc::downgrade(&player),
+            this_ref
+                .settings
+                .sponsorblock
+                .clone()
+                .unwrap_or_default()
+                .categories,
+        ));
+        tokio::spawn(tasks::idle::watch(
+            Arc::downgrade(&player),
+            this_ref.settings.quit_idle_after,
+        ));
+        tokio::spawn(tasks::preemptive_dl::follow(Arc::downgrade(&player)));
 
         player.handle().playlist_load_files(&prepared_items)?;
 
-        for i in items {
-            player.preemptive_download().song_queued(&i);
+        if player.preemptive_download().policy() == PrecachePolicy::Queue {
+            for i in items {
+                player.preemptive_download().song_queued(&i);
+            }
+        } else {
+            player.preemptive_download().sync();
         }
 
         let index = this_ref.players.add(player);
-        tracing::debug!("setting current default to {index}");
-        this_ref.current_default.send_replace(Some(index));
+        this_ref.current_default.send_if_modified(|cur| {
+            if background && cur.is_some() {
+                return false;
+            }
+            tracing::debug!("setting current default to {index}");
+            *cur = Some(index);
+            true
+        });
         Ok(PlayerIndex::of(index))
     }
 
@@ -313,6 +586,13 @@ impl PlayersDaemon {
             .collect()
     }
 
+    /// The index of the player called `name`.
+    pub(super) fn resolve_name(&self, name: &str) -> Option<usize> {
+        self.players
+            .iter()
+            .position(|p| p.is_some_and(|p| p.name().lock().as_deref() == Some(name)))
+    }
+
     #[cfg(feature = "mpris")]
     pub(super) fn len(&self) -> usize {
         self.players.len()
@@ -364,7 +644,11 @@ impl PlayersDaemon {
     }
 
     pub(super) async fn cycle_pause(&self, index: PlayerIndex) -> MpvResult<()> {
-        self.current_player(index)?.cycle_property("pause", true)?;
+        let player = self.current_player(index)?;
+        if player.simple_prop::<bool>("pause")? {
+            player.fader().fade_in();
+        }
+        player.cycle_property("pause", true)?;
         Ok(())
     }
 
@@ -374,11 +658,14 @@ impl PlayersDaemon {
     }
 
     pub(super) async fn resume(&self, index: PlayerIndex) -> MpvResult<()> {
-        self.current_player(index)?.unpause()?;
+        let player = self.current_player(index)?;
+        if player.simple_prop::<bool>("pause")? {
+            player.fader().fade_in();
+        }
+        player.unpause()?;
         Ok(())
     }
 
-    #[cfg(feature = "mpris")]
     pub(super) async fn jump_to(&self, index: PlayerIndex, pos: usize) -> MpvResult<()> {
         self.current_player(index)?
             .command("playlist-play-index", &[&pos.to_string()])?;
@@ -389,18 +676,36 @@ impl PlayersDaemon {
         let player = self.current_player(index)?;
         player.playlist_clear()?;
         player.preemptive_download().stop_all();
+        player.queue_changed(QueueChange::Replaced);
         Ok(())
     }
 
     pub(super) async fn load_file(&self, index: PlayerIndex, item: Item) -> MpvResult<()> {
-        let player = self.current_player(index)?;
-        player.playlist_load_files(&[(
-            (&item).try_into().map_err(|_| MpvError::InvalidUtf8)?,
-            FileState::AppendPlay,
-            None,
-        )])?;
-        player.preemptive_download().song_queued(&item);
-        Ok(())
+        insert_file(self.current_player(index)?, &item, None, &[])
+    }
+
+    /// Like [`Self::load_file`] but puts the file at position `at` in the queue, or at the end if
+    /// the queue is shorter.
+    pub(super) async fn load_file_at(
+        &self,
+        index: PlayerIndex,
+        item: Item,
+        at: usize,
+    ) -> MpvResult<()> {
+        insert_file(self.current_player(index)?, &item, Some(at), &[])
+    }
+
+    pub(super) async fn load_file_with_options(
+        &self,
+        index: PlayerIndex,
+        item: Item,
+        options: Vec<(String, String)>,
+        at: Option<usize>,
+    ) -> MpvResult<()> {
+        for (name, _) in &options {
+            raw_property::check_per_file_option(name)?;
+        }
+        insert_file(self.current_player(index)?, &item, at, &options)
     }
 
     pub(super) async fn load_list(&self, index: PlayerIndex, path: PathBuf) -> MpvResult<()> {
@@ -415,7 +720,23 @@ impl PlayersDaemon {
         from: usize,
         to: usize,
     ) -> MpvResult<()> {
-        self.current_player(index)?.playlist_move_fixed(from, to)
+        let player = self.current_player(index)?;
+        player.playlist_move_fixed(from, to)?;
+        player.queue_changed(QueueChange::Replaced);
+        Ok(())
+    }
+
+    pub(super) async fn queue_swap(&self, index: PlayerIndex, a: usize, b: usize) -> MpvResult<()> {
+        let (a, b) = (a.min(b), a.max(b));
+        if a == b {
+            return Ok(());
+        }
+        let player = self.current_player(index)?;
+        // `b` takes `a`'s place, pushing it one down, and then `a` goes where `b` was.
+        player.playlist_move_fixed(b, a)?;
+        player.playlist_move_fixed(a + 1, b + 1)?;
+        player.queue_changed(QueueChange::Replaced);
+        Ok(())
     }
 
     pub(super) async fn queue_remove(&self, index: PlayerIndex, to_remove: usize) -> MpvResult<()> {
@@ -431,9 +752,15 @@ impl PlayersDaemon {
         }
 
         let item = player.simple_prop::<String>("path").map(Item::from);
+        let id = player.entry_id(to_remove as i64);
 
         player.playlist_remove_index(to_remove)?;
 
+        match id {
+            Ok(id) => player.queue_changed(QueueChange::Removed { id }),
+            Err(e) => tracing::error!(error = ?e, "failed to get the id of the removed file"),
+        }
+
         match item {
             Ok(item) => {
                 if let Item::Link(crate::Link::Video(video)) = &item {
@@ -453,13 +780,50 @@ impl PlayersDaemon {
         index: PlayerIndex,
         start_looping: bool,
     ) -> MpvResult<()> {
-        self.current_player(index)?
-            .set_property("loop-playlist", if start_looping { "inf" } else { "no" })?;
+        let player = self.current_player(index)?;
+        player.set_property("loop-playlist", if start_looping { "inf" } else { "no" })?;
+        player.notify(OwnedLibMpvEvent::LoopChanged(if start_looping {
+            LoopStatus::Inf
+        } else {
+            LoopStatus::No
+        }));
         Ok(())
     }
 
     pub(super) async fn queue_shuffle(&self, index: PlayerIndex) -> MpvResult<()> {
-        self.current_player(index)?.playlist_shuffle()?;
+        let player = self.current_player(index)?;
+        player.playlist_shuffle()?;
+        player.notify(OwnedLibMpvEvent::Shuffled);
+        player.queue_changed(QueueChange::Replaced);
+        Ok(())
+    }
+
+    /// Puts the item that was at `order[i]` at position `i`, for every `i`.
+    pub(super) async fn queue_reorder(
+        &self,
+        index: PlayerIndex,
+        order: Vec<usize>,
+    ) -> MpvResult<()> {
+        let player = self.current_player(index)?;
+        let count = player.simple_prop::<i64>("playlist-count")? as usize;
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        if !sorted.into_iter().eq(0..count) {
+            return Err(MpvError::FailedToExecute {
+                reason: format!("the new order has to have each of the {count} positions once"),
+            });
+        }
+        let mut arrangement = (0..count).collect::<Vec<_>>();
+        for (to, item) in order.into_iter().enumerate() {
+            // everything before `to` is already in place, so the item is at or after it
+            let from = arrangement.iter().position(|i| *i == item).unwrap();
+            if from != to {
+                player.playlist_move_fixed(from, to)?;
+                arrangement.remove(from);
+                arrangement.insert(to, item);
+            }
+        }
+        player.queue_changed(QueueChange::Replaced);
         Ok(())
     }
 
@@ -490,9 +854,76 @@ impl PlayersDaemon {
         Ok(())
     }
 
-    pub(super) async fn change_volume(&self, index: PlayerIndex, delta: i32) -> MpvResult<()> {
-        self.current_player(index)?
-            .add_property("volume", delta as isize)?;
+    pub(super) async fn set_sleep_timer(
+        &self,
+        index: PlayerIndex,
+        after: Duration,
+        action: StopOrPause,
+    ) -> MpvResult<()> {
+        self.current_player(index)?.set_sleep_timer(after, action);
+        Ok(())
+    }
+
+    pub(super) async fn cancel_sleep_timer(&self, index: PlayerIndex) -> MpvResult<bool> {
+        Ok(self.current_player(index)?.cancel_sleep_timer())
+    }
+
+    pub(super) async fn sleep_timer_status(
+        &self,
+        index: PlayerIndex,
+    ) -> MpvResult<Option<SleepTimer>> {
+        Ok(self.current_player(index)?.sleep_timer())
+    }
+
+    /// Runs `command` on every player, one after the other.
+    pub(super) async fn for_all(
+        &mut self,
+        command: Broadcast,
+    ) -> MpvResult<Vec<(PlayerIndex, MpvResult<()>)>> {
+        let mut results = Vec::new();
+        for index in self.list() {
+            let result = match command {
+                Broadcast::CyclePause => self.cycle_pause(index).await,
+                Broadcast::Pause => self.pause(index).await,
+                Broadcast::Resume => self.resume(index).await,
+                Broadcast::Quit => self.quit(index).await,
+                Broadcast::StepVolume { delta, curve } => {
+                    self.change_volume(index, delta, curve).await
+                }
+            };
+            results.push((index, result));
+        }
+        Ok(results)
+    }
+
+    pub(super) async fn change_volume(
+        &self,
+        index: PlayerIndex,
+        delta: f64,
+        curve: VolumeCurve,
+    ) -> MpvResult<()> {
+        let player = self.current_player(index)?;
+        player.fader().cancel();
+        let volume = player.simple_prop::<f64>("volume")?;
+        let max = player.simple_prop::<f64>("volume-max")?;
+        player.set_property("volume", curve.step(volume, delta).min(max))?;
+        Ok(())
+    }
+
+    #[cfg(feature = "mpris")]
+    pub(super) async fn set_volume(&self, index: PlayerIndex, volume: f64) -> MpvResult<()> {
+        let player = self.current_player(index)?;
+        player.fader().cancel();
+        let max = player.simple_prop::<f64>("volume-max")?;
+        player.set_property("volume", volume.clamp(0., max))?;
+        Ok(())
+    }
+
+    /// Enables fading in on unpause and large seeks for all players, or disables it with
+    /// `None`. The length is clamped to [`tasks::fade::MIN`]..=[`tasks::fade::MAX`].
+    pub(super) async fn set_fade(&self, length: Option<Duration>) -> MpvResult<()> {
+        self.fade
+            .send_replace(length.map(|l| l.clamp(tasks::fade::MIN, tasks::fade::MAX)));
         Ok(())
     }
 
@@ -538,8 +969,205 @@ impl PlayersDaemon {
         Ok(())
     }
 
+    /// Goes back or forward in the list of songs that were actually played, as opposed to
+    /// [`Self::change_file`] which follows the queue order.
+    pub(super) async fn history_step(
+        &self,
+        index: PlayerIndex,
+        direction: Direction,
+    ) -> MpvResult<Option<HistoryEntry>> {
+        let player = self.current_player(index)?;
+        let ids = player
+            .playlist()?
+            .into_iter()
+            .map(|i| i.map(|i| i.id))
+            .collect::<MpvResult<Vec<_>>>()?;
+        let Some((entry, pos)) = player
+            .history()
+            .lock()
+            .step(direction, |id| ids.iter().position(|i| *i == id))
+        else {
+            return Ok(None);
+        };
+        player.command("playlist-play-index", &[&pos.to_string()])?;
+        Ok(Some(HistoryEntry {
+            index: pos,
+            played_at: entry.played_at,
+        }))
+    }
+
+    pub(super) async fn failed_items(&self, index: PlayerIndex) -> MpvResult<Vec<FailedItem>> {
+        Ok(self.current_player(index)?.failed().lock().list())
+    }
+
+    pub(super) async fn take_failed(
+        &self,
+        index: PlayerIndex,
+        at: Option<usize>,
+    ) -> MpvResult<Vec<FailedItem>> {
+        Ok(self.current_player(index)?.failed().lock().take(at))
+    }
+
+    pub(super) async fn forget_failed(&self, index: PlayerIndex, item: Item) -> MpvResult<()> {
+        self.current_player(index)?.failed().lock().forget(&item);
+        Ok(())
+    }
+
+    pub(super) async fn set_end_of_queue(
+        &self,
+        index: PlayerIndex,
+        policy: EndOfQueuePolicy,
+    ) -> MpvResult<()> {
+        *self.current_player(index)?.end_of_queue().lock() = policy;
+        Ok(())
+    }
+
+    pub(super) async fn end_of_queue(&self, index: PlayerIndex) -> MpvResult<EndOfQueuePolicy> {
+        Ok(self.current_player(index)?.end_of_queue().lock().clone())
+    }
+
+    pub(super) async fn set_name(&self, index: PlayerIndex, name: Option<String>) -> MpvResult<()> {
+        let player = self.current_player(index)?;
+        if let Some(name) = &name {
+            let taken = self
+                .players
+                .iter()
+                .enumerate()
+                .filter_map(|(i, p)| Some((i, p?)))
+                .find(|(_, p)| !std::ptr::eq(*p, player) && p.name().lock().as_ref() == Some(name));
+            if let Some((i, _)) = taken {
+                return Err(MpvError::FailedToExecute {
+                    reason: format!("player {i} is already called {name}"),
+                });
+            }
+        }
+        *player.name().lock() = name;
+        Ok(())
+    }
+
+    pub(super) async fn name(&self, index: PlayerIndex) -> MpvResult<Option<String>> {
+        Ok(self.current_player(index)?.name().lock().clone())
+    }
+
+    pub(super) async fn set_focus(
+        &self,
+        index: PlayerIndex,
+        focus: Option<Focus>,
+    ) -> MpvResult<Vec<SkippedItem>> {
+        let previous = std::mem::replace(
+            &mut *self.current_player(index)?.focus().lock(),
+            focus.map(tasks::focus::FocusMode::new),
+        );
+        Ok(previous.map(|f| f.skipped).unwrap_or_default())
+    }
+
+    pub(super) async fn focus(&self, index: PlayerIndex) -> MpvResult<Option<Focus>> {
+        Ok(self
+            .current_player(index)?
+            .focus()
+            .lock()
+            .as_ref()
+            .map(|f| f.focus.clone()))
+    }
+
+    pub(super) async fn start_radio(&self, index: PlayerIndex, radio: Radio) -> MpvResult<()> {
+        let player = self.current_player(index)?;
+        if let RadioSeed::Song(song) = &radio.seed {
+            if song.id().is_none() {
+                return Err(MpvError::FailedToExecute {
+                    reason: format!("{song} isn't a youtube video, it has no mix"),
+                });
+            }
+        }
+        player.radio().send_replace(Some(radio));
+        Ok(())
+    }
+
+    pub(super) async fn set_equalizer(&self, index: PlayerIndex, bands: Vec<f32>) -> MpvResult<()> {
+        let player = self.current_player(index)?;
+        let filter =
+            equalizer::filter(&bands).map_err(|reason| MpvError::FailedToExecute { reason })?;
+        player.set_labeled_af(equalizer::LABEL, filter.as_deref())?;
+        *player.equalizer().lock() = if filter.is_some() { bands } else { vec![] };
+        Ok(())
+    }
+
+    pub(super) async fn get_equalizer(&self, index: PlayerIndex) -> MpvResult<Vec<f32>> {
+        Ok(self.current_player(index)?.equalizer().lock().clone())
+    }
+
+    pub(super) async fn set_property_raw(
+        &self,
+        index: PlayerIndex,
+        name: &str,
+        value: serde_json::Value,
+    ) -> MpvResult<()> {
+        raw_property::check_writable(name)?;
+        let player = self.current_player(index)?;
+        match value {
+            serde_json::Value::Bool(b) => player.set_property(name, b)?,
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => player.set_property(name, i)?,
+                None => player.set_property(name, n.as_f64().unwrap_or_default())?,
+            },
+            serde_json::Value::String(s) => player.set_property(name, s)?,
+            value => {
+                return Err(MpvError::FailedToExecute {
+                    reason: format!("{value} can't be set, only booleans, numbers and strings can"),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) async fn get_property_raw(
+        &self,
+        index: PlayerIndex,
+        name: &str,
+    ) -> MpvResult<serde_json::Value> {
+        let node = self.current_player(index)?.get_property::<MpvNode>(name)?;
+        raw_property::to_json(node.into())
+    }
+
+    pub(super) async fn set_resume(&self, index: PlayerIndex, resume: bool) -> MpvResult<()> {
+        let player = self.current_player(index)?;
+        *player.resume().lock() = resume;
+        if resume {
+            // the current file may have loaded before this was turned on
+            tasks::resume::resume_current(player).await;
+        }
+        Ok(())
+    }
+
+    pub(super) async fn set_sponsorblock(&self, index: PlayerIndex, on: bool) -> MpvResult<()> {
+        let player = self.current_player(index)?;
+        if on && cfg!(not(feature = "sponsorblock")) {
+            return Err(MpvError::FailedToExecute {
+                reason: "the daemon was built without sponsorblock support".into(),
+            });
+        }
+        player.sponsorblock().send_replace(on);
+        Ok(())
+    }
+
+    pub(super) async fn sponsorblock(&self, index: PlayerIndex) -> MpvResult<bool> {
+        Ok(*self.current_player(index)?.sponsorblock().borrow())
+    }
+
+    pub(super) async fn stop_radio(&self, index: PlayerIndex) -> MpvResult<bool> {
+        Ok(self
+            .current_player(index)?
+            .radio()
+            .send_replace(None)
+            .is_some())
+    }
+
     pub(super) async fn seek(&self, index: PlayerIndex, seconds: f64) -> MpvResult<()> {
-        self.current_player(index)?.seek_forward(seconds)?;
+        let player = self.current_player(index)?;
+        if seconds.abs() >= tasks::fade::LARGE_SEEK {
+            player.fader().fade_in();
+        }
+        player.seek_forward(seconds)?;
         Ok(())
     }
 
@@ -549,7 +1177,26 @@ impl PlayersDaemon {
         direction: Direction,
         amount: i32,
     ) -> MpvResult<()> {
-        self.current_player(index)?
+        let player = self.current_player(index)?;
+        if let Some(chapters) = player.virtual_chapters() {
+            let time = player.simple_prop::<f64>("playback-time")?;
+            let current =
+                tasks::virtual_chapters::current(&chapters, time).map_or(-1, |i| i as i64);
+            let target = match direction {
+                Direction::Next => current + amount as i64,
+                Direction::Prev => current - amount as i64,
+            };
+            match usize::try_from(target).ok().map(|t| chapters.get(t)) {
+                Some(Some(chapter)) => player.command(
+                    "seek",
+                    &[&chapter.start.as_secs_f64().to_string(), "absolute"],
+                )?,
+                Some(None) => player.command("playlist-next", &[])?,
+                None => player.command("seek", &["0", "absolute"])?,
+            }
+            return Ok(());
+        }
+        player
             .add_property(
                 "chapter",
                 match direction {
@@ -569,10 +1216,17 @@ impl PlayersDaemon {
 
     pub(super) async fn chapter_metadata(&self, index: PlayerIndex) -> MpvResult<Option<Metadata>> {
         use MpvErrorCode as MEC;
-        let t = match self
-            .current_player(index)?
-            .get_property::<MpvNode>("chapter-metadata")
-        {
+        let player = self.current_player(index)?;
+        if let Some(chapters) = player.virtual_chapters() {
+            let time = player.simple_prop::<f64>("playback-time")?;
+            return Ok(
+                tasks::virtual_chapters::current(&chapters, time).map(|index| Metadata {
+                    title: chapters[index].title.clone(),
+                    index,
+                }),
+            );
+        }
+        let t = match player.get_property::<MpvNode>("chapter-metadata") {
             Ok(t) => t,
             Err(e) => {
                 return match e {
@@ -620,14 +1274,33 @@ impl PlayersDaemon {
     }
 
     pub(super) async fn filename(&self, index: PlayerIndex) -> MpvResult<String> {
-        let mut filename = self.simple_prop::<String>(index, "filename")?;
-        static YT_ID: OnceLock<Regex> = OnceLock::new();
-        let pat = YT_ID.get_or_init(|| Regex::new(r"^[a-zA-Z\-_0-9]{11}$").unwrap());
+        let filename = self.simple_prop::<String>(index, "filename")?;
         // mpv now returns only the video id instead of the full youtube url
-        if pat.is_match(&filename) {
-            filename.insert_str(0, "https://youtu.be/");
-        }
-        Ok(filename)
+        Ok(match VideoId::bare(&filename) {
+            Some(id) => VideoLink::from_id(id).as_str().to_owned(),
+            None => filename,
+        })
+    }
+
+    pub(super) async fn idle_for(&self, index: PlayerIndex) -> MpvResult<Option<Duration>> {
+        let player = self.current_player(index)?;
+        let since = player.idle().lock().since();
+        Ok(since.map(|since| player.clock().instant().saturating_duration_since(since)))
+    }
+
+    pub(super) async fn set_precache(
+        &self,
+        index: PlayerIndex,
+        policy: PrecachePolicy,
+    ) -> MpvResult<()> {
+        self.current_player(index)?
+            .preemptive_download()
+            .set_policy(policy);
+        Ok(())
+    }
+
+    pub(super) async fn precache(&self, index: PlayerIndex) -> MpvResult<PrecachePolicy> {
+        Ok(self.current_player(index)?.preemptive_download().policy())
     }
 
     pub(super) async fn is_paused(&self, index: PlayerIndex) -> MpvResult<bool> {
@@ -638,6 +1311,28 @@ impl PlayersDaemon {
         self.simple_prop(index, "media-title")
     }
 
+    pub(super) async fn track_metadata(&self, index: PlayerIndex) -> MpvResult<TrackMetadata> {
+        let player = self.current_player(index)?;
+        // not every file has these, so they aren't errors worth logging like simple_prop does
+        let tags = player
+            .get_property::<MpvNode>("metadata")
+            .map(|tags| track_metadata::from_tags(&tags))
+            .unwrap_or_default();
+        let ytdl = player
+            .get_property::<MpvNode>(track_metadata::YTDL_RESULT)
+            .ok()
+            .and_then(|result| track_metadata::from_ytdl_result(&result))
+            .unwrap_or_default();
+        let mut metadata = track_metadata::or(tags, ytdl);
+        if metadata.title.is_none() {
+            metadata.title = player.get_property::<String>("media-title").ok();
+        }
+        if let Ok(duration) = player.get_property::<f64>("duration") {
+            metadata.duration = Duration::try_from_secs_f64(duration).ok();
+        }
+        Ok(metadata)
+    }
+
     pub(super) async fn percent_position(&self, index: PlayerIndex) -> MpvResult<f64> {
         self.simple_prop(index, "percent-pos")
     }
@@ -668,7 +1363,11 @@ impl PlayersDaemon {
     }
 
     pub(super) async fn volume(&self, index: PlayerIndex) -> MpvResult<f64> {
-        self.simple_prop(index, "volume")
+        let player = self.current_player(index)?;
+        match player.fader().target() {
+            Some(target) => Ok(target),
+            None => player.simple_prop("volume"),
+        }
     }
 
     pub(super) async fn queue_at_filename(
@@ -685,6 +1384,24 @@ impl PlayersDaemon {
         )
     }
 
+    /// The items from `from` up to, but not including, `to`, fewer if the queue ends before.
+    pub(super) async fn queue_range(
+        &self,
+        index: PlayerIndex,
+        from: usize,
+        to: usize,
+    ) -> MpvResult<Vec<QueueItem>> {
+        let player = self.current_player(index)?;
+        let count = player.simple_prop::<i64>("playlist-count")? as usize;
+        (from..to.min(count))
+            .map(|at| {
+                libmpv_parsing::parse_queue_item(
+                    player.simple_prop::<MpvNode>(&format!("playlist/{at}"))?,
+                )
+            })
+            .collect()
+    }
+
     pub(super) async fn duration(&self, index: PlayerIndex) -> MpvResult<f64> {
         self.simple_prop(index, "duration")
     }
@@ -728,12 +1445,24 @@ async fn handle_messages(
         };
     }
     match kind {
-        MessageKind::Create { items, with_video } => {
-            PlayersDaemon::create(players, items, with_video)
+        MessageKind::Create {
+            items,
+            with_video,
+            profile,
+            options,
+            background,
+            name,
+        } => {
+            let mut profile = profile.unwrap_or_default();
+            profile.mpv_options.extend(options);
+            PlayersDaemon::create(players, items, with_video, profile, background, name)
                 .await
                 .map(Response::Create)
         }
         MessageKind::PlayerList => Ok(Response::PlayerList(players.lock().await.list())),
+        MessageKind::ResolveName { name } => Ok(Response::MaybeInteger(
+            players.lock().await.resolve_name(&name),
+        )),
         MessageKind::LastQueue => players
             .lock()
             .await
@@ -757,10 +1486,21 @@ async fn handle_messages(
         MessageKind::Resume => call!(players.resume(index)),
         MessageKind::QueueClear => call!(players.queue_clear(index)),
         MessageKind::LoadFile { item } => call!(players.load_file(index, item)),
+        MessageKind::LoadFileAt { item, at } => call!(players.load_file_at(index, item, at)),
+        MessageKind::LoadFileWithOptions { item, options, at } => {
+            call!(players.load_file_with_options(index, item, options, at))
+        }
         MessageKind::LoadList { path } => call!(players.load_list(index, path)),
         MessageKind::QueueMove { from, to } => {
             call!(players.queue_move(index, from, to))
         }
+        MessageKind::QueueSwap { a, b } => call!(players.queue_swap(index, a, b)),
+        MessageKind::QueueReorder { order } => call!(players.queue_reorder(index, order)),
+        MessageKind::ForAll { command } => call!(players.for_all(command) => PerPlayer),
+        MessageKind::IdleFor => call!(players.idle_for(index) => MaybeDuration),
+        MessageKind::SetPrecache { policy } => call!(players.set_precache(index, policy)),
+        MessageKind::Precache => call!(players.precache(index) => PrecachePolicy),
+        MessageKind::JumpTo { pos } => call!(players.jump_to(index, pos)),
         MessageKind::QueueRemove { to_remove } => {
             call!(players.queue_remove(index, to_remove))
         }
@@ -770,12 +1510,54 @@ async fn handle_messages(
         MessageKind::QueueShuffle => call!(players.queue_shuffle(index)),
         MessageKind::Quit => call!(players.quit(index)),
         MessageKind::ChangeVolume { delta } => {
-            call!(players.change_volume(index, delta))
+            call!(players.change_volume(index, delta.into(), VolumeCurve::Linear))
         }
+        MessageKind::StepVolume { delta, curve } => {
+            call!(players.change_volume(index, delta, curve))
+        }
+        MessageKind::SetFade { length } => call!(players.set_fade(length)),
         MessageKind::CycleVideo => call!(players.cycle_video(index)),
+        MessageKind::SetSleepTimer { after, action } => {
+            call!(players.set_sleep_timer(index, after, action))
+        }
+        MessageKind::CancelSleepTimer => {
+            call!(players.cancel_sleep_timer(index) => Bool)
+        }
+        MessageKind::SleepTimerStatus => {
+            call!(players.sleep_timer_status(index) => MaybeSleepTimer)
+        }
         MessageKind::ChangeFile { direction } => {
             call!(players.change_file(index, direction))
         }
+        MessageKind::HistoryBack => {
+            call!(players.history_step(index, Direction::Prev) => MaybeHistoryEntry)
+        }
+        MessageKind::HistoryForward => {
+            call!(players.history_step(index, Direction::Next) => MaybeHistoryEntry)
+        }
+        MessageKind::FailedItems => call!(players.failed_items(index) => FailedItems),
+        MessageKind::TakeFailed { at } => call!(players.take_failed(index, at) => FailedItems),
+        MessageKind::ForgetFailed { item } => call!(players.forget_failed(index, item)),
+        MessageKind::SetEndOfQueue { policy } => call!(players.set_end_of_queue(index, policy)),
+        MessageKind::EndOfQueue => call!(players.end_of_queue(index) => EndOfQueuePolicy),
+        MessageKind::SetName { name } => call!(players.set_name(index, name)),
+        MessageKind::Name => call!(players.name(index) => MaybeText),
+        MessageKind::SetFocus { focus } => call!(players.set_focus(index, focus) => SkippedItems),
+        MessageKind::Focus => call!(players.focus(index) => MaybeFocus),
+        MessageKind::StartRadio { radio } => call!(players.start_radio(index, radio)),
+        MessageKind::StopRadio => call!(players.stop_radio(index) => Bool),
+        MessageKind::SetEqualizer { bands } => call!(players.set_equalizer(index, bands)),
+        MessageKind::GetEqualizer => call!(players.get_equalizer(index) => Bands),
+        MessageKind::TrackMetadata => call!(players.track_metadata(index) => TrackMetadata),
+        MessageKind::SetPropertyRaw { name, value } => {
+            call!(players.set_property_raw(index, &name, value))
+        }
+        MessageKind::GetPropertyRaw { name } => {
+            call!(players.get_property_raw(index, &name) => Json)
+        }
+        MessageKind::SetResume { resume } => call!(players.set_resume(index, resume)),
+        MessageKind::SetSponsorBlock { on } => call!(players.set_sponsorblock(index, on)),
+        MessageKind::SponsorBlock => call!(players.sponsorblock(index) => Bool),
         MessageKind::Seek { seconds } => call!(players.seek(index, seconds)),
         MessageKind::ChangeChapter { direction, amount } => {
             call!(players.change_chapter(index, direction, amount))
@@ -806,6 +1588,9 @@ async fn handle_messages(
         MessageKind::QueueN { at } => {
             call!(players.queue_at(index, at) => Item)
         }
+        MessageKind::QueueRange { from, to } => {
+            call!(players.queue_range(index, from, to) => Items)
+        }
         MessageKind::Duration => {
             call!(players.duration(index) => Real)
         }
@@ -816,6 +1601,53 @@ async fn handle_messages(
     .map_err(From::from)
 }
 
+/// Formats per file options for `loadfile`, each value quoted as `%<length>%<value>` so it can
+/// have commas in it. The names have to be [checked](raw_property::check_per_file_option) first.
+fn per_file_options(options: &[(String, String)]) -> Option<String> {
+    (!options.is_empty()).then(|| {
+        options
+            .iter()
+            .map(|(name, value)| format!("{name}=%{}%{value}", value.len()))
+            .collect::<Vec<_>>()
+            .join(",")
+    })
+}
+
+/// Adds `item` to the queue, at the end or at `at`, keeping the last queued position pointing at
+/// the same entry if the ones after `at` are pushed down. `options` only apply to this entry.
+fn insert_file(
+    player: &Player,
+    item: &Item,
+    at: Option<usize>,
+    options: &[(String, String)],
+) -> MpvResult<()> {
+    let options = per_file_options(options);
+    player.playlist_load_files(&[(
+        item.try_into().map_err(|_| MpvError::InvalidUtf8)?,
+        FileState::AppendPlay,
+        options.as_deref(),
+    )])?;
+    player.preemptive_download().song_queued(item);
+    let end = player.simple_prop::<i64>("playlist-count")? as usize - 1;
+    let at = at.map_or(end, |at| at.min(end));
+    if at != end {
+        player.playlist_move_fixed(end, at)?;
+        player.shift_last_queue(at);
+    }
+    let added = (|| {
+        let id = player.entry_id(at as i64)?;
+        let after = (at > 0)
+            .then(|| player.entry_id(at as i64 - 1))
+            .transpose()?;
+        MpvResult::Ok(QueueChange::Added { id, after })
+    })();
+    match added {
+        Ok(change) => player.queue_changed(change),
+        Err(e) => tracing::error!(error = ?e, "failed to get the id of the queued file"),
+    }
+    Ok(())
+}
+
 async fn event_stream(daemon: SharedPlayersDaemon) -> impl Stream<Item = PlayerEvent> {
     let (current_default, events) = {
         let daemon = daemon.lock().await;
@@ -828,9 +1660,18 @@ async fn event_stream(daemon: SharedPlayersDaemon) -> impl Stream<Item = PlayerE
         (current_default, events, daemon),
         move |(mut current_default, mut events, daemon)| async move {
             let player_events = async {
-                match &mut events {
-                    Some(e) => e.recv().await,
-                    None => std::future::pending().await,
+                let Some(events) = &mut events else {
+                    return std::future::pending().await;
+                };
+                loop {
+                    match events.recv().await {
+                        Ok(e) => return e,
+                        Err(RecvError::Lagged(missed)) => {
+                            tracing::warn!(missed, "missed player events")
+                        }
+                        // the player is gone, wait for the next one to become the current
+                        Err(RecvError::Closed) => return std::future::pending().await,
+                    }
                 }
             };
             let evs = tokio::select! {
@@ -838,7 +1679,7 @@ async fn event_stream(daemon: SharedPlayersDaemon) -> impl Stream<Item = PlayerE
                     events = daemon.lock().await.subscribe_to_current();
                     None
                 },
-                Ok(e) = player_events => {
+                e = player_events => {
                     Some(e)
                 }
             };
@@ -849,10 +1690,39 @@ async fn event_stream(daemon: SharedPlayersDaemon) -> impl Stream<Item = PlayerE
     .flatten()
 }
 
-#[tracing::instrument(name = "players-daemon")]
-pub async fn start_daemon_if_running_as_daemon() -> Result<(), super::Error> {
+/// Like [`event_stream`] but starting with the current state of the current player, for
+/// subscribers that show it. Tasks that count changes shouldn't use this.
+async fn event_stream_with_replay(daemon: SharedPlayersDaemon) -> impl Stream<Item = PlayerEvent> {
+    // subscribe first so that nothing is missed between the snapshot and the first change
+    let events = event_stream(daemon.clone()).await;
+    let current = daemon.lock().await.current_state();
+    stream::iter(current).chain(events)
+}
+
+/// Turns this process into the players daemon if it was started as one, set up with `settings`,
+/// calling `bootstrapped` with what the process that started it gave to
+/// [`set_daemon_bootstrap`](super::set_daemon_bootstrap) before it starts.
+#[tracing::instrument(name = "players-daemon", skip_all)]
+pub async fn start_daemon_if_running_as_daemon<B>(
+    settings: DaemonSettings,
+    bootstrapped: impl FnOnce(B),
+) -> Result<(), super::Error>
+where
+    B: DeserializeOwned + Default,
+{
     if let Some(builder) = super::connection::PLAYERS.build_daemon_process().await {
-        let players = Arc::new(Mutex::new(PlayersDaemon::default()));
+        bootstrapped(
+            builder
+                .bootstrap()
+                .map_err(io::Error::from)?
+                .unwrap_or_default(),
+        );
+        let builder = builder.with_metrics(|m| m.kind.name(), Result::is_err);
+        let metrics = builder.metrics();
+        let namespace = builder.socket_namespace().map(String::from);
+        // the daemon runs until the process exits
+        let settings: &'static DaemonSettings = Box::leak(Box::new(settings));
+        let players = Arc::new(Mutex::new(PlayersDaemon::new(settings)));
         let run_with_events = builder.run_with_events(
             {
                 let players = players.clone();
@@ -860,11 +1730,11 @@ pub async fn start_daemon_if_running_as_daemon() -> Result<(), super::Error> {
             },
             {
                 let players = players.clone();
-                move || event_stream(players)
+                move || event_stream_with_replay(players)
             },
         );
 
-        let background_tasks = tasks::register_global_tasks(players);
+        let background_tasks = tasks::register_global_tasks(players, metrics, namespace, settings);
 
         let (run_with_events, _) = join!(run_with_events, background_tasks);
         run_with_events?;
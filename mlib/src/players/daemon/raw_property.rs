@@ -0,0 +1,148 @@
+//! Reading and writing mpv properties the protocol has no message for, and the options players
+//! can be created with.
+
+use crate::players::{error::MpvError, event::OwnedMpvNode};
+use serde_json::{Number, Value};
+
+/// The properties that can be written. Others are either kept in sync by the daemon, like
+/// `volume` or `af`, or can make mpv run arbitrary things, like `script-opts`.
+const WRITABLE: &[&str] = &[
+    "ab-loop-a",
+    "ab-loop-b",
+    "aid",
+    "audio-delay",
+    "audio-pitch-correction",
+    "chapter",
+    "fullscreen",
+    "loop-file",
+    "mute",
+    "ontop",
+    "pause",
+    "pitch",
+    "replaygain",
+    "sid",
+    "speed",
+    "sub-delay",
+    "sub-scale",
+    "sub-visibility",
+    "time-pos",
+    "vid",
+    "video-zoom",
+];
+
+/// Where `m` keeps settings of its own that are tied to a player, these can always be written.
+const OWN_USER_DATA: &str = "user-data/m/";
+
+pub(super) fn check_writable(name: &str) -> Result<(), MpvError> {
+    if WRITABLE.contains(&name) || name.starts_with(OWN_USER_DATA) {
+        Ok(())
+    } else {
+        Err(MpvError::FailedToExecute {
+            reason: format!("{name} can't be set, only {} can", WRITABLE.join(", ")),
+        })
+    }
+}
+
+/// The options that can be given to a new player on top of the writable properties. Others are
+/// either set up by the daemon, like `input-ipc-server`, or can make mpv run arbitrary things,
+/// like `script` or `input-conf`.
+const STARTUP_OPTIONS: &[&str] = &[
+    "alang",
+    "audio-device",
+    "autofit",
+    "autofit-larger",
+    "border",
+    "cache",
+    "demuxer-max-bytes",
+    "force-window",
+    "geometry",
+    "hwdec",
+    "loop-playlist",
+    "osc",
+    "slang",
+    "sub-auto",
+    "volume",
+    "ytdl-format",
+];
+
+pub(super) fn check_startup_option(name: &str) -> Result<(), MpvError> {
+    if STARTUP_OPTIONS.contains(&name) || WRITABLE.contains(&name) {
+        Ok(())
+    } else {
+        Err(MpvError::FailedToExecute {
+            reason: format!(
+                "{name} can't be given to a new player, only {} and the properties that can be set can",
+                STARTUP_OPTIONS.join(", ")
+            ),
+        })
+    }
+}
+
+/// The options that can be given to a single file when it's queued.
+const PER_FILE_OPTIONS: &[&str] = &["start", "end", "force-media-title", "ytdl-format"];
+
+pub(super) fn check_per_file_option(name: &str) -> Result<(), MpvError> {
+    if PER_FILE_OPTIONS.contains(&name) {
+        Ok(())
+    } else {
+        Err(MpvError::FailedToExecute {
+            reason: format!(
+                "{name} can't be given to a queued file, only {} can",
+                PER_FILE_OPTIONS.join(", ")
+            ),
+        })
+    }
+}
+
+pub(super) fn to_json(node: OwnedMpvNode) -> Result<Value, MpvError> {
+    Ok(match node {
+        OwnedMpvNode::String(s) | OwnedMpvNode::OsdString(s) => Value::String(s),
+        OwnedMpvNode::Flag(b) => Value::Bool(b),
+        OwnedMpvNode::Int64(i) => Value::from(i),
+        // json has no NaN or infinity
+        OwnedMpvNode::Double(d) => Number::from_f64(d).map_or(Value::Null, Value::Number),
+        OwnedMpvNode::Array(a) => a.into_iter().map(to_json).collect::<Result<_, _>>()?,
+        OwnedMpvNode::Map(m) => Value::Object(
+            m.into_iter()
+                .map(|(k, v)| Ok((k, to_json(v)?)))
+                .collect::<Result<_, MpvError>>()?,
+        ),
+        OwnedMpvNode::None => Value::Null,
+        OwnedMpvNode::Invalid(e) => return Err(e),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nodes_become_json() {
+        let node = OwnedMpvNode::Map(
+            [
+                ("speed".into(), OwnedMpvNode::Double(1.25)),
+                ("delay".into(), OwnedMpvNode::Double(f64::NAN)),
+                (
+                    "tracks".into(),
+                    OwnedMpvNode::Array(vec![OwnedMpvNode::Int64(1), OwnedMpvNode::None]),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(
+            to_json(node).unwrap(),
+            serde_json::json!({ "speed": 1.25, "delay": null, "tracks": [1, null] })
+        );
+        assert!(check_writable("speed").is_ok());
+        assert!(check_writable("script-opts").is_err());
+        assert!(check_writable("user-data/m/volume-step").is_ok());
+        assert!(check_writable("user-data/mpv/ytdl/path").is_err());
+        assert!(check_startup_option("ytdl-format").is_ok());
+        assert!(check_startup_option("speed").is_ok());
+        assert!(check_startup_option("input-ipc-server").is_err());
+        assert!(check_per_file_option("start").is_ok());
+        assert!(check_per_file_option("ytdl-raw-options").is_err());
+        assert!(check_per_file_option("start=0,stream-record").is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{
+    players::{
+        daemon::{player::MpvExt, Player},
+        error::MpvResult,
+        event::OwnedLibMpvEvent,
+        EndOfQueuePolicy,
+    },
+    Item,
+};
+use std::sync::Weak;
+
+/// The youtube mix of a song, which youtube keeps filling with similar songs.
+fn mix_of(item: &Item) -> Option<String> {
+    let Item::Link(link) = item else {
+        return None;
+    };
+    let id = link.as_video()?.id().as_str();
+    Some(format!("https://www.youtube.com/watch?v={id}&list=RD{id}"))
+}
+
+fn last_song(player: &Player) -> MpvResult<Option<String>> {
+    let count = player.simple_prop::<i64>("playlist-count")?;
+    if count <= 0 {
+        return Ok(None);
+    }
+    Ok(Some(player.simple_prop::<String>(&format!(
+        "playlist/{}/filename",
+        count - 1
+    ))?))
+}
+
+fn apply(player: &Player, index: usize, policy: &EndOfQueuePolicy) -> MpvResult<()> {
+    match policy {
+        EndOfQueuePolicy::Stop => {}
+        EndOfQueuePolicy::Loop => player.command("playlist-play-index", &["0"])?,
+        EndOfQueuePolicy::Quit => player.command("quit", &[])?,
+        EndOfQueuePolicy::Radio => {
+            match last_song(player)?
+                .map(Item::from_mpv_filename)
+                .as_ref()
+                .and_then(mix_of)
+            {
+                Some(mix) => player.command("loadfile", &[&mix, "append-play"])?,
+                None => tracing::warn!("the last song isn't a youtube video, can't start a radio"),
+            }
+        }
+        EndOfQueuePolicy::Hook(cmd) => {
+            let mut hook = tokio::process::Command::new("sh");
+            hook.args(["-c", cmd]).env("M_PLAYER", index.to_string());
+            if let Some(last) = last_song(player)? {
+                hook.env("M_LAST", last);
+            }
+            let cmd = cmd.clone();
+            tokio::spawn(async move {
+                match hook.status().await {
+                    Ok(status) if !status.success() => {
+                        tracing::error!(%cmd, %status, "end of queue hook failed")
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::error!(%cmd, error = ?e, "failed to run end of queue hook"),
+                }
+            });
+        }
+    }
+    Ok(())
+}
+
+#[tracing::instrument("end of queue", skip_all)]
+pub async fn watch(player: Weak<Player>) {
+    let Some(mut events) = player.upgrade().map(|p| p.subscribe()) else {
+        return;
+    };
+    tracing::info!("starting");
+    // mpv reports -1 before the first song starts too
+    let mut playing = false;
+    while let Ok(e) = events.recv().await {
+        let OwnedLibMpvEvent::PropertyChange { name, change, .. } = e.event else {
+            continue;
+        };
+        if name != "playlist-pos" {
+            continue;
+        }
+        let Ok(pos) = change.into_int() else {
+            continue;
+        };
+        if pos >= 0 {
+            playing = true;
+            continue;
+        }
+        if !std::mem::take(&mut playing) {
+            continue;
+        }
+        let Some(player) = player.upgrade() else {
+            return;
+        };
+        let policy = player.end_of_queue().lock().clone();
+        tracing::debug!(%policy, "queue ended");
+        if let Err(e) = apply(&player, e.player_index, &policy) {
+            tracing::error!(error = ?e, %policy, "failed to apply end of queue policy");
+        }
+    }
+    tracing::info!("terminating");
+}
@@ -0,0 +1,166 @@
+use crate::players::{
+    daemon::Player,
+    event::{OwnedLibMpvEvent, OwnedMpvNode},
+};
+use libmpv::Mpv;
+use std::{sync::Weak, time::Duration};
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{interval, Instant},
+};
+
+/// Shortest fade allowed.
+pub const MIN: Duration = Duration::from_millis(200);
+/// Longest fade allowed.
+pub const MAX: Duration = Duration::from_millis(1000);
+/// Seeks at least this long fade back in.
+pub const LARGE_SEEK: f64 = 10.;
+
+const STEP: Duration = Duration::from_millis(20);
+
+/// The volume `elapsed` into a ramp from `from` to `to` that takes `over`.
+pub fn volume_at(from: f64, to: f64, elapsed: Duration, over: Duration) -> f64 {
+    if over.is_zero() {
+        return to;
+    }
+    let progress = (elapsed.as_secs_f64() / over.as_secs_f64()).min(1.);
+    from + (to - from) * progress
+}
+
+/// Gradually changes the volume of a player from `from` to `to`.
+pub async fn ramp(player: Weak<Mpv>, from: f64, to: f64, over: Duration) {
+    let start = Instant::now();
+    let mut ticks = interval(STEP);
+    loop {
+        ticks.tick().await;
+        let Some(player) = player.upgrade() else {
+            return;
+        };
+        let elapsed = start.elapsed();
+        if let Err(e) = player.set_property("volume", volume_at(from, to, elapsed, over)) {
+            tracing::error!(error = ?e, "failed to set volume");
+            return;
+        }
+        if elapsed >= over {
+            return;
+        }
+    }
+}
+
+struct Running {
+    target: f64,
+    task: JoinHandle<()>,
+}
+
+/// Fades the volume of a player in from silence.
+pub struct Fader {
+    player: Weak<Mpv>,
+    length: watch::Receiver<Option<Duration>>,
+    running: parking_lot::Mutex<Option<Running>>,
+}
+
+impl Fader {
+    pub fn new(player: Weak<Mpv>, length: watch::Receiver<Option<Duration>>) -> Self {
+        Self {
+            player,
+            length,
+            running: parking_lot::Mutex::new(None),
+        }
+    }
+
+    /// Starts fading in, unless fading is disabled or a fade is already running.
+    pub fn fade_in(&self) {
+        let Some(length) = *self.length.borrow() else {
+            return;
+        };
+        let mut running = self.running.lock();
+        if running.as_ref().is_some_and(|r| !r.task.is_finished()) {
+            return;
+        }
+        let Some(player) = self.player.upgrade() else {
+            return;
+        };
+        let target = match player.get_property::<f64>("volume") {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::error!(error = ?e, "failed to get volume");
+                return;
+            }
+        };
+        if let Err(e) = player.set_property("volume", 0.) {
+            tracing::error!(error = ?e, "failed to set volume");
+            return;
+        }
+        *running = Some(Running {
+            target,
+            task: tokio::spawn(ramp(self.player.clone(), 0., target, length)),
+        });
+    }
+
+    /// The volume the running fade is heading to.
+    pub fn target(&self) -> Option<f64> {
+        self.running
+            .lock()
+            .as_ref()
+            .filter(|r| !r.task.is_finished())
+            .map(|r| r.target)
+    }
+
+    /// Stops the running fade, if any, jumping straight to the volume it was heading to.
+    pub fn cancel(&self) {
+        let Some(running) = self.running.lock().take() else {
+            return;
+        };
+        if running.task.is_finished() {
+            return;
+        }
+        running.task.abort();
+        if let Some(player) = self.player.upgrade() {
+            if let Err(e) = player.set_property("volume", running.target) {
+                tracing::error!(error = ?e, "failed to restore volume");
+            }
+        }
+    }
+}
+
+/// Fades in whenever the player is unpaused, be it through m, mpris or mpv itself.
+#[tracing::instrument("fade on unpause", skip_all)]
+pub async fn fade_on_unpause(player: Weak<Player>) {
+    let Some(mut events) = player.upgrade().map(|p| p.subscribe()) else {
+        return;
+    };
+    tracing::info!("starting");
+    while let Ok(e) = events.recv().await {
+        let OwnedLibMpvEvent::PropertyChange {
+            name,
+            change: OwnedMpvNode::Flag(false),
+            ..
+        } = e.event
+        else {
+            continue;
+        };
+        if name != "pause" {
+            continue;
+        }
+        let Some(player) = player.upgrade() else {
+            return;
+        };
+        player.fader().fade_in();
+    }
+    tracing::info!("terminating");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ramp_is_linear_and_clamped() {
+        let over = Duration::from_millis(400);
+        assert_eq!(volume_at(0., 80., Duration::ZERO, over), 0.);
+        assert_eq!(volume_at(0., 80., Duration::from_millis(100), over), 20.);
+        assert_eq!(volume_at(0., 80., Duration::from_millis(900), over), 80.);
+        assert_eq!(volume_at(0., 80., Duration::ZERO, Duration::ZERO), 80.);
+    }
+}
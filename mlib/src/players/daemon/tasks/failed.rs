@@ -0,0 +1,155 @@
+use crate::{
+    players::{
+        daemon::{player::MpvExt, Player},
+        event::OwnedLibMpvEvent,
+        FailedItem,
+    },
+    ytdl::probe,
+    Item,
+};
+use std::{collections::VecDeque, sync::Weak};
+
+const CAPACITY: usize = 50;
+
+/// mpv's `MPV_END_FILE_REASON_ERROR`.
+const END_FILE_REASON_ERROR: u32 = 4;
+
+/// The items a player failed to play, oldest first.
+#[derive(Debug, Default)]
+pub struct Failed {
+    items: VecDeque<FailedItem>,
+}
+
+impl Failed {
+    /// Records a failure, replacing an earlier failure of the same item.
+    pub fn push(&mut self, failed: FailedItem) {
+        self.items.retain(|f| f.item != failed.item);
+        self.items.push_back(failed);
+        if self.items.len() > CAPACITY {
+            self.items.pop_front();
+        }
+    }
+
+    pub fn list(&self) -> Vec<FailedItem> {
+        self.items.iter().cloned().collect()
+    }
+
+    /// Forgets the failure of `item`, if it failed.
+    pub fn forget(&mut self, item: &Item) {
+        self.items.retain(|f| f.item != *item);
+    }
+
+    /// Removes and returns the failure at `at`, or all of them if `None`.
+    pub fn take(&mut self, at: Option<usize>) -> Vec<FailedItem> {
+        match at {
+            Some(at) => self.items.remove(at).into_iter().collect(),
+            None => self.items.drain(..).collect(),
+        }
+    }
+}
+
+/// Best effort explanation of why `item` couldn't be played.
+async fn reason_for(item: &Item) -> String {
+    match item {
+        Item::File(path) if !tokio::fs::try_exists(path).await.unwrap_or(true) => {
+            "file not found".into()
+        }
+        Item::Link(link) => match link.as_video() {
+            Some(video) => match probe::unavailable([video.id()]).await {
+                Ok(dead) => match dead.into_iter().next() {
+                    Some(u) => u.reason,
+                    None => "the video is available but mpv failed to play it".into(),
+                },
+                Err(e) => {
+                    tracing::error!(error = ?e, "failed to ask yt-dlp why the video failed");
+                    "failed to load".into()
+                }
+            },
+            None => "failed to load".into(),
+        },
+        _ => "failed to load".into(),
+    }
+}
+
+#[tracing::instrument("failed items recorder", skip_all)]
+pub async fn record(player: Weak<Player>) {
+    let Some(mut events) = player.upgrade().map(|p| p.subscribe()) else {
+        return;
+    };
+    tracing::info!("starting");
+    // mpv has already moved on by the time it reports the error, so remember what it started
+    let mut current = None;
+    while let Ok(e) = events.recv().await {
+        match e.event {
+            OwnedLibMpvEvent::PropertyChange { name, change, .. } if name == "playlist-pos" => {
+                let Ok(pos) = change.into_int() else {
+                    continue;
+                };
+                if pos < 0 {
+                    continue;
+                }
+                let Some(player) = player.upgrade() else {
+                    return;
+                };
+                current = player
+                    .simple_prop::<String>(&format!("playlist/{pos}/filename"))
+                    .map_err(|e| tracing::error!(error = ?e, "failed to get playlist entry"))
+                    .ok();
+            }
+            OwnedLibMpvEvent::EndFile(END_FILE_REASON_ERROR) => {
+                let Some(filename) = current.take() else {
+                    continue;
+                };
+                let item = Item::from_mpv_filename(filename);
+                let reason = reason_for(&item).await;
+                tracing::warn!(%item, %reason, "failed to play");
+                let Some(player) = player.upgrade() else {
+                    return;
+                };
+                let failed_at = player.clock().now();
+                player.failed().lock().push(FailedItem {
+                    item,
+                    reason,
+                    failed_at,
+                });
+            }
+            _ => {}
+        }
+    }
+    tracing::info!("terminating");
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, time::SystemTime};
+
+    use super::*;
+
+    fn failed(name: &str) -> FailedItem {
+        FailedItem {
+            item: Item::File(PathBuf::from(name)),
+            reason: "gone".into(),
+            failed_at: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn failing_again_moves_to_the_back() {
+        let mut list = Failed::default();
+        for name in ["a", "b", "a"] {
+            list.push(failed(name));
+        }
+        let names = |list: Vec<FailedItem>| {
+            list.into_iter()
+                .map(|f| f.item.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(list.list()), ["b", "a"]);
+        assert_eq!(names(list.take(Some(1))), ["a"]);
+        assert!(list.take(Some(5)).is_empty());
+        list.push(failed("c"));
+        list.forget(&failed("b").item);
+        assert_eq!(names(list.take(None)), ["c"]);
+        assert!(list.list().is_empty());
+    }
+}
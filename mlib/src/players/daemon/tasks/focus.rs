@@ -0,0 +1,149 @@
+use crate::{
+    players::{
+        daemon::{player::MpvExt, Player},
+        event::OwnedLibMpvEvent,
+        Focus, SkippedItem,
+    },
+    playlist::{Category, Playlist},
+    Item, Link,
+};
+use std::{sync::Weak, time::Duration};
+
+/// The focus the player is in and what it has skipped so far.
+#[derive(Debug)]
+pub struct FocusMode {
+    pub focus: Focus,
+    pub skipped: Vec<SkippedItem>,
+}
+
+impl FocusMode {
+    pub fn new(focus: Focus) -> Self {
+        Self {
+            focus,
+            skipped: vec![],
+        }
+    }
+}
+
+fn minutes(d: Duration) -> String {
+    format!("{}:{:02}", d.as_secs() / 60, d.as_secs() % 60)
+}
+
+/// Why a song doesn't fit in `focus`, if it doesn't. `categories` is `None` if the song isn't in
+/// the playlist.
+fn reason_to_skip(
+    focus: &Focus,
+    duration: Option<Duration>,
+    categories: Option<&[Category]>,
+) -> Option<String> {
+    if let (Some(max), Some(duration)) = (focus.max_duration, duration) {
+        if duration > max {
+            return Some(format!(
+                "lasts {}, longer than {}",
+                minutes(duration),
+                minutes(max)
+            ));
+        }
+    }
+    if focus.categories.is_empty() {
+        return None;
+    }
+    let Some(categories) = categories else {
+        return Some("not in the playlist".into());
+    };
+    let fits = focus.categories.iter().any(|wanted| {
+        let wanted = Category::new(wanted);
+        categories.iter().any(|c| c.is_within(&wanted))
+    });
+    (!fits).then(|| format!("not in {}", focus.categories.join(", ")))
+}
+
+async fn categories_of(item: &Item) -> Option<Vec<Category>> {
+    let Item::Link(Link::Video(link)) = item else {
+        return None;
+    };
+    let playlist = match Playlist::load().await {
+        Ok(p) => p,
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to load the playlist to check categories");
+            return None;
+        }
+    };
+    playlist
+        .find_by_link(link)
+        .map(|s| s.categories.iter().cloned().collect())
+}
+
+#[tracing::instrument("focus", skip_all)]
+pub async fn skip_unfocused(player: Weak<Player>) {
+    let Some(mut events) = player.upgrade().map(|p| p.subscribe()) else {
+        return;
+    };
+    tracing::info!("starting");
+    while let Ok(e) = events.recv().await {
+        let OwnedLibMpvEvent::FileLoaded = e.event else {
+            continue;
+        };
+        let Some(p) = player.upgrade() else {
+            return;
+        };
+        let Some(focus) = p.focus().lock().as_ref().map(|f| f.focus.clone()) else {
+            continue;
+        };
+        let Ok(path) = p.simple_prop::<String>("path") else {
+            continue;
+        };
+        let item = Item::from(path);
+        let duration = p
+            .simple_prop::<f64>("duration")
+            .ok()
+            .and_then(|d| Duration::try_from_secs_f64(d).ok());
+        let categories = if focus.categories.is_empty() {
+            None
+        } else {
+            categories_of(&item).await
+        };
+        let Some(reason) = reason_to_skip(&focus, duration, categories.as_deref()) else {
+            continue;
+        };
+        tracing::info!(%item, reason, "skipping");
+        let skipped_at = p.clock().now();
+        if let Some(mode) = p.focus().lock().as_mut() {
+            mode.skipped.push(SkippedItem {
+                item,
+                reason,
+                skipped_at,
+            });
+        }
+        if let Err(e) = p.command("playlist-next", &["force"]) {
+            tracing::error!(error = ?e, "failed to skip");
+        }
+    }
+    tracing::info!("terminating");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn skips_long_and_uncategorized_songs() {
+        let focus = Focus {
+            max_duration: Some(Duration::from_secs(600)),
+            categories: vec!["chill".into()],
+        };
+        let chill = [Category::new("chill/lofi")];
+        let minute = Some(Duration::from_secs(60));
+        assert_eq!(reason_to_skip(&focus, minute, Some(&chill)), None);
+        assert_eq!(
+            reason_to_skip(&focus, Some(Duration::from_secs(3725)), Some(&chill)).unwrap(),
+            "lasts 62:05, longer than 10:00"
+        );
+        assert_eq!(
+            reason_to_skip(&focus, minute, Some(&[Category::new("rock")])).unwrap(),
+            "not in chill"
+        );
+        assert!(reason_to_skip(&focus, minute, None).is_some());
+        assert_eq!(reason_to_skip(&Focus::default(), None, None), None);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::players::{
+    daemon::{player::MpvExt, Player},
+    event::OwnedLibMpvEvent,
+    Direction,
+};
+use std::{collections::VecDeque, sync::Weak, time::SystemTime};
+
+const CAPACITY: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    /// The mpv playlist entry id, which unlike the position survives shuffles and moves.
+    pub id: usize,
+    pub played_at: SystemTime,
+}
+
+/// The songs a player actually played, in the order they were played in.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: VecDeque<Entry>,
+    cursor: usize,
+}
+
+impl History {
+    /// Records that the entry `id` started playing. Going somewhere new after going back
+    /// forgets the entries ahead of the cursor, like in a browser.
+    pub fn played(&mut self, id: usize, at: SystemTime) {
+        if self.entries.get(self.cursor).is_some_and(|e| e.id == id) {
+            return;
+        }
+        self.entries.truncate(self.cursor + 1);
+        self.entries.push_back(Entry { id, played_at: at });
+        if self.entries.len() > CAPACITY {
+            self.entries.pop_front();
+        }
+        self.cursor = self.entries.len() - 1;
+    }
+
+    /// Moves the cursor one entry in `direction`, skipping (and forgetting) entries for which
+    /// `position_of` returns `None`, i.e. songs that have since been removed from the queue.
+    /// Returns the entry and its position in the queue.
+    pub fn step(
+        &mut self,
+        direction: Direction,
+        position_of: impl Fn(usize) -> Option<usize>,
+    ) -> Option<(Entry, usize)> {
+        loop {
+            let target = match direction {
+                Direction::Prev => self.cursor.checked_sub(1)?,
+                Direction::Next if self.cursor + 1 < self.entries.len() => self.cursor + 1,
+                Direction::Next => return None,
+            };
+            let entry = self.entries[target];
+            match position_of(entry.id) {
+                Some(pos) => {
+                    self.cursor = target;
+                    return Some((entry, pos));
+                }
+                None => {
+                    self.entries.remove(target);
+                    if target < self.cursor {
+                        self.cursor -= 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[tracing::instrument("history recorder", skip_all)]
+pub async fn record(player: Weak<Player>) {
+    let Some(mut events) = player.upgrade().map(|p| p.subscribe()) else {
+        return;
+    };
+    tracing::info!("starting");
+    while let Ok(e) = events.recv().await {
+        let OwnedLibMpvEvent::PropertyChange { name, change, .. } = e.event else {
+            continue;
+        };
+        if name != "playlist-pos" {
+            continue;
+        }
+        let Ok(pos) = change.into_int() else {
+            continue;
+        };
+        if pos < 0 {
+            continue;
+        }
+        let Some(player) = player.upgrade() else {
+            return;
+        };
+        match player.simple_prop::<i64>(&format!("playlist/{pos}/id")) {
+            Ok(id) => player
+                .history()
+                .lock()
+                .played(id as usize, player.clock().now()),
+            Err(e) => tracing::error!(error = ?e, "failed to get playlist entry id"),
+        }
+    }
+    tracing::info!("terminating");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn back_and_forward_skip_removed_songs() {
+        let mut history = History::default();
+        for id in [1, 2, 3, 4] {
+            history.played(id, SystemTime::UNIX_EPOCH);
+        }
+        let queue = [1, 2, 4];
+        let position_of = |id| queue.iter().position(|i| *i == id);
+
+        assert_eq!(history.step(Direction::Prev, position_of).unwrap().1, 1);
+        assert_eq!(history.step(Direction::Prev, position_of).unwrap().1, 0);
+        assert_eq!(history.step(Direction::Prev, position_of), None);
+        assert_eq!(history.step(Direction::Next, position_of).unwrap().1, 1);
+        assert_eq!(history.step(Direction::Next, position_of).unwrap().1, 2);
+        assert_eq!(history.step(Direction::Next, position_of), None);
+    }
+
+    #[test]
+    fn playing_something_new_drops_the_forward_entries() {
+        let mut history = History::default();
+        for id in [1, 2, 3] {
+            history.played(id, SystemTime::UNIX_EPOCH);
+        }
+        let position_of = Some;
+        history.step(Direction::Prev, position_of);
+        history.step(Direction::Prev, position_of);
+        history.played(7, SystemTime::UNIX_EPOCH);
+        assert_eq!(history.step(Direction::Next, position_of), None);
+        assert_eq!(history.step(Direction::Prev, position_of).unwrap().0.id, 1);
+    }
+}
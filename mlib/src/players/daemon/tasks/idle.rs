@@ -0,0 +1,159 @@
+//! Keeps track of how long players haven't been playing anything, and quits the ones left
+//! paused, or done with their queue, for too long so their mpv instances don't linger forever.
+
+use crate::players::{
+    daemon::{player::MpvExt, Player},
+    event::OwnedLibMpvEvent,
+};
+use std::{sync::Weak, time::Duration};
+use tokio::{sync::broadcast::error::RecvError, time::Instant};
+
+/// Since when a player has been paused or out of songs.
+#[derive(Debug, Default)]
+pub struct Idle {
+    paused: bool,
+    ended: bool,
+    since: Option<Instant>,
+}
+
+impl Idle {
+    pub fn since(&self) -> Option<Instant> {
+        self.since
+    }
+
+    fn set_paused(&mut self, paused: bool, now: Instant) {
+        self.paused = paused;
+        self.update(now);
+    }
+
+    fn set_ended(&mut self, ended: bool, now: Instant) {
+        self.ended = ended;
+        self.update(now);
+    }
+
+    fn update(&mut self, now: Instant) {
+        if !self.paused && !self.ended {
+            self.since = None;
+        } else if self.since.is_none() {
+            self.since = Some(now);
+        }
+    }
+}
+
+fn quit(player: &Player, idle_for: Duration) {
+    tracing::info!(?idle_for, "quitting idle player");
+    player.notify(OwnedLibMpvEvent::IdleQuit { idle_for });
+    if let Err(e) = player.command("quit", &[]) {
+        tracing::error!(error = ?e, "failed to quit idle player");
+    }
+}
+
+/// Asks mpv whether `player` is paused or out of songs, returning whether it's playing one.
+fn resync(player: &Player, now: Instant) -> bool {
+    let playing = player
+        .simple_prop::<i64>("playlist-pos")
+        .is_ok_and(|pos| pos >= 0);
+    let paused = player.simple_prop::<bool>("pause").unwrap_or(false);
+    let mut idle = player.idle().lock();
+    idle.set_paused(paused, now);
+    idle.set_ended(!playing, now);
+    playing
+}
+
+/// Follows whether the player is idle, quitting it once it has been for `quit_after`.
+#[tracing::instrument("idle", skip_all)]
+pub async fn watch(player: Weak<Player>, quit_after: Option<Duration>) {
+    let Some((mut events, clock)) = player.upgrade().map(|p| (p.subscribe(), p.clock().clone()))
+    else {
+        return;
+    };
+    tracing::info!("starting");
+    // a new player is idle until it plays something, which it may already be doing by now
+    let mut playing = match player.upgrade() {
+        Some(p) => resync(&p, clock.instant()),
+        None => return,
+    };
+    loop {
+        let Some(until_quit) = player.upgrade().map(|p| {
+            let since = p.idle().lock().since()?;
+            let after = quit_after?;
+            Some(after.saturating_sub(clock.instant().saturating_duration_since(since)))
+        }) else {
+            return;
+        };
+        let event = match until_quit {
+            Some(until_quit) => tokio::select! {
+                event = events.recv() => event,
+                _ = clock.sleep(until_quit) => {
+                    if let Some(player) = player.upgrade() {
+                        quit(&player, quit_after.unwrap_or_default());
+                    }
+                    return;
+                }
+            },
+            None => events.recv().await,
+        };
+        let event = match event {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                // the missed events may have been the player pausing or running out of songs
+                tracing::warn!(missed, "missed player events");
+                match player.upgrade() {
+                    Some(p) => playing = resync(&p, clock.instant()),
+                    None => return,
+                }
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        let OwnedLibMpvEvent::PropertyChange { name, change, .. } = event.event else {
+            continue;
+        };
+        let Some(player) = player.upgrade() else {
+            return;
+        };
+        let now = clock.instant();
+        match name.as_str() {
+            "pause" => {
+                if let Ok(paused) = change.into_bool() {
+                    player.idle().lock().set_paused(paused, now);
+                }
+            }
+            "playlist-pos" => match change.into_int() {
+                Ok(pos) if pos >= 0 => {
+                    playing = true;
+                    player.idle().lock().set_ended(false, now);
+                }
+                Ok(_) if std::mem::take(&mut playing) => {
+                    player.idle().lock().set_ended(true, now);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    tracing::info!("terminating");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn idle_while_paused_or_ended_and_since_the_first_of_them() {
+        let start = Instant::now();
+        let at = |s| start + Duration::from_secs(s);
+        let mut idle = Idle::default();
+        assert_eq!(idle.since(), None);
+        idle.set_paused(true, at(1));
+        assert_eq!(idle.since(), Some(at(1)));
+        idle.set_ended(true, at(2));
+        assert_eq!(idle.since(), Some(at(1)));
+        idle.set_paused(false, at(3));
+        assert_eq!(idle.since(), Some(at(1)));
+        idle.set_ended(false, at(4));
+        assert_eq!(idle.since(), None);
+        idle.set_ended(true, at(5));
+        assert_eq!(idle.since(), Some(at(5)));
+    }
+}
@@ -1,5 +1,57 @@
-use crate::players::{daemon::Player, event::OwnedLibMpvEvent};
-use std::sync::Weak;
+use crate::players::{
+    daemon::{clock::SharedClock, Player},
+    event::OwnedLibMpvEvent,
+};
+use std::{
+    sync::Weak,
+    time::{Duration, SystemTime},
+};
+
+/// How long queueing keeps going after the last queued song, after that it's as if nothing had
+/// been queued.
+const EXPIRY: Duration = Duration::from_secs(60 * 60 * 3);
+
+/// Where the last song was queued.
+#[derive(Debug)]
+pub struct LastQueue {
+    last: Option<(usize, SystemTime)>,
+    clock: SharedClock,
+}
+
+impl LastQueue {
+    pub fn new(clock: SharedClock) -> Self {
+        Self { last: None, clock }
+    }
+
+    pub fn get(&mut self) -> Option<usize> {
+        let (index, set) = self.last?;
+        let age = self.clock.now().duration_since(set).unwrap_or_default();
+        if age > EXPIRY {
+            self.last = None;
+            None
+        } else {
+            Some(index)
+        }
+    }
+
+    pub fn set(&mut self, index: usize) {
+        self.last = Some((index, self.clock.now()));
+    }
+
+    pub fn clear(&mut self) {
+        self.last = None;
+    }
+
+    /// Follows the last queued song when something is inserted at or before it, without counting
+    /// as queueing.
+    pub fn shift(&mut self, inserted_at: usize) {
+        if let Some((index, _)) = &mut self.last {
+            if inserted_at <= *index {
+                *index += 1;
+            }
+        }
+    }
+}
 
 #[tracing::instrument("queue wraparound reseter")]
 pub async fn reset(player: Weak<Player>) {
@@ -28,3 +80,43 @@ pub async fn reset(player: Weak<Player>) {
     }
     tracing::info!("terminating");
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::players::daemon::clock::MockClock;
+
+    #[test]
+    fn expires_after_three_hours() {
+        let clock = MockClock::new();
+        let mut last = LastQueue::new(clock.clone());
+        last.set(4);
+        clock.advance(EXPIRY);
+        assert_eq!(last.get(), Some(4));
+        last.set(5);
+        clock.advance(EXPIRY);
+        assert_eq!(last.get(), Some(5));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(last.get(), None);
+        last.set(1);
+        last.clear();
+        assert_eq!(last.get(), None);
+    }
+
+    #[test]
+    fn follows_insertions_before_it() {
+        let clock = MockClock::new();
+        let mut last = LastQueue::new(clock.clone());
+        last.shift(0);
+        assert_eq!(last.get(), None);
+        last.set(4);
+        last.shift(5);
+        assert_eq!(last.get(), Some(4));
+        last.shift(4);
+        assert_eq!(last.get(), Some(5));
+        last.shift(1);
+        assert_eq!(last.get(), Some(6));
+        clock.advance(EXPIRY + Duration::from_secs(1));
+        assert_eq!(last.get(), None);
+    }
+}
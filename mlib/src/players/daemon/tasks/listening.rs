@@ -0,0 +1,45 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Time spent listening to the current song, not counting pauses.
+pub struct Listening<T> {
+    song: Option<T>,
+    playing_since: Option<Instant>,
+    listened: Duration,
+}
+
+impl<T> Default for Listening<T> {
+    fn default() -> Self {
+        Self {
+            song: None,
+            playing_since: None,
+            listened: Duration::ZERO,
+        }
+    }
+}
+
+impl<T> Listening<T> {
+    pub fn pause(&mut self) {
+        if let Some(since) = self.playing_since.take() {
+            self.listened += since.elapsed();
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.playing_since.get_or_insert_with(Instant::now);
+    }
+
+    /// Switches to a new song, returning the previous one and how long it was listened to.
+    pub fn switch(&mut self, song: Option<T>, paused: bool) -> Option<(T, Duration)> {
+        self.pause();
+        let previous = std::mem::replace(
+            self,
+            Self {
+                song,
+                playing_since: (!paused).then(Instant::now),
+                listened: Duration::ZERO,
+            },
+        );
+        previous.song.map(|s| (s, previous.listened))
+    }
+}
@@ -1,25 +1,65 @@
 use super::SharedPlayersDaemon;
+use crate::players::DaemonSettings;
+use cli_daemon::Recorder;
 use futures_util::join;
 
-pub mod last_queue_monitor;
+pub(super) mod end_of_queue;
+pub(super) mod fade;
+pub(super) mod failed;
+pub(super) mod focus;
+pub(super) mod history;
+pub(super) mod idle;
+pub(super) mod last_queue_monitor;
+#[cfg(any(feature = "statistics", feature = "scrobble"))]
+mod listening;
 #[cfg(feature = "mpris")]
-pub mod mpris;
-pub mod preemptive_dl;
+pub(super) mod mpris;
+#[cfg(feature = "pause-others")]
+mod pause_others;
+pub(super) mod preemptive_dl;
+pub(super) mod radio;
+pub(super) mod resume;
+#[cfg(feature = "scrobble")]
+pub(super) mod scrobble;
+#[cfg(feature = "pulse")]
+mod sink_watch;
+pub(super) mod sleep_timer;
+#[cfg(feature = "sponsorblock")]
+pub(super) mod sponsorblock;
 #[cfg(feature = "statistics")]
-pub mod statistics;
+pub(super) mod statistics;
+pub(super) mod title_cache_gc;
+pub(super) mod virtual_chapters;
+#[cfg(feature = "web-remote")]
+pub(super) mod web_remote;
 
-pub async fn register_global_tasks(players: SharedPlayersDaemon) {
+/// Starts what the daemon does on its own. A daemon in a `namespace` is a second instance of m,
+/// which takes a bus name of its own and leaves the web remote to the main one.
+pub(super) async fn register_global_tasks(
+    players: SharedPlayersDaemon,
+    metrics: Recorder,
+    namespace: Option<String>,
+    settings: &'static DaemonSettings,
+) {
     #[cfg(feature = "mpris")]
     let signal_mpris_events = {
         let players = players.clone();
+        let bus_name = mpris::bus_name(namespace.as_deref());
         // do it like this so that the await on the "new_with_all" function can't block this
         // from calling "run_with_events".
         async move {
-            match mpris_server::Server::new_with_all("m", mpris::MprisPlayer::new(players.clone()))
-                .await
+            match mpris_server::Server::new_with_all(
+                &bus_name,
+                mpris::MprisPlayer::new(players.clone()),
+            )
+            .await
             {
                 Ok(server) => {
-                    mpris::signal_mpris_events(server, super::event_stream(players).await).await
+                    mpris::signal_mpris_events(
+                        server,
+                        super::event_stream_with_replay(players).await,
+                    )
+                    .await
                 }
                 Err(e) => {
                     tracing::error!(?e, "failed to initialize mpris server");
@@ -29,10 +69,68 @@ pub async fn register_global_tasks(players: SharedPlayersDaemon) {
     };
     #[cfg(not(feature = "mpris"))]
     let signal_mpris_events = std::future::ready(());
+    let title_cache_gc = title_cache_gc::prune_weekly(players.clone());
+    #[cfg(feature = "scrobble")]
+    let scrobble_task = scrobble::register_scrobbler(
+        players.clone(),
+        super::event_stream(players.clone()).await,
+        &settings.scrobble,
+    );
+    #[cfg(not(feature = "scrobble"))]
+    let scrobble_task = std::future::ready(());
+    #[cfg(feature = "web-remote")]
+    let web_remote = {
+        let players = players.clone();
+        async move {
+            match (&settings.web_remote, namespace) {
+                (Some(_), Some(namespace)) => {
+                    tracing::info!(namespace, "not serving the web remote from a namespace")
+                }
+                (Some(config), None) => web_remote::serve(players, config, metrics).await,
+                (None, _) => {}
+            }
+        }
+    };
+    #[cfg(not(feature = "web-remote"))]
+    let web_remote = {
+        drop((metrics, namespace, settings));
+        std::future::ready(())
+    };
+    #[cfg(feature = "pause-others")]
+    let pause_others = {
+        let players = players.clone();
+        async move {
+            if settings.pause_other_players {
+                let events = super::event_stream(players.clone()).await;
+                pause_others::pause_on_play(players, events).await
+            }
+        }
+    };
+    #[cfg(not(feature = "pause-others"))]
+    let pause_others = std::future::ready(());
+    #[cfg(feature = "pulse")]
+    let sink_watch = {
+        let players = players.clone();
+        async move {
+            if let Some(config) = &settings.pause_on_unplug {
+                sink_watch::watch(players, config).await
+            }
+        }
+    };
+    #[cfg(not(feature = "pulse"))]
+    let sink_watch = std::future::ready(());
     #[cfg(feature = "statistics")]
     let stats_task = statistics::register_statistics_listener(super::event_stream(players).await);
     #[cfg(not(feature = "statistics"))]
     let stats_task = std::future::ready(());
 
-    join!(signal_mpris_events, stats_task);
+    join!(
+        signal_mpris_events,
+        stats_task,
+        scrobble_task,
+        title_cache_gc,
+        web_remote,
+        sink_watch,
+        pause_others
+    );
 }
@@ -1,33 +1,104 @@
-use std::collections::{hash_map::Entry, HashMap};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::SystemTime,
+};
 
 use crate::players::daemon;
 use futures_util::{Stream, StreamExt, TryFutureExt, TryStreamExt};
 use mpris_server::{
     builder::MetadataBuilder, LoopStatus, Metadata, PlaybackRate, PlaybackStatus, PlayerInterface,
     Playlist, PlaylistId, PlaylistOrdering, PlaylistsInterface, RootInterface, Time, TrackId,
-    TrackListInterface, Uri, Volume,
+    TrackListInterface, TrackListSignal, Uri, Volume,
 };
 use zbus::fdo;
 
 use crate::{
     players::{event, PlayerIndex},
-    Item,
+    playlist::Playlist,
+    Item, VideoId,
 };
 
 use daemon::{event::PlayerEvent, Direction};
 
 pub struct MprisPlayer {
     pub(super) daemon: daemon::SharedPlayersDaemon,
+    genres: tokio::sync::Mutex<Genres>,
 }
 
 impl MprisPlayer {
     pub fn new(daemon: daemon::SharedPlayersDaemon) -> Self {
-        Self { daemon }
+        Self {
+            daemon,
+            genres: Default::default(),
+        }
+    }
+}
+
+/// What comes after `org.mpris.MediaPlayer2.` in the name of the daemon on the session bus,
+/// `m` or, for a daemon in a namespace, `m.` and the namespace with what isn't allowed in a bus
+/// name replaced.
+pub(super) fn bus_name(namespace: Option<&str>) -> String {
+    let Some(namespace) = namespace else {
+        return "m".into();
+    };
+    let namespace = namespace
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    match namespace.chars().next() {
+        Some('a'..='z' | 'A'..='Z' | '_' | '-') => format!("m.{namespace}"),
+        _ => format!("m._{namespace}"),
+    }
+}
+
+/// The categories of each song in the playlist, reloaded whenever the playlist changes.
+#[derive(Debug, Default)]
+struct Genres {
+    modified: Option<SystemTime>,
+    by_id: HashMap<String, Vec<String>>,
+}
+
+impl Genres {
+    async fn of(&mut self, id: &VideoId) -> Vec<String> {
+        if let Err(e) = self.refresh().await {
+            tracing::warn!(error = ?e, "failed to load the playlist's categories");
+        }
+        self.by_id.get(id.as_str()).cloned().unwrap_or_default()
+    }
+
+    async fn refresh(&mut self) -> Result<(), crate::Error> {
+        let modified = tokio::fs::metadata(Playlist::path()?).await?.modified()?;
+        if self.modified == Some(modified) {
+            return Ok(());
+        }
+        self.by_id = Playlist::load()
+            .await?
+            .songs
+            .into_iter()
+            .map(|song| {
+                let genres = song.categories.iter().map(|c| c.to_string()).collect();
+                (song.link.id().as_str().to_owned(), genres)
+            })
+            .collect();
+        self.modified = Some(modified);
+        Ok(())
     }
 }
 
 const C: PlayerIndex = PlayerIndex::CURRENT;
 
+fn to_mpris_loop_status(status: daemon::LoopStatus) -> LoopStatus {
+    match status {
+        daemon::LoopStatus::Inf | daemon::LoopStatus::Force | daemon::LoopStatus::N(_) => {
+            LoopStatus::Playlist
+        }
+        daemon::LoopStatus::No => LoopStatus::None,
+    }
+}
+
 fn to_fdo_err<E: ToString>(e: E) -> fdo::Error {
     fdo::Error::Failed(e.to_string())
 }
@@ -215,12 +286,7 @@ impl PlayerInterface for MprisPlayer {
         daemon
             .queue_is_looping(current)
             .map_err(to_fdo_err)
-            .map(|status| match status {
-                daemon::LoopStatus::Inf | daemon::LoopStatus::Force | daemon::LoopStatus::N(_) => {
-                    LoopStatus::Playlist
-                }
-                daemon::LoopStatus::No => LoopStatus::None,
-            })
+            .map(to_mpris_loop_status)
     }
 
     #[tracing::instrument(skip(self))]
@@ -272,11 +338,30 @@ impl PlayerInterface for MprisPlayer {
             return Err(fdo::Error::NoServer("no players".into()));
         };
         let pos = daemon.queue_position(C).await.map_err(to_fdo_err)?;
-        let id = daemon.queue(C).await.map_err(to_fdo_err)?[pos as usize].id;
+        let current = daemon
+            .queue(C)
+            .await
+            .map_err(to_fdo_err)?
+            .swap_remove(pos as usize);
         let title = daemon.media_title(C).await.map_err(to_fdo_err)?;
         let chapter_metadata = daemon.chapter_metadata(player).await.map_err(to_fdo_err)?;
+        let track = daemon.track_metadata(player).await.map_err(to_fdo_err)?;
 
-        let builder = MetadataBuilder::default().trackid(track_id_on_player(player, id));
+        let mut builder =
+            MetadataBuilder::default().trackid(track_id_on_player(player, current.id));
+        if let Some(uploader) = track.uploader {
+            builder = builder.artist([uploader]);
+        }
+        if let Some(duration) = track.duration {
+            builder = builder.length(Time::from_micros(duration.as_micros() as i64));
+        }
+        if let Some(date) = track.upload_date {
+            builder = builder.content_created(date);
+        }
+        let builder = match current.to_item().id() {
+            Some(id) => builder.genre(self.genres.lock().await.of(id).await),
+            None => builder,
+        };
 
         let builder = if let Some(m) = chapter_metadata {
             builder
@@ -284,6 +369,10 @@ impl PlayerInterface for MprisPlayer {
                 .title(m.title)
                 .track_number(m.index as _)
         } else {
+            let builder = match track.album {
+                Some(album) => builder.album(album),
+                None => builder,
+            };
             builder.title(title)
         };
 
@@ -297,13 +386,10 @@ impl PlayerInterface for MprisPlayer {
 
     #[tracing::instrument(skip(self))]
     async fn set_volume(&self, volume: Volume) -> zbus::Result<()> {
-        let daemon = self.daemon.lock().await;
-        daemon
-            .change_volume(C, i32::MIN)
+        self.daemon
+            .lock()
             .await
-            .map_err(to_zbus_err)?;
-        daemon
-            .change_volume(C, unsafe { (volume * 100.).to_int_unchecked() })
+            .set_volume(C, volume * 100.)
             .await
             .map_err(to_zbus_err)
     }
@@ -596,19 +682,68 @@ impl TrackListInterface for MprisPlayer {
     }
 }
 
+/// The signal for a change the daemon made to the queue of `player`.
+async fn track_list_signal(
+    mpris: &MprisPlayer,
+    player: PlayerIndex,
+    change: event::QueueChange,
+) -> fdo::Result<TrackListSignal> {
+    let signal = match change {
+        event::QueueChange::Added { id, after } => {
+            let queue = mpris
+                .daemon
+                .lock()
+                .await
+                .queue(player)
+                .await
+                .map_err(to_fdo_err)?;
+            let track_id = track_id_on_player(player, id);
+            let metadata = match queue.iter().find(|i| i.id == id) {
+                Some(item) => MetadataBuilder::default()
+                    .title(item.filename.clone())
+                    .trackid(track_id)
+                    .build(),
+                None => MetadataBuilder::default().trackid(track_id).build(),
+            };
+            TrackListSignal::TrackAdded {
+                metadata,
+                after_track: after
+                    .map_or(TrackId::NO_TRACK, |after| track_id_on_player(player, after)),
+            }
+        }
+        event::QueueChange::Removed { id } => TrackListSignal::TrackRemoved {
+            track_id: track_id_on_player(player, id),
+        },
+        event::QueueChange::Replaced => {
+            let queue = mpris
+                .daemon
+                .lock()
+                .await
+                .queue(player)
+                .await
+                .map_err(to_fdo_err)?;
+            let current_track = queue
+                .iter()
+                .find(|i| i.status.as_ref().is_some_and(|s| s.current))
+                .map_or(TrackId::NO_TRACK, |i| track_id_on_player(player, i.id));
+            TrackListSignal::TrackListReplaced {
+                tracks: mpris.tracks().await?,
+                current_track,
+            }
+        }
+    };
+    Ok(signal)
+}
+
 pub async fn signal_mpris_events<S>(server: mpris_server::Server<MprisPlayer>, events: S)
 where
     S: Stream<Item = PlayerEvent>,
 {
     // Missing Property signals
-    // - LoopStatus
     // - Rate (not implemented in general)
     use mpris_server::{Property, Signal};
 
-    // TODO TrackListSignal is not about files currently playing so I need to figure out how to get
-    // these fired
-    // TrackListProperty as well.
-    // use mpris_server::{TrackListSignal, TrackListProperty};
+    // TODO TrackListProperty is not signaled, clients have to call tracks themselves.
 
     // PlaylistsSignal does not make sense for this player.
     //
@@ -681,6 +816,30 @@ where
                     tracing::error!(?e, "failed to emit properties_changed playback status");
                 }
             }
+            event::OwnedLibMpvEvent::LoopChanged(status) => {
+                let property = Property::LoopStatus(to_mpris_loop_status(status));
+                if let Err(e) = server.properties_changed([property]).await {
+                    tracing::error!(?e, "failed to emit properties_changed loop status");
+                }
+            }
+            event::OwnedLibMpvEvent::Shuffled => {
+                if let Err(e) = server.properties_changed([Property::Shuffle(true)]).await {
+                    tracing::error!(?e, "failed to emit properties_changed shuffle");
+                }
+            }
+            event::OwnedLibMpvEvent::QueueChanged(change) => {
+                let player = PlayerIndex::of(event.player_index);
+                let signal = match track_list_signal(server.imp(), player, change).await {
+                    Ok(signal) => signal,
+                    Err(e) => {
+                        tracing::error!(?e, "failed to get the track list change");
+                        continue;
+                    }
+                };
+                if let Err(e) = server.track_list_emit(signal).await {
+                    tracing::error!(?e, "failed to emit track list signal");
+                }
+            }
             event::OwnedLibMpvEvent::StartFile
             | event::OwnedLibMpvEvent::EndFile(_)
             | event::OwnedLibMpvEvent::PlaybackRestart
@@ -694,7 +853,23 @@ where
             | event::OwnedLibMpvEvent::AudioReconfig
             | event::OwnedLibMpvEvent::Deprecated { .. }
             | event::OwnedLibMpvEvent::LogMessage { .. }
-            | event::OwnedLibMpvEvent::Errored(_) => {}
+            | event::OwnedLibMpvEvent::Errored(_)
+            | event::OwnedLibMpvEvent::IdleQuit { .. }
+            | event::OwnedLibMpvEvent::Unknown => {}
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn namespaces_make_valid_bus_names() {
+        assert_eq!(bus_name(None), "m");
+        assert_eq!(bus_name(Some("test")), "m.test");
+        assert_eq!(bus_name(Some("my seat.2")), "m.my_seat_2");
+        assert_eq!(bus_name(Some("2")), "m._2");
+        assert_eq!(bus_name(Some("")), "m._");
+    }
+}
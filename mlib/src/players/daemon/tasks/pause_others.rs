@@ -0,0 +1,104 @@
+//! Pauses the other media players on the session bus, like browsers, whenever one of the
+//! players starts playing, so that only one thing plays at a time.
+
+use super::super::SharedPlayersDaemon;
+use crate::players::{
+    event::{OwnedLibMpvEvent, OwnedMpvNode, PlayerEvent},
+    PlayerIndex,
+};
+use futures_util::{Stream, StreamExt};
+use std::collections::HashSet;
+use zbus::{fdo::DBusProxy, Connection};
+
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+/// Whether `name` is another player's, and not the mpris server of this or another m daemon, in
+/// some namespace, or an mpv plugin running in one of the players.
+fn is_other_player(name: &str) -> bool {
+    let Some(player) = name.strip_prefix(MPRIS_PREFIX) else {
+        return false;
+    };
+    player != "m"
+        && !player.starts_with("m.")
+        && !player.ends_with(&format!(".instance{}", std::process::id()))
+}
+
+async fn pause_others(connection: &Connection) -> zbus::Result<()> {
+    let names = DBusProxy::new(connection).await?.list_names().await?;
+    for name in names.iter().filter(|n| is_other_player(n.as_str())) {
+        tracing::debug!(%name, "pausing");
+        if let Err(e) = connection
+            .call_method(
+                Some(name.as_str()),
+                "/org/mpris/MediaPlayer2",
+                Some("org.mpris.MediaPlayer2.Player"),
+                "Pause",
+                &(),
+            )
+            .await
+        {
+            tracing::warn!(%name, error = ?e, "failed to pause other player");
+        }
+    }
+    Ok(())
+}
+
+#[tracing::instrument("pause others", skip_all)]
+pub(crate) async fn pause_on_play(
+    players: SharedPlayersDaemon,
+    events: impl Stream<Item = PlayerEvent>,
+) {
+    let connection = match Connection::session().await {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to connect to the session bus");
+            return;
+        }
+    };
+    // players that already played something, which count as starting only when unpaused
+    let mut started = HashSet::new();
+    let mut events = std::pin::pin!(events);
+    while let Some(event) = events.next().await {
+        let index = event.player_index;
+        let playing = match event.event {
+            OwnedLibMpvEvent::PropertyChange { name, change, .. } => {
+                name == "pause" && matches!(change, OwnedMpvNode::Flag(false))
+            }
+            OwnedLibMpvEvent::FileLoaded if started.insert(index) => {
+                let players = players.lock().await;
+                matches!(players.is_paused(PlayerIndex::of(index)).await, Ok(false))
+            }
+            OwnedLibMpvEvent::Shutdown => {
+                started.remove(&index);
+                false
+            }
+            _ => false,
+        };
+        if playing {
+            if let Err(e) = pause_others(&connection).await {
+                tracing::error!(error = ?e, "failed to list the players on the session bus");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_other_players_are_paused() {
+        assert!(is_other_player(
+            "org.mpris.MediaPlayer2.firefox.instance_1_42"
+        ));
+        assert!(is_other_player("org.mpris.MediaPlayer2.spotify"));
+        assert!(!is_other_player("org.mpris.MediaPlayer2.m"));
+        assert!(!is_other_player("org.mpris.MediaPlayer2.m.test"));
+        assert!(is_other_player("org.mpris.MediaPlayer2.mpv"));
+        assert!(!is_other_player(&format!(
+            "org.mpris.MediaPlayer2.mpv.instance{}",
+            std::process::id()
+        )));
+        assert!(!is_other_player("org.freedesktop.Notifications"));
+    }
+}
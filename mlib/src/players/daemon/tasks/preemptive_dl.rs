@@ -1,7 +1,11 @@
 use crate::{
-    downloaded::{download, search_cache_for},
+    downloaded::{download, policy::DownloadPolicy, search_cache_for},
     item::{link::Id, VideoLink},
-    players::daemon::player::MpvExt,
+    players::{
+        daemon::{player::MpvExt, Player},
+        event::OwnedLibMpvEvent,
+        PrecachePolicy,
+    },
     Item, Link, VideoId,
 };
 use libmpv::{FileState, Mpv};
@@ -13,6 +17,15 @@ pub struct Task {
     cancel: Option<oneshot::Sender<()>>,
 }
 
+/// The `ytdl-format` of `player`, if it was started with one, e.g. by `m play --quality`.
+fn ytdl_format(player: &Weak<Mpv>) -> Option<String> {
+    player
+        .upgrade()?
+        .simple_prop::<String>("ytdl-format")
+        .ok()
+        .filter(|f| !f.is_empty())
+}
+
 #[tracing::instrument(skip_all, fields(%song))]
 async fn do_it(cache_dir: &Path, song: &VideoLink, player: Weak<Mpv>) {
     let path = {
@@ -23,7 +36,12 @@ async fn do_it(cache_dir: &Path, song: &VideoLink, player: Weak<Mpv>) {
             Ok(None) | Err(_) => {
                 static CONCURRENT_DOWNLOADS: Semaphore = Semaphore::const_new(4);
                 let _permit = CONCURRENT_DOWNLOADS.acquire().await;
-                match download(dl_dir, song, false).await {
+                // the same quality the player would stream, so precaching doesn't use more data
+                let policy = DownloadPolicy {
+                    format: ytdl_format(&player),
+                    ..Default::default()
+                };
+                match download(dl_dir, song, false, &policy).await {
                     Ok(path) => match path.get().await {
                         Ok(path) => path,
                         Err(e) => {
@@ -130,20 +148,45 @@ impl Drop for Task {
     }
 }
 
+impl PrecachePolicy {
+    /// Whether the song at `pos` is downloaded while the one at `current` is playing.
+    fn wants(self, pos: usize, current: usize) -> bool {
+        match self {
+            Self::Off => false,
+            Self::Next(n) => pos >= current && pos - current <= n,
+            Self::Queue => true,
+        }
+    }
+}
+
 pub struct PreemptiveDownload {
     player: Weak<Mpv>,
+    policy: Mutex<PrecachePolicy>,
     inflight: Mutex<HashMap<Box<VideoId>, Task>>,
 }
 
 impl PreemptiveDownload {
-    pub fn new(player: Weak<Mpv>) -> Self {
+    pub fn new(player: Weak<Mpv>, policy: PrecachePolicy) -> Self {
         Self {
             player,
+            policy: Mutex::new(policy),
             inflight: Default::default(),
         }
     }
 
+    pub fn policy(&self) -> PrecachePolicy {
+        *self.policy.lock()
+    }
+
+    pub fn set_policy(&self, policy: PrecachePolicy) {
+        *self.policy.lock() = policy;
+        self.sync();
+    }
+
     pub fn song_queued(&self, item: &Item) {
+        if self.policy() != PrecachePolicy::Queue {
+            return self.sync();
+        }
         match item {
             Item::Link(Link::Video(video_id)) => {
                 self.inflight.lock().insert(
@@ -163,4 +206,81 @@ impl PreemptiveDownload {
     pub fn stop_all(&self) {
         self.inflight.lock().clear();
     }
+
+    /// Starts downloading the songs the policy wants that aren't being downloaded yet, and stops
+    /// the ones it doesn't want anymore.
+    pub fn sync(&self) {
+        let Some(player) = self.player.upgrade() else {
+            return;
+        };
+        let policy = self.policy();
+        let current = player
+            .simple_prop::<i64>("playlist-pos")
+            .ok()
+            .and_then(|pos| usize::try_from(pos).ok())
+            .unwrap_or(0);
+        let Ok(playlist) = player.playlist() else {
+            return;
+        };
+        let wanted = playlist
+            .into_iter()
+            .enumerate()
+            .filter(|(pos, _)| policy.wants(*pos, current))
+            .filter_map(
+                |(_, item)| match Item::from_mpv_filename(item.ok()?.filename) {
+                    Item::Link(Link::Video(video)) => Some((video.id().boxed(), video)),
+                    _ => None,
+                },
+            )
+            .collect::<HashMap<_, _>>();
+        let mut inflight = self.inflight.lock();
+        inflight.retain(|id, _| wanted.contains_key(id));
+        for (id, video) in wanted {
+            inflight
+                .entry(id)
+                .or_insert_with(|| Task::new(&video, self.player.clone()));
+        }
+    }
+}
+
+/// Keeps the songs coming up downloaded as the player moves through the queue, for policies
+/// that only download some of them.
+#[tracing::instrument("preemptive download", skip_all)]
+pub async fn follow(player: Weak<Player>) {
+    let Some(mut events) = player.upgrade().map(|p| p.subscribe()) else {
+        return;
+    };
+    while let Ok(e) = events.recv().await {
+        let OwnedLibMpvEvent::PropertyChange { name, .. } = e.event else {
+            continue;
+        };
+        if name != "playlist-pos" {
+            continue;
+        }
+        let Some(player) = player.upgrade() else {
+            return;
+        };
+        let pre_cacher = player.preemptive_download();
+        if let PrecachePolicy::Next(_) = pre_cacher.policy() {
+            pre_cacher.sync();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_the_next_songs_are_wanted() {
+        let wanted = |policy: PrecachePolicy| {
+            (0..8)
+                .filter(|pos| policy.wants(*pos, 3))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(wanted(PrecachePolicy::Off), []);
+        assert_eq!(wanted(PrecachePolicy::Next(2)), [3, 4, 5]);
+        assert_eq!(wanted(PrecachePolicy::Next(0)), [3]);
+        assert_eq!(wanted(PrecachePolicy::Queue), (0..8).collect::<Vec<_>>());
+    }
 }
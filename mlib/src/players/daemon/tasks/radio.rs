@@ -0,0 +1,196 @@
+use crate::{
+    item::{link::Id, PlaylistId, PlaylistLink},
+    players::{
+        daemon::{player::MpvExt, Player},
+        error::MpvResult,
+        event::OwnedLibMpvEvent,
+        Radio, RadioSeed,
+    },
+    playlist::{Category, Playlist},
+    ytdl::expand::{expand_items, NoCache},
+    Item, Link, VideoId,
+};
+use futures_util::StreamExt;
+use libmpv::FileState;
+use std::{
+    collections::{hash_map::RandomState, HashSet},
+    hash::{BuildHasher, Hasher},
+    sync::Weak,
+};
+
+/// How many songs are queued each time the queue is about to run out.
+const BATCH: usize = 10;
+
+/// A radio that is on and what it has queued so far.
+struct Station {
+    radio: Radio,
+    /// Songs that were queued at some point, so they aren't queued again.
+    seen: HashSet<Box<VideoId>>,
+    /// The last song the radio queued, whose mix is used next.
+    last: Option<Box<VideoId>>,
+}
+
+/// How many songs are left after the current one.
+fn remaining(player: &Player) -> MpvResult<usize> {
+    let count = player.simple_prop::<i64>("playlist-count")?;
+    let pos = player.simple_prop::<i64>("playlist-pos")?;
+    // -1 when the queue already ended
+    Ok(if pos < 0 { 0 } else { count - pos - 1 }.max(0) as usize)
+}
+
+/// The youtube mix of a song, expanded into the songs in it.
+async fn mix_of(id: &VideoId) -> Vec<Box<VideoId>> {
+    let mix = PlaylistLink::from_id(PlaylistId::new(&format!("RD{}", id.as_str())));
+    expand_items([Item::Link(mix.into())], &NoCache)
+        .filter_map(|item| async move {
+            let Item::Link(Link::Video(video)) = item else {
+                return None;
+            };
+            Some(video.id().boxed())
+        })
+        .collect()
+        .await
+}
+
+/// A random index smaller than `len`, which must not be 0.
+fn random_index(len: usize) -> usize {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_usize(len);
+    hasher.finish() as usize % len
+}
+
+impl Station {
+    fn new(radio: Radio) -> Self {
+        Self {
+            radio,
+            seen: HashSet::new(),
+            last: None,
+        }
+    }
+
+    /// The song whose mix has the next songs.
+    async fn seed(&self) -> Option<Box<VideoId>> {
+        match &self.radio.seed {
+            RadioSeed::Song(song) => self.last.as_deref().or(song.id()).map(VideoId::boxed),
+            RadioSeed::Category(category) => {
+                let playlist = match Playlist::load().await {
+                    Ok(p) => p,
+                    Err(e) => {
+                        tracing::error!(error = ?e, "failed to load the playlist");
+                        return None;
+                    }
+                };
+                let category = Category::new(category);
+                let songs = playlist
+                    .songs
+                    .iter()
+                    .filter(|s| s.is_in(&category))
+                    .map(|s| s.link.id())
+                    .collect::<Vec<_>>();
+                let unseen = songs
+                    .iter()
+                    .filter(|id| !self.seen.contains(**id))
+                    .copied()
+                    .collect::<Vec<_>>();
+                let songs = if unseen.is_empty() { songs } else { unseen };
+                if songs.is_empty() {
+                    tracing::warn!(%category, "no songs in the category");
+                    return None;
+                }
+                Some(songs[random_index(songs.len())].boxed())
+            }
+        }
+    }
+
+    /// Queues more songs if the queue is about to run out.
+    async fn top_up(&mut self, player: &Weak<Player>) -> MpvResult<()> {
+        {
+            let Some(p) = player.upgrade() else {
+                return Ok(());
+            };
+            if remaining(&p)? >= self.radio.threshold {
+                return Ok(());
+            }
+            for item in p.playlist()?.into_iter() {
+                if let Some(id) = item?.to_item().id() {
+                    self.seen.insert(id.boxed());
+                }
+            }
+        }
+        let Some(seed) = self.seed().await else {
+            return Ok(());
+        };
+        tracing::debug!(seed = seed.as_str(), "fetching the mix");
+        let new = mix_of(&seed)
+            .await
+            .into_iter()
+            .filter(|id| !self.seen.contains(id))
+            .take(BATCH)
+            .collect::<Vec<_>>();
+        if new.is_empty() {
+            tracing::warn!(seed = seed.as_str(), "the mix has no new songs");
+            // start over from the seed song instead of getting stuck on this one
+            self.last = None;
+            return Ok(());
+        }
+        let Some(p) = player.upgrade() else {
+            return Ok(());
+        };
+        // the radio may have been changed while the mix was fetched
+        if p.radio().borrow().as_ref() != Some(&self.radio) {
+            return Ok(());
+        }
+        tracing::info!(n = new.len(), "queueing songs");
+        for id in new {
+            let item = Item::Link(Link::from_video_id(&id));
+            let Ok(url) = <&str>::try_from(&item) else {
+                continue;
+            };
+            p.playlist_load_files(&[(url, FileState::AppendPlay, None)])?;
+            p.preemptive_download().song_queued(&item);
+            self.seen.insert(id.boxed());
+            self.last = Some(id);
+        }
+        Ok(())
+    }
+}
+
+#[tracing::instrument("radio", skip_all)]
+pub async fn keep_topped_up(player: Weak<Player>) {
+    let Some((mut events, mut radio)) = player
+        .upgrade()
+        .map(|p| (p.subscribe(), p.radio().subscribe()))
+    else {
+        return;
+    };
+    tracing::info!("starting");
+    let mut station = None;
+    loop {
+        tokio::select! {
+            changed = radio.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                station = radio.borrow_and_update().clone().map(Station::new);
+            }
+            e = events.recv() => {
+                let Ok(e) = e else {
+                    break;
+                };
+                let OwnedLibMpvEvent::PropertyChange { name, .. } = e.event else {
+                    continue;
+                };
+                if name != "playlist-pos" {
+                    continue;
+                }
+            }
+        }
+        let Some(station) = &mut station else {
+            continue;
+        };
+        if let Err(e) = station.top_up(&player).await {
+            tracing::error!(error = ?e, "failed to top up the queue");
+        }
+    }
+    tracing::info!("terminating");
+}
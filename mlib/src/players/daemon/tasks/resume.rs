@@ -0,0 +1,271 @@
+use crate::{
+    players::{
+        daemon::{clock::SharedClock, player::MpvExt, Player},
+        event::OwnedLibMpvEvent,
+    },
+    Item,
+};
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    io,
+    path::PathBuf,
+    sync::Weak,
+    time::{Duration, SystemTime},
+};
+
+/// Positions before this, in seconds, aren't worth resuming from.
+const MIN_POSITION: f64 = 30.;
+
+/// Songs shorter than this, in seconds, always start from the beginning. Resuming is for long
+/// mixes and podcasts.
+const MIN_DURATION: f64 = 10. * 60.;
+
+/// How many positions are remembered, the oldest are forgotten first.
+const CAPACITY: usize = 200;
+
+/// How often the position of the current file is sampled, to know where it was once it changes.
+const SAMPLE_EVERY: Duration = Duration::from_secs(5);
+
+pub(crate) fn store_path() -> io::Result<PathBuf> {
+    let Some(mut path) = dirs::data_dir() else {
+        tracing::error!("failed to get data dir for the playback positions");
+        return Err(io::ErrorKind::NotFound.into());
+    };
+    path.push("m");
+    path.push("positions");
+    Ok(path)
+}
+
+/// Where files were stopped, by video id or path, with when that was.
+#[derive(Debug, Default, PartialEq)]
+struct Positions {
+    by_key: HashMap<String, (f64, u64)>,
+}
+
+impl Positions {
+    /// The positions say what was listened to, so they are encrypted like the statistics.
+    async fn load() -> io::Result<Self> {
+        match tokio::fs::read(store_path()?).await {
+            Ok(bytes) => Self::decode(bytes),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Changes the stored positions with `f`, without losing what other players store meanwhile.
+    async fn update(f: impl FnOnce(&mut Self) + Send + 'static) -> io::Result<()> {
+        crate::update_file(store_path()?, |bytes| {
+            let mut positions = if bytes.is_empty() {
+                Self::default()
+            } else {
+                Self::decode(bytes)?
+            };
+            f(&mut positions);
+            crate::encode_history(positions.render().into_bytes())
+        })
+        .await
+    }
+
+    fn decode(bytes: Vec<u8>) -> io::Result<Self> {
+        let s = String::from_utf8(crate::decode_history(bytes)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Self::parse(&s))
+    }
+
+    /// One `key\tposition\tsaved_at` per line, lines that don't parse are dropped.
+    fn parse(s: &str) -> Self {
+        let by_key = s
+            .lines()
+            .filter_map(|l| {
+                let mut fields = l.rsplitn(3, '\t');
+                let saved_at = fields.next()?.parse().ok()?;
+                let position = fields.next()?.parse().ok()?;
+                Some((fields.next()?.to_owned(), (position, saved_at)))
+            })
+            .collect();
+        Self { by_key }
+    }
+
+    fn render(&self) -> String {
+        self.by_key
+            .iter()
+            .fold(String::new(), |mut s, (key, (position, saved_at))| {
+                let _ = writeln!(s, "{key}\t{position}\t{saved_at}");
+                s
+            })
+    }
+
+    fn get(&self, key: &str) -> Option<f64> {
+        self.by_key.get(key).map(|(position, _)| *position)
+    }
+
+    /// Remembers that `key` was stopped at `position`, or forgets it if there is nothing to resume
+    /// because it was barely started or already finished.
+    fn record(&mut self, key: &str, position: f64, duration: f64, now: u64) {
+        if position < MIN_POSITION || duration - position < MIN_POSITION || duration < MIN_DURATION
+        {
+            self.by_key.remove(key);
+            return;
+        }
+        self.by_key.insert(key.to_owned(), (position, now));
+        while self.by_key.len() > CAPACITY {
+            let Some(oldest) = self
+                .by_key
+                .iter()
+                .min_by_key(|(_, (_, saved_at))| *saved_at)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            self.by_key.remove(&oldest);
+        }
+    }
+}
+
+/// What a file is remembered by, its video id if it has one so downloads and links are the same.
+fn key_of(path: String) -> Option<String> {
+    let item = Item::from_mpv_filename(path);
+    match (item.id(), &item) {
+        (Some(id), _) => Some(id.as_str().to_owned()),
+        (None, Item::File(path)) => Some(path.to_string_lossy().into_owned()),
+        (None, _) => None,
+    }
+}
+
+#[derive(Debug)]
+struct Sample {
+    key: String,
+    position: f64,
+    duration: f64,
+}
+
+fn sample(player: &Player) -> Option<Sample> {
+    Some(Sample {
+        key: key_of(player.simple_prop("path").ok()?)?,
+        position: player.simple_prop("playback-time").ok()?,
+        duration: player.simple_prop("duration").ok()?,
+    })
+}
+
+async fn remember(sample: &Sample, clock: &SharedClock) {
+    let now = clock
+        .now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let result = Positions::update({
+        let (key, position, duration) = (sample.key.clone(), sample.position, sample.duration);
+        move |positions| positions.record(&key, position, duration, now)
+    });
+    if let Err(e) = result.await {
+        tracing::error!(error = ?e, ?sample, "failed to save the playback position");
+    }
+}
+
+/// Seeks the current file to where it was stopped last time, if it was barely started.
+pub async fn resume_current(player: &Player) {
+    let Some(key) = player.simple_prop("path").ok().and_then(key_of) else {
+        return;
+    };
+    let position = match Positions::load().await {
+        Ok(positions) => positions.get(&key),
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to load the playback positions");
+            return;
+        }
+    };
+    let Some(position) = position else {
+        return;
+    };
+    if player
+        .simple_prop::<f64>("playback-time")
+        .is_ok_and(|t| t >= MIN_POSITION)
+    {
+        return;
+    }
+    tracing::info!(key, position, "resuming");
+    if let Err(e) = player.command("seek", &[&position.to_string(), "absolute"]) {
+        tracing::error!(error = ?e, "failed to resume");
+    }
+}
+
+#[tracing::instrument("resume", skip_all)]
+pub async fn record(player: Weak<Player>) {
+    let Some((mut events, clock)) = player.upgrade().map(|p| (p.subscribe(), p.clock().clone()))
+    else {
+        return;
+    };
+    tracing::info!("starting");
+    let mut current = None;
+    let mut every = tokio::time::interval(SAMPLE_EVERY);
+    loop {
+        let event = tokio::select! {
+            _ = every.tick() => None,
+            e = events.recv() => match e {
+                Ok(e) => Some(e.event),
+                Err(_) => break,
+            },
+        };
+        let Some(p) = player.upgrade() else {
+            break;
+        };
+        match event {
+            None | Some(OwnedLibMpvEvent::Seek) => {
+                if !p.simple_prop::<bool>("pause").unwrap_or(true) {
+                    current = sample(&p).or(current);
+                }
+            }
+            Some(OwnedLibMpvEvent::PropertyChange { name, change, .. }) if name == "pause" => {
+                if change.into_bool().unwrap_or(false) {
+                    current = sample(&p).or(current);
+                    if let Some(s) = &current {
+                        remember(s, &clock).await;
+                    }
+                }
+            }
+            Some(OwnedLibMpvEvent::EndFile(_) | OwnedLibMpvEvent::Shutdown) => {
+                if let Some(s) = current.take() {
+                    remember(&s, &clock).await;
+                }
+            }
+            Some(OwnedLibMpvEvent::FileLoaded) => {
+                if *p.resume().lock() {
+                    resume_current(&p).await;
+                }
+                current = sample(&p);
+            }
+            Some(_) => {}
+        }
+    }
+    if let Some(s) = current {
+        remember(&s, &clock).await;
+    }
+    tracing::info!("terminating");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_long_unfinished_files_are_remembered() {
+        let mut positions = Positions::default();
+        positions.record("mix", 1200., 3600., 1);
+        positions.record("song", 120., 200., 1);
+        positions.record("barely", 10., 3600., 1);
+        assert_eq!(positions.get("mix"), Some(1200.));
+        assert_eq!(positions.get("song"), None);
+        assert_eq!(positions.get("barely"), None);
+        positions.record("mix", 3590., 3600., 2);
+        assert_eq!(positions.get("mix"), None);
+    }
+
+    #[test]
+    fn positions_survive_a_round_trip() {
+        let mut positions = Positions::default();
+        positions.record("/music/a\tb.mp3", 1200.5, 3600., 1);
+        positions.record("dQw4w9WgXcQ", 700., 3600., 2);
+        assert_eq!(Positions::parse(&positions.render()), positions);
+    }
+}
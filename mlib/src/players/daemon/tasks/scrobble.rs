@@ -0,0 +1,158 @@
+use super::listening::Listening;
+use crate::{
+    players::{
+        daemon::{PlayerEvent, SharedPlayersDaemon},
+        event, PlayerIndex,
+    },
+    scrobble::{self, Track},
+};
+use futures_util::StreamExt;
+use libmpv::MpvNode;
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+use tokio::sync::mpsc;
+
+struct Started {
+    track: Track,
+    at: SystemTime,
+}
+
+/// What a player is playing, each player is scrobbled on its own.
+#[derive(Default)]
+struct PlayerState {
+    listening: Listening<Started>,
+    paused: bool,
+    /// Whether the filename changed and the title is yet to be known.
+    new_file: bool,
+}
+
+enum Submission {
+    NowPlaying(Track),
+    Scrobble(Track, SystemTime),
+}
+
+/// Prefers the file's tags, falling back to guessing from the title.
+async fn track_info(
+    players: &SharedPlayersDaemon,
+    index: PlayerIndex,
+    media_title: &str,
+) -> Option<Track> {
+    let daemon = players.lock().await;
+    let duration = daemon
+        .duration(index)
+        .await
+        .ok()
+        .and_then(|d| Duration::try_from_secs_f64(d).ok());
+    let (mut artist, mut title) = (None, None);
+    if let Ok(metadata) = daemon.simple_prop::<MpvNode>(index, "metadata") {
+        for (k, v) in metadata.to_map().into_iter().flatten() {
+            let tag = match k.to_lowercase().as_str() {
+                "artist" => &mut artist,
+                "title" => &mut title,
+                _ => continue,
+            };
+            *tag = v.to_str().filter(|s| !s.is_empty()).map(String::from);
+        }
+    }
+    match (artist, title) {
+        (Some(artist), Some(title)) => Some(Track {
+            artist,
+            title,
+            duration,
+        }),
+        _ => Track::from_media_title(media_title, duration),
+    }
+}
+
+fn finished(previous: Option<(Started, Duration)>, tx: &mpsc::UnboundedSender<Submission>) {
+    if let Some((started, listened)) = previous {
+        if started.track.should_scrobble(listened) {
+            let _ = tx.send(Submission::Scrobble(started.track, started.at));
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn register_scrobbler(
+    players: SharedPlayersDaemon,
+    events: impl futures_util::Stream<Item = PlayerEvent>,
+    config: &'static scrobble::Config,
+) {
+    if !config.is_enabled() {
+        return;
+    }
+    tracing::info!("starting scrobbler");
+
+    // submissions go through the network, so they are done separately to not fall behind on
+    // events.
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let submit = async move {
+        while let Some(submission) = rx.recv().await {
+            match submission {
+                Submission::NowPlaying(track) => scrobble::now_playing(config, &track).await,
+                Submission::Scrobble(track, at) => scrobble::scrobble(config, track, at).await,
+            }
+        }
+    };
+
+    let listen = async move {
+        let mut events = std::pin::pin!(events);
+        let mut players_state = HashMap::<usize, PlayerState>::new();
+        while let Some(event) = events.next().await {
+            let state = players_state.entry(event.player_index).or_default();
+            match event.event {
+                event::OwnedLibMpvEvent::PropertyChange { name, .. } if name == "filename" => {
+                    finished(state.listening.switch(None, state.paused), &tx);
+                    state.new_file = true;
+                }
+                // the title is only known once the file is loaded, which is after the
+                // filename changes.
+                event::OwnedLibMpvEvent::PropertyChange { name, change, .. }
+                    if name == "media-title" && state.new_file =>
+                {
+                    let Ok(media_title) = change.into_string() else {
+                        continue;
+                    };
+                    state.new_file = false;
+                    let index = PlayerIndex::of(event.player_index);
+                    let track = track_info(&players, index, &media_title).await;
+                    match &track {
+                        Some(track) => {
+                            let _ = tx.send(Submission::NowPlaying(track.clone()));
+                        }
+                        None => {
+                            tracing::debug!(media_title, "can't tell the artist, not scrobbling")
+                        }
+                    }
+                    let started = track.map(|track| Started {
+                        track,
+                        at: SystemTime::now(),
+                    });
+                    finished(state.listening.switch(started, state.paused), &tx);
+                }
+                event::OwnedLibMpvEvent::PropertyChange {
+                    name,
+                    change: event::OwnedMpvNode::Flag(p),
+                    ..
+                } if name == "pause" => {
+                    state.paused = p;
+                    if p {
+                        state.listening.pause()
+                    } else {
+                        state.listening.resume()
+                    }
+                }
+                event::OwnedLibMpvEvent::Shutdown => {
+                    if let Some(mut state) = players_state.remove(&event.player_index) {
+                        finished(state.listening.switch(None, state.paused), &tx);
+                    }
+                }
+                _ => {}
+            }
+        }
+    };
+
+    futures_util::join!(listen, submit);
+}
@@ -0,0 +1,195 @@
+//! Follows the default sink for [`crate::players::sink_watch`].
+
+use super::super::SharedPlayersDaemon;
+use crate::players::{sink_watch::Config, PlayerIndex};
+use std::{io, process::Stdio, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+};
+
+/// How long to wait before following the sink again when `pactl subscribe` stops, e.g. because
+/// the sound server restarted.
+const RESUBSCRIBE_AFTER: Duration = Duration::from_secs(5);
+
+/// What to do with the players after the default sink changed.
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Nothing,
+    Pause,
+    Resume,
+}
+
+#[derive(Debug, Default)]
+struct Sinks {
+    default: Option<String>,
+    /// The default sink that went away, if the players were paused because of it.
+    lost: Option<String>,
+}
+
+impl Sinks {
+    /// Takes note of the new `default` sink, out of all that exist in `sinks`.
+    fn update(&mut self, default: Option<String>, sinks: &[String]) -> Action {
+        if default == self.default {
+            return Action::Nothing;
+        }
+        let previous = std::mem::replace(&mut self.default, default);
+        if self.lost.is_some() && self.lost == self.default {
+            self.lost = None;
+            return Action::Resume;
+        }
+        match previous {
+            Some(previous) if !sinks.contains(&previous) => {
+                self.lost = Some(previous);
+                Action::Pause
+            }
+            _ => Action::Nothing,
+        }
+    }
+}
+
+async fn pactl(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("pactl").args(args).output().await?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "pactl {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+async fn default_sink() -> io::Result<Option<String>> {
+    let sink = pactl(&["get-default-sink"]).await?;
+    let sink = sink.trim();
+    Ok((!sink.is_empty()).then(|| sink.to_owned()))
+}
+
+/// The names of all sinks, from the second column of `pactl list short sinks`.
+async fn sinks() -> io::Result<Vec<String>> {
+    Ok(pactl(&["list", "short", "sinks"])
+        .await?
+        .lines()
+        .filter_map(|l| l.split('\t').nth(1))
+        .map(str::to_owned)
+        .collect())
+}
+
+pub(crate) async fn watch(players: SharedPlayersDaemon, config: &'static Config) {
+    let mut sinks = Sinks::default();
+    let mut paused = Vec::new();
+    loop {
+        match follow(&players, config, &mut sinks, &mut paused).await {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                tracing::error!(
+                    "pactl isn't installed, players won't be paused when the sink goes away"
+                );
+                return;
+            }
+            Err(e) => tracing::error!(error = ?e, "failed to follow the default sink"),
+            Ok(()) => tracing::warn!("pactl subscribe stopped"),
+        }
+        tokio::time::sleep(RESUBSCRIBE_AFTER).await;
+    }
+}
+
+async fn follow(
+    players: &SharedPlayersDaemon,
+    config: &Config,
+    sinks: &mut Sinks,
+    paused: &mut Vec<PlayerIndex>,
+) -> io::Result<()> {
+    let mut subscription = Command::new("pactl")
+        .arg("subscribe")
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut events = BufReader::new(subscription.stdout.take().expect("stdout is piped")).lines();
+    loop {
+        let action = sinks.update(default_sink().await?, &self::sinks().await?);
+        act(players, config, action, paused).await;
+        // the default sink changing is a change on the server
+        loop {
+            match events.next_line().await? {
+                None => return Ok(()),
+                Some(e) if e.contains("on sink") || e.contains("on server") => break,
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+async fn act(
+    players: &SharedPlayersDaemon,
+    config: &Config,
+    action: Action,
+    paused: &mut Vec<PlayerIndex>,
+) {
+    let players = players.lock().await;
+    match action {
+        Action::Nothing => {}
+        Action::Pause => {
+            paused.clear();
+            for index in players.list() {
+                if let Ok(false) = players.is_paused(index).await {
+                    match players.pause(index).await {
+                        Ok(()) => paused.push(index),
+                        Err(e) => tracing::error!(error = ?e, ?index, "failed to pause"),
+                    }
+                }
+            }
+            tracing::info!(?paused, "paused because the sink went away");
+        }
+        Action::Resume if config.resume => {
+            for index in paused.drain(..) {
+                // the player might have quit in the meantime
+                let _ = players.resume(index).await;
+            }
+        }
+        Action::Resume => paused.clear(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pauses_when_the_default_sink_goes_away_and_resumes_when_it_comes_back() {
+        let names = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let mut sinks = Sinks::default();
+        assert_eq!(
+            sinks.update(Some("headphones".into()), &[]),
+            Action::Nothing
+        );
+        // switching to another sink on purpose
+        assert_eq!(
+            sinks.update(Some("speakers".into()), &names(&["speakers", "headphones"])),
+            Action::Nothing
+        );
+        assert_eq!(
+            sinks.update(
+                Some("headphones".into()),
+                &names(&["speakers", "headphones"])
+            ),
+            Action::Nothing
+        );
+        // the headphones are disconnected
+        assert_eq!(
+            sinks.update(Some("speakers".into()), &names(&["speakers"])),
+            Action::Pause
+        );
+        assert_eq!(
+            sinks.update(Some("speakers".into()), &names(&["speakers"])),
+            Action::Nothing
+        );
+        assert_eq!(
+            sinks.update(
+                Some("headphones".into()),
+                &names(&["speakers", "headphones"])
+            ),
+            Action::Resume
+        );
+    }
+}
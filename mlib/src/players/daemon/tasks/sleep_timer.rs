@@ -0,0 +1,114 @@
+use crate::players::{daemon::clock::SharedClock, SleepTimer, StopOrPause};
+use libmpv::Mpv;
+use std::{sync::Weak, time::Duration};
+use tokio::{sync::oneshot, time::Instant};
+
+pub struct Timer {
+    deadline: Instant,
+    action: StopOrPause,
+    clock: SharedClock,
+    cancel: Option<oneshot::Sender<()>>,
+}
+
+#[tracing::instrument(skip(player))]
+pub fn expire(action: StopOrPause, player: Weak<Mpv>) {
+    let Some(player) = player.upgrade() else {
+        return;
+    };
+    tracing::info!("sleep timer expired");
+    let result = match action {
+        StopOrPause::Pause => player.pause(),
+        StopOrPause::Stop => player.command("quit", &[]),
+    };
+    if let Err(e) = result {
+        tracing::error!(error = ?e, "failed to execute sleep timer action");
+    }
+}
+
+impl Timer {
+    /// Calls `on_expire` after `after`, unless the timer is dropped first.
+    pub fn new(
+        after: Duration,
+        action: StopOrPause,
+        clock: SharedClock,
+        on_expire: impl FnOnce(StopOrPause) + Send + 'static,
+    ) -> Self {
+        let (tx, rx) = oneshot::channel();
+        let sleep = clock.sleep(after);
+        tokio::spawn(async move {
+            tokio::select! {
+                // a timer that was cancelled right as it expired stays cancelled
+                biased;
+                _ = rx => {}
+                _ = sleep => on_expire(action),
+            }
+        });
+        Self {
+            deadline: clock.instant() + after,
+            action,
+            clock,
+            cancel: Some(tx),
+        }
+    }
+
+    /// The status of the timer, or `None` if it already expired.
+    pub fn status(&self) -> Option<SleepTimer> {
+        let remaining = self.deadline.checked_duration_since(self.clock.instant())?;
+        Some(SleepTimer {
+            remaining,
+            action: self.action,
+        })
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let Some(cancel) = self.cancel.take() else {
+            return;
+        };
+        let _ = cancel.send(());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::players::daemon::clock::MockClock;
+
+    fn timer(clock: &std::sync::Arc<MockClock>) -> (Timer, oneshot::Receiver<StopOrPause>) {
+        let (tx, rx) = oneshot::channel();
+        let timer = Timer::new(
+            Duration::from_secs(60),
+            StopOrPause::Pause,
+            clock.clone(),
+            |action| {
+                let _ = tx.send(action);
+            },
+        );
+        (timer, rx)
+    }
+
+    #[tokio::test]
+    async fn expires_once_the_time_is_up() {
+        let clock = MockClock::new();
+        let (timer, mut expired) = timer(&clock);
+        clock.advance(Duration::from_secs(45));
+        tokio::task::yield_now().await;
+        assert_eq!(
+            timer.status().map(|s| s.remaining),
+            Some(Duration::from_secs(15))
+        );
+        assert!(expired.try_recv().is_err());
+        clock.advance(Duration::from_secs(15));
+        assert!(matches!(expired.await, Ok(StopOrPause::Pause)));
+    }
+
+    #[tokio::test]
+    async fn dropping_cancels() {
+        let clock = MockClock::new();
+        let (timer, expired) = timer(&clock);
+        drop(timer);
+        clock.advance(Duration::from_secs(60));
+        assert!(expired.await.is_err());
+    }
+}
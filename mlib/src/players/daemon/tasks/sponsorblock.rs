@@ -0,0 +1,100 @@
+use crate::{
+    players::{
+        daemon::{player::MpvExt, Player},
+        event::OwnedLibMpvEvent,
+    },
+    sponsorblock::{self, Segment, SegmentCategory},
+    Item,
+};
+use std::{sync::Weak, time::Duration};
+use tokio::{sync::broadcast::error::RecvError, time::MissedTickBehavior};
+
+/// How often the position is checked while the current file has segments to skip.
+const CHECK_EVERY: Duration = Duration::from_millis(250);
+
+/// The segments in `categories` of the current file, if it's a youtube video.
+async fn segments_of_current(player: &Player, categories: &[SegmentCategory]) -> Vec<Segment> {
+    let Ok(path) = player.simple_prop::<String>("path") else {
+        return Vec::new();
+    };
+    let item = Item::from_mpv_filename(path);
+    let Some(id) = item.id() else {
+        return Vec::new();
+    };
+    match sponsorblock::segments(id, categories).await {
+        Ok(segments) => {
+            tracing::debug!(%item, n = segments.len(), "got segments");
+            segments
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %item, "failed to get the segments");
+            Vec::new()
+        }
+    }
+}
+
+fn skip(player: &Player, segments: &[Segment]) {
+    let Ok(position) = player.simple_prop::<f64>("playback-time") else {
+        return;
+    };
+    let Some(to) = sponsorblock::skip_to(segments, position) else {
+        return;
+    };
+    tracing::info!(from = position, to, "skipping segment");
+    if let Err(e) = player.command("seek", &[&to.to_string(), "absolute+exact"]) {
+        tracing::error!(error = ?e, "failed to skip the segment");
+    }
+}
+
+#[tracing::instrument("sponsorblock", skip_all)]
+pub async fn skip_segments(player: Weak<Player>, categories: Vec<SegmentCategory>) {
+    let Some((mut events, mut on)) = player
+        .upgrade()
+        .map(|p| (p.subscribe(), p.sponsorblock().subscribe()))
+    else {
+        return;
+    };
+    tracing::info!("starting");
+    let mut segments = Vec::new();
+    let mut every = tokio::time::interval(CHECK_EVERY);
+    every.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        let refetch = tokio::select! {
+            changed = on.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                true
+            }
+            e = events.recv() => match e {
+                Ok(e) => match e.event {
+                    OwnedLibMpvEvent::FileLoaded => true,
+                    OwnedLibMpvEvent::EndFile(_) => {
+                        segments.clear();
+                        continue;
+                    }
+                    _ => continue,
+                },
+                Err(RecvError::Lagged(missed)) => {
+                    // the missed events may have been a new file being loaded
+                    tracing::warn!(missed, "missed player events");
+                    true
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = every.tick(), if !segments.is_empty() => false,
+        };
+        let Some(p) = player.upgrade() else {
+            break;
+        };
+        if refetch {
+            segments = if *on.borrow_and_update() {
+                segments_of_current(&p, &categories).await
+            } else {
+                Vec::new()
+            };
+        }
+        skip(&p, &segments);
+    }
+    tracing::info!("terminating");
+}
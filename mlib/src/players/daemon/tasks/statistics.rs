@@ -1,12 +1,29 @@
-use crate::players::{daemon::PlayerEvent, event};
+use super::listening::Listening;
+use crate::{
+    item::Item,
+    players::{daemon::PlayerEvent, event},
+};
+use std::{collections::HashMap, time::Duration};
 use tokio_stream::StreamExt;
 
+async fn record_listened(previous: Option<(Item, Duration)>) {
+    let Some((item, listened)) = previous else {
+        return;
+    };
+    if let Err(error) = crate::statistics::listened_to_song(item, listened).await {
+        tracing::error!(?error, "failed to register listening time")
+    }
+}
+
 #[tracing::instrument(skip_all)]
 pub async fn register_statistics_listener(events: impl futures_util::Stream<Item = PlayerEvent>) {
     tracing::info!("starting statistics listener");
 
     let mut events = std::pin::pin!(events);
+    // each player is listened to on its own, as (what it's playing, whether it's paused)
+    let mut players = HashMap::<usize, (Listening<Item>, bool)>::new();
     while let Some(event) = events.next().await {
+        let (listening, paused) = players.entry(event.player_index).or_default();
         match event.event {
             event::OwnedLibMpvEvent::PropertyChange {
                 name,
@@ -14,14 +31,31 @@ pub async fn register_statistics_listener(events: impl futures_util::Stream<Item
                 reply_userdata: _,
             } if name == "filename" => {
                 tracing::info!(name, ?change, "property change");
-                if let Ok(filename) = change.into_string() {
-                    if let Err(error) =
-                        crate::statistics::played_song(crate::item::Item::from(filename)).await
-                    {
+                let song = change.into_string().ok().map(Item::from_mpv_filename);
+                record_listened(listening.switch(song.clone(), *paused)).await;
+                if let Some(song) = song {
+                    if let Err(error) = crate::statistics::played_song(song).await {
                         tracing::error!(?error, "failed to register a played song")
                     }
                 }
             }
+            event::OwnedLibMpvEvent::PropertyChange {
+                name,
+                change: event::OwnedMpvNode::Flag(p),
+                reply_userdata: _,
+            } if name == "pause" => {
+                *paused = p;
+                if p {
+                    listening.pause()
+                } else {
+                    listening.resume()
+                }
+            }
+            event::OwnedLibMpvEvent::Shutdown => {
+                let paused = *paused;
+                record_listened(listening.switch(None, paused)).await;
+                players.remove(&event.player_index);
+            }
             _ => {}
         }
     }
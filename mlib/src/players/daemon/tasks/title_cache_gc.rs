@@ -0,0 +1,49 @@
+use super::super::{player::MpvExt, SharedPlayersDaemon};
+use crate::{item::title_cache, playlist::PlaylistIds};
+use std::{collections::HashSet, time::Duration};
+use tokio::time::MissedTickBehavior;
+
+const WEEK: Duration = Duration::from_secs(60 * 60 * 24 * 7);
+
+async fn queued_ids(players: &SharedPlayersDaemon) -> HashSet<String> {
+    let daemon = players.lock().await;
+    daemon
+        .players
+        .iter()
+        .flatten()
+        .filter_map(|p| p.playlist().ok())
+        .flat_map(|playlist| {
+            (&playlist)
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter_map(|i| i.to_item().id().map(|id| id.as_str().to_string()))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn prune_weekly(players: SharedPlayersDaemon) {
+    let mut interval = tokio::time::interval(WEEK);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let playlist = match PlaylistIds::load().await {
+            Ok(playlist) => playlist,
+            Err(error) => {
+                tracing::warn!(?error, "failed to load playlist, not pruning title cache");
+                continue;
+            }
+        };
+        let queued = queued_ids(&players).await;
+        match title_cache::prune(
+            |id| playlist.contains(id) || queued.contains(id),
+            title_cache::PRUNE_AFTER,
+        )
+        .await
+        {
+            Ok(removed) => tracing::info!(removed, "pruned title cache"),
+            Err(error) => tracing::error!(?error, "failed to prune title cache"),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::{
+    players::{
+        daemon::{player::MpvExt, Player},
+        event::OwnedLibMpvEvent,
+    },
+    ytdl::chapters::{self, Chapter},
+    Item, Link,
+};
+use std::sync::Weak;
+
+/// Finds the chapter that contains the `time` (in seconds).
+pub fn current(chapters: &[Chapter], time: f64) -> Option<usize> {
+    chapters.iter().rposition(|c| c.start.as_secs_f64() <= time)
+}
+
+#[tracing::instrument("virtual chapters", skip_all)]
+pub async fn fetch(player: Weak<Player>) {
+    let Some(mut events) = player.upgrade().map(|p| p.subscribe()) else {
+        return;
+    };
+    tracing::info!("starting");
+    while let Ok(e) = events.recv().await {
+        let OwnedLibMpvEvent::FileLoaded = e.event else {
+            continue;
+        };
+        let Some(p) = player.upgrade() else {
+            return;
+        };
+        p.clear_virtual_chapters();
+        if !matches!(p.simple_prop::<i64>("chapters"), Ok(0)) {
+            continue;
+        }
+        let Ok(path) = p.simple_prop::<String>("path") else {
+            continue;
+        };
+        let Item::Link(Link::Video(link)) = Item::from(path.clone()) else {
+            continue;
+        };
+        let player = player.clone();
+        tokio::spawn(async move {
+            match chapters::from_description(&link).await {
+                Ok(chapters) if chapters.is_empty() => {}
+                Ok(chapters) => {
+                    tracing::debug!(%link, n = chapters.len(), "found virtual chapters");
+                    if let Some(p) = player.upgrade() {
+                        p.set_virtual_chapters(path, chapters);
+                    }
+                }
+                Err(e) => tracing::warn!(%link, error = ?e, "failed to fetch description"),
+            }
+        });
+    }
+    tracing::info!("terminating");
+}
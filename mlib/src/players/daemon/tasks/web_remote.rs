@@ -0,0 +1,656 @@
+//! A small http server for [`crate::players::web_remote`]. Only what browsers need is
+//! implemented: one request per connection and websockets that the server only writes to.
+
+use super::super::{event_stream_with_replay, handle_messages, SharedPlayersDaemon};
+use crate::players::{
+    web_remote::{Config, Scope},
+    Message,
+};
+use base64::Engine;
+use cli_daemon::{Metrics, Recorder};
+use futures_util::StreamExt;
+use sha1::{Digest, Sha1};
+use std::{collections::HashMap, io, net::IpAddr, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+
+/// The most a request, or a websocket frame, can take.
+const MAX_SIZE: usize = 64 * 1024;
+
+/// Appended to the key of the client to accept a websocket, from RFC 6455.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const TEXT: u8 = 0x1;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+const PAGE: &str = include_str!("web_remote.html");
+
+/// How long a websocket has to send the token before it's closed.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What can be asked with [`Scope::Read`]: questions about what's playing.
+const READ: &[&str] = &[
+    "PlayerList",
+    "Current",
+    "ChapterMetadata",
+    "Filename",
+    "IsPaused",
+    "MediaTitle",
+    "PercentPosition",
+    "Queue",
+    "QueueIsLooping",
+    "QueuePos",
+    "QueueSize",
+    "Volume",
+    "QueueNFilename",
+    "QueueN",
+    "QueueRange",
+    "Duration",
+    "PlaybackTime",
+    "SleepTimerStatus",
+    "Name",
+    "TrackMetadata",
+];
+
+/// What can also be done with [`Scope::Control`]: playback controls. Anything that runs
+/// commands, loads files or changes how players are set up needs [`Scope::Admin`].
+const CONTROL: &[&str] = &[
+    "CyclePause",
+    "Pause",
+    "Resume",
+    "ChangeVolume",
+    "StepVolume",
+    "ChangeFile",
+    "HistoryBack",
+    "HistoryForward",
+    "Seek",
+    "ChangeChapter",
+    "JumpTo",
+    "SetSleepTimer",
+    "CancelSleepTimer",
+];
+
+/// Whether the message called `name` can be sent with `scope`.
+fn allows(scope: Scope, name: &str) -> bool {
+    match scope {
+        Scope::Read => READ.contains(&name),
+        Scope::Control => READ.contains(&name) || CONTROL.contains(&name),
+        Scope::Admin => true,
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// What the bearer token in the `Authorization` header lets the request do, if it's one of
+    /// the tokens.
+    fn scope(&self, config: &Config) -> Option<Scope> {
+        let given = self
+            .headers
+            .get("authorization")
+            .and_then(|auth| auth.strip_prefix("Bearer "));
+        config.scope_of(given.map(str::as_bytes))
+    }
+
+    /// Whether a browser made the request from the page itself, or from one of the
+    /// `cors_origins`. Without a token the host also has to be an address, or `localhost`, so a
+    /// page on some other domain that resolves to this machine can't talk to it.
+    fn trusted(&self, config: &Config) -> bool {
+        let Some(host) = self.headers.get("host") else {
+            return false;
+        };
+        let allowed_origin = self.headers.get("origin").map_or(true, |origin| {
+            origin.split_once("://").is_some_and(|(_, o)| o == host) || self.cors(config).is_some()
+        });
+        allowed_origin && (config.has_tokens() || is_address(host))
+    }
+
+    /// The origin of the request, if it's one of the `cors_origins`.
+    fn cors(&self, config: &Config) -> Option<&str> {
+        let origin = self.headers.get("origin")?;
+        config
+            .cors_origins
+            .iter()
+            .any(|o| o.trim_end_matches('/') == origin)
+            .then_some(origin.as_str())
+    }
+
+    /// The headers that let a page of one of the `cors_origins` read the response.
+    fn cors_headers(&self, config: &Config) -> String {
+        match self.cors(config) {
+            Some(origin) => format!("access-control-allow-origin: {origin}\r\nvary: origin\r\n"),
+            None => String::new(),
+        }
+    }
+}
+
+/// Whether `host`, from a `Host` header, is an ip address or `localhost`, with or without a port.
+fn is_address(host: &str) -> bool {
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if port.parse::<u16>().is_ok() => name,
+        _ => host,
+    };
+    name == "localhost"
+        || name
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok()
+}
+
+fn invalid(why: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, why)
+}
+
+async fn read_request(stream: &mut BufReader<TcpStream>) -> io::Result<Option<Request>> {
+    let mut line = String::new();
+    if stream.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let path = target.split_once('?').map_or(target, |(path, _)| path);
+    let (method, path) = (method.to_owned(), path.to_owned());
+    let mut headers = HashMap::new();
+    let mut size = line.len();
+    loop {
+        line.clear();
+        size += stream.read_line(&mut line).await?;
+        if size > MAX_SIZE {
+            return Err(invalid("request too large"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+        }
+    }
+    let len = headers
+        .get("content-length")
+        .and_then(|l| l.parse::<usize>().ok())
+        .unwrap_or(0);
+    if len > MAX_SIZE {
+        return Err(invalid("request too large"));
+    }
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await?;
+    Ok(Some(Request {
+        method,
+        path,
+        headers,
+        body,
+    }))
+}
+
+/// Responds to the request, `headers` being more header lines, each ending in `\r\n`.
+async fn respond(
+    stream: &mut BufReader<TcpStream>,
+    status: &str,
+    content_type: &str,
+    headers: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {status}\r\ncontent-type: {content_type}\r\ncontent-length: {}\r\n{headers}connection: close\r\n\r\n",
+        body.len()
+    );
+    let stream = stream.get_mut();
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await
+}
+
+fn accept_key(key: &str) -> String {
+    let mut sha = Sha1::new();
+    sha.update(key.as_bytes());
+    sha.update(WEBSOCKET_GUID.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(sha.finalize())
+}
+
+/// A websocket frame from the server, which unlike the ones from clients isn't masked.
+fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Reads a websocket frame from a client, returning its opcode and unmasked payload.
+async fn read_frame(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<(u8, Vec<u8>)> {
+    let mut head = [0; 2];
+    reader.read_exact(&mut head).await?;
+    let len = match head[1] & 0x7F {
+        126 => reader.read_u16().await? as u64,
+        127 => reader.read_u64().await?,
+        len => len as u64,
+    };
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|len| *len <= MAX_SIZE)
+        .ok_or_else(|| invalid("frame too large"))?;
+    let mut mask = [0; 4];
+    if head[1] & 0x80 != 0 {
+        reader.read_exact(&mut mask).await?;
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload).await?;
+    for (i, b) in payload.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+    Ok((head[0] & 0x0F, payload))
+}
+
+/// Pushes events to a websocket. Browsers can't set headers on websockets, so with a token it has
+/// to be the first message the client sends.
+async fn push_events(
+    stream: BufReader<TcpStream>,
+    key: &str,
+    players: SharedPlayersDaemon,
+    config: &Config,
+) -> io::Result<()> {
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let accept = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nupgrade: websocket\r\nconnection: Upgrade\r\nsec-websocket-accept: {}\r\n\r\n",
+        accept_key(key)
+    );
+    writer.write_all(accept.as_bytes()).await?;
+    if config.has_tokens() {
+        let authorized = matches!(
+            tokio::time::timeout(AUTH_TIMEOUT, read_frame(&mut reader)).await,
+            Ok(Ok((TEXT, given))) if config.scope_of(Some(&given[..])).is_some()
+        );
+        if !authorized {
+            return writer.write_all(&frame(CLOSE, &[])).await;
+        }
+    }
+    let mut events = std::pin::pin!(event_stream_with_replay(players).await);
+    // reading a frame isn't cancel safe, so it can't be raced against the events directly
+    let (frames_tx, mut frames) = mpsc::channel(4);
+    let read_frames = tokio::spawn(async move {
+        while let Ok(received) = read_frame(&mut reader).await {
+            if frames_tx.send(received).await.is_err() {
+                break;
+            }
+        }
+    });
+    let result = async {
+        loop {
+            tokio::select! {
+                event = events.next() => {
+                    let Some(event) = event else {
+                        break;
+                    };
+                    writer.write_all(&frame(TEXT, &serde_json::to_vec(&event)?)).await?;
+                }
+                received = frames.recv() => match received {
+                    Some((PING, payload)) => writer.write_all(&frame(PONG, &payload)).await?,
+                    Some((CLOSE, _)) | None => {
+                        let _ = writer.write_all(&frame(CLOSE, &[])).await;
+                        break;
+                    }
+                    Some(_) => {}
+                },
+            }
+        }
+        Ok(())
+    }
+    .await;
+    read_frames.abort();
+    result
+}
+
+/// The metrics of the daemon in prometheus' text format.
+fn prometheus(metrics: &Metrics) -> String {
+    use std::fmt::Write;
+
+    let mut text = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(Option<&str>, String)]| {
+        let _ = writeln!(text, "# HELP m_players_{name} {help}");
+        let _ = writeln!(text, "# TYPE m_players_{name} {kind}");
+        for (label, value) in samples {
+            let _ = match label {
+                Some(label) => writeln!(text, "m_players_{name}{{kind=\"{label}\"}} {value}"),
+                None => writeln!(text, "m_players_{name} {value}"),
+            };
+        }
+    };
+    metric(
+        "uptime_seconds",
+        "gauge",
+        "How long the daemon has been running.",
+        &[(None, metrics.uptime.as_secs_f64().to_string())],
+    );
+    metric(
+        "connections_total",
+        "counter",
+        "Clients that connected.",
+        &[(None, metrics.connections.to_string())],
+    );
+    metric(
+        "malformed_messages_total",
+        "counter",
+        "Messages that couldn't be understood.",
+        &[(None, metrics.malformed.to_string())],
+    );
+    let by_kind = |count: fn(&cli_daemon::MessageCount) -> u64| {
+        metrics
+            .messages
+            .iter()
+            .map(|(kind, c)| (Some(kind.as_str()), count(c).to_string()))
+            .collect::<Vec<_>>()
+    };
+    metric(
+        "messages_total",
+        "counter",
+        "Messages handled, by kind.",
+        &by_kind(|c| c.handled),
+    );
+    metric(
+        "message_failures_total",
+        "counter",
+        "Messages that failed, by kind.",
+        &by_kind(|c| c.failed),
+    );
+    text
+}
+
+async fn handle(
+    stream: TcpStream,
+    players: SharedPlayersDaemon,
+    config: &Config,
+    metrics: Recorder,
+) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    let Some(request) = read_request(&mut stream).await? else {
+        return Ok(());
+    };
+    tracing::debug!(method = request.method, path = request.path, "request");
+    if !request.trusted(config) {
+        return respond(
+            &mut stream,
+            "403 Forbidden",
+            "text/plain",
+            "",
+            b"untrusted origin",
+        )
+        .await;
+    }
+    let cors = request.cors_headers(config);
+    let scope = request.scope(config);
+    match (request.method.as_str(), request.path.as_str()) {
+        // browsers ask before sending requests from other origins, without the token
+        ("OPTIONS", _) => {
+            let headers = format!(
+                "{cors}access-control-allow-methods: GET, POST\r\naccess-control-allow-headers: authorization, content-type\r\n"
+            );
+            respond(&mut stream, "204 No Content", "text/plain", &headers, b"").await
+        }
+        // the page has nothing secret, it asks for the token itself
+        ("GET", "/") => {
+            respond(
+                &mut stream,
+                "200 OK",
+                "text/html; charset=utf-8",
+                &cors,
+                PAGE.as_bytes(),
+            )
+            .await
+        }
+        ("GET", "/events") => match request.headers.get("sec-websocket-key") {
+            Some(key) => push_events(stream, key, players, config).await,
+            None => {
+                let body = b"expected a websocket";
+                respond(&mut stream, "400 Bad Request", "text/plain", &cors, body).await
+            }
+        },
+        _ if scope.is_none() => {
+            respond(
+                &mut stream,
+                "401 Unauthorized",
+                "text/plain",
+                &cors,
+                b"wrong token",
+            )
+            .await
+        }
+        ("POST", "/") => match serde_json::from_slice::<Message>(&request.body) {
+            Ok(message) if !scope.is_some_and(|scope| allows(scope, message.kind.name())) => {
+                let body = format!("{} can't be sent from the web remote", message.kind.name());
+                respond(
+                    &mut stream,
+                    "403 Forbidden",
+                    "text/plain",
+                    &cors,
+                    body.as_bytes(),
+                )
+                .await
+            }
+            Ok(message) => {
+                let response = handle_messages(message, players).await;
+                let body = serde_json::to_vec(&response)?;
+                respond(&mut stream, "200 OK", "application/json", &cors, &body).await
+            }
+            Err(e) => {
+                let e = e.to_string();
+                respond(
+                    &mut stream,
+                    "400 Bad Request",
+                    "text/plain",
+                    &cors,
+                    e.as_bytes(),
+                )
+                .await
+            }
+        },
+        ("GET", "/metrics") => {
+            let body = prometheus(&metrics.snapshot());
+            respond(
+                &mut stream,
+                "200 OK",
+                "text/plain; version=0.0.4",
+                &cors,
+                body.as_bytes(),
+            )
+            .await
+        }
+        _ => {
+            respond(
+                &mut stream,
+                "404 Not Found",
+                "text/plain",
+                &cors,
+                b"not found",
+            )
+            .await
+        }
+    }
+}
+
+#[tracing::instrument("web remote", skip_all)]
+pub async fn serve(players: SharedPlayersDaemon, config: &'static Config, metrics: Recorder) {
+    if !config.has_tokens() && !config.listen.ip().is_loopback() {
+        tracing::error!(
+            addr = %config.listen,
+            "refusing to listen on a non loopback address without a token"
+        );
+        return;
+    }
+    let listener = match TcpListener::bind(config.listen).await {
+        Ok(l) => l,
+        Err(e) => {
+            tracing::error!(error = ?e, addr = %config.listen, "failed to listen");
+            return;
+        }
+    };
+    tracing::info!(addr = %config.listen, "listening");
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                let players = players.clone();
+                let metrics = metrics.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle(stream, players, config, metrics).await {
+                        tracing::debug!(error = ?e, %addr, "failed to handle request");
+                    }
+                });
+            }
+            Err(e) => tracing::error!(error = ?e, "failed to accept connection"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accepts_the_key_from_the_rfc() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn metrics_are_labeled_by_kind() {
+        let metrics = Metrics {
+            messages: [(
+                "Create".to_owned(),
+                cli_daemon::MessageCount {
+                    handled: 3,
+                    failed: 1,
+                },
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let text = prometheus(&metrics);
+        assert!(text.contains("\nm_players_messages_total{kind=\"Create\"} 3\n"));
+        assert!(text.contains("\nm_players_message_failures_total{kind=\"Create\"} 1\n"));
+        assert!(text.contains("# TYPE m_players_uptime_seconds gauge\n"));
+    }
+
+    fn request(headers: &[(&str, &str)]) -> Request {
+        Request {
+            method: "POST".into(),
+            path: "/".into(),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body: vec![],
+        }
+    }
+
+    fn config(listen: &str, token: Option<&str>) -> Config {
+        Config {
+            listen: listen.parse().unwrap(),
+            token: token.map(Into::into),
+            tokens: Default::default(),
+            cors_origins: vec![],
+        }
+    }
+
+    #[test]
+    fn only_the_page_itself_is_trusted() {
+        let config = config("127.0.0.1:7878", None);
+        let host = ("host", "127.0.0.1:7878");
+        assert!(request(&[host]).trusted(&config));
+        assert!(request(&[host, ("origin", "http://127.0.0.1:7878")]).trusted(&config));
+        assert!(!request(&[host, ("origin", "https://evil.example")]).trusted(&config));
+        assert!(!request(&[("host", "evil.example:7878")]).trusted(&config));
+        assert!(request(&[("host", "[::1]:7878")]).trusted(&config));
+        assert!(!request(&[]).trusted(&config));
+    }
+
+    #[test]
+    fn cors_origins_are_trusted() {
+        let config = Config {
+            cors_origins: vec!["https://remote.example/".into()],
+            ..config("127.0.0.1:7878", None)
+        };
+        let host = ("host", "127.0.0.1:7878");
+        let remote = request(&[host, ("origin", "https://remote.example")]);
+        assert!(remote.trusted(&config));
+        assert_eq!(
+            remote.cors_headers(&config),
+            "access-control-allow-origin: https://remote.example\r\nvary: origin\r\n"
+        );
+        let evil = request(&[host, ("origin", "https://evil.example")]);
+        assert!(!evil.trusted(&config));
+        assert_eq!(evil.cors_headers(&config), "");
+        assert_eq!(request(&[host]).cors_headers(&config), "");
+    }
+
+    #[test]
+    fn tokens_go_in_the_authorization_header() {
+        let config = Config {
+            tokens: [("viewer".into(), Scope::Read)].into(),
+            ..config("0.0.0.0:7878", Some("secret"))
+        };
+        let scope = |auth| request(&[("authorization", auth)]).scope(&config);
+        assert_eq!(scope("Bearer secret"), Some(Scope::Control));
+        assert_eq!(scope("Bearer viewer"), Some(Scope::Read));
+        assert_eq!(scope("Bearer secreT"), None);
+        assert_eq!(scope("Bearer "), None);
+        assert_eq!(scope("secret"), None);
+        assert_eq!(request(&[]).scope(&config), None);
+    }
+
+    #[test]
+    fn scopes_allow_what_they_say() {
+        for name in READ.iter().chain(CONTROL) {
+            assert!(
+                include_str!("../../fixtures/v1/messages.jsonl").contains(&format!("\"{name}\"")),
+                "{name} is not a message"
+            );
+        }
+        assert!(allows(Scope::Read, "Current"));
+        assert!(!allows(Scope::Read, "Pause"));
+        assert!(allows(Scope::Control, "Current"));
+        assert!(allows(Scope::Control, "Pause"));
+        for name in ["SetEndOfQueue", "Create", "SetPropertyRaw"] {
+            assert!(!allows(Scope::Control, name));
+            assert!(allows(Scope::Admin, name));
+        }
+    }
+
+    #[tokio::test]
+    async fn masked_frames_are_unmasked() {
+        // "Hello" from a client, from RFC 6455
+        let bytes: &[u8] = &[
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let (opcode, payload) = read_frame(&mut &bytes[..]).await.unwrap();
+        assert_eq!(opcode, TEXT);
+        assert_eq!(payload, b"Hello");
+        assert_eq!(
+            frame(TEXT, b"Hello"),
+            [0x81, 0x05, b'H', b'e', b'l', b'l', b'o']
+        );
+    }
+}
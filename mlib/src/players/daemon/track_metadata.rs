@@ -0,0 +1,103 @@
+//! Putting together a [`TrackMetadata`] from mpv's `metadata` property and from the json yt-dlp
+//! printed when mpv's ytdl hook opened the file.
+
+use crate::players::TrackMetadata;
+use libmpv::MpvNode;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Where the ytdl hook keeps what yt-dlp printed.
+pub(super) const YTDL_RESULT: &str = "user-data/mpv/ytdl/json-subprocess-result";
+
+/// The fields of yt-dlp's json that are used.
+#[derive(Debug, Deserialize)]
+struct YtdlInfo {
+    title: Option<String>,
+    artist: Option<String>,
+    uploader: Option<String>,
+    album: Option<String>,
+    duration: Option<f64>,
+    upload_date: Option<String>,
+}
+
+/// The metadata in the tags of the file, from mpv's `metadata` property.
+pub(super) fn from_tags(tags: &MpvNode) -> TrackMetadata {
+    let mut metadata = TrackMetadata::default();
+    for (k, v) in tags.to_map().into_iter().flatten() {
+        let field = match k.to_lowercase().as_str() {
+            "title" => &mut metadata.title,
+            "artist" => &mut metadata.uploader,
+            "album" => &mut metadata.album,
+            "date" => &mut metadata.upload_date,
+            _ => continue,
+        };
+        *field = v.to_str().filter(|s| !s.is_empty()).map(String::from);
+    }
+    metadata
+}
+
+/// The metadata in the subprocess result the ytdl hook left in [`YTDL_RESULT`].
+pub(super) fn from_ytdl_result(result: &MpvNode) -> Option<TrackMetadata> {
+    let (_, stdout) = result.to_map()?.find(|(k, _)| *k == "stdout")?;
+    from_ytdl_json(stdout.to_str()?)
+}
+
+fn from_ytdl_json(json: &str) -> Option<TrackMetadata> {
+    let info = match serde_json::from_str::<YtdlInfo>(json) {
+        Ok(info) => info,
+        Err(e) => {
+            tracing::warn!(error = ?e, "failed to parse yt-dlp's json");
+            return None;
+        }
+    };
+    Some(TrackMetadata {
+        title: info.title,
+        uploader: info.artist.or(info.uploader),
+        album: info.album,
+        duration: info
+            .duration
+            .and_then(|d| Duration::try_from_secs_f64(d).ok()),
+        upload_date: info.upload_date,
+    })
+}
+
+/// Fills what `metadata` is missing from `fallback`.
+pub(super) fn or(metadata: TrackMetadata, fallback: TrackMetadata) -> TrackMetadata {
+    TrackMetadata {
+        title: metadata.title.or(fallback.title),
+        uploader: metadata.uploader.or(fallback.uploader),
+        album: metadata.album.or(fallback.album),
+        duration: metadata.duration.or(fallback.duration),
+        upload_date: metadata.upload_date.or(fallback.upload_date),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tags_win_over_ytdl() {
+        let json = r#"{
+            "id": "dQw4w9WgXcQ",
+            "title": "Rick Astley - Never Gonna Give You Up (Official Music Video)",
+            "uploader": "Rick Astley",
+            "duration": 212.0,
+            "upload_date": "20091025"
+        }"#;
+        let tags = TrackMetadata {
+            title: Some("Never Gonna Give You Up".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            or(tags, from_ytdl_json(json).unwrap()),
+            TrackMetadata {
+                title: Some("Never Gonna Give You Up".into()),
+                uploader: Some("Rick Astley".into()),
+                album: None,
+                duration: Some(Duration::from_secs(212)),
+                upload_date: Some("20091025".into()),
+            }
+        );
+    }
+}
@@ -1,6 +1,8 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 
+use super::LoopStatus;
+
 #[cfg(feature = "player")]
 use super::error::MpvResult;
 #[cfg(feature = "player")]
@@ -14,16 +16,38 @@ use std::{future::Future, sync::Weak, thread, time::Duration};
 use tokio::sync::broadcast;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct PlayerEvent {
     pub player_index: usize,
+    #[serde(deserialize_with = "event_or_unknown")]
     pub event: OwnedLibMpvEvent,
 }
 
+/// Events added by newer daemons become [`OwnedLibMpvEvent::Unknown`]. `#[serde(other)]` only
+/// covers the ones without data, for the others the tag is checked on its own.
+fn event_or_unknown<'de, D: Deserializer<'de>>(d: D) -> Result<OwnedLibMpvEvent, D::Error> {
+    let value = serde_json::Value::deserialize(d)?;
+    let tag = match &value {
+        serde_json::Value::Object(o) if o.len() == 1 => o.keys().next().cloned(),
+        _ => None,
+    };
+    match OwnedLibMpvEvent::deserialize(value) {
+        Ok(event) => Ok(event),
+        Err(e) => match tag.map(|t| OwnedLibMpvEvent::deserialize(serde_json::Value::String(t))) {
+            Some(Ok(OwnedLibMpvEvent::Unknown)) => Ok(OwnedLibMpvEvent::Unknown),
+            _ => Err(serde::de::Error::custom(e)),
+        },
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum OwnedLibMpvEvent {
     /// Received when the player is shutting down
+    #[serde(rename = "Shutdown")]
     Shutdown,
     /// *Has not been tested*, received when explicitly asked to MPV
+    #[serde(rename = "LogMessage")]
     LogMessage {
         prefix: String,
         level: String,
@@ -31,41 +55,82 @@ pub enum OwnedLibMpvEvent {
         log_level: u32,
     },
     /// Received when using get_property_async
+    #[serde(rename = "GetPropertyReply")]
     GetPropertyReply {
         name: String,
         result: OwnedMpvNode,
         reply_userdata: u64,
     },
     /// Received when using set_property_async
+    #[serde(rename = "SetPropertyReply")]
     SetPropertyReply(u64),
     /// Received when using command_async
+    #[serde(rename = "CommandReply")]
     CommandReply(u64),
     /// Event received when a new file is playing
+    #[serde(rename = "StartFile")]
     StartFile,
     /// Event received when the file being played currently has stopped, for an error or not
+    #[serde(rename = "EndFile")]
     EndFile(u32),
     /// Event received when a file has been *loaded*, but has not been started
+    #[serde(rename = "FileLoaded")]
     FileLoaded,
+    #[serde(rename = "ClientMessage")]
     ClientMessage(Vec<Box<str>>),
+    #[serde(rename = "VideoReconfig")]
     VideoReconfig,
+    #[serde(rename = "AudioReconfig")]
     AudioReconfig,
     /// The player changed current position
+    #[serde(rename = "Seek")]
     Seek,
+    #[serde(rename = "PlaybackRestart")]
     PlaybackRestart,
     /// Received when used with observe_property
+    #[serde(rename = "PropertyChange")]
     PropertyChange {
         name: String,
         change: OwnedMpvNode,
         reply_userdata: u64,
     },
     /// Received when the Event Queue is full
+    #[serde(rename = "QueueOverflow")]
     QueueOverflow,
     /// A deprecated event
-    Deprecated {
-        event_id: u32,
-    },
+    #[serde(rename = "Deprecated")]
+    Deprecated { event_id: u32 },
     /// Emited when an error occurred while receiving an event.
+    #[serde(rename = "Errored")]
     Errored(String),
+    /// Emited by the daemon when it changed the queue, mpv has no event for this.
+    #[serde(rename = "QueueChanged")]
+    QueueChanged(QueueChange),
+    /// Emited by the daemon when it started or stopped looping the queue.
+    #[serde(rename = "LoopChanged")]
+    LoopChanged(LoopStatus),
+    /// Emited by the daemon when it shuffled the queue.
+    #[serde(rename = "Shuffled")]
+    Shuffled,
+    /// Emited by the daemon right before it quits a player that was idle for `idle_for`.
+    #[serde(rename = "IdleQuit")]
+    IdleQuit { idle_for: std::time::Duration },
+    /// An event from a newer daemon that this version doesn't know about.
+    #[serde(other, rename = "Unknown")]
+    Unknown,
+}
+
+/// How the queue was changed, entries are identified by their mpv playlist entry id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum QueueChange {
+    /// An entry was added after the entry `after`, or at the start if `None`.
+    #[serde(rename = "Added")]
+    Added { id: usize, after: Option<usize> },
+    #[serde(rename = "Removed")]
+    Removed { id: usize },
+    /// The queue was reordered or cleared.
+    #[serde(rename = "Replaced")]
+    Replaced,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -234,12 +299,23 @@ impl From<Event<'_>> for OwnedLibMpvEvent {
 }
 
 #[cfg(feature = "player")]
-pub(super) struct EventSubscriber(broadcast::Sender<PlayerEvent>);
+pub(super) struct EventSubscriber {
+    tx: broadcast::Sender<PlayerEvent>,
+    player_index: usize,
+}
 
 #[cfg(feature = "player")]
 impl EventSubscriber {
     pub fn subscribe(&self) -> broadcast::Receiver<PlayerEvent> {
-        self.0.subscribe()
+        self.tx.subscribe()
+    }
+
+    /// Sends an event that didn't come from mpv to the subscribers.
+    pub fn send(&self, event: OwnedLibMpvEvent) {
+        let _ = self.tx.send(PlayerEvent {
+            player_index: self.player_index,
+            event,
+        });
     }
 }
 
@@ -269,7 +345,7 @@ where
                 events.enable_event(events::mpv_event_id::Shutdown)?;
                 events.enable_event(events::mpv_event_id::FileLoaded)?;
                 events.enable_event(events::mpv_event_id::StartFile)?;
-                let mut first_event = true;
+                events.enable_event(events::mpv_event_id::EndFile)?;
                 loop {
                     let Some(ev) = events.wait_event(-1. /* never timeout */) else {
                         tracing::debug!("got none event");
@@ -284,14 +360,6 @@ where
                             tracing::info!(?player_index, "got shutdown event");
                             break;
                         }
-                        Event::PropertyChange {
-                            name: "playlist-pos",
-                            change: PropertyData::Int64(-1),
-                            reply_userdata: _,
-                        } if !first_event => {
-                            tracing::debug!("playlist-pos => -1");
-                            break;
-                        }
                         Event::Deprecated(_) => continue,
                         e => {
                             tracing::debug!(?player_index, event = ?e, "got event");
@@ -301,7 +369,6 @@ where
                         player_index,
                         event: ev.into(),
                     });
-                    first_event = false;
                 }
                 drop(mpv);
                 let _ = tx.send(PlayerEvent {
@@ -317,5 +384,5 @@ where
             }
         }
     });
-    EventSubscriber(tx)
+    EventSubscriber { tx, player_index }
 }
@@ -6,14 +6,21 @@ pub mod event;
 mod legacy_back_compat;
 #[cfg(feature = "player")]
 mod libmpv_parsing;
+#[cfg(feature = "pulse")]
+pub mod sink_watch;
+#[cfg(feature = "web-remote")]
+pub mod web_remote;
 
-use std::{fmt, io, ops::Deref, path::PathBuf, str::FromStr};
+use std::{collections::BTreeMap, fmt, io, ops::Deref, path::PathBuf, str::FromStr, time};
 
 use futures_util::Stream;
 use serde::{Deserialize, Serialize};
 
 use crate::Item;
 
+pub use cli_daemon::Transport as DaemonTransport;
+#[cfg(all(feature = "player", feature = "encryption"))]
+pub(crate) use daemon::resume_positions_path;
 #[cfg(feature = "player")]
 pub use daemon::start_daemon_if_running_as_daemon;
 pub use error::Error;
@@ -73,6 +80,10 @@ impl PlayerLink {
         }
     }
 
+    pub fn index(&self) -> PlayerIndex {
+        self.index
+    }
+
     pub fn linked_to(&self, user: String) -> Self {
         Self {
             index: self.index,
@@ -81,6 +92,51 @@ impl PlayerLink {
     }
 }
 
+/// Set what is given to the players daemon if this process has to start it, see
+/// [`start_daemon_if_running_as_daemon`].
+pub fn set_daemon_bootstrap<B: Serialize>(bootstrap: &B) {
+    connection::PLAYERS.set_bootstrap(bootstrap)
+}
+
+/// Talk to the players daemon in `namespace`, starting it there if needed. Players in different
+/// namespaces don't see each other.
+pub fn set_daemon_namespace(namespace: String) {
+    connection::PLAYERS.set_socket_namespace(namespace)
+}
+
+/// How the players daemon sets up new players and what it does on its own, given to
+/// [`start_daemon_if_running_as_daemon`]. Only the process that becomes the daemon uses them.
+#[cfg(feature = "player")]
+#[derive(Debug, Default, Clone)]
+pub struct DaemonSettings {
+    /// Quit players once they have been paused, or done with their queue, for this long.
+    pub quit_idle_after: Option<time::Duration>,
+    /// Which of the queued songs new players download before they play.
+    pub precache: PrecachePolicy,
+    /// Pause other media players on the session bus, like browsers, when a player starts playing
+    /// or is unpaused.
+    #[cfg(feature = "pause-others")]
+    pub pause_other_players: bool,
+    /// Pause the players when the audio output they play on goes away.
+    #[cfg(feature = "pulse")]
+    pub pause_on_unplug: Option<sink_watch::Config>,
+    /// Serve the web remote.
+    #[cfg(feature = "web-remote")]
+    pub web_remote: Option<web_remote::Config>,
+    /// Where to scrobble to, nowhere by default.
+    #[cfg(feature = "scrobble")]
+    pub scrobble: crate::scrobble::Config,
+    /// Make new players skip segments, see [`PlayerLink::set_sponsorblock`].
+    #[cfg(feature = "sponsorblock")]
+    pub sponsorblock: Option<crate::sponsorblock::Config>,
+}
+
+/// Set how the players daemon is reached, which is also how it listens if this process starts it
+/// or becomes it, see [`start_daemon_if_running_as_daemon`].
+pub fn set_daemon_transport(transport: DaemonTransport) {
+    connection::PLAYERS.set_transport(transport)
+}
+
 impl From<PlayerIndex> for PlayerLink {
     fn from(index: PlayerIndex) -> Self {
         Self {
@@ -109,74 +165,515 @@ impl Message {
     const fn new(index: PlayerIndex, kind: MessageKind) -> Self {
         Self { index, kind }
     }
-    const fn create(items: Vec<Item>, with_video: bool) -> Self {
-        Self::new(PlayerIndex(None), MessageKind::Create { items, with_video })
+    const fn create(
+        items: Vec<Item>,
+        with_video: bool,
+        profile: Option<PlayerProfile>,
+        options: Vec<(String, String)>,
+        background: bool,
+        name: Option<String>,
+    ) -> Self {
+        Self::new(
+            PlayerIndex(None),
+            MessageKind::Create {
+                items,
+                with_video,
+                profile,
+                options,
+                background,
+                name,
+            },
+        )
     }
 }
 
+/// The commands that can be sent to every player at once, see [`for_all`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[non_exhaustive]
+pub enum Broadcast {
+    #[serde(rename = "CyclePause")]
+    CyclePause,
+    #[serde(rename = "Pause")]
+    Pause,
+    #[serde(rename = "Resume")]
+    Resume,
+    #[serde(rename = "Quit")]
+    Quit,
+    #[serde(rename = "StepVolume")]
+    StepVolume { delta: f64, curve: VolumeCurve },
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Direction {
     Next,
     Prev,
 }
 
+/// What to do when a sleep timer expires.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum StopOrPause {
+    Stop,
+    Pause,
+}
+
+/// How volume steps are spread over the range of volumes.
+#[derive(
+    Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeCurve {
+    /// Every step changes the volume by the same amount.
+    #[default]
+    Linear,
+    /// Steps are taken on a cubic scale, which is close to how loudness (in dB) is perceived, so
+    /// steps are smaller at low volumes.
+    Perceptual,
+}
+
+impl VolumeCurve {
+    fn position_of(self, volume: f64) -> f64 {
+        match self {
+            Self::Linear => volume,
+            Self::Perceptual => 100. * (volume / 100.).cbrt(),
+        }
+    }
+
+    fn volume_at(self, position: f64) -> f64 {
+        match self {
+            Self::Linear => position,
+            Self::Perceptual => 100. * (position / 100.).powi(3),
+        }
+    }
+
+    /// The volume after taking a step of `delta` from `volume`, never below 0.
+    pub fn step(self, volume: f64, delta: f64) -> f64 {
+        self.volume_at(self.position_of(volume.max(0.)) + delta)
+            .max(0.)
+    }
+}
+
+/// What a player does once it has played the last song in its queue.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum EndOfQueuePolicy {
+    /// Stay open, songs queued afterwards start playing right away.
+    Stop,
+    /// Start again from the first song.
+    Loop,
+    /// Shut the player down.
+    #[default]
+    Quit,
+    /// Keep going with the youtube mix of the last song.
+    Radio,
+    /// Run a shell command, with the player's index in `$M_PLAYER` and the last song in
+    /// `$M_LAST`.
+    Hook(String),
+}
+
+impl FromStr for EndOfQueuePolicy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stop" => Ok(Self::Stop),
+            "loop" => Ok(Self::Loop),
+            "quit" => Ok(Self::Quit),
+            "radio" => Ok(Self::Radio),
+            _ => match s.strip_prefix("hook:") {
+                Some(cmd) if !cmd.trim().is_empty() => Ok(Self::Hook(cmd.into())),
+                _ => Err(format!(
+                    "Expected one of 'stop', 'loop', 'quit', 'radio' or 'hook:<command>' but got {s}"
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for EndOfQueuePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stop => f.write_str("stop"),
+            Self::Loop => f.write_str("loop"),
+            Self::Quit => f.write_str("quit"),
+            Self::Radio => f.write_str("radio"),
+            Self::Hook(cmd) => write!(f, "hook:{cmd}"),
+        }
+    }
+}
+
+/// Which of the queued songs a player downloads before they play, so that they don't have to be
+/// streamed.
+#[derive(
+    Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PrecachePolicy {
+    /// None of them.
+    Off,
+    /// The current song and the `n` after it.
+    Next(usize),
+    /// All of them.
+    #[default]
+    Queue,
+}
+
+impl FromStr for PrecachePolicy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "queue" => Ok(Self::Queue),
+            _ => s.parse().map(Self::Next).map_err(|_| {
+                format!("Expected one of 'off', 'queue' or a number of songs but got {s}")
+            }),
+        }
+    }
+}
+
+impl fmt::Display for PrecachePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => f.write_str("off"),
+            Self::Next(n) => write!(f, "{n}"),
+            Self::Queue => f.write_str("queue"),
+        }
+    }
+}
+
+/// How a new player is set up, anything left out is set up like any other player.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PlayerProfile {
+    /// The size and position of the window, in mpv's `--geometry` format. `820x466` by default.
+    #[serde(default)]
+    pub geometry: Option<String>,
+    /// The volume it starts at.
+    #[serde(default)]
+    pub volume: Option<u32>,
+    /// Whether mpv's on screen controller is shown, it is by default.
+    #[serde(default)]
+    pub osc: Option<bool>,
+    /// Other mpv options, e.g. `{ loop-file = "inf" }`. Only some options are allowed, the ones
+    /// the daemon sets up itself or that can make mpv run arbitrary things aren't.
+    #[serde(default)]
+    pub mpv_options: BTreeMap<String, String>,
+}
+
+/// The version of the messages to and from the players daemon. It matches the fixtures in
+/// `fixtures/` and has to be bumped, with new fixtures, if they stop deserializing.
+const PROTOCOL_VERSION: u32 = 1;
+
+/// Variant names are part of the wire protocol, so they are pinned with explicit renames. Any
+/// change to the serialized form must keep the fixtures in `fixtures/` deserializing.
 #[derive(Debug, Serialize, Deserialize)]
 enum MessageKind {
     // meta
-    Create { items: Vec<Item>, with_video: bool },
+    #[serde(rename = "Create")]
+    Create {
+        items: Vec<Item>,
+        with_video: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        profile: Option<PlayerProfile>,
+        /// More mpv options, which take precedence over the profile's.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        options: Vec<(String, String)>,
+        /// Don't make the new player the current one, unless there is no other.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        background: bool,
+        /// What to call the new player. It isn't created if another one is already called that.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+    #[serde(rename = "PlayerList")]
     PlayerList,
+    #[serde(rename = "LastQueue")]
     LastQueue,
+    #[serde(rename = "LastClear")]
     LastClear,
+    #[serde(rename = "LastQueueSet")]
     LastQueueSet { to: usize },
+    #[serde(rename = "Current")]
     Current,
     // actions
+    #[serde(rename = "CyclePause")]
     CyclePause,
+    #[serde(rename = "Pause")]
     Pause,
+    #[serde(rename = "Resume")]
     Resume,
+    #[serde(rename = "QueueClear")]
     QueueClear,
+    #[serde(rename = "LoadFile")]
     LoadFile { item: Item },
+    #[serde(rename = "LoadFileAt")]
+    LoadFileAt { item: Item, at: usize },
+    #[serde(rename = "LoadFileWithOptions")]
+    LoadFileWithOptions {
+        item: Item,
+        options: Vec<(String, String)>,
+        at: Option<usize>,
+    },
+    #[serde(rename = "LoadList")]
     LoadList { path: PathBuf },
+    #[serde(rename = "QueueMove")]
     QueueMove { from: usize, to: usize },
+    #[serde(rename = "QueueSwap")]
+    QueueSwap { a: usize, b: usize },
+    #[serde(rename = "QueueReorder")]
+    QueueReorder { order: Vec<usize> },
+    #[serde(rename = "ForAll")]
+    ForAll { command: Broadcast },
+    #[serde(rename = "ResolveName")]
+    ResolveName { name: String },
+    #[serde(rename = "IdleFor")]
+    IdleFor,
+    #[serde(rename = "SetPrecache")]
+    SetPrecache { policy: PrecachePolicy },
+    #[serde(rename = "Precache")]
+    Precache,
+    #[serde(rename = "JumpTo")]
+    JumpTo { pos: usize },
+    #[serde(rename = "QueueRemove")]
     QueueRemove { to_remove: usize },
+    #[serde(rename = "QueueLoop")]
     QueueLoop { start_looping: bool },
+    #[serde(rename = "QueueShuffle")]
     QueueShuffle,
+    #[serde(rename = "Quit")]
     Quit,
+    #[serde(rename = "ChangeVolume")]
     ChangeVolume { delta: i32 },
+    #[serde(rename = "CycleVideo")]
     CycleVideo,
+    #[serde(rename = "ChangeFile")]
     ChangeFile { direction: Direction },
+    #[serde(rename = "HistoryBack")]
+    HistoryBack,
+    #[serde(rename = "HistoryForward")]
+    HistoryForward,
+    #[serde(rename = "Seek")]
     Seek { seconds: f64 },
+    #[serde(rename = "ChangeChapter")]
     ChangeChapter { direction: Direction, amount: i32 },
+    #[serde(rename = "SetSleepTimer")]
+    SetSleepTimer {
+        after: time::Duration,
+        action: StopOrPause,
+    },
+    #[serde(rename = "CancelSleepTimer")]
+    CancelSleepTimer,
+    #[serde(rename = "SetFade")]
+    SetFade { length: Option<time::Duration> },
+    #[serde(rename = "StepVolume")]
+    StepVolume { delta: f64, curve: VolumeCurve },
+    #[serde(rename = "TakeFailed")]
+    TakeFailed { at: Option<usize> },
+    #[serde(rename = "ForgetFailed")]
+    ForgetFailed { item: Item },
+    #[serde(rename = "SetEndOfQueue")]
+    SetEndOfQueue { policy: EndOfQueuePolicy },
+    #[serde(rename = "SetName")]
+    SetName { name: Option<String> },
+    #[serde(rename = "SetFocus")]
+    SetFocus { focus: Option<Focus> },
+    #[serde(rename = "StartRadio")]
+    StartRadio { radio: Radio },
+    #[serde(rename = "StopRadio")]
+    StopRadio,
+    #[serde(rename = "SetEqualizer")]
+    SetEqualizer { bands: Vec<f32> },
+    #[serde(rename = "SetResume")]
+    SetResume { resume: bool },
+    #[serde(rename = "SetSponsorBlock")]
+    SetSponsorBlock { on: bool },
+    #[serde(rename = "SetPropertyRaw")]
+    SetPropertyRaw {
+        name: String,
+        value: serde_json::Value,
+    },
     // getters
+    #[serde(rename = "ChapterMetadata")]
     ChapterMetadata,
+    #[serde(rename = "Filename")]
     Filename,
+    #[serde(rename = "IsPaused")]
     IsPaused,
+    #[serde(rename = "MediaTitle")]
     MediaTitle,
+    #[serde(rename = "PercentPosition")]
     PercentPosition,
+    #[serde(rename = "Queue")]
     Queue,
+    #[serde(rename = "QueueIsLooping")]
     QueueIsLooping,
+    #[serde(rename = "QueuePos")]
     QueuePos,
+    #[serde(rename = "QueueSize")]
     QueueSize,
+    #[serde(rename = "Volume")]
     Volume,
+    #[serde(rename = "QueueNFilename")]
     QueueNFilename { at: usize },
+    #[serde(rename = "QueueN")]
     QueueN { at: usize },
+    #[serde(rename = "QueueRange")]
+    QueueRange { from: usize, to: usize },
+    #[serde(rename = "Duration")]
     Duration,
+    #[serde(rename = "PlaybackTime")]
     PlaybackTime,
+    #[serde(rename = "SleepTimerStatus")]
+    SleepTimerStatus,
+    #[serde(rename = "FailedItems")]
+    FailedItems,
+    #[serde(rename = "EndOfQueue")]
+    EndOfQueue,
+    #[serde(rename = "Name")]
+    Name,
+    #[serde(rename = "Focus")]
+    Focus,
+    #[serde(rename = "GetEqualizer")]
+    GetEqualizer,
+    #[serde(rename = "TrackMetadata")]
+    TrackMetadata,
+    #[serde(rename = "GetPropertyRaw")]
+    GetPropertyRaw { name: String },
+    #[serde(rename = "SponsorBlock")]
+    SponsorBlock,
+}
+
+impl MessageKind {
+    /// The name of the variant on the wire, which names it in the daemon's metrics.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Create { .. } => "Create",
+            Self::PlayerList => "PlayerList",
+            Self::LastQueue => "LastQueue",
+            Self::LastClear => "LastClear",
+            Self::LastQueueSet { .. } => "LastQueueSet",
+            Self::Current => "Current",
+            Self::CyclePause => "CyclePause",
+            Self::Pause => "Pause",
+            Self::Resume => "Resume",
+            Self::QueueClear => "QueueClear",
+            Self::LoadFile { .. } => "LoadFile",
+            Self::LoadFileAt { .. } => "LoadFileAt",
+            Self::LoadFileWithOptions { .. } => "LoadFileWithOptions",
+            Self::LoadList { .. } => "LoadList",
+            Self::QueueMove { .. } => "QueueMove",
+            Self::QueueSwap { .. } => "QueueSwap",
+            Self::QueueReorder { .. } => "QueueReorder",
+            Self::ForAll { .. } => "ForAll",
+            Self::ResolveName { .. } => "ResolveName",
+            Self::IdleFor => "IdleFor",
+            Self::SetPrecache { .. } => "SetPrecache",
+            Self::Precache => "Precache",
+            Self::JumpTo { .. } => "JumpTo",
+            Self::QueueRemove { .. } => "QueueRemove",
+            Self::QueueLoop { .. } => "QueueLoop",
+            Self::QueueShuffle => "QueueShuffle",
+            Self::Quit => "Quit",
+            Self::ChangeVolume { .. } => "ChangeVolume",
+            Self::CycleVideo => "CycleVideo",
+            Self::ChangeFile { .. } => "ChangeFile",
+            Self::HistoryBack => "HistoryBack",
+            Self::HistoryForward => "HistoryForward",
+            Self::Seek { .. } => "Seek",
+            Self::ChangeChapter { .. } => "ChangeChapter",
+            Self::SetSleepTimer { .. } => "SetSleepTimer",
+            Self::CancelSleepTimer => "CancelSleepTimer",
+            Self::SetFade { .. } => "SetFade",
+            Self::StepVolume { .. } => "StepVolume",
+            Self::TakeFailed { .. } => "TakeFailed",
+            Self::ForgetFailed { .. } => "ForgetFailed",
+            Self::SetEndOfQueue { .. } => "SetEndOfQueue",
+            Self::SetName { .. } => "SetName",
+            Self::SetFocus { .. } => "SetFocus",
+            Self::StartRadio { .. } => "StartRadio",
+            Self::StopRadio => "StopRadio",
+            Self::SetEqualizer { .. } => "SetEqualizer",
+            Self::SetResume { .. } => "SetResume",
+            Self::SetSponsorBlock { .. } => "SetSponsorBlock",
+            Self::SetPropertyRaw { .. } => "SetPropertyRaw",
+            Self::ChapterMetadata => "ChapterMetadata",
+            Self::Filename => "Filename",
+            Self::IsPaused => "IsPaused",
+            Self::MediaTitle => "MediaTitle",
+            Self::PercentPosition => "PercentPosition",
+            Self::Queue => "Queue",
+            Self::QueueIsLooping => "QueueIsLooping",
+            Self::QueuePos => "QueuePos",
+            Self::QueueSize => "QueueSize",
+            Self::Volume => "Volume",
+            Self::QueueNFilename { .. } => "QueueNFilename",
+            Self::QueueN { .. } => "QueueN",
+            Self::QueueRange { .. } => "QueueRange",
+            Self::Duration => "Duration",
+            Self::PlaybackTime => "PlaybackTime",
+            Self::SleepTimerStatus => "SleepTimerStatus",
+            Self::FailedItems => "FailedItems",
+            Self::EndOfQueue => "EndOfQueue",
+            Self::Name => "Name",
+            Self::Focus => "Focus",
+            Self::GetEqualizer => "GetEqualizer",
+            Self::TrackMetadata => "TrackMetadata",
+            Self::GetPropertyRaw { .. } => "GetPropertyRaw",
+            Self::SponsorBlock => "SponsorBlock",
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 enum Response {
+    #[serde(rename = "Create")]
     Create(PlayerIndex),
+    #[serde(rename = "Metadata")]
     Metadata(Metadata),
+    #[serde(rename = "MaybeMetadata")]
     MaybeMetadata(Option<Metadata>),
+    #[serde(rename = "Bool")]
     Bool(bool),
+    #[serde(rename = "Text")]
     Text(String),
+    #[serde(rename = "Real")]
     Real(f64),
+    #[serde(rename = "Item")]
     Item(QueueItem),
+    #[serde(rename = "Items")]
     Items(Vec<QueueItem>),
+    #[serde(rename = "Integer")]
     Integer(i64),
+    #[serde(rename = "LoopStatus")]
     LoopStatus(LoopStatus),
+    #[serde(rename = "PlayerList")]
     PlayerList(Vec<PlayerIndex>),
+    #[serde(rename = "MaybeInteger")]
     MaybeInteger(Option<usize>),
+    #[serde(rename = "MaybeSleepTimer")]
+    MaybeSleepTimer(Option<SleepTimer>),
+    #[serde(rename = "MaybeHistoryEntry")]
+    MaybeHistoryEntry(Option<HistoryEntry>),
+    #[serde(rename = "FailedItems")]
+    FailedItems(Vec<FailedItem>),
+    #[serde(rename = "EndOfQueuePolicy")]
+    EndOfQueuePolicy(EndOfQueuePolicy),
+    #[serde(rename = "MaybeText")]
+    MaybeText(Option<String>),
+    #[serde(rename = "SkippedItems")]
+    SkippedItems(Vec<SkippedItem>),
+    #[serde(rename = "MaybeFocus")]
+    MaybeFocus(Option<Focus>),
+    #[serde(rename = "Bands")]
+    Bands(Vec<f32>),
+    #[serde(rename = "TrackMetadata")]
+    TrackMetadata(TrackMetadata),
+    #[serde(rename = "Json")]
+    Json(serde_json::Value),
+    #[serde(rename = "MaybeDuration")]
+    MaybeDuration(Option<time::Duration>),
+    #[serde(rename = "PrecachePolicy")]
+    PrecachePolicy(PrecachePolicy),
+    #[serde(rename = "PerPlayer")]
+    PerPlayer(Vec<(PlayerIndex, Result<(), error::MpvError>)>),
+    #[serde(rename = "Unit")]
     Unit,
 }
 
@@ -186,6 +683,78 @@ pub struct Metadata {
     pub index: usize,
 }
 
+/// What is known about the file that is playing, from its tags or, failing that, from yt-dlp.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackMetadata {
+    pub title: Option<String>,
+    /// The artist, or who uploaded it if it has no artist.
+    pub uploader: Option<String>,
+    pub album: Option<String>,
+    pub duration: Option<time::Duration>,
+    /// As given by the tags or by yt-dlp, which uses `YYYYMMDD`.
+    pub upload_date: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SleepTimer {
+    pub remaining: time::Duration,
+    pub action: StopOrPause,
+}
+
+/// A song that was played before, see [`PlayerLink::history_back`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Its current position in the queue.
+    pub index: usize,
+    pub played_at: time::SystemTime,
+}
+
+/// A song the player failed to play.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedItem {
+    pub item: Item,
+    pub reason: String,
+    pub failed_at: time::SystemTime,
+}
+
+/// Songs that don't fit are skipped as soon as they start, see [`PlayerLink::set_focus`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Focus {
+    /// Skip songs longer than this.
+    pub max_duration: Option<time::Duration>,
+    /// Skip songs that aren't in any of these categories, or their children. Empty lets all
+    /// songs through.
+    pub categories: Vec<String>,
+}
+
+/// A song skipped because of [`Focus`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedItem {
+    pub item: Item,
+    pub reason: String,
+    pub skipped_at: time::SystemTime,
+}
+
+/// The frequencies, in Hz, of the bands of the equalizer, see [`PlayerLink::set_equalizer`].
+pub const EQUALIZER_BANDS: [u32; 10] = [31, 62, 125, 250, 500, 1000, 2000, 4000, 8000, 16000];
+
+/// Keeps the queue from running out with songs like `seed`, see [`PlayerLink::start_radio`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Radio {
+    pub seed: RadioSeed,
+    /// Queue more songs when fewer than this are left after the current one.
+    pub threshold: usize,
+}
+
+/// Where the songs of a [`Radio`] come from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RadioSeed {
+    /// The youtube mix of this song, and then of the last song the radio queued.
+    Song(Item),
+    /// The youtube mixes of songs from this category of the playlist, or its children.
+    Category(String),
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QueueItem {
     pub filename: String,
@@ -193,6 +762,12 @@ pub struct QueueItem {
     pub id: usize,
 }
 
+impl QueueItem {
+    pub fn to_item(&self) -> Item {
+        Item::from_mpv_filename(self.filename.clone())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct QueueItemStatus {
     pub current: bool,
@@ -291,17 +866,57 @@ pub async fn subscribe() -> Result<impl Stream<Item = io::Result<PlayerEvent>>,
     Ok(connection::PLAYERS.subscribe().await?)
 }
 
+/// Checks that the players daemon is running, without starting it.
+pub async fn ping_daemon() -> Result<cli_daemon::Pong, Error> {
+    Ok(connection::PLAYERS.ping().await?)
+}
+
+/// The version of mlib the running players daemon was built with.
+pub async fn daemon_version() -> Result<Option<String>, Error> {
+    Ok(connection::PLAYERS.version().await?)
+}
+
+/// What the players daemon has done since it started, by kind of message.
+pub async fn daemon_metrics() -> Result<cli_daemon::Metrics, Error> {
+    Ok(connection::PLAYERS.metrics().await?)
+}
+
+/// The last `lines` lines the players daemon logged, if it was started by m.
+pub async fn daemon_log_tail(lines: usize) -> Result<Vec<String>, Error> {
+    Ok(connection::PLAYERS.log_tail(lines).await?)
+}
+
+/// Makes the players daemon exit, which quits every player.
+pub async fn stop_daemon() -> Result<(), Error> {
+    Ok(connection::PLAYERS.shutdown().await?)
+}
+
 pub async fn wait_for_music_daemon_to_start() {
     connection::PLAYERS.wait_for_daemon_to_spawn().await;
 }
 
-/// Create a new player instance, with the given items
+/// Create a new player instance, with the given items, set up like `profile` says if given and
+/// with the extra mpv `options`. Only some mpv options are allowed.
+///
+/// The new player becomes the current one, unless `background` is set and there already is a
+/// current player. If given a `name`, it fails when another player is already called that.
 pub async fn create(
     items: impl Iterator<Item = &Item>,
     with_video: bool,
+    profile: Option<PlayerProfile>,
+    options: Vec<(String, String)>,
+    background: bool,
+    name: Option<String>,
 ) -> Result<PlayerIndex, Error> {
     match connection::PLAYERS
-        .exchange(Message::create(items.cloned().collect(), with_video))
+        .exchange(Message::create(
+            items.cloned().collect(),
+            with_video,
+            profile,
+            options,
+            background,
+            name,
+        ))
         .await??
     {
         Response::Create(index) => Ok(index),
@@ -309,6 +924,36 @@ pub async fn create(
     }
 }
 
+/// Sends `command` to every running player, returning how it went for each of them
+pub async fn for_all(
+    command: Broadcast,
+) -> Result<Vec<(PlayerIndex, Result<(), error::MpvError>)>, Error> {
+    match connection::PLAYERS
+        .exchange(Message::new(
+            PlayerIndex(None),
+            MessageKind::ForAll { command },
+        ))
+        .await??
+    {
+        Response::PerPlayer(results) => Ok(results),
+        x => panic!("invalid response: {x:?}"),
+    }
+}
+
+/// The player called `name`, if there is one. See [`PlayerLink::set_name`].
+pub async fn resolve_name(name: String) -> Result<Option<PlayerIndex>, Error> {
+    match connection::PLAYERS
+        .exchange(Message::new(
+            PlayerIndex(None),
+            MessageKind::ResolveName { name },
+        ))
+        .await??
+    {
+        Response::MaybeInteger(index) => Ok(index.map(PlayerIndex::of)),
+        x => panic!("invalid response: {x:?}"),
+    }
+}
+
 /// List all running player indexes
 pub async fn all() -> Result<Vec<PlayerLink>, Error> {
     match PlayerLink::current()
@@ -333,7 +978,33 @@ pub async fn current() -> Result<Option<usize>, Error> {
 }
 
 pub struct SmartQueueOpts {
-    pub no_move: bool,
+    pub placement: QueuePlacement,
+}
+
+/// Where [`PlayerLink::smart_queue`] puts a song.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum QueuePlacement {
+    /// After the current song, or after the last queued one if that's further along, so that
+    /// songs play in the order they were queued.
+    #[default]
+    Smart,
+    /// Right after the current song, before anything queued since.
+    Next,
+    /// At this position in the queue.
+    At(usize),
+    /// At the end of the queue.
+    End,
+}
+
+impl QueuePlacement {
+    /// Where the song queued after one that ended up at `moved_to` goes, so that songs queued
+    /// together with [`Next`](Self::Next) or [`At`](Self::At) keep their order.
+    pub fn after(self, moved_to: usize) -> Self {
+        match self {
+            Self::Next | Self::At(_) => Self::At(moved_to + 1),
+            other => other,
+        }
+    }
 }
 
 pub struct SmartQueueSummary {
@@ -348,10 +1019,16 @@ impl PlayerLink {
         item: Item,
         opts: SmartQueueOpts,
     ) -> Result<SmartQueueSummary, Error> {
+        if let QueuePlacement::Next | QueuePlacement::At(_) = opts.placement {
+            let len = self.queue_size().await?;
+            if len > 0 {
+                return self.insert(item, opts.placement, len).await;
+            }
+        }
         self.load_file(item.clone()).await?;
         let count = self.queue_size().await?;
         let current = self.queue_pos().await?;
-        let queue_summary = if opts.no_move {
+        let queue_summary = if opts.placement != QueuePlacement::Smart {
             SmartQueueSummary {
                 from: count,
                 moved_to: count,
@@ -386,6 +1063,33 @@ impl PlayerLink {
         };
         Ok(queue_summary)
     }
+
+    /// Queues `item` at an explicit position in a queue of `len` songs.
+    async fn insert(
+        &self,
+        item: Item,
+        placement: QueuePlacement,
+        len: usize,
+    ) -> Result<SmartQueueSummary, Error> {
+        let current = self.queue_pos().await?;
+        let target = match placement {
+            QueuePlacement::At(at) => at.min(len),
+            _ => current + 1,
+        };
+        self.load_file_at(item, target).await?;
+        // the daemon already moved the last queued position along if it was pushed down, but
+        // songs queued normally after this one should still go after it
+        if placement == QueuePlacement::Next
+            && self.last_queue().await?.map_or(true, |last| last < target)
+        {
+            self.last_queue_set(target).await?;
+        }
+        Ok(SmartQueueSummary {
+            from: len + 1,
+            moved_to: target,
+            current,
+        })
+    }
 }
 
 pub async fn smart_queue(item: Item, opts: SmartQueueOpts) -> Result<SmartQueueSummary, Error> {
@@ -409,10 +1113,25 @@ commands! {
     queue_clear as QueueClear;
     /// Adds a file to the queue.
     load_file as LoadFile { item: Item };
+    /// Adds a file to the queue at position `at`, or at the end if the queue is shorter.
+    load_file_at as LoadFileAt { item: Item, at: usize };
+    /// Adds a file to the queue, at position `at` or at the end, with mpv options that only apply
+    /// while it plays, like `start` and `end`.
+    load_file_with_options as LoadFileWithOptions {
+        item: Item,
+        options: Vec<(String, String)>,
+        at: Option<usize>
+    };
     /// Adds all items in a file to the queue.
     load_list as LoadList { path: PathBuf };
     /// Move an item from one postion to the another.
     queue_move as QueueMove { from: usize, to: usize };
+    /// Swap the items at two positions.
+    queue_swap as QueueSwap { a: usize, b: usize };
+    /// Put the item that is at `order[i]` at position `i`, `order` has to have every position.
+    queue_reorder as QueueReorder { order: Vec<usize> };
+    /// Play the item at this position.
+    queue_jump as JumpTo { pos: usize };
     /// Remove an item from the queue.
     queue_remove as QueueRemove { to_remove: usize };
     /// Change whether the queue should loop.
@@ -423,14 +1142,30 @@ commands! {
     quit as Quit;
     /// Changes the volume of the player
     change_volume as ChangeVolume { delta: i32 };
+    /// Changes the volume of the player by a step along a volume curve, `delta` can be
+    /// fractional.
+    step_volume as StepVolume { delta: f64, curve: VolumeCurve };
     /// Toggle video on and off
     toggle_video as CycleVideo;
     /// Change the currently playing file
     change_file as ChangeFile { direction: Direction };
+    /// Go back to the song that was playing before this one, regardless of queue order.
+    history_back as HistoryBack
+        / Response::MaybeHistoryEntry(e) => e => Option<HistoryEntry>;
+    /// Undo a [`PlayerLink::history_back`].
+    history_forward as HistoryForward
+        / Response::MaybeHistoryEntry(e) => e => Option<HistoryEntry>;
     /// Seek to a new point in the file
     seek as Seek { seconds: f64 };
     /// Jump to a chapter in the file
     change_chapter as ChangeChapter { direction: Direction, amount: i32 };
+    /// Pause or stop the player after some time
+    set_sleep_timer as SetSleepTimer { after: time::Duration, action: StopOrPause };
+    /// Cancel the sleep timer, returns whether there was one running.
+    cancel_sleep_timer as CancelSleepTimer
+        / Response::Bool(b) => b => bool;
+    /// Fade in on unpause and after large seeks, `None` disables it.
+    set_fade as SetFade { length: Option<time::Duration> };
     /// Get chapter metadata.
     chapter_metadata as ChapterMetadata
         / Response::MaybeMetadata(m) => m => Option<Metadata>;
@@ -452,6 +1187,9 @@ commands! {
     /// Get the queued item at an index
     queue_at as QueueN { at: usize }
         / Response::Item(i) => i => QueueItem;
+    /// Get the queued items from `from` up to, but not including, `to`.
+    queue_range as QueueRange { from: usize, to: usize }
+        / Response::Items(items) => items => Vec<QueueItem>;
     /// Check whether the queue is currently looping.
     queue_is_looping as QueueIsLooping
         / Response::LoopStatus(l) => l => LoopStatus;
@@ -470,4 +1208,536 @@ commands! {
     /// Get the total time of the current track
     playback_time as PlaybackTime
         / Response::Real(r) => r => f64;
+    /// Get the sleep timer, if one is running.
+    sleep_timer_status as SleepTimerStatus
+        / Response::MaybeSleepTimer(t) => t => Option<SleepTimer>;
+    /// Get the songs the player failed to play, oldest first.
+    failed_items as FailedItems
+        / Response::FailedItems(f) => f => Vec<FailedItem>;
+    /// Forget the failed song at `at`, or all of them if `None`, returning what was forgotten.
+    take_failed as TakeFailed { at: Option<usize> }
+        / Response::FailedItems(f) => f => Vec<FailedItem>;
+    /// Forget that `item` failed to play, once it's queued again.
+    forget_failed as ForgetFailed { item: Item };
+    /// Change what the player does once the queue ends.
+    set_end_of_queue as SetEndOfQueue { policy: EndOfQueuePolicy };
+    /// Get what the player does once the queue ends.
+    end_of_queue as EndOfQueue
+        / Response::EndOfQueuePolicy(p) => p => EndOfQueuePolicy;
+    /// Give the player a name, unique among the running players, `None` removes it.
+    set_name as SetName { name: Option<String> };
+    /// Get the player's name, if it has one.
+    name as Name
+        / Response::MaybeText(n) => n => Option<String>;
+    /// How long the player has been paused or done with its queue, `None` if it's playing.
+    idle_for as IdleFor
+        / Response::MaybeDuration(d) => d => Option<time::Duration>;
+    /// Change which of the queued songs the player downloads before they play.
+    set_precache as SetPrecache { policy: PrecachePolicy };
+    /// Get which of the queued songs the player downloads before they play.
+    precache as Precache
+        / Response::PrecachePolicy(p) => p => PrecachePolicy;
+    /// Skip the songs that don't fit `focus` when they start, `None` turns it off. Returns the
+    /// songs skipped since it was last turned on.
+    set_focus as SetFocus { focus: Option<Focus> }
+        / Response::SkippedItems(s) => s => Vec<SkippedItem>;
+    /// Get what songs are let through, if focus mode is on.
+    focus as Focus
+        / Response::MaybeFocus(f) => f => Option<Focus>;
+    /// Keep queueing songs from `radio` whenever the queue is about to run out, replacing the
+    /// radio that was on.
+    start_radio as StartRadio { radio: Radio };
+    /// Stop queueing songs from the radio. Returns whether one was on.
+    stop_radio as StopRadio
+        / Response::Bool(b) => b => bool;
+    /// Set the gain, in dB, of each of the [`EQUALIZER_BANDS`]. Bands that aren't given are left
+    /// flat and no bands turns the equalizer off.
+    set_equalizer as SetEqualizer { bands: Vec<f32> };
+    /// Continue long files from where they were stopped last time, when they are loaded. The
+    /// current file resumes too, if it just started.
+    set_resume as SetResume { resume: bool };
+    /// Seek over the sponsors and other segments of youtube videos submitted to SponsorBlock, as
+    /// they come up.
+    set_sponsorblock as SetSponsorBlock { on: bool };
+    /// Check whether SponsorBlock segments are being skipped.
+    sponsorblock as SponsorBlock
+        / Response::Bool(b) => b => bool;
+    /// Get the gain of each of the [`EQUALIZER_BANDS`], empty if the equalizer is off.
+    get_equalizer as GetEqualizer
+        / Response::Bands(b) => b => Vec<f32>;
+    /// Get the title, artist, album and so on of the current file.
+    track_metadata as TrackMetadata
+        / Response::TrackMetadata(m) => m => TrackMetadata;
+    /// Set an mpv property the other messages don't cover. Only a few properties are allowed,
+    /// the ones the daemon doesn't keep track of itself.
+    set_property_raw as SetPropertyRaw { name: String, value: serde_json::Value };
+    /// Get any mpv property, as json.
+    get_property_raw as GetPropertyRaw { name: String }
+        / Response::Json(v) => v => serde_json::Value;
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use serde::{de::DeserializeOwned, Serialize};
+    use url::Url;
+
+    use super::{
+        error::{MpvError, MpvResult},
+        event::{OwnedLibMpvEvent, OwnedMpvNode, PlayerEvent, QueueChange},
+        *,
+    };
+    use crate::item::Link;
+
+    /// Checks that each sample serializes to the matching line of the fixture and that each line
+    /// of the fixture can still be deserialized.
+    fn check<T: Serialize + DeserializeOwned>(samples: &[T], fixture: &str) {
+        let lines = fixture.lines().collect::<Vec<_>>();
+        assert_eq!(
+            samples.len(),
+            lines.len(),
+            "samples and fixture are out of sync"
+        );
+        for (sample, line) in samples.iter().zip(lines) {
+            let golden = serde_json::from_str::<serde_json::Value>(line).unwrap();
+            assert_eq!(serde_json::to_value(sample).unwrap(), golden);
+            let decoded = serde_json::from_str::<T>(line).unwrap_or_else(|e| panic!("{line}: {e}"));
+            assert_eq!(serde_json::to_value(decoded).unwrap(), golden);
+        }
+    }
+
+    fn items() -> Vec<Item> {
+        let link = Url::parse("https://www.youtube.com/watch?v=dQw4w9WgXcQ").unwrap();
+        vec![
+            Item::Link(Link::try_from(link).unwrap()),
+            Item::File(PathBuf::from("/music/song.mp3")),
+        ]
+    }
+
+    fn queue_item() -> QueueItem {
+        QueueItem {
+            filename: "/music/song.mp3".into(),
+            status: Some(QueueItemStatus {
+                current: true,
+                playing: false,
+            }),
+            id: 3,
+        }
+    }
+
+    #[test]
+    fn messages_v1() {
+        use MessageKind::*;
+        let kinds = vec![
+            Create {
+                items: items(),
+                with_video: false,
+                profile: None,
+                options: vec![],
+                background: false,
+                name: None,
+            },
+            PlayerList,
+            LastQueue,
+            LastClear,
+            LastQueueSet { to: 2 },
+            Current,
+            CyclePause,
+            Pause,
+            Resume,
+            QueueClear,
+            LoadFile {
+                item: items().remove(1),
+            },
+            LoadList {
+                path: PathBuf::from("/tmp/list"),
+            },
+            QueueMove { from: 1, to: 4 },
+            QueueRemove { to_remove: 3 },
+            QueueLoop {
+                start_looping: true,
+            },
+            QueueShuffle,
+            Quit,
+            ChangeVolume { delta: -2 },
+            CycleVideo,
+            ChangeFile {
+                direction: Direction::Next,
+            },
+            Seek { seconds: -10.5 },
+            ChangeChapter {
+                direction: Direction::Prev,
+                amount: 1,
+            },
+            SetSleepTimer {
+                after: time::Duration::from_secs(1800),
+                action: StopOrPause::Pause,
+            },
+            CancelSleepTimer,
+            ChapterMetadata,
+            Filename,
+            IsPaused,
+            MediaTitle,
+            PercentPosition,
+            Queue,
+            QueueIsLooping,
+            QueuePos,
+            QueueSize,
+            Volume,
+            QueueNFilename { at: 1 },
+            QueueN { at: 1 },
+            Duration,
+            PlaybackTime,
+            SleepTimerStatus,
+            SetFade {
+                length: Some(time::Duration::from_millis(400)),
+            },
+            HistoryBack,
+            HistoryForward,
+            StepVolume {
+                delta: -2.5,
+                curve: VolumeCurve::Perceptual,
+            },
+            FailedItems,
+            TakeFailed { at: Some(0) },
+            SetEndOfQueue {
+                policy: EndOfQueuePolicy::Hook("notify-send done".into()),
+            },
+            EndOfQueue,
+            SetName {
+                name: Some("kitchen".into()),
+            },
+            Name,
+            SetFocus {
+                focus: Some(super::Focus {
+                    max_duration: Some(time::Duration::from_secs(600)),
+                    categories: vec!["chill".into()],
+                }),
+            },
+            Focus,
+            StartRadio {
+                radio: super::Radio {
+                    seed: super::RadioSeed::Category("chill".into()),
+                    threshold: 3,
+                },
+            },
+            StopRadio,
+            SetEqualizer {
+                bands: vec![3.0, 1.5, 0.0, -2.0],
+            },
+            GetEqualizer,
+            SetResume { resume: true },
+            TrackMetadata,
+            SetPropertyRaw {
+                name: "speed".into(),
+                value: serde_json::json!(1.25),
+            },
+            GetPropertyRaw {
+                name: "volume".into(),
+            },
+            LoadFileAt {
+                item: items().remove(1),
+                at: 2,
+            },
+            QueueSwap { a: 1, b: 3 },
+            JumpTo { pos: 4 },
+            QueueRange { from: 2, to: 7 },
+            QueueReorder {
+                order: vec![0, 2, 1],
+            },
+            Create {
+                items: vec![],
+                with_video: true,
+                profile: Some(PlayerProfile {
+                    geometry: Some("1280x720".into()),
+                    volume: Some(40),
+                    osc: Some(false),
+                    mpv_options: [("loop-file".into(), "inf".into())].into(),
+                }),
+                options: vec![],
+                background: false,
+                name: None,
+            },
+            Create {
+                items: vec![],
+                with_video: false,
+                profile: None,
+                options: vec![("ytdl-format".into(), "bestaudio".into())],
+                background: false,
+                name: None,
+            },
+            ForAll {
+                command: Broadcast::StepVolume {
+                    delta: 2.5,
+                    curve: VolumeCurve::Linear,
+                },
+            },
+            ResolveName {
+                name: "kitchen".into(),
+            },
+            IdleFor,
+            SetPrecache {
+                policy: PrecachePolicy::Next(3),
+            },
+            Precache,
+            SetSponsorBlock { on: true },
+            SponsorBlock,
+            LoadFileWithOptions {
+                item: items().remove(0),
+                options: vec![
+                    ("start".into(), "600".into()),
+                    ("end".into(), "842.5".into()),
+                ],
+                at: Some(3),
+            },
+            ForgetFailed {
+                item: items().remove(1),
+            },
+            Create {
+                items: vec![],
+                with_video: false,
+                profile: None,
+                options: vec![],
+                background: true,
+                name: None,
+            },
+            Create {
+                items: vec![],
+                with_video: false,
+                profile: None,
+                options: vec![],
+                background: false,
+                name: Some("work".into()),
+            },
+        ];
+        let messages = kinds
+            .into_iter()
+            .enumerate()
+            .map(|(i, kind)| {
+                let index = if i % 2 == 0 {
+                    PlayerIndex::CURRENT
+                } else {
+                    PlayerIndex::of(i)
+                };
+                Message::new(index, kind)
+            })
+            .collect::<Vec<_>>();
+        check(&messages, include_str!("fixtures/v1/messages.jsonl"));
+    }
+
+    #[test]
+    fn message_names_are_their_tags() {
+        for line in include_str!("fixtures/v1/messages.jsonl").lines() {
+            let message = serde_json::from_str::<Message>(line).unwrap();
+            let tag = match serde_json::to_value(&message.kind).unwrap() {
+                serde_json::Value::String(tag) => tag,
+                serde_json::Value::Object(kind) => kind.keys().next().unwrap().clone(),
+                kind => panic!("unexpected kind {kind}"),
+            };
+            assert_eq!(message.kind.name(), tag);
+        }
+    }
+
+    #[test]
+    fn volume_curves() {
+        assert_eq!(VolumeCurve::Linear.step(50., 2.5), 52.5);
+        assert_eq!(VolumeCurve::Linear.step(1., -2.), 0.);
+        let perceptual = |volume| VolumeCurve::Perceptual.step(volume, 2.) - volume;
+        assert!(perceptual(5.) < perceptual(50.));
+        assert!(perceptual(50.) < perceptual(100.));
+        let there_and_back =
+            VolumeCurve::Perceptual.step(VolumeCurve::Perceptual.step(30., 2.), -2.);
+        assert!((there_and_back - 30.).abs() < 1e-9);
+        assert_eq!(VolumeCurve::Perceptual.step(0.5, -20.), 0.);
+    }
+
+    #[test]
+    fn end_of_queue_policies() {
+        for policy in [
+            EndOfQueuePolicy::Stop,
+            EndOfQueuePolicy::Loop,
+            EndOfQueuePolicy::Quit,
+            EndOfQueuePolicy::Radio,
+            EndOfQueuePolicy::Hook("echo $M_LAST".into()),
+        ] {
+            assert_eq!(policy.to_string().parse(), Ok(policy));
+        }
+        assert!("hook:".parse::<EndOfQueuePolicy>().is_err());
+        assert!("again".parse::<EndOfQueuePolicy>().is_err());
+    }
+
+    #[test]
+    fn precache_policies() {
+        for policy in [
+            PrecachePolicy::Off,
+            PrecachePolicy::Next(3),
+            PrecachePolicy::Queue,
+        ] {
+            assert_eq!(policy.to_string().parse(), Ok(policy));
+        }
+        assert!("-1".parse::<PrecachePolicy>().is_err());
+        assert!("all".parse::<PrecachePolicy>().is_err());
+    }
+
+    #[test]
+    fn responses_v1() {
+        use Response::*;
+        let responses: Vec<MpvResult<Response>> = vec![
+            Ok(Create(PlayerIndex::of(0))),
+            Ok(Metadata(super::Metadata {
+                title: "Intro".into(),
+                index: 0,
+            })),
+            Ok(MaybeMetadata(None)),
+            Ok(Bool(true)),
+            Ok(Text("song".into())),
+            Ok(Real(0.5)),
+            Ok(Item(queue_item())),
+            Ok(Items(vec![queue_item()])),
+            Ok(Integer(-1)),
+            Ok(LoopStatus(super::LoopStatus::N(3))),
+            Ok(PlayerList(vec![PlayerIndex::of(0), PlayerIndex::of(2)])),
+            Ok(MaybeInteger(Some(1))),
+            Ok(MaybeSleepTimer(Some(SleepTimer {
+                remaining: time::Duration::from_millis(1500),
+                action: StopOrPause::Stop,
+            }))),
+            Ok(Unit),
+            Err(MpvError::NoMpvInstance),
+            Ok(MaybeHistoryEntry(Some(HistoryEntry {
+                index: 2,
+                played_at: time::UNIX_EPOCH + time::Duration::from_secs(1_700_000_000),
+            }))),
+            Ok(FailedItems(vec![super::FailedItem {
+                item: items().remove(0),
+                reason: "Video unavailable".into(),
+                failed_at: time::UNIX_EPOCH + time::Duration::from_secs(1_700_000_000),
+            }])),
+            Ok(EndOfQueuePolicy(super::EndOfQueuePolicy::Radio)),
+            Ok(MaybeText(Some("kitchen".into()))),
+            Ok(SkippedItems(vec![super::SkippedItem {
+                item: items().remove(0),
+                reason: "longer than 10m".into(),
+                skipped_at: time::UNIX_EPOCH + time::Duration::from_secs(1_700_000_000),
+            }])),
+            Ok(MaybeFocus(None)),
+            Ok(Bands(vec![3.0, 1.5, 0.0, -2.0])),
+            Ok(TrackMetadata(super::TrackMetadata {
+                title: Some("Never Gonna Give You Up".into()),
+                uploader: Some("Rick Astley".into()),
+                album: None,
+                duration: Some(time::Duration::from_secs(213)),
+                upload_date: Some("20091025".into()),
+            })),
+            Ok(Json(serde_json::json!({ "speed": 1.25, "tracks": [1, 2] }))),
+            Ok(PerPlayer(vec![
+                (PlayerIndex::of(0), Ok(())),
+                (PlayerIndex::of(2), Err(MpvError::NoMpvInstance)),
+            ])),
+            Ok(MaybeDuration(Some(time::Duration::from_secs(90)))),
+            Ok(PrecachePolicy(super::PrecachePolicy::Off)),
+        ];
+        check(&responses, include_str!("fixtures/v1/responses.jsonl"));
+    }
+
+    #[test]
+    fn events_v1() {
+        use OwnedLibMpvEvent::*;
+        let events = vec![
+            Shutdown,
+            LogMessage {
+                prefix: "cplayer".into(),
+                level: "info".into(),
+                text: "hello".into(),
+                log_level: 30,
+            },
+            GetPropertyReply {
+                name: "volume".into(),
+                result: OwnedMpvNode::Double(50.0),
+                reply_userdata: 1,
+            },
+            SetPropertyReply(2),
+            CommandReply(3),
+            StartFile,
+            EndFile(0),
+            FileLoaded,
+            ClientMessage(vec!["script-message".into()]),
+            VideoReconfig,
+            AudioReconfig,
+            Seek,
+            PlaybackRestart,
+            PropertyChange {
+                name: "chapter-metadata".into(),
+                change: OwnedMpvNode::Map(HashMap::from([(
+                    "title".into(),
+                    OwnedMpvNode::String("Intro".into()),
+                )])),
+                reply_userdata: 4,
+            },
+            QueueOverflow,
+            Deprecated { event_id: 5 },
+            Errored("oops".into()),
+            QueueChanged(QueueChange::Added {
+                id: 6,
+                after: Some(5),
+            }),
+            QueueChanged(QueueChange::Added { id: 1, after: None }),
+            QueueChanged(QueueChange::Removed { id: 6 }),
+            QueueChanged(QueueChange::Replaced),
+            LoopChanged(super::LoopStatus::Inf),
+            Shuffled,
+            IdleQuit {
+                idle_for: time::Duration::from_secs(1800),
+            },
+        ];
+        let events = events
+            .into_iter()
+            .map(|event| PlayerEvent {
+                player_index: 1,
+                event,
+            })
+            .collect::<Vec<_>>();
+        check(&events, include_str!("fixtures/v1/events.jsonl"));
+    }
+
+    #[test]
+    fn events_from_newer_daemons_are_unknown() {
+        for line in include_str!("fixtures/v1/events.jsonl").lines() {
+            let event = serde_json::from_str::<PlayerEvent>(line).unwrap();
+            assert!(!matches!(event.event, OwnedLibMpvEvent::Unknown), "{line}");
+        }
+        for line in [
+            r#"{"player_index":1,"event":"Rewound"}"#,
+            r#"{"player_index":1,"event":{"Rewound":{"by":3}}}"#,
+        ] {
+            let event = serde_json::from_str::<PlayerEvent>(line).unwrap();
+            assert!(matches!(event.event, OwnedLibMpvEvent::Unknown), "{line}");
+        }
+        assert!(serde_json::from_str::<PlayerEvent>(
+            r#"{"player_index":1,"event":{"EndFile":"x"}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn songs_queued_together_keep_their_order() {
+        let mut placement = QueuePlacement::Next;
+        let mut queued = vec![];
+        for moved_to in [4, 5, 6] {
+            queued.push(placement);
+            placement = placement.after(moved_to);
+        }
+        assert_eq!(
+            queued,
+            [
+                QueuePlacement::Next,
+                QueuePlacement::At(5),
+                QueuePlacement::At(6)
+            ]
+        );
+        assert_eq!(QueuePlacement::At(2).after(2), QueuePlacement::At(3));
+        assert_eq!(QueuePlacement::Smart.after(7), QueuePlacement::Smart);
+        assert_eq!(QueuePlacement::End.after(7), QueuePlacement::End);
+    }
 }
@@ -0,0 +1,12 @@
+//! Pausing the players when the audio output they play on goes away, like when bluetooth or usb
+//! headphones are disconnected. The players daemon follows the default sink with `pactl`, which
+//! PipeWire provides too through pipewire-pulse.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Config {
+    /// Resume the players that were paused once the sink is back.
+    #[serde(default)]
+    pub resume: bool,
+}
@@ -0,0 +1,105 @@
+//! Controlling the players from a browser, e.g. from a phone on the same network. The players
+//! daemon serves a page with the usual buttons, takes [`Message`](super::Message)s as JSON with
+//! `POST /` and pushes [`PlayerEvent`](super::event::PlayerEvent)s to websockets on `/events`.
+
+use std::{collections::BTreeMap, net::SocketAddr};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Config {
+    /// Where to listen, e.g. `0.0.0.0:7878` to be reachable from other devices, which needs a
+    /// `token`.
+    pub listen: SocketAddr,
+    /// If set, requests must have it as a bearer token in the `Authorization` header, and
+    /// websockets must send it first. The page takes it from the url's fragment, as `#...`. It
+    /// has the [`Scope::Control`] scope.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// More tokens, each with what it lets its holder do. They work like `token`.
+    #[serde(default)]
+    pub tokens: BTreeMap<String, Scope>,
+    /// Origins, like `https://remote.example`, whose pages can also make requests, for web uis
+    /// that aren't served by the daemon.
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+}
+
+impl Config {
+    /// Whether requests need a token.
+    pub fn has_tokens(&self) -> bool {
+        self.token.is_some() || !self.tokens.is_empty()
+    }
+
+    /// What a request with `given` as its token can do, if it's one of the tokens. Without any
+    /// tokens every request can control the players.
+    pub fn scope_of(&self, given: Option<&[u8]>) -> Option<Scope> {
+        if !self.has_tokens() {
+            return Some(Scope::Control);
+        }
+        let given = given?;
+        // every token is compared, so how long it takes doesn't say which one matched
+        self.token
+            .iter()
+            .map(|t| (t, Scope::Control))
+            .chain(self.tokens.iter().map(|(t, s)| (t, *s)))
+            .filter(|(token, _)| same_token(given, token.as_bytes()))
+            .fold(None, |found, (_, scope)| found.max(Some(scope)))
+    }
+}
+
+/// What a token lets its holder do, each including the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Ask about what's playing, follow the events and read the metrics.
+    Read,
+    /// Also use the playback controls, like pausing, seeking or changing the volume.
+    Control,
+    /// Send any message, including the ones that run commands, load files or change how the
+    /// players are set up.
+    Admin,
+}
+
+/// Compares tokens in a time that doesn't depend on how much of them matches.
+fn same_token(given: &[u8], token: &[u8]) -> bool {
+    given.len() == token.len() && given.iter().zip(token).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(token: Option<&str>, tokens: &[(&str, Scope)]) -> Config {
+        Config {
+            listen: "0.0.0.0:7878".parse().unwrap(),
+            token: token.map(Into::into),
+            tokens: tokens.iter().map(|(t, s)| (t.to_string(), *s)).collect(),
+            cors_origins: vec![],
+        }
+    }
+
+    #[test]
+    fn tokens_have_scopes() {
+        let config = config(
+            Some("secret"),
+            &[("viewer", Scope::Read), ("root", Scope::Admin)],
+        );
+        let scope = |given: &str| config.scope_of(Some(given.as_bytes()));
+        assert_eq!(scope("secret"), Some(Scope::Control));
+        assert_eq!(scope("viewer"), Some(Scope::Read));
+        assert_eq!(scope("root"), Some(Scope::Admin));
+        assert_eq!(scope("secreT"), None);
+        assert_eq!(scope(""), None);
+        assert_eq!(config.scope_of(None), None);
+    }
+
+    #[test]
+    fn without_tokens_everyone_controls() {
+        assert_eq!(config(None, &[]).scope_of(None), Some(Scope::Control));
+        assert_eq!(
+            config(None, &[("viewer", Scope::Read)]).scope_of(None),
+            None
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    borrow::Borrow,
+    convert::Infallible,
+    fmt::{self, Display},
+    ops::Deref,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// A song category.
+///
+/// Categories are hierarchical, with levels separated by `/`, so `rock/metal` is a kind of
+/// `rock`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(from = "String", into = "String")]
+pub struct Category(String);
+
+impl Category {
+    pub const SEPARATOR: char = '/';
+
+    /// Creates a category, trimming whitespace and empty levels (`" rock//metal/"` becomes
+    /// `rock/metal`).
+    pub fn new(s: &str) -> Self {
+        Self(
+            s.split(Self::SEPARATOR)
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .collect::<Vec<_>>()
+                .join("/"),
+        )
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// This category followed by all of its parents, from the most to the least specific.
+    pub fn ancestors(&self) -> impl Iterator<Item = &str> {
+        std::iter::successors(Some(self.as_str()), |c| {
+            c.rfind(Self::SEPARATOR).map(|i| &c[..i])
+        })
+    }
+
+    /// Whether this category is `other` or one of its children. `rock/metal` is within `rock`,
+    /// but `rockabilly` is not.
+    pub fn is_within(&self, other: &Category) -> bool {
+        self.0
+            .strip_prefix(other.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(Self::SEPARATOR))
+    }
+}
+
+impl From<String> for Category {
+    fn from(s: String) -> Self {
+        if s.split(Self::SEPARATOR)
+            .all(|l| !l.is_empty() && l.trim() == l)
+        {
+            Self(s)
+        } else {
+            Self::new(&s)
+        }
+    }
+}
+
+impl From<Category> for String {
+    fn from(c: Category) -> Self {
+        c.0
+    }
+}
+
+impl From<&str> for Category {
+    fn from(s: &str) -> Self {
+        Self::new(s)
+    }
+}
+
+impl FromStr for Category {
+    type Err = Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(s))
+    }
+}
+
+impl Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Deref for Category {
+    type Target = str;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Category {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Category {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn normalizes() {
+        assert_eq!(Category::new(" rock//metal/ ").as_str(), "rock/metal");
+        assert_eq!(
+            Category::from(String::from("rock/ metal")).as_str(),
+            "rock/metal"
+        );
+    }
+
+    #[test]
+    fn hierarchy() {
+        let metal = Category::new("rock/metal");
+        assert!(metal.is_within(&Category::new("rock")));
+        assert!(metal.is_within(&metal));
+        assert!(!Category::new("rockabilly").is_within(&Category::new("rock")));
+        assert!(!Category::new("rock").is_within(&metal));
+        assert_eq!(
+            Category::new("rock/metal/doom")
+                .ancestors()
+                .collect::<Vec<_>>(),
+            ["rock/metal/doom", "rock/metal", "rock"]
+        );
+    }
+}
@@ -1,8 +1,14 @@
+mod category;
+pub mod query;
+mod set;
 mod uniq_vec;
 
 use csv_async::{AsyncReaderBuilder, AsyncWriterBuilder, StringRecord};
 use dirs::config_dir;
-use futures_util::{stream::TryStreamExt, Stream};
+use futures_util::{
+    stream::{StreamExt, TryStreamExt},
+    Stream,
+};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::{
@@ -12,22 +18,108 @@ use std::{
     fmt::{self, Display},
     io,
     ops::{Deref, DerefMut},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Duration, SystemTime},
 };
 use tokio::{
     fs::{File, OpenOptions},
     io::{AsyncRead, AsyncReadExt},
 };
 
-use crate::{item::link::VideoLink, Error, VideoId};
+use crate::{
+    item::link::{Id, VideoLink},
+    Error, VideoId,
+};
+
+pub use category::Category;
+pub use set::PlaylistSet;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A song in the playlist, stored as a tab separated line of its name, link, duration, when it
+/// was added (`added=<unix timestamp>`, missing for songs added before it was recorded) and
+/// categories.
+#[derive(Debug)]
 pub struct Song {
     pub name: String,
     pub link: VideoLink,
+    /// The duration in seconds, 0 if it isn't known.
     pub time: u64,
-    #[serde(default)]
-    pub categories: uniq_vec::UniqVec<String>,
+    pub added: Option<SystemTime>,
+    pub categories: uniq_vec::UniqVec<Category>,
+}
+
+const ADDED_PREFIX: &str = "added=";
+
+impl TryFrom<Vec<String>> for Song {
+    type Error = String;
+
+    fn try_from(fields: Vec<String>) -> Result<Self, Self::Error> {
+        let mut fields = fields.into_iter().peekable();
+        let mut next_field = || {
+            fields
+                .next()
+                .ok_or_else(|| String::from("not enough fields"))
+        };
+        let name = next_field()?;
+        let link = next_field()?
+            .try_into()
+            .map_err(|e| format!("invalid link: {e}"))?;
+        let time = next_field()?
+            .parse()
+            .map_err(|_| String::from("invalid duration"))?;
+        let added = match fields.next_if(|f| f.starts_with(ADDED_PREFIX)) {
+            Some(f) => Some(
+                f[ADDED_PREFIX.len()..]
+                    .parse()
+                    .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+                    .map_err(|_| format!("invalid added time: {f}"))?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            name,
+            link,
+            time,
+            added,
+            categories: fields.map(Category::from).collect(),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Song {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        Vec::<String>::deserialize(d)?
+            .try_into()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Song {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeSeq;
+        let mut seq = s.serialize_seq(None)?;
+        seq.serialize_element(&self.name)?;
+        seq.serialize_element(&self.link)?;
+        seq.serialize_element(&self.time)?;
+        if let Some(added) = self.added {
+            let secs = added
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            seq.serialize_element(&format!("{ADDED_PREFIX}{secs}"))?;
+        }
+        for c in self.categories.iter() {
+            seq.serialize_element(c)?;
+        }
+        seq.end()
+    }
+}
+
+impl Song {
+    /// Whether any of the song's categories is `category` or one of its children.
+    pub fn is_in(&self, category: &Category) -> bool {
+        self.categories.iter().any(|c| c.is_within(category))
+    }
 }
 
 impl Display for Song {
@@ -36,7 +128,7 @@ impl Display for Song {
         if !self.categories.is_empty() {
             write!(f, " :: ")?;
             std::iter::repeat(",")
-                .zip(self.categories.iter().map(String::as_str))
+                .zip(self.categories.iter().map(Category::as_str))
                 .flat_map(|(a, b)| [a, b])
                 .skip(1)
                 .try_for_each(|s| f.write_str(s))?;
@@ -47,6 +139,28 @@ impl Display for Song {
 
 pub struct Playlist {
     pub songs: Vec<Song>,
+    /// The songs that came from [`include_playlists`], and the file each came from. They are
+    /// left out when saving.
+    included: HashMap<Box<VideoId>, PathBuf>,
+}
+
+static PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+static INCLUDES: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+/// Use `path` as the main playlist instead of `~/.config/m/playlist`, unless `$PLAYLIST` is set.
+/// Has to be called before the playlist is first used.
+pub fn override_playlist_path(path: PathBuf) {
+    let _ = PATH_OVERRIDE.set(path);
+}
+
+/// Other playlist files whose songs are merged into the main playlist when it's loaded, see
+/// [`Playlist::load`]. They are only read, never written to.
+pub fn include_playlists(paths: Vec<PathBuf>) {
+    let _ = INCLUDES.set(paths);
+}
+
+fn includes() -> &'static [PathBuf] {
+    INCLUDES.get().map_or(&[], Vec::as_slice)
 }
 
 static WRITER_BUILDER: Lazy<AsyncWriterBuilder> = Lazy::new(|| {
@@ -81,6 +195,7 @@ impl Playlist {
                 Err(_) => {
                     let path = env::var_os("PLAYLIST")
                         .map(PathBuf::from)
+                        .or_else(|| PATH_OVERRIDE.get().cloned())
                         .or_else(|| {
                             let mut playlist_path = config_dir()?;
                             playlist_path.push("m");
@@ -95,9 +210,37 @@ impl Playlist {
         })
     }
 
+    /// Loads the main playlist, with the songs of [`include_playlists`] that aren't in it
+    /// appended.
     pub async fn load() -> Result<Self, Error> {
         let playlist_path = Self::path()?;
-        Self::load_from(playlist_path).await
+        let mut playlist = Self::load_from(playlist_path).await?;
+        for path in includes() {
+            let included = match Self::load_from(path.clone()).await {
+                Ok(included) => included,
+                Err(e) => {
+                    tracing::error!(error = ?e, path = %path.display(), "failed to load included playlist");
+                    continue;
+                }
+            };
+            playlist.include(path, included.songs);
+        }
+        Ok(playlist)
+    }
+
+    fn include(&mut self, from: &Path, songs: Vec<Song>) {
+        let mut known = self
+            .songs
+            .iter()
+            .map(|s| s.link.id().boxed())
+            .collect::<HashSet<_>>();
+        for song in songs {
+            if known.insert(song.link.id().boxed()) {
+                self.included
+                    .insert(song.link.id().boxed(), from.to_owned());
+                self.songs.push(song);
+            }
+        }
     }
 
     pub async fn load_from(playlist_path: PathBuf) -> Result<Self, Error> {
@@ -115,12 +258,24 @@ impl Playlist {
         let reader = READER_BUILDER.create_deserializer(source);
         Ok(Self {
             songs: reader.into_deserialize().try_collect().await?,
+            included: HashMap::new(),
         })
     }
 
+    /// Streams the main playlist followed by the [`include_playlists`]. Unlike
+    /// [`Playlist::load`], a song that is in more than one of them comes up more than once.
     pub async fn stream() -> Result<impl Stream<Item = Result<Song, csv_async::Error>>, Error> {
         let playlist_path = Self::path()?;
-        Self::stream_from(playlist_path).await
+        let mut stream = Self::stream_from(playlist_path).await?.boxed();
+        for path in includes() {
+            match Self::stream_from(path.clone()).await {
+                Ok(included) => stream = stream.chain(included).boxed(),
+                Err(e) => {
+                    tracing::error!(error = ?e, path = %path.display(), "failed to open included playlist")
+                }
+            }
+        }
+        Ok(stream)
     }
 
     pub async fn stream_from(
@@ -137,36 +292,48 @@ impl Playlist {
         Ok(reader.into_deserialize())
     }
 
+    /// Every category and how many songs are in it. Songs count towards the parents of their
+    /// categories as well.
     pub fn categories(&self) -> impl Iterator<Item = (&str, usize)> {
         self.songs
             .iter()
-            .flat_map(|s| s.categories.iter())
+            .flat_map(|s| {
+                s.categories
+                    .iter()
+                    .flat_map(Category::ancestors)
+                    .collect::<HashSet<_>>()
+            })
             .fold(HashMap::new(), |mut set, c| {
                 *set.entry(c).or_default() += 1;
                 set
             })
             .into_iter()
-            .map(|(k, v)| (k.as_str(), v))
     }
 
     pub async fn contains_song(song: &str) -> io::Result<bool> {
-        let path = Playlist::path()?;
+        Self::contains_song_in(&Playlist::path()?, song).await
+    }
+
+    pub async fn contains_song_in(path: &Path, song: &str) -> io::Result<bool> {
         let mut buf = Vec::new();
-        File::open(&path).await?.read_to_end(&mut buf).await?;
+        File::open(path).await?.read_to_end(&mut buf).await?;
         Ok(memchr::memmem::find(&buf, song.as_bytes()).is_some())
     }
 
     pub async fn add_song(song: &Song) -> Result<(), Error> {
+        Self::add_song_to(&Self::path()?, song).await
+    }
+
+    pub async fn add_song_to(path: &Path, song: &Song) -> Result<(), Error> {
+        let _lock = lock(path).await?;
         let file = OpenOptions::new()
             .append(true)
             .create(true)
-            .open(Self::path()?)
+            .open(path)
             .await?;
-        WRITER_BUILDER
-            .create_serializer(file)
-            .serialize(song)
-            .await
-            .map_err(io::Error::from)?;
+        let mut writer = WRITER_BUILDER.create_serializer(file);
+        writer.serialize(song).await.map_err(io::Error::from)?;
+        writer.flush().await?;
         Ok(())
     }
 
@@ -234,17 +401,173 @@ impl Playlist {
     }
 
     pub async fn save(&self) -> Result<(), Error> {
-        let file = File::create(Self::path()?).await?;
-        let mut writer = WRITER_BUILDER.create_serializer(file);
-        for song in self.songs.iter() {
-            writer.serialize(song).await?;
+        self.save_atomically_to(&Self::path()?).await
+    }
+
+    pub async fn save_atomically(&self) -> Result<(), Error> {
+        self.save_atomically_to(&Self::path()?).await
+    }
+
+    /// Saves to a temporary file next to `path` and renames it over `path`, so a crash or a
+    /// concurrent reader never sees a half written playlist. Other writers wait for it to be
+    /// done, see [`Error::PlaylistLocked`].
+    ///
+    /// Changes saved by others since this playlist was loaded are overwritten, to avoid that
+    /// take the [lock](Self::lock) before loading it and save with [`PlaylistLock::save`].
+    pub async fn save_atomically_to(&self, path: &Path) -> Result<(), Error> {
+        lock(path).await?.save(self).await
+    }
+
+    /// Takes the lock that writes to the main playlist hold, see [`PlaylistLock`].
+    pub async fn lock() -> Result<PlaylistLock, Error> {
+        lock(&Self::path()?).await
+    }
+
+    /// Takes the lock that writes to the playlist at `path` hold, see [`PlaylistLock`].
+    pub async fn lock_at(path: &Path) -> Result<PlaylistLock, Error> {
+        lock(path).await
+    }
+
+    async fn write_atomically_to(&self, path: &Path) -> Result<(), Error> {
+        let tmp = hidden_sibling(path, &format!("{}.tmp", std::process::id()));
+        let write = async {
+            let mut file = File::create(&tmp).await?;
+            {
+                let mut writer = WRITER_BUILDER.create_serializer(&mut file);
+                for song in self.songs.iter() {
+                    if self.included.contains_key(song.link.id()) {
+                        continue;
+                    }
+                    writer.serialize(song).await?;
+                }
+                writer.flush().await?;
+            }
+            file.sync_all().await?;
+            tokio::fs::rename(&tmp, path).await?;
+            Ok(())
+        };
+        let r = write.await;
+        if r.is_err() {
+            let _ = tokio::fs::remove_file(&tmp).await;
         }
-        Ok(())
+        r
     }
 
     pub fn find_by_link(&self, link: &VideoLink) -> Option<&Song> {
         self.songs.iter().find(|s| s.link.id() == link.id())
     }
+
+    /// Groups of songs that point to the same video, in the order they first appear.
+    pub fn duplicates(&self) -> Vec<Vec<&Song>> {
+        let mut groups = HashMap::<&VideoId, Vec<&Song>>::new();
+        let mut order = Vec::new();
+        for song in &self.songs {
+            let group = groups.entry(song.link.id()).or_insert_with(|| {
+                order.push(song.link.id());
+                Vec::new()
+            });
+            group.push(song);
+        }
+        order
+            .into_iter()
+            .filter_map(|id| groups.remove(id))
+            .filter(|g| g.len() > 1)
+            .collect()
+    }
+
+    /// Adds `category` to the songs `pick` returns true for, returning the ones that didn't
+    /// have it yet. See [`Playlist::ensure_editable`].
+    pub fn add_category<F>(&mut self, category: &Category, mut pick: F) -> Result<Vec<&Song>, Error>
+    where
+        F: FnMut(&Song) -> bool,
+    {
+        self.change_songs(
+            |song| pick(song) && !song.categories.contains(category),
+            |song| {
+                song.categories.push(category.clone());
+            },
+        )
+    }
+
+    /// Renames `old` to `new` in every song, children included (`rock/metal` becomes
+    /// `new/metal`), returning the songs that were in it. See [`Playlist::ensure_editable`].
+    pub fn rename_category(&mut self, old: &Category, new: &Category) -> Result<Vec<&Song>, Error> {
+        self.map_categories(|c| {
+            let rest = c.strip_prefix(old.as_str())?;
+            (rest.is_empty() || rest.starts_with(Category::SEPARATOR))
+                .then(|| Category::new(&format!("{new}{rest}")))
+        })
+    }
+
+    /// Takes `category` and its children out of every song, returning the songs that were in
+    /// it. See [`Playlist::ensure_editable`].
+    pub fn remove_category(&mut self, category: &Category) -> Result<Vec<&Song>, Error> {
+        self.change_songs(
+            |song| song.categories.iter().any(|c| c.is_within(category)),
+            |song| {
+                song.categories = std::mem::take(&mut song.categories)
+                    .into_vec()
+                    .into_iter()
+                    .filter(|c| !c.is_within(category))
+                    .collect();
+            },
+        )
+    }
+
+    /// Replaces the categories `f` returns a new category for, returning the songs that had
+    /// any of them.
+    fn map_categories<F>(&mut self, f: F) -> Result<Vec<&Song>, Error>
+    where
+        F: Fn(&Category) -> Option<Category>,
+    {
+        self.change_songs(
+            |song| song.categories.iter().any(|c| f(c).is_some()),
+            |song| {
+                let mut categories = uniq_vec::UniqVec::new();
+                for c in std::mem::take(&mut song.categories).into_vec() {
+                    categories.push(f(&c).unwrap_or(c));
+                }
+                song.categories = categories;
+            },
+        )
+    }
+
+    /// Applies `change` to the songs `affected` returns true for, returning them, unless any of
+    /// them isn't editable, in which case none are changed.
+    fn change_songs<A, C>(&mut self, mut affected: A, mut change: C) -> Result<Vec<&Song>, Error>
+    where
+        A: FnMut(&Song) -> bool,
+        C: FnMut(&mut Song),
+    {
+        let affected = (0..self.songs.len())
+            .filter(|&i| affected(&self.songs[i]))
+            .collect::<Vec<_>>();
+        for &i in &affected {
+            self.ensure_editable(&self.songs[i])?;
+        }
+        for &i in &affected {
+            change(&mut self.songs[i]);
+        }
+        Ok(affected.into_iter().map(|i| &self.songs[i]).collect())
+    }
+
+    /// The [included playlist](include_playlists) `song` came from, if it isn't from the main
+    /// one.
+    pub fn included_from(&self, song: &Song) -> Option<&Path> {
+        self.included.get(song.link.id()).map(PathBuf::as_path)
+    }
+
+    /// Fails if `song` came from an [included playlist](include_playlists), since changes to
+    /// those songs are never saved.
+    pub fn ensure_editable(&self, song: &Song) -> Result<(), Error> {
+        match self.included_from(song) {
+            Some(path) => Err(Error::IncludedSong {
+                song: song.name.clone(),
+                path: path.to_owned(),
+            }),
+            None => Ok(()),
+        }
+    }
 }
 
 pub enum PartialSearchResult<T> {
@@ -255,7 +578,7 @@ pub enum PartialSearchResult<T> {
 
 impl<T> PartialSearchResult<T> {
     #[inline(always)]
-    fn map<R>(self, f: impl FnOnce(T) -> R) -> PartialSearchResult<R> {
+    pub fn map<R>(self, f: impl FnOnce(T) -> R) -> PartialSearchResult<R> {
         match self {
             PartialSearchResult::One(t) => PartialSearchResult::One(f(t)),
             PartialSearchResult::None => PartialSearchResult::None,
@@ -278,6 +601,13 @@ pub struct PlaylistIndex<'p> {
     index: usize,
 }
 
+impl<'p> PlaylistIndex<'p> {
+    /// The song, borrowed for as long as the playlist is.
+    pub fn into_song(self) -> &'p Song {
+        &self.source.songs[self.index]
+    }
+}
+
 impl Deref for PlaylistIndex<'_> {
     type Target = Song;
 
@@ -306,15 +636,95 @@ impl DerefMut for PlaylistIndexMut<'_> {
 }
 
 impl PlaylistIndexMut<'_> {
+    /// See [`Playlist::ensure_editable`].
+    pub fn ensure_editable(&self) -> Result<(), Error> {
+        self.source.ensure_editable(self)
+    }
+
     pub fn delete(self) -> Song {
         self.source.songs.remove(self.index)
     }
 }
 
+/// `.<file name>.<suffix>` next to `path`, hidden so it isn't mistaken for a playlist by
+/// [`PlaylistSet::names`].
+fn hidden_sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{name}.{suffix}"))
+}
+
+/// How long a write waits for others to the same playlist before giving up.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The lock that writes to a playlist hold, so they happen one at a time. Holding it from before
+/// loading the playlist until after saving it means no one else's changes are lost in between.
+/// It's released when dropped.
+pub struct PlaylistLock {
+    path: PathBuf,
+    _release: std::sync::mpsc::Sender<()>,
+}
+
+impl PlaylistLock {
+    /// The file of the locked playlist.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Saves `playlist` over the locked one, like [`Playlist::save_atomically_to`] but without
+    /// waiting for the lock again.
+    pub async fn save(&self, playlist: &Playlist) -> Result<(), Error> {
+        playlist.write_atomically_to(&self.path).await
+    }
+}
+
+/// Takes the lock that writes to the playlist at `path` hold. The lock is on a file next to the
+/// playlist, because saving replaces the playlist's file.
+async fn lock(path: &Path) -> Result<PlaylistLock, Error> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(hidden_sibling(path, "lock"))?;
+    let (locked, is_locked) = tokio::sync::oneshot::channel();
+    let (release, released) = std::sync::mpsc::channel();
+    // the lock borrows the file, so they both live in a thread that waits for the guard to be
+    // dropped
+    std::thread::spawn(move || {
+        let _lock = raii_flock::FileLock::wrap_exclusive(&file);
+        if locked.send(()).is_ok() {
+            let _ = released.recv();
+        }
+    });
+    match tokio::time::timeout(LOCK_TIMEOUT, is_locked).await {
+        Ok(Ok(())) => Ok(PlaylistLock {
+            path: path.to_owned(),
+            _release: release,
+        }),
+        Ok(Err(_)) => Err(io::Error::other("failed to lock the playlist").into()),
+        Err(_) => Err(Error::PlaylistLocked(path.to_owned())),
+    }
+}
+
+/// Finds a song in the main playlist or, failing that, in the [`include_playlists`].
 pub async fn find_song(id: &VideoId) -> Result<Option<Song>, Error> {
-    let path = Playlist::path()?;
+    if let Some(song) = find_song_in(&Playlist::path()?, id).await? {
+        return Ok(Some(song));
+    }
+    for path in includes() {
+        match find_song_in(path, id).await {
+            Ok(Some(song)) => return Ok(Some(song)),
+            Ok(None) => {}
+            Err(e) => {
+                tracing::error!(error = ?e, path = %path.display(), "failed to search included playlist")
+            }
+        }
+    }
+    Ok(None)
+}
+
+async fn find_song_in(path: &Path, id: &VideoId) -> Result<Option<Song>, Error> {
     let mut buf = Vec::new();
-    File::open(&path).await?.read_to_end(&mut buf).await?;
+    File::open(path).await?.read_to_end(&mut buf).await?;
     match memchr::memmem::find(&buf, id.as_bytes()) {
         Some(i) => {
             let end = memchr::memmem::find(&buf[i..], b"\n")
@@ -323,26 +733,13 @@ pub async fn find_song(id: &VideoId) -> Result<Option<Song>, Error> {
             let start = memchr::memmem::rfind(&buf[..i], b"\n")
                 .map(|i| i + 1)
                 .unwrap_or(0);
-            let mut fields = buf[start..end]
+            let fields = buf[start..end]
                 .split(|c| *c == b'\t')
-                .map(<[u8]>::to_vec)
-                .map(String::from_utf8)
-                .map(Result::unwrap);
-            let mut next_field = || {
-                fields
-                    .next()
-                    .ok_or_else(|| Error::PlaylistFile(String::from("not enough fields")))
-            };
-            Ok(Some(Song {
-                name: next_field()?,
-                link: next_field()?
-                    .try_into()
-                    .map_err(|e| Error::PlaylistFile(format!("invalid link: {e}")))?,
-                time: next_field()?
-                    .parse()
-                    .map_err(|_| Error::PlaylistFile("invalid duration".into()))?,
-                categories: fields.collect(),
-            }))
+                .map(|f| String::from_utf8_lossy(f).into_owned())
+                .collect::<Vec<_>>();
+            Song::try_from(fields)
+                .map(Some)
+                .map_err(Error::PlaylistFile)
         }
         None => Ok(None),
     }
@@ -352,20 +749,216 @@ pub struct PlaylistIds(HashSet<String>);
 
 impl PlaylistIds {
     pub async fn load() -> io::Result<Self> {
-        let playlist_path = Playlist::path()?;
+        let mut set = HashSet::new();
+        Self::read_into(Playlist::path()?, &mut set).await?;
+        for path in includes() {
+            if let Err(e) = Self::read_into(path.clone(), &mut set).await {
+                tracing::error!(error = ?e, path = %path.display(), "failed to read included playlist");
+            }
+        }
+        Ok(Self(set))
+    }
+
+    async fn read_into(playlist_path: PathBuf, set: &mut HashSet<String>) -> io::Result<()> {
         let file = File::open(playlist_path).await?;
         let mut reader = READER_BUILDER.create_deserializer(file);
         let mut record = StringRecord::new();
-        let mut set = HashSet::new();
         while reader.read_record(&mut record).await? {
             //TODO: unwrap
             let id = record.get(1).unwrap().split('/').last().unwrap();
             set.insert(id.to_string());
         }
-        Ok(Self(set))
+        Ok(())
     }
 
     pub fn contains(&self, l: &str) -> bool {
         self.0.contains(l)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn song(name: &str, categories: &[&str]) -> Song {
+        Song {
+            name: name.into(),
+            link: VideoLink::from_id(VideoId::new("dQw4w9WgXcQ")),
+            time: 0,
+            added: None,
+            categories: categories.iter().map(|c| Category::new(c)).collect(),
+        }
+    }
+
+    #[test]
+    fn categories_change_in_bulk() {
+        let mut playlist = Playlist {
+            songs: vec![
+                song("a", &["rock", "rock/metal"]),
+                song("b", &["rockabilly", "metal"]),
+                song("c", &["chill"]),
+            ],
+            included: HashMap::new(),
+        };
+        let names = |songs: Vec<&Song>| songs.iter().map(|s| s.name.clone()).collect::<Vec<_>>();
+
+        let renamed = playlist
+            .rename_category(&Category::new("rock"), &Category::new("metal"))
+            .unwrap();
+        assert_eq!(names(renamed), ["a"]);
+        assert_eq!(
+            &*playlist.songs[0].categories,
+            [Category::new("metal"), Category::new("metal/metal")]
+        );
+        assert_eq!(
+            &*playlist.songs[1].categories,
+            [Category::new("rockabilly"), Category::new("metal")]
+        );
+
+        let added = playlist
+            .add_category(&Category::new("metal"), |s| s.name != "c")
+            .unwrap();
+        assert!(added.is_empty());
+
+        let removed = playlist.remove_category(&Category::new("metal")).unwrap();
+        assert_eq!(names(removed), ["a", "b"]);
+        assert!(playlist.songs[0].categories.is_empty());
+    }
+
+    #[test]
+    fn added_time_is_optional() {
+        let fields = |s: &str| s.split('\t').map(String::from).collect::<Vec<_>>();
+        let old = Song::try_from(fields("a\thttps://youtu.be/dQw4w9WgXcQ\t212\tchill")).unwrap();
+        assert_eq!(old.added, None);
+        assert_eq!(&*old.categories, [Category::new("chill")]);
+        let new = Song::try_from(fields(
+            "a\thttps://youtu.be/dQw4w9WgXcQ\t212\tadded=1700000000\tchill",
+        ))
+        .unwrap();
+        assert_eq!(
+            new.added,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+        assert_eq!(&*new.categories, [Category::new("chill")]);
+    }
+
+    #[tokio::test]
+    async fn songs_round_trip() {
+        let mut added = song("a", &["chill", "rock/metal"]);
+        added.added = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        let mut writer = WRITER_BUILDER.create_serializer(Vec::new());
+        writer.serialize(&added).await.unwrap();
+        writer.serialize(&song("b", &[])).await.unwrap();
+        let Ok(bytes) = writer.into_inner().await else {
+            panic!("failed to write the playlist");
+        };
+        assert_eq!(
+            String::from_utf8_lossy(&bytes),
+            "a\thttps://youtu.be/dQw4w9WgXcQ\t0\tadded=1700000000\tchill\trock/metal\n\
+             b\thttps://youtu.be/dQw4w9WgXcQ\t0\n"
+        );
+        let playlist = Playlist::load_from_reader(&bytes[..]).await.unwrap();
+        assert_eq!(playlist.songs[0].added, added.added);
+        assert_eq!(&*playlist.songs[0].categories, &*added.categories);
+        assert_eq!(playlist.songs[1].added, None);
+    }
+
+    #[tokio::test]
+    async fn writes_wait_for_the_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("playlist");
+        let held = Playlist::lock_at(&path).await.unwrap();
+        let add = tokio::spawn({
+            let path = path.clone();
+            async move { Playlist::add_song_to(&path, &song("a", &[])).await }
+        });
+        let playlist = Playlist {
+            songs: vec![song("b", &[])],
+            included: HashMap::new(),
+        };
+        held.save(&playlist).await.unwrap();
+        drop(held);
+        add.await.unwrap().unwrap();
+        // had the song been added while the lock was held, this save would have overwritten it
+        let playlist = Playlist::load_from(path).await.unwrap();
+        let names = playlist.songs.iter().map(|s| &s.name).collect::<Vec<_>>();
+        assert_eq!(names, ["b", "a"]);
+    }
+
+    #[tokio::test]
+    async fn changes_made_under_the_lock_are_not_lost() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("playlist");
+        tokio::fs::write(&path, "").await.unwrap();
+        let change = |name: &'static str| {
+            let path = path.clone();
+            tokio::spawn(async move {
+                let lock = Playlist::lock_at(&path).await?;
+                let mut playlist = Playlist::load_from(path).await?;
+                playlist.songs.push(song(name, &[]));
+                lock.save(&playlist).await
+            })
+        };
+        let (a, b) = (change("a"), change("b"));
+        a.await.unwrap().unwrap();
+        b.await.unwrap().unwrap();
+        let playlist = Playlist::load_from(path).await.unwrap();
+        assert_eq!(playlist.songs.len(), 2);
+    }
+
+    fn with_id(name: &str, id: &str) -> Song {
+        Song {
+            link: VideoLink::from_id(VideoId::new(id)),
+            ..song(name, &[])
+        }
+    }
+
+    #[tokio::test]
+    async fn included_songs_are_not_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("playlist");
+        let mut playlist = Playlist {
+            songs: vec![with_id("a", "dQw4w9WgXcQ")],
+            included: HashMap::new(),
+        };
+        playlist.include(
+            &dir.path().join("included"),
+            vec![
+                with_id("a again", "dQw4w9WgXcQ"),
+                with_id("b", "9bZkp7q1_ew"),
+            ],
+        );
+        assert_eq!(
+            playlist.songs.iter().map(|s| &*s.name).collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+        playlist.save_atomically_to(&path).await.unwrap();
+        let saved = Playlist::load_from(path).await.unwrap();
+        assert_eq!(saved.songs.len(), 1);
+        assert_eq!(saved.songs[0].name, "a");
+    }
+
+    #[test]
+    fn included_songs_are_not_edited() {
+        let mut playlist = Playlist {
+            songs: vec![with_id("a", "dQw4w9WgXcQ")],
+            included: HashMap::new(),
+        };
+        let included = PathBuf::from("/sync/playlist");
+        playlist.include(&included, vec![with_id("b", "9bZkp7q1_ew")]);
+        let chill = Category::new("chill");
+
+        let changed = playlist.add_category(&chill, |s| s.name == "a").unwrap();
+        assert_eq!(changed.len(), 1);
+        match playlist.add_category(&chill, |_| true) {
+            Err(Error::IncludedSong { song, path }) => {
+                assert_eq!(song, "b");
+                assert_eq!(path, included);
+            }
+            r => panic!("edited an included song: {:?}", r.map(|s| s.len())),
+        }
+        assert!(playlist.songs[1].categories.is_empty());
+        let b = playlist.find_song_mut(|s| s.name == "b").unwrap();
+        assert!(b.ensure_editable().is_err());
+    }
+}
@@ -0,0 +1,508 @@
+//! A small query language over the playlist, e.g.
+//! `cat:chill AND duration<5m AND NOT name:"live at"`.
+//!
+//! Terms are `field:value` or `field<op>value` where `<op>` is one of `<`, `<=`, `>`, `>=` or `=`.
+//! The supported fields are:
+//! - `cat`: the song is in the category or one of its children.
+//! - `name`: the song's name contains the value, ignoring case.
+//! - `duration`: compares the song's duration, written like `90s`, `5m` or `1h30m`.
+//! - `played`: compares when the song was last played, only supported when parsed with
+//!   [`Query::parse_with_dates`]. Songs that were never played were played before any date.
+//! - `added`: compares when the song was added to the playlist, like `played`. Songs added
+//!   before that was recorded were added before any date.
+//!
+//! Terms are combined with `AND`, `OR` and `NOT` (in decreasing order of precedence: `NOT`,
+//! `AND`, `OR`) and grouped with parenthesis. Terms next to each other are implicitly `AND`ed.
+
+use std::{
+    iter::Peekable,
+    ops::Range,
+    str::CharIndices,
+    time::{Duration, SystemTime},
+};
+
+use thiserror::Error;
+
+use super::{Category, Song};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+}
+
+impl Cmp {
+    fn test<T: Ord>(self, a: T, b: T) -> bool {
+        match self {
+            Cmp::Lt => a < b,
+            Cmp::Le => a <= b,
+            Cmp::Gt => a > b,
+            Cmp::Ge => a >= b,
+            Cmp::Eq => a == b,
+        }
+    }
+
+    /// Compares a point in time with a range of time, `<` being before all of it and `>` after
+    /// all of it.
+    fn test_range(self, t: SystemTime, range: &Range<SystemTime>) -> bool {
+        match self {
+            Cmp::Lt => t < range.start,
+            Cmp::Le => t < range.end.max(range.start),
+            Cmp::Gt => t >= range.end.max(range.start),
+            Cmp::Ge => t >= range.start,
+            Cmp::Eq => range.contains(&t),
+        }
+    }
+}
+
+/// Parses the value of a date field into the range of time it refers to.
+pub type DateParser<'s> = &'s dyn Fn(&str) -> Option<Range<SystemTime>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+    Category(Category),
+    /// Lowercased, matched case insensitively.
+    Name(String),
+    Duration(Cmp, Duration),
+    Played(Cmp, Range<SystemTime>),
+    Added(Cmp, Range<SystemTime>),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum QueryError {
+    #[error("the query is empty")]
+    Empty,
+    #[error("unexpected end of query")]
+    UnexpectedEnd,
+    #[error("unexpected '{0}'")]
+    Unexpected(String),
+    #[error("unclosed quote")]
+    UnclosedQuote,
+    #[error("unknown field '{0}', expected one of cat, name, duration, played or added")]
+    UnknownField(String),
+    #[error("'{0}' can't be used with {1}, use ':' instead")]
+    InvalidComparison(&'static str, String),
+    #[error("invalid duration '{0}', expected something like 90s, 5m or 1h30m")]
+    InvalidDuration(String),
+    #[error("invalid date '{0}'")]
+    InvalidDate(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Term(String),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, QueryError> {
+    fn word(chars: &mut Peekable<CharIndices<'_>>) -> Result<String, QueryError> {
+        let mut word = String::new();
+        while let Some(&(_, c)) = chars.peek() {
+            match c {
+                '(' | ')' => break,
+                c if c.is_whitespace() => break,
+                '"' => {
+                    chars.next();
+                    loop {
+                        match chars.next() {
+                            Some((_, '"')) => break,
+                            Some((_, c)) => word.push(c),
+                            None => return Err(QueryError::UnclosedQuote),
+                        }
+                    }
+                }
+                c => {
+                    chars.next();
+                    word.push(c);
+                }
+            }
+        }
+        Ok(word)
+    }
+
+    let mut chars = s.char_indices().peekable();
+    let mut tokens = Vec::new();
+    while let Some(&(_, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen)
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen)
+            }
+            _ => {
+                let word = word(&mut chars)?;
+                tokens.push(match &word[..] {
+                    w if w.eq_ignore_ascii_case("and") => Token::And,
+                    w if w.eq_ignore_ascii_case("or") => Token::Or,
+                    w if w.eq_ignore_ascii_case("not") => Token::Not,
+                    _ => Token::Term(word),
+                })
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses durations like `90`, `90s`, `5m` or `1h30m`. Numbers without a unit are seconds.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let mut total = 0u64;
+    let mut number = None::<u64>;
+    for c in s.chars() {
+        match c {
+            '0'..='9' => {
+                number = Some(
+                    number
+                        .unwrap_or(0)
+                        .checked_mul(10)?
+                        .checked_add(u64::from(c as u8 - b'0'))?,
+                )
+            }
+            'h' | 'm' | 's' => {
+                let unit = match c {
+                    'h' => 3600,
+                    'm' => 60,
+                    _ => 1,
+                };
+                total = total.checked_add(number.take()?.checked_mul(unit)?)?;
+            }
+            _ => return None,
+        }
+    }
+    if s.is_empty() {
+        return None;
+    }
+    Some(Duration::from_secs(total.checked_add(number.unwrap_or(0))?))
+}
+
+fn parse_term(term: &str, dates: DateParser<'_>) -> Result<Query, QueryError> {
+    let Some(op_start) = term.find([':', '<', '>', '=']) else {
+        return Err(QueryError::Unexpected(term.into()));
+    };
+    let (field, rest) = term.split_at(op_start);
+    let (cmp, op, value) = [
+        ("<=", Cmp::Le),
+        (">=", Cmp::Ge),
+        ("<", Cmp::Lt),
+        (">", Cmp::Gt),
+        ("=", Cmp::Eq),
+        (":", Cmp::Eq),
+    ]
+    .into_iter()
+    .find_map(|(op, cmp)| Some((cmp, op, rest.strip_prefix(op)?)))
+    .expect("rest starts with one of the operators");
+    let only_eq = |field: &str| {
+        if cmp == Cmp::Eq {
+            Ok(())
+        } else {
+            Err(QueryError::InvalidComparison(op, field.into()))
+        }
+    };
+    match field {
+        "cat" | "category" => {
+            only_eq(field)?;
+            Ok(Query::Category(Category::new(value)))
+        }
+        "name" => {
+            only_eq(field)?;
+            Ok(Query::Name(value.to_lowercase()))
+        }
+        "duration" => parse_duration(value)
+            .map(|d| Query::Duration(cmp, d))
+            .ok_or_else(|| QueryError::InvalidDuration(value.into())),
+        "played" => dates(value)
+            .map(|range| Query::Played(cmp, range))
+            .ok_or_else(|| QueryError::InvalidDate(value.into())),
+        "added" => dates(value)
+            .map(|range| Query::Added(cmp, range))
+            .ok_or_else(|| QueryError::InvalidDate(value.into())),
+        _ => Err(QueryError::UnknownField(field.into())),
+    }
+}
+
+struct Parser<'s> {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+    dates: DateParser<'s>,
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Result<Query, QueryError> {
+        let mut q = self.and()?;
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            q = Query::Or(Box::new(q), Box::new(self.and()?));
+        }
+        Ok(q)
+    }
+
+    fn and(&mut self) -> Result<Query, QueryError> {
+        let mut q = self.not()?;
+        loop {
+            match self.tokens.peek() {
+                Some(Token::And) => {
+                    self.tokens.next();
+                }
+                Some(Token::Term(_) | Token::Not | Token::LParen) => {}
+                _ => break,
+            }
+            q = Query::And(Box::new(q), Box::new(self.not()?));
+        }
+        Ok(q)
+    }
+
+    fn not(&mut self) -> Result<Query, QueryError> {
+        if self.tokens.next_if_eq(&Token::Not).is_some() {
+            Ok(Query::Not(Box::new(self.not()?)))
+        } else {
+            self.atom()
+        }
+    }
+
+    fn atom(&mut self) -> Result<Query, QueryError> {
+        match self.tokens.next() {
+            Some(Token::LParen) => {
+                let q = self.or()?;
+                match self.tokens.next() {
+                    Some(Token::RParen) => Ok(q),
+                    Some(t) => Err(unexpected(t)),
+                    None => Err(QueryError::UnexpectedEnd),
+                }
+            }
+            Some(Token::Term(t)) => parse_term(&t, self.dates),
+            Some(t) => Err(unexpected(t)),
+            None => Err(QueryError::UnexpectedEnd),
+        }
+    }
+}
+
+fn unexpected(t: Token) -> QueryError {
+    QueryError::Unexpected(match t {
+        Token::LParen => "(".into(),
+        Token::RParen => ")".into(),
+        Token::And => "AND".into(),
+        Token::Or => "OR".into(),
+        Token::Not => "NOT".into(),
+        Token::Term(t) => t,
+    })
+}
+
+impl Query {
+    /// Parses a query without support for dates, see [`Query::parse_with_dates`].
+    pub fn parse(s: &str) -> Result<Self, QueryError> {
+        Self::parse_with_dates(s, &|_| None)
+    }
+
+    /// Parses a query, using `dates` for the values of date fields.
+    pub fn parse_with_dates(s: &str, dates: DateParser<'_>) -> Result<Self, QueryError> {
+        let tokens = tokenize(s)?;
+        if tokens.is_empty() {
+            return Err(QueryError::Empty);
+        }
+        let mut parser = Parser {
+            tokens: tokens.into_iter().peekable(),
+            dates,
+        };
+        let q = parser.or()?;
+        match parser.tokens.next() {
+            None => Ok(q),
+            Some(t) => Err(unexpected(t)),
+        }
+    }
+
+    /// Replaces every category in the query, used to expand aliases.
+    pub fn map_categories(&mut self, f: &impl Fn(&Category) -> Category) {
+        match self {
+            Query::And(a, b) | Query::Or(a, b) => {
+                a.map_categories(f);
+                b.map_categories(f);
+            }
+            Query::Not(q) => q.map_categories(f),
+            Query::Category(c) => *c = f(c),
+            Query::Name(_) | Query::Duration(..) | Query::Played(..) | Query::Added(..) => {}
+        }
+    }
+
+    /// Whether matching needs to know when songs were last played, see [`Query::matches_with`].
+    pub fn uses_plays(&self) -> bool {
+        match self {
+            Query::And(a, b) | Query::Or(a, b) => a.uses_plays() || b.uses_plays(),
+            Query::Not(q) => q.uses_plays(),
+            Query::Played(..) => true,
+            Query::Category(_) | Query::Name(_) | Query::Duration(..) | Query::Added(..) => false,
+        }
+    }
+
+    /// Matches a song as if it had never been played.
+    pub fn matches(&self, song: &Song) -> bool {
+        self.matches_with(song, None)
+    }
+
+    pub fn matches_with(&self, song: &Song, last_played: Option<SystemTime>) -> bool {
+        match self {
+            Query::And(a, b) => {
+                a.matches_with(song, last_played) && b.matches_with(song, last_played)
+            }
+            Query::Or(a, b) => {
+                a.matches_with(song, last_played) || b.matches_with(song, last_played)
+            }
+            Query::Not(q) => !q.matches_with(song, last_played),
+            Query::Category(c) => song.is_in(c),
+            Query::Name(n) => song.name.to_lowercase().contains(n),
+            Query::Duration(cmp, d) => cmp.test(Duration::from_secs(song.time), *d),
+            Query::Played(cmp, range) => {
+                cmp.test_range(last_played.unwrap_or(SystemTime::UNIX_EPOCH), range)
+            }
+            Query::Added(cmp, range) => {
+                cmp.test_range(song.added.unwrap_or(SystemTime::UNIX_EPOCH), range)
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Query {
+    type Err = QueryError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn song(name: &str, time: u64, categories: &[&str]) -> Song {
+        Song {
+            name: name.into(),
+            link: "https://youtu.be/dQw4w9WgXcQ"
+                .parse::<url::Url>()
+                .unwrap()
+                .try_into()
+                .unwrap(),
+            time,
+            added: None,
+            categories: categories.iter().map(|&c| Category::new(c)).collect(),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        let cat = |c| Box::new(Query::Category(Category::new(c)));
+        assert_eq!(
+            Query::parse("cat:a OR NOT cat:b cat:c").unwrap(),
+            Query::Or(
+                cat("a"),
+                Box::new(Query::And(Box::new(Query::Not(cat("b"))), cat("c")))
+            )
+        );
+        assert_eq!(
+            Query::parse("(cat:a OR cat:b) AND duration<=1h30m").unwrap(),
+            Query::And(
+                Box::new(Query::Or(cat("a"), cat("b"))),
+                Box::new(Query::Duration(Cmp::Le, Duration::from_secs(5400)))
+            )
+        );
+    }
+
+    #[test]
+    fn evaluation() {
+        let q = Query::parse(r#"cat:chill AND duration<5m AND NOT name:"live at""#).unwrap();
+        assert!(q.matches(&song("Calm", 200, &["chill/lofi"])));
+        assert!(!q.matches(&song("Calm (Live at Home)", 200, &["chill"])));
+        assert!(!q.matches(&song("Long", 400, &["chill"])));
+        assert!(!q.matches(&song("Loud", 200, &["metal"])));
+    }
+
+    #[test]
+    fn played() {
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        // every date is the range [100, 200)
+        let dates = |s: &str| (s == "d").then(|| at(100)..at(200));
+        let q = |s| Query::parse_with_dates(s, &dates).unwrap();
+        let s = song("a", 1, &[]);
+        assert!(q("played:d").uses_plays());
+        assert!(q("played<d").matches_with(&s, Some(at(99))));
+        assert!(!q("played<d").matches_with(&s, Some(at(100))));
+        assert!(q("played<=d").matches_with(&s, Some(at(150))));
+        assert!(q("played>d").matches_with(&s, Some(at(200))));
+        assert!(!q("played>d").matches_with(&s, Some(at(199))));
+        assert!(q("played>=d").matches_with(&s, Some(at(100))));
+        assert!(q("played:d").matches_with(&s, Some(at(150))));
+        assert!(!q("played:d").matches_with(&s, Some(at(200))));
+        // never played
+        assert!(q("played<d").matches(&s));
+        assert!(!q("played>=d").matches(&s));
+
+        assert_eq!(
+            Query::parse("played>d"),
+            Err(QueryError::InvalidDate("d".into()))
+        );
+        assert_eq!(
+            Query::parse_with_dates("played>x", &dates),
+            Err(QueryError::InvalidDate("x".into()))
+        );
+    }
+
+    #[test]
+    fn added() {
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let dates = |s: &str| (s == "d").then(|| at(100)..at(200));
+        let q = |s| Query::parse_with_dates(s, &dates).unwrap();
+        let added = |secs| Song {
+            added: Some(at(secs)),
+            ..song("a", 1, &[])
+        };
+        assert!(!q("added:d").uses_plays());
+        assert!(q("added<d").matches(&added(99)));
+        assert!(!q("added<d").matches(&added(100)));
+        assert!(q("added>d").matches(&added(200)));
+        assert!(!q("added>d").matches(&added(199)));
+        assert!(q("added:d").matches(&added(150)));
+        // plays don't matter
+        assert!(q("added:d").matches_with(&added(150), Some(at(0))));
+        // added before it was recorded
+        assert!(q("added<d").matches(&song("a", 1, &[])));
+        assert!(!q("added>=d").matches(&song("a", 1, &[])));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Query::parse(""), Err(QueryError::Empty));
+        assert_eq!(Query::parse("(cat:a"), Err(QueryError::UnexpectedEnd));
+        assert_eq!(
+            Query::parse("year>2000"),
+            Err(QueryError::UnknownField("year".into()))
+        );
+        assert_eq!(
+            Query::parse("cat<a"),
+            Err(QueryError::InvalidComparison("<", "cat".into()))
+        );
+        assert_eq!(
+            Query::parse("duration<5x"),
+            Err(QueryError::InvalidDuration("5x".into()))
+        );
+        for overflowing in [
+            "18446744073709551616",
+            "18446744073709551615h",
+            "1s18446744073709551615",
+        ] {
+            assert_eq!(
+                Query::parse(&format!("duration<{overflowing}")),
+                Err(QueryError::InvalidDuration(overflowing.into()))
+            );
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    env, io,
+    path::{Path, PathBuf},
+};
+
+use dirs::config_dir;
+use futures_util::Stream;
+
+use super::{Playlist, PlaylistLock, Song};
+use crate::Error;
+
+/// A directory of named playlists, each one stored in the same format as the main playlist file.
+pub struct PlaylistSet {
+    dir: PathBuf,
+}
+
+impl PlaylistSet {
+    /// The default location of the playlists directory, `$PLAYLISTS` or `~/.config/m/playlists`.
+    pub fn default_dir() -> io::Result<PathBuf> {
+        env::var_os("PLAYLISTS")
+            .map(PathBuf::from)
+            .or_else(|| {
+                let mut dir = config_dir()?;
+                dir.push("m");
+                dir.push("playlists");
+                Some(dir)
+            })
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    pub fn open() -> io::Result<Self> {
+        Ok(Self::at(Self::default_dir()?))
+    }
+
+    pub fn at(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The path of the file where the playlist with this name is stored.
+    pub fn path_of(&self, name: &str) -> Result<PathBuf, Error> {
+        if name.is_empty() || name.starts_with('.') || name.contains('/') {
+            return Err(Error::PlaylistFile(format!(
+                "invalid playlist name: {name:?}"
+            )));
+        }
+        Ok(self.dir.join(name))
+    }
+
+    /// The names of all playlists in the set, sorted.
+    pub async fn names(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(names),
+            Err(e) => return Err(e),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if !entry.file_type().await?.is_file() {
+                continue;
+            }
+            if let Ok(name) = entry.file_name().into_string() {
+                if !name.starts_with('.') {
+                    names.push(name);
+                }
+            }
+        }
+        names.sort_unstable();
+        Ok(names)
+    }
+
+    pub async fn load(&self, name: &str) -> Result<Playlist, Error> {
+        Playlist::load_from(self.path_of(name)?).await
+    }
+
+    pub async fn stream(
+        &self,
+        name: &str,
+    ) -> Result<impl Stream<Item = Result<Song, csv_async::Error>>, Error> {
+        Playlist::stream_from(self.path_of(name)?).await
+    }
+
+    /// Loads every playlist in the set.
+    pub async fn load_all(&self) -> Result<Vec<(String, Playlist)>, Error> {
+        let mut playlists = Vec::new();
+        for name in self.names().await? {
+            let playlist = self.load(&name).await?;
+            playlists.push((name, playlist));
+        }
+        Ok(playlists)
+    }
+
+    /// Saves a playlist with this name, creating it if it doesn't exist.
+    pub async fn save(&self, name: &str, playlist: &Playlist) -> Result<(), Error> {
+        let path = self.path_of(name)?;
+        tokio::fs::create_dir_all(&self.dir).await?;
+        playlist.save_atomically_to(&path).await
+    }
+
+    /// Takes the lock that writes to the playlist with this name hold, see [`PlaylistLock`].
+    pub async fn lock(&self, name: &str) -> Result<PlaylistLock, Error> {
+        let path = self.path_of(name)?;
+        tokio::fs::create_dir_all(&self.dir).await?;
+        Playlist::lock_at(&path).await
+    }
+
+    pub async fn add_song(&self, name: &str, song: &Song) -> Result<(), Error> {
+        let path = self.path_of(name)?;
+        tokio::fs::create_dir_all(&self.dir).await?;
+        Playlist::add_song_to(&path, song).await
+    }
+
+    pub async fn contains_song(&self, name: &str, song: &str) -> Result<bool, Error> {
+        match Playlist::contains_song_in(&self.path_of(name)?, song).await {
+            Ok(b) => Ok(b),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
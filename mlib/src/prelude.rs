@@ -0,0 +1,21 @@
+//! The types most programs built on top of mlib need, meant to be glob imported.
+//!
+//! ```no_run
+//! use mlib::prelude::*;
+//!
+//! # #[cfg(feature = "player-connection")]
+//! # async fn f() -> Result<(), Error> {
+//! let title = PlayerLink::current().media_title().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "player-connection")]
+pub use crate::players::{PlayerIndex, PlayerLink};
+#[cfg(feature = "playlist")]
+pub use crate::playlist::{Category, Playlist, Song};
+#[cfg(feature = "queue")]
+pub use crate::queue::Queue;
+#[cfg(any(feature = "ytdl", feature = "playlist", feature = "player-connection"))]
+pub use crate::Error;
+pub use crate::{Item, Link, Search, VideoId};
@@ -1,9 +1,14 @@
-use std::time::Duration;
+use std::{
+    io,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 
 pub use crate::Item;
 use crate::{
     item::id_from_path,
-    players::{PlayerLink, QueueItem},
+    players::{PlayerLink, QueueItem, TrackMetadata},
+    playlist::Category,
     Error, Link,
 };
 
@@ -58,7 +63,7 @@ impl Queue {
     pub async fn link(player: &PlayerLink) -> Result<Item, Error> {
         let current_idx = player.queue_pos().await?;
         let current = player.queue_at(current_idx).await?;
-        match Item::from(current.filename) {
+        match current.to_item() {
             Item::Link(l) => Ok(Item::Link(l)),
             Item::File(p) => Ok(id_from_path(&p)
                 .map(Link::from_video_id)
@@ -128,6 +133,11 @@ impl Queue {
                 .flatten()
                 .map(|m| (m.index, m.title));
 
+            let track = player.track_metadata().await.unwrap_or_else(|e| {
+                tracing::error!(%e, "getting the track metadata");
+                TrackMetadata::default()
+            });
+
             tracing::trace!("done");
             Ok((
                 title,
@@ -138,6 +148,7 @@ impl Queue {
                 duration,
                 categories,
                 chapter,
+                track,
             ))
         }
         .instrument(tracing::trace_span!("metadata"));
@@ -145,18 +156,22 @@ impl Queue {
         let next = async {
             tracing::trace!("getting");
             let current_idx = player.queue_pos().await?;
-            let next = match opt {
-                CurrentOptions::GetNext => Self::up_next(player, current_idx).await?,
-                CurrentOptions::None => None,
+            let (next, upcoming) = match opt {
+                CurrentOptions::GetNext => (Self::up_next(player, current_idx).await?, vec![]),
+                CurrentOptions::GetUpcoming(n) => {
+                    let upcoming = Self::upcoming(player, current_idx, n).await?;
+                    (upcoming.first().cloned(), upcoming)
+                }
+                CurrentOptions::None => (None, vec![]),
             };
             tracing::trace!("done");
-            Ok::<_, Error>((current_idx, next))
+            Ok::<_, Error>((current_idx, next, upcoming))
         }
         .instrument(tracing::trace_span!("up next"));
 
         let (
-            (current_idx, next),
-            (title, playing, volume, progress, playback_time, duration, categories, chapter),
+            (current_idx, next, upcoming),
+            (title, playing, volume, progress, playback_time, duration, categories, chapter, track),
         ) = futures_util::try_join!(next, metadata)?;
 
         Ok(Current {
@@ -166,10 +181,12 @@ impl Queue {
             categories: categories.into_vec(),
             volume,
             progress,
-            duration: Duration::from_secs_f64(duration),
-            playback_time: (playback_time >= 0.0).then(|| Duration::from_secs_f64(playback_time)),
+            duration: Duration::try_from_secs_f64(duration).unwrap_or_default(),
+            playback_time: Duration::try_from_secs_f64(playback_time).ok(),
             index: current_idx,
             next,
+            upcoming,
+            track,
         })
     }
 
@@ -179,8 +196,6 @@ impl Queue {
     where
         I: Into<Option<usize>> + std::fmt::Debug,
     {
-        use crate::item::link::VideoLink;
-
         tracing::trace!("getting queue_size");
         let size = player.queue_size().await?;
         if size == 1 {
@@ -195,16 +210,29 @@ impl Queue {
         };
         tracing::trace!("getting queue_at");
         let next = player.queue_at((queue_index + 1) % size).await?.filename;
-        let next = Some(match VideoLink::try_from(next) {
-            Ok(l) => {
-                tracing::trace!("resolving link");
-                l.resolve_link().await
-            }
-            Err(next) => crate::item::clean_up_path(&next)
-                .unwrap_or(&next)
-                .to_owned(),
-        });
-        Ok(next)
+        Ok(Some(display_name(next).await))
+    }
+
+    /// The names of the `n` songs after the one at `queue_index`, fetched all at once. If the
+    /// queue loops, these wrap around to its start, up to the song at `queue_index`.
+    #[tracing::instrument(skip(player))]
+    #[cfg(feature = "ytdl")]
+    pub async fn upcoming(
+        player: &PlayerLink,
+        queue_index: usize,
+        n: usize,
+    ) -> Result<Vec<String>, Error> {
+        let mut items = player
+            .queue_range(queue_index + 1, queue_index + 1 + n)
+            .await?;
+        let missing = n - items.len();
+        if missing > 0 && player.queue_is_looping().await? != crate::players::LoopStatus::No {
+            items.extend(player.queue_range(0, missing.min(queue_index)).await?);
+        }
+        Ok(
+            futures_util::future::join_all(items.into_iter().map(|i| display_name(i.filename)))
+                .await,
+        )
     }
 
     pub fn iter(&self) -> impl DoubleEndedIterator<Item = &SongIdent> {
@@ -231,9 +259,49 @@ impl IntoIterator for Queue {
     }
 }
 
+/// What to call a queued file: the title of a video, or the path without the clutter.
+#[cfg(feature = "ytdl")]
+async fn display_name(filename: String) -> String {
+    use crate::item::link::VideoLink;
+
+    match VideoLink::try_from(filename) {
+        Ok(l) => {
+            tracing::trace!("resolving link");
+            l.resolve_link().await
+        }
+        Err(next) => crate::item::clean_up_path(&next)
+            .unwrap_or(&next)
+            .to_owned(),
+    }
+}
+
+/// How long until an item of the queue starts playing and until the queue is over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Eta {
+    pub until_target: Duration,
+    pub until_end: Duration,
+    /// How many of the upcoming items have an unknown duration, and weren't accounted for.
+    pub unknown: usize,
+}
+
+impl Eta {
+    /// Computes the eta of the `target`th upcoming item (0 being the one after the current),
+    /// given what's left of the current item and the durations of the upcoming ones.
+    pub fn compute(remaining: Duration, upcoming: &[Option<Duration>], target: usize) -> Self {
+        let sum = |items: &[Option<Duration>]| remaining + items.iter().flatten().sum::<Duration>();
+        Self {
+            until_target: sum(&upcoming[..target.min(upcoming.len())]),
+            until_end: sum(upcoming),
+            unknown: upcoming.iter().filter(|d| d.is_none()).count(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Copy, Default)]
 pub enum CurrentOptions {
     GetNext,
+    /// Get this many of the songs after the current one, see [`Current::upcoming`].
+    GetUpcoming(usize),
     #[default]
     None,
 }
@@ -247,9 +315,13 @@ pub struct Current {
     pub progress: Option<f64>,
     pub playback_time: Option<Duration>,
     pub duration: Duration,
-    pub categories: Vec<String>,
+    pub categories: Vec<Category>,
     pub index: usize,
     pub next: Option<String>,
+    /// The songs after the current one, when asked for with [`CurrentOptions::GetUpcoming`].
+    #[serde(default)]
+    pub upcoming: Vec<String>,
+    pub track: TrackMetadata,
 }
 
 fn slice_queue(mut queue: Vec<QueueItem>, at_most: usize) -> (Vec<SongIdent>, usize, bool) {
@@ -283,8 +355,164 @@ fn slice_queue(mut queue: Vec<QueueItem>, at_most: usize) -> (Vec<SongIdent>, us
         .drain(start_index..end_index)
         .map(|i| SongIdent {
             index: next_index(),
-            item: Item::from(i.filename),
+            item: i.to_item(),
         })
         .collect();
     (items, current_idx, st.playing)
 }
+
+/// A queue saved under a name with [`Snapshot::save`], to be played again later. They are kept in
+/// the data dir with one item per line, so they can also be loaded like any other file of songs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub name: String,
+    pub saved_at: SystemTime,
+    pub items: usize,
+}
+
+fn snapshots_dir() -> io::Result<PathBuf> {
+    let Some(mut path) = dirs::data_dir() else {
+        tracing::error!("failed to get data dir for the saved queues");
+        return Err(io::ErrorKind::NotFound.into());
+    };
+    path.push("m");
+    path.push("queues");
+    Ok(path)
+}
+
+fn snapshot_path(name: &str) -> io::Result<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid queue name: {name:?}"),
+        ));
+    }
+    Ok(snapshots_dir()?.join(name))
+}
+
+fn not_saved(name: &str, e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::NotFound {
+        io::Error::new(e.kind(), format!("no queue saved as {name:?}"))
+    } else {
+        e
+    }
+}
+
+impl Snapshot {
+    /// Saves the whole queue of `player` as `name`, replacing what was saved with that name.
+    pub async fn save(player: &PlayerLink, name: &str) -> Result<Self, Error> {
+        let path = snapshot_path(name)?;
+        let queue = Queue::load_full(player).await?;
+        tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+        tokio::fs::write(&path, render(queue.iter().map(|s| &s.item))).await?;
+        Ok(Self {
+            name: name.to_owned(),
+            saved_at: SystemTime::now(),
+            items: queue.iter().count(),
+        })
+    }
+
+    /// The saved queues, most recent first.
+    pub async fn list() -> io::Result<Vec<Self>> {
+        let mut entries = match tokio::fs::read_dir(snapshots_dir()?).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut snapshots = vec![];
+        while let Some(entry) = entries.next_entry().await? {
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if snapshot_path(&name).is_err() || !entry.file_type().await?.is_file() {
+                continue;
+            }
+            let saved_at = entry.metadata().await?.modified()?;
+            let items = parse(&tokio::fs::read_to_string(entry.path()).await?).len();
+            snapshots.push(Self {
+                name,
+                saved_at,
+                items,
+            });
+        }
+        snapshots.sort_by(|a, b| b.saved_at.cmp(&a.saved_at));
+        Ok(snapshots)
+    }
+
+    /// The items of the queue saved as `name`.
+    pub async fn load(name: &str) -> io::Result<Vec<Item>> {
+        let contents = tokio::fs::read_to_string(snapshot_path(name)?)
+            .await
+            .map_err(|e| not_saved(name, e))?;
+        Ok(parse(&contents))
+    }
+
+    pub async fn delete(name: &str) -> io::Result<()> {
+        tokio::fs::remove_file(snapshot_path(name)?)
+            .await
+            .map_err(|e| not_saved(name, e))
+    }
+
+    /// Deletes the queues saved before `before`, returning them.
+    pub async fn prune(before: SystemTime) -> io::Result<Vec<Self>> {
+        let mut pruned = Self::list().await?;
+        pruned.retain(|s| s.saved_at < before);
+        for snapshot in &pruned {
+            Self::delete(&snapshot.name).await?;
+        }
+        Ok(pruned)
+    }
+}
+
+fn render<'i>(items: impl Iterator<Item = &'i Item>) -> Vec<u8> {
+    items.fold(Vec::new(), |mut bytes, item| {
+        bytes.extend_from_slice(item.as_bytes());
+        bytes.push(b'\n');
+        bytes
+    })
+}
+
+fn parse(contents: &str) -> Vec<Item> {
+    contents
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| Item::from(l.to_owned()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn eta() {
+        let secs = Duration::from_secs;
+        let upcoming = [Some(secs(60)), None, Some(secs(30))];
+        assert_eq!(
+            Eta::compute(secs(10), &upcoming, 2),
+            Eta {
+                until_target: secs(70),
+                until_end: secs(100),
+                unknown: 1,
+            }
+        );
+        assert_eq!(Eta::compute(secs(10), &upcoming, 0).until_target, secs(10));
+    }
+
+    #[test]
+    fn snapshots_are_one_item_per_line() {
+        let items = parse("/music/a.mp3\n\nhttps://www.youtube.com/watch?v=dQw4w9WgXcQ\n");
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            render(items.iter()),
+            b"/music/a.mp3\nhttps://www.youtube.com/watch?v=dQw4w9WgXcQ\n"
+        );
+    }
+
+    #[test]
+    fn snapshot_names_stay_in_their_dir() {
+        for name in ["", ".hidden", "../up", "a/b"] {
+            assert!(snapshot_path(name).is_err(), "{name:?}");
+        }
+    }
+}
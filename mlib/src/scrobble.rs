@@ -0,0 +1,394 @@
+//! Submits what was listened to to Last.fm and/or ListenBrainz.
+//!
+//! Scrobbles that fail because the service couldn't be reached are kept in a queue in the data
+//! dir and retried before the next one is submitted. The queue is encrypted like the statistics,
+//! see [`crate::encryption`].
+
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    path::PathBuf,
+    sync::OnceLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+const LASTFM_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+const LISTENBRAINZ_URL: &str = "https://api.listenbrainz.org/1/submit-listens";
+
+/// Last.fm credentials, the session key is obtained through
+/// <https://www.last.fm/api/desktopauth>.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct LastFm {
+    pub api_key: String,
+    pub api_secret: String,
+    pub session_key: String,
+}
+
+/// ListenBrainz credentials, the token is in <https://listenbrainz.org/settings/>.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ListenBrainz {
+    pub token: String,
+}
+
+/// Which services to scrobble to, nothing is submitted if neither is set.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Config {
+    pub lastfm: Option<LastFm>,
+    pub listenbrainz: Option<ListenBrainz>,
+}
+
+impl Config {
+    /// Whether there is anywhere to scrobble to.
+    pub fn is_enabled(&self) -> bool {
+        self.lastfm.is_some() || self.listenbrainz.is_some()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Track {
+    pub artist: String,
+    pub title: String,
+    pub duration: Option<Duration>,
+}
+
+impl Track {
+    /// Guesses the artist from a title like `Artist - Song`, as most music on youtube is named.
+    pub fn from_media_title(media_title: &str, duration: Option<Duration>) -> Option<Self> {
+        let (artist, title) = media_title.split_once(" - ")?;
+        let (artist, title) = (artist.trim(), title.trim());
+        if artist.is_empty() || title.is_empty() {
+            return None;
+        }
+        Some(Self {
+            artist: artist.into(),
+            title: title.into(),
+            duration,
+        })
+    }
+
+    /// Last.fm's rules: the track is longer than 30 seconds and was listened to for half its
+    /// duration or 4 minutes, whichever comes first.
+    pub fn should_scrobble(&self, listened: Duration) -> bool {
+        const MIN_LENGTH: Duration = Duration::from_secs(30);
+        const ENOUGH: Duration = Duration::from_secs(4 * 60);
+        match self.duration {
+            Some(d) if d <= MIN_LENGTH => false,
+            Some(d) => listened >= (d / 2).min(ENOUGH),
+            None => listened >= ENOUGH,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("http: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("server error {0}: {1}")]
+    Unavailable(reqwest::StatusCode, String),
+    #[error("rejected: {0}")]
+    Rejected(String),
+}
+
+impl Error {
+    /// Whether trying again later might work.
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Http(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+            Self::Unavailable(..) => true,
+            Self::Rejected(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Service {
+    LastFm,
+    ListenBrainz,
+}
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::LastFm => "last.fm",
+            Self::ListenBrainz => "listenbrainz",
+        })
+    }
+}
+
+fn services(config: &Config) -> impl Iterator<Item = Service> {
+    [
+        config.lastfm.as_ref().map(|_| Service::LastFm),
+        config.listenbrainz.as_ref().map(|_| Service::ListenBrainz),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// A scrobble that couldn't be submitted yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Pending {
+    service: Service,
+    track: Track,
+    /// Unix timestamp of when the track started playing.
+    listened_at: u64,
+}
+
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .expect("failed to build http client")
+    })
+}
+
+/// Last.fm signs requests with the md5 of every parameter, sorted by name, followed by the
+/// secret.
+fn lastfm_signature(params: &BTreeMap<&str, String>, secret: &str) -> String {
+    let mut sig = String::new();
+    for (k, v) in params {
+        sig.push_str(k);
+        sig.push_str(v);
+    }
+    sig.push_str(secret);
+    format!("{:x}", md5::compute(sig))
+}
+
+async fn lastfm(
+    creds: &LastFm,
+    method: &str,
+    track: &Track,
+    listened_at: Option<u64>,
+) -> Result<(), Error> {
+    let mut params = BTreeMap::from([
+        ("method", method.to_string()),
+        ("artist", track.artist.clone()),
+        ("track", track.title.clone()),
+        ("api_key", creds.api_key.clone()),
+        ("sk", creds.session_key.clone()),
+    ]);
+    if let Some(d) = track.duration {
+        params.insert("duration", d.as_secs().to_string());
+    }
+    if let Some(t) = listened_at {
+        params.insert("timestamp", t.to_string());
+    }
+    let sig = lastfm_signature(&params, &creds.api_secret);
+    params.insert("api_sig", sig);
+    params.insert("format", "json".into());
+
+    let response = client().post(LASTFM_URL).form(&params).send().await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if status.is_server_error() {
+        return Err(Error::Unavailable(status, body));
+    }
+    #[derive(Deserialize)]
+    struct LastFmError {
+        error: u32,
+        message: String,
+    }
+    match serde_json::from_str::<LastFmError>(&body) {
+        // 11: service offline, 16: temporarily unavailable
+        Ok(e) if matches!(e.error, 11 | 16) => Err(Error::Unavailable(status, e.message)),
+        Ok(e) => Err(Error::Rejected(format!("{}: {}", e.error, e.message))),
+        Err(_) if !status.is_success() => Err(Error::Rejected(format!("{status}: {body}"))),
+        Err(_) => Ok(()),
+    }
+}
+
+async fn listenbrainz(
+    creds: &ListenBrainz,
+    track: &Track,
+    listened_at: Option<u64>,
+) -> Result<(), Error> {
+    let mut listen = serde_json::json!({
+        "track_metadata": {
+            "artist_name": track.artist,
+            "track_name": track.title,
+            "additional_info": {
+                "media_player": "m",
+                "duration_ms": track.duration.map(|d| d.as_millis() as u64),
+            },
+        },
+    });
+    if let Some(t) = listened_at {
+        listen["listened_at"] = t.into();
+    }
+    let body = serde_json::json!({
+        "listen_type": if listened_at.is_some() { "single" } else { "playing_now" },
+        "payload": [listen],
+    });
+
+    let response = client()
+        .post(LISTENBRAINZ_URL)
+        .header(
+            reqwest::header::AUTHORIZATION,
+            format!("Token {}", creds.token),
+        )
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()
+        .await?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = response.text().await?;
+    if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        Err(Error::Unavailable(status, body))
+    } else {
+        Err(Error::Rejected(format!("{status}: {body}")))
+    }
+}
+
+async fn submit(
+    config: &Config,
+    service: Service,
+    track: &Track,
+    listened_at: Option<u64>,
+) -> Result<(), Error> {
+    match (service, &config.lastfm, &config.listenbrainz) {
+        (Service::LastFm, Some(creds), _) => {
+            let method = match listened_at {
+                Some(_) => "track.scrobble",
+                None => "track.updateNowPlaying",
+            };
+            lastfm(creds, method, track, listened_at).await
+        }
+        (Service::ListenBrainz, _, Some(creds)) => listenbrainz(creds, track, listened_at).await,
+        // the service was removed from the config since this was queued
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn queue_path() -> io::Result<PathBuf> {
+    let Some(mut path) = dirs::data_dir() else {
+        return Err(io::ErrorKind::NotFound.into());
+    };
+    path.push("m");
+    path.push("scrobble-queue.jsonl");
+    Ok(path)
+}
+
+async fn load_queue() -> io::Result<Vec<Pending>> {
+    let bytes = match tokio::fs::read(queue_path()?).await {
+        Ok(b) => b,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let contents = String::from_utf8(crate::decode_history(bytes)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(contents
+        .lines()
+        .filter_map(|l| match serde_json::from_str(l) {
+            Ok(p) => Some(p),
+            Err(error) => {
+                tracing::warn!(?error, line = l, "dropping corrupted queued scrobble");
+                None
+            }
+        })
+        .collect())
+}
+
+async fn store_queue(queue: &[Pending]) -> io::Result<()> {
+    let path = queue_path()?;
+    if queue.is_empty() {
+        return match tokio::fs::remove_file(path).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    let mut contents = String::new();
+    for p in queue {
+        contents.push_str(&serde_json::to_string(p)?);
+        contents.push('\n');
+    }
+    tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+    tokio::fs::write(path, crate::encode_history(contents.into_bytes())?).await
+}
+
+/// Tells every service that `track` started playing. Failures aren't retried, by the time they
+/// would be it's no longer playing.
+pub async fn now_playing(config: &Config, track: &Track) {
+    for service in services(config) {
+        if let Err(error) = submit(config, service, track, None).await {
+            tracing::warn!(%service, ?error, "failed to update now playing");
+        }
+    }
+}
+
+/// Scrobbles `track` to every service, after retrying the ones that previously failed. Those
+/// that fail because the service couldn't be reached are queued to be retried later.
+pub async fn scrobble(config: &Config, track: Track, started_at: SystemTime) {
+    let listened_at = started_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut queue = load_queue().await.unwrap_or_else(|error| {
+        tracing::error!(?error, "failed to load queued scrobbles");
+        Vec::new()
+    });
+    queue.extend(services(config).map(|service| Pending {
+        service,
+        track: track.clone(),
+        listened_at,
+    }));
+
+    let mut failed = Vec::new();
+    for pending in queue {
+        match submit(
+            config,
+            pending.service,
+            &pending.track,
+            Some(pending.listened_at),
+        )
+        .await
+        {
+            Ok(()) => {}
+            Err(error) if error.is_transient() => {
+                tracing::warn!(service = %pending.service, ?error, "queueing scrobble");
+                failed.push(pending);
+            }
+            Err(error) => {
+                tracing::error!(service = %pending.service, ?error, ?pending.track, "scrobble rejected");
+            }
+        }
+    }
+    if let Err(error) = store_queue(&failed).await {
+        tracing::error!(?error, "failed to store queued scrobbles");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn artist_is_taken_from_the_title() {
+        let track = Track::from_media_title("Daft Punk - One More Time ", None).unwrap();
+        assert_eq!(track.artist, "Daft Punk");
+        assert_eq!(track.title, "One More Time");
+        assert_eq!(Track::from_media_title("One More Time", None), None);
+        assert_eq!(Track::from_media_title(" - One More Time", None), None);
+    }
+
+    #[test]
+    fn scrobble_threshold() {
+        let track = |secs: Option<u64>| Track {
+            artist: "a".into(),
+            title: "t".into(),
+            duration: secs.map(Duration::from_secs),
+        };
+        let min = |m: u64| Duration::from_secs(m * 60);
+        assert!(!track(Some(20)).should_scrobble(min(1)));
+        assert!(track(Some(180)).should_scrobble(Duration::from_secs(90)));
+        assert!(!track(Some(180)).should_scrobble(Duration::from_secs(89)));
+        assert!(track(Some(60 * 60)).should_scrobble(min(4)));
+        assert!(!track(None).should_scrobble(min(3)));
+    }
+}
@@ -0,0 +1,204 @@
+//! Skips the parts of youtube videos that aren't the video itself, like sponsors, as submitted to
+//! <https://sponsor.ajay.app>.
+
+use std::{sync::OnceLock, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::VideoId;
+
+const API_URL: &str = "https://sponsor.ajay.app/api/skipSegments";
+
+/// How many hex digits of the sha256 of a video's id are sent. The server answers with the
+/// segments of every video whose hash starts with them, so it can't tell which one is playing.
+const HASH_PREFIX_LEN: usize = 4;
+
+/// Segments that end less than this many seconds after the position aren't worth seeking over,
+/// seeking isn't exact enough to always land past their end.
+const TOLERANCE: f64 = 0.5;
+
+/// What a segment has in it, see <https://wiki.sponsor.ajay.app/w/Segment_Categories>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SegmentCategory {
+    Sponsor,
+    Selfpromo,
+    Interaction,
+    Intro,
+    Outro,
+    Preview,
+    MusicOfftopic,
+    Filler,
+}
+
+/// Which segments are skipped, sponsors and self promotion by default.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default = "default_categories")]
+    pub categories: Vec<SegmentCategory>,
+}
+
+fn default_categories() -> Vec<SegmentCategory> {
+    vec![SegmentCategory::Sponsor, SegmentCategory::Selfpromo]
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            categories: default_categories(),
+        }
+    }
+}
+
+/// A part of a video, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub start: f64,
+    pub end: f64,
+    pub category: SegmentCategory,
+}
+
+#[derive(Deserialize)]
+struct RawVideo {
+    #[serde(rename = "videoID")]
+    video_id: String,
+    segments: Vec<RawSegment>,
+}
+
+#[derive(Deserialize)]
+struct RawSegment {
+    segment: [f64; 2],
+    category: SegmentCategory,
+}
+
+/// The first [`HASH_PREFIX_LEN`] hex digits of the sha256 of `id`.
+fn hash_prefix(id: &VideoId) -> String {
+    Sha256::digest(id.as_str().as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>()[..HASH_PREFIX_LEN]
+        .to_owned()
+}
+
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .user_agent(concat!("m/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("failed to build http client")
+    })
+}
+
+/// The segments of a video in any of `categories`, sorted by where they start. Videos nobody
+/// submitted segments for have none. Only a prefix of the hash of the id is sent, see
+/// [`HASH_PREFIX_LEN`].
+pub async fn segments(
+    id: &VideoId,
+    categories: &[SegmentCategory],
+) -> Result<Vec<Segment>, reqwest::Error> {
+    let categories = serde_json::to_string(categories).expect("categories are valid json");
+    let response = client()
+        .get(format!("{API_URL}/{}", hash_prefix(id)))
+        .query(&[("categories", &categories)])
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(Vec::new());
+    }
+    let videos = response.error_for_status()?.json::<Vec<RawVideo>>().await?;
+    Ok(segments_of(id, videos))
+}
+
+/// The segments of the video with `id` out of the ones of all `videos` that share its hash
+/// prefix.
+fn segments_of(id: &VideoId, videos: Vec<RawVideo>) -> Vec<Segment> {
+    let mut segments = videos
+        .into_iter()
+        .filter(|v| v.video_id == id.as_str())
+        .flat_map(|v| v.segments)
+        .map(|s| Segment {
+            start: s.segment[0],
+            end: s.segment[1],
+            category: s.category,
+        })
+        .collect::<Vec<_>>();
+    segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+    segments
+}
+
+/// Where to seek to from `position` to get past the segments it's in, following segments that
+/// overlap or start right where the previous one ends.
+pub fn skip_to(segments: &[Segment], position: f64) -> Option<f64> {
+    let mut to = position;
+    while let Some(s) = segments
+        .iter()
+        .find(|s| s.start <= to && to < s.end - TOLERANCE)
+    {
+        to = s.end;
+    }
+    (to > position).then_some(to)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::item::link::Id;
+
+    #[test]
+    fn skips_past_overlapping_segments() {
+        let segment = |start, end| Segment {
+            start,
+            end,
+            category: SegmentCategory::Sponsor,
+        };
+        let segments = [segment(10., 20.), segment(19., 30.), segment(60., 70.)];
+        assert_eq!(skip_to(&segments, 5.), None);
+        assert_eq!(skip_to(&segments, 10.), Some(30.));
+        assert_eq!(skip_to(&segments, 25.), Some(30.));
+        assert_eq!(skip_to(&segments, 29.8), None);
+        assert_eq!(skip_to(&segments, 65.), Some(70.));
+    }
+
+    #[test]
+    fn only_the_segments_of_the_video_are_kept() {
+        let id = VideoId::new("dQw4w9WgXcQ");
+        assert_eq!(hash_prefix(id).len(), HASH_PREFIX_LEN);
+        let videos = serde_json::from_str(
+            r#"[
+                {"videoID": "9bZkp7q1_ew", "segments": [{"segment": [1, 2], "category": "sponsor"}]},
+                {"videoID": "dQw4w9WgXcQ", "segments": [
+                    {"segment": [30, 40], "category": "intro"},
+                    {"segment": [10, 20], "category": "sponsor"}
+                ]}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            segments_of(id, videos),
+            [
+                Segment {
+                    start: 10.,
+                    end: 20.,
+                    category: SegmentCategory::Sponsor
+                },
+                Segment {
+                    start: 30.,
+                    end: 40.,
+                    category: SegmentCategory::Intro
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn categories_are_named_like_the_api() {
+        assert_eq!(
+            serde_json::to_string(&[SegmentCategory::Selfpromo, SegmentCategory::MusicOfftopic])
+                .unwrap(),
+            r#"["selfpromo","music_offtopic"]"#
+        );
+    }
+}
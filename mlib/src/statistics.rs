@@ -5,25 +5,47 @@
 // # times a category was unqueued
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs::File,
-    io::{self, BufReader, BufWriter},
+    io::{self, Read, Write},
+    ops::AddAssign,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
-use chrono::Datelike;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
 use raii_flock::FileLock;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_map_to_array::HashMapToArray;
 use tempfile::NamedTempFile;
 
-use crate::Item;
+use crate::{
+    decode_history as decode, encode_history as encode,
+    item::link::{Id, VideoLink},
+    Item, VideoId,
+};
 
 #[derive(Default, Debug, Clone, Copy, Serialize, Deserialize)]
 struct SongStats {
     played: u64,
     skipped: u64,
     dequeued: u64,
+    /// Unix timestamp, missing from stats recorded before it was tracked.
+    #[serde(default)]
+    last_played: Option<i64>,
+    /// Seconds spent actually playing the song, i.e. not paused.
+    #[serde(default)]
+    listened: u64,
+}
+
+impl AddAssign for SongStats {
+    fn add_assign(&mut self, other: SongStats) {
+        self.played += other.played;
+        self.skipped += other.skipped;
+        self.dequeued += other.dequeued;
+        self.last_played = self.last_played.max(other.last_played);
+        self.listened += other.listened;
+    }
 }
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -33,38 +55,81 @@ struct Stats {
     songs: HashMap<Item, SongStats>,
 }
 
-async fn update_db<F>(f: F) -> io::Result<()>
-where
-    F: FnOnce(&mut Stats) + Send + 'static,
-{
-    async fn path() -> io::Result<PathBuf> {
-        let Some(mut stats_path) = dirs::data_dir() else {
-            tracing::error!("failed to get data dir for stat tracking");
-            return Err(io::ErrorKind::NotFound.into());
-        };
-
-        let current_year = chrono::Utc::now().date_naive().year();
-        stats_path.push("m");
-        tokio::fs::create_dir_all(&stats_path).await?;
-        stats_path.push(format!("statistics-{current_year}.json"));
-        Ok(stats_path)
+impl Stats {
+    /// Moves the stats of every item with the video id `from` to `to`.
+    fn rename(&mut self, from: &VideoId, to: &Item) {
+        let moved = self
+            .songs
+            .keys()
+            .filter(|item| item.id() == Some(from) && *item != to)
+            .cloned()
+            .collect::<Vec<_>>();
+        for item in moved {
+            let song = self.songs.remove(&item).unwrap();
+            *self.songs.entry(to.clone()).or_default() += song;
+        }
     }
+}
 
-    fn load_db(stats_file: &File) -> io::Result<Stats> {
-        let reader = BufReader::new(stats_file);
-        Ok(serde_json::from_reader(reader)?)
-    }
-    fn store_db(stats_path: &Path, stats: Stats) -> io::Result<()> {
-        let dir = stats_path.parent().unwrap();
-        let (file, temp_path) = NamedTempFile::new_in(dir)?.into_parts();
-        let writer = BufWriter::new(file);
-        serde_json::to_writer(writer, &stats)?;
+/// The same stats as [`Stats`] but split by day (`YYYY-MM-DD`, so they sort chronologically),
+/// to be able to answer questions about periods of time.
+type DailyStats = BTreeMap<String, Stats>;
+
+fn stats_dir() -> io::Result<PathBuf> {
+    let Some(mut stats_dir) = dirs::data_dir() else {
+        tracing::error!("failed to get data dir for stat tracking");
+        return Err(io::ErrorKind::NotFound.into());
+    };
+    stats_dir.push("m");
+    Ok(stats_dir)
+}
 
-        temp_path.persist(stats_path).map_err(|e| e.error)?;
+const YEARLY_PREFIX: &str = "statistics-";
+const DAILY_PREFIX: &str = "daily-statistics-";
 
-        Ok(())
+fn yearly_file(year: i32) -> String {
+    format!("{YEARLY_PREFIX}{year}.json")
+}
+
+fn daily_file(year: i32) -> String {
+    format!("{DAILY_PREFIX}{year}.json")
+}
+
+#[cfg(feature = "encryption")]
+fn key() -> io::Result<Option<crate::encryption::Key>> {
+    crate::encryption::Key::load()
+}
+
+fn read_all(mut file: &File) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn load_db<T: DeserializeOwned>(stats_file: &File) -> io::Result<T> {
+    Ok(serde_json::from_slice(&decode(read_all(stats_file)?)?)?)
+}
+
+/// Replaces the file at `stats_path` with `bytes`, atomically.
+fn write_db(stats_path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let dir = stats_path.parent().unwrap();
+    let (mut file, temp_path) = NamedTempFile::new_in(dir)?.into_parts();
+    file.write_all(bytes)?;
+    temp_path.persist(stats_path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+async fn update_db<T, F>(file_name: String, f: F) -> io::Result<()>
+where
+    T: Default + Serialize + DeserializeOwned,
+    F: FnOnce(&mut T) + Send + 'static,
+{
+    fn store_db<T: Serialize>(stats_path: &Path, stats: T) -> io::Result<()> {
+        write_db(stats_path, &encode(serde_json::to_vec(&stats)?)?)
     }
-    let stats_path = path().await?;
+    let mut stats_path = stats_dir()?;
+    tokio::fs::create_dir_all(&stats_path).await?;
+    stats_path.push(file_name);
     tokio::task::spawn_blocking(move || {
         let file;
         let (_file_lock, mut stats) = match File::open(&stats_path) {
@@ -74,7 +139,7 @@ where
             }
             Err(e) if e.kind() == io::ErrorKind::NotFound => {
                 file = File::create(&stats_path)?;
-                (FileLock::wrap_exclusive(&file), Stats::default())
+                (FileLock::wrap_exclusive(&file), T::default())
             }
             Err(e) => return Err(e),
         };
@@ -84,23 +149,376 @@ where
     .await?
 }
 
-pub async fn played_song(item: Item) -> io::Result<()> {
-    update_db(|stats| {
-        stats.songs.entry(item).or_default().played += 1;
+/// Updates the stats of a song, both for the whole year and for today.
+async fn update_song<F>(item: Item, f: F) -> io::Result<()>
+where
+    F: Fn(&mut SongStats) + Send + Sync + Clone + 'static,
+{
+    let now = chrono::Local::now();
+    let today = now.date_naive().format("%Y-%m-%d").to_string();
+    let year = now.year();
+    update_db(yearly_file(year), {
+        let (item, f) = (item.clone(), f.clone());
+        move |stats: &mut Stats| f(stats.songs.entry(item).or_default())
+    })
+    .await?;
+    update_db(daily_file(year), move |days: &mut DailyStats| {
+        f(days
+            .entry(today)
+            .or_default()
+            .songs
+            .entry(item)
+            .or_default())
     })
     .await
 }
 
-pub async fn skipped_song(item: Item) -> io::Result<()> {
-    update_db(|stats| {
-        stats.songs.entry(item).or_default().skipped += 1;
+pub async fn played_song(item: Item) -> io::Result<()> {
+    update_song(item, |song| {
+        song.played += 1;
+        song.last_played = Some(Utc::now().timestamp());
     })
     .await
 }
 
+pub async fn skipped_song(item: Item) -> io::Result<()> {
+    update_song(item, |song| song.skipped += 1).await
+}
+
 pub async fn dequeued_song(item: Item) -> io::Result<()> {
-    update_db(|stats| {
-        stats.songs.entry(item).or_default().dequeued += 1;
+    update_song(item, |song| song.dequeued += 1).await
+}
+
+/// Records time spent listening to a song, anything under a second is ignored.
+pub async fn listened_to_song(item: Item, time: Duration) -> io::Result<()> {
+    let secs = time.as_secs();
+    if secs == 0 {
+        return Ok(());
+    }
+    update_song(item, move |song| song.listened += secs).await
+}
+
+/// Moves the statistics of the video `from` to the video `to`, like when a song that was taken
+/// down is replaced by a reupload. If both have statistics they are added up.
+pub async fn rename_song(from: &VideoId, to: &VideoId) -> io::Result<()> {
+    let to = Item::Link(VideoLink::from_id(to).into());
+    for (year, _) in stats_files(YEARLY_PREFIX)? {
+        let (from, to) = (from.boxed(), to.clone());
+        update_db(yearly_file(year), move |stats: &mut Stats| {
+            stats.rename(&from, &to)
+        })
+        .await?;
+    }
+    for (year, _) in stats_files(DAILY_PREFIX)? {
+        let (from, to) = (from.boxed(), to.clone());
+        update_db(daily_file(year), move |days: &mut DailyStats| {
+            days.values_mut().for_each(|stats| stats.rename(&from, &to))
+        })
+        .await?;
+    }
+    Ok(())
+}
+
+/// How often and how recently a song was played, and how often it was skipped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Plays {
+    pub count: u64,
+    pub last: Option<DateTime<Utc>>,
+    pub skipped: u64,
+}
+
+impl Plays {
+    fn add(&mut self, stats: &SongStats) {
+        self.count += stats.played;
+        self.skipped += stats.skipped;
+        let last = stats
+            .last_played
+            .and_then(|t| DateTime::from_timestamp(t, 0));
+        self.last = self.last.max(last);
+    }
+}
+
+/// The statistics files of every year named `<prefix><year>.json`, in no particular order.
+fn stats_files(prefix: &str) -> io::Result<Vec<(i32, PathBuf)>> {
+    let dir = match stats_dir() {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let year = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix(prefix)?.strip_suffix(".json")?.parse().ok());
+        if let Some(year) = year {
+            files.push((year, path));
+        }
+    }
+    Ok(files)
+}
+
+fn load_shared<T: DeserializeOwned>(path: &Path) -> io::Result<Option<T>> {
+    let file = File::open(path)?;
+    let _file_lock = FileLock::wrap_shared(&file);
+    // failing to decrypt isn't corruption, it's a missing or wrong key
+    let bytes = decode(read_all(&file)?)?;
+    match serde_json::from_slice(&bytes) {
+        Ok(stats) => Ok(Some(stats)),
+        Err(e) => {
+            tracing::error!(error = ?e, path = %path.display(), "corrupted statistics file");
+            Ok(None)
+        }
+    }
+}
+
+/// Adds up the plays of every song with a video id, over every year statistics were kept for.
+pub async fn plays() -> io::Result<HashMap<Box<VideoId>, Plays>> {
+    tokio::task::spawn_blocking(move || {
+        let mut plays = HashMap::<Box<VideoId>, Plays>::new();
+        for (_, path) in stats_files(YEARLY_PREFIX)? {
+            let Some(stats) = load_shared::<Stats>(&path)? else {
+                continue;
+            };
+            for (item, song) in &stats.songs {
+                if let Some(id) = item.id() {
+                    plays.entry(id.boxed()).or_default().add(song);
+                }
+            }
+        }
+        Ok(plays)
     })
-    .await
+    .await?
+}
+
+/// What happened to a song, or on a day, over a period of time.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Totals {
+    pub played: u64,
+    pub skipped: u64,
+    pub dequeued: u64,
+    pub listened: Duration,
+}
+
+impl Totals {
+    /// Fraction of plays that were skipped.
+    pub fn skip_rate(&self) -> f64 {
+        if self.played == 0 {
+            0.
+        } else {
+            self.skipped as f64 / self.played as f64
+        }
+    }
+}
+
+impl AddAssign<&SongStats> for Totals {
+    fn add_assign(&mut self, song: &SongStats) {
+        self.played += song.played;
+        self.skipped += song.skipped;
+        self.dequeued += song.dequeued;
+        self.listened += Duration::from_secs(song.listened);
+    }
+}
+
+impl AddAssign for Totals {
+    fn add_assign(&mut self, other: Totals) {
+        self.played += other.played;
+        self.skipped += other.skipped;
+        self.dequeued += other.dequeued;
+        self.listened += other.listened;
+    }
+}
+
+/// Statistics for a period of time, see [`report`].
+#[derive(Debug, Default, Clone)]
+pub struct Report {
+    pub songs: HashMap<Item, Totals>,
+    /// Only days where something happened are present.
+    pub days: BTreeMap<NaiveDate, Totals>,
+}
+
+impl Report {
+    fn add_days(&mut self, days: &DailyStats, since: Option<NaiveDate>, until: Option<NaiveDate>) {
+        let key = |d: NaiveDate| d.format("%Y-%m-%d").to_string();
+        let range = (
+            since.map_or(std::ops::Bound::Unbounded, |d| {
+                std::ops::Bound::Included(key(d))
+            }),
+            until.map_or(std::ops::Bound::Unbounded, |d| {
+                std::ops::Bound::Included(key(d))
+            }),
+        );
+        for (day, stats) in days.range::<String, _>(range) {
+            let Ok(day) = NaiveDate::parse_from_str(day, "%Y-%m-%d") else {
+                tracing::warn!(day, "invalid day in statistics");
+                continue;
+            };
+            let day_totals = self.days.entry(day).or_default();
+            for (item, song) in &stats.songs {
+                *day_totals += song;
+                *self.songs.entry(item.clone()).or_default() += song;
+            }
+        }
+    }
+}
+
+/// Aggregates the statistics between `since` and `until`, both inclusive.
+///
+/// Only what happened after per day statistics started being recorded is included.
+pub async fn report(since: Option<NaiveDate>, until: Option<NaiveDate>) -> io::Result<Report> {
+    tokio::task::spawn_blocking(move || {
+        let mut report = Report::default();
+        for (year, path) in stats_files(DAILY_PREFIX)? {
+            if since.is_some_and(|s| year < s.year()) || until.is_some_and(|u| year > u.year()) {
+                continue;
+            }
+            if let Some(days) = load_shared::<DailyStats>(&path)? {
+                report.add_days(&days, since, until);
+            }
+        }
+        Ok(report)
+    })
+    .await?
+}
+
+/// Encrypts every statistics file with a newly generated key, see [`crate::encryption`].
+/// Returns how many files there were.
+#[cfg(feature = "encryption")]
+pub async fn lock() -> io::Result<usize> {
+    tokio::task::spawn_blocking(|| {
+        let key = crate::encryption::Key::generate()?;
+        rewrite_all(Some(&key), Some(&key))
+    })
+    .await?
+}
+
+/// Decrypts every statistics file and deletes the key. Returns how many files there were, or
+/// `None` if they weren't encrypted.
+#[cfg(feature = "encryption")]
+pub async fn unlock() -> io::Result<Option<usize>> {
+    tokio::task::spawn_blocking(|| {
+        let Some(key) = key()? else {
+            return Ok(None);
+        };
+        let count = rewrite_all(Some(&key), None)?;
+        crate::encryption::Key::forget()?;
+        // the player may have written with the key while the files were being decrypted
+        rewrite_all(Some(&key), None)?;
+        Ok(Some(count))
+    })
+    .await?
+}
+
+/// The files outside of the statistics dir that also say what was listened to, they are locked
+/// and unlocked along with the statistics.
+#[cfg(feature = "encryption")]
+fn other_history_files() -> Vec<PathBuf> {
+    #[allow(unused_mut)]
+    let mut files = Vec::new();
+    #[cfg(feature = "scrobble")]
+    files.extend(crate::scrobble::queue_path().ok());
+    #[cfg(feature = "player")]
+    files.extend(crate::players::resume_positions_path().ok());
+    files
+}
+
+#[cfg(feature = "encryption")]
+fn rewrite_all(
+    from: Option<&crate::encryption::Key>,
+    to: Option<&crate::encryption::Key>,
+) -> io::Result<usize> {
+    use crate::encryption::{open, seal};
+
+    let files = stats_files(YEARLY_PREFIX)?
+        .into_iter()
+        .chain(stats_files(DAILY_PREFIX)?)
+        .map(|(_, path)| path)
+        .chain(other_history_files());
+    let mut count = 0;
+    for path in files {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let _file_lock = FileLock::wrap_exclusive(&file);
+        let plain = open(from, read_all(&file)?)?;
+        write_db(&path, &seal(to, plain)?)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn plays_add_up_across_years() {
+        let mut plays = Plays::default();
+        let year = |played, last_played| SongStats {
+            played,
+            last_played,
+            ..Default::default()
+        };
+        plays.add(&year(3, None));
+        plays.add(&year(2, Some(1_700_000_000)));
+        plays.add(&year(1, Some(1_600_000_000)));
+        assert_eq!(plays.count, 6);
+        assert_eq!(plays.last, DateTime::from_timestamp(1_700_000_000, 0));
+    }
+
+    #[test]
+    fn report_only_includes_days_in_range() {
+        let item = Item::from(String::from("/music/song.mp3"));
+        let day = |played| Stats {
+            songs: HashMap::from([(
+                item.clone(),
+                SongStats {
+                    played,
+                    listened: 60 * played,
+                    ..Default::default()
+                },
+            )]),
+        };
+        let days = DailyStats::from([
+            ("2024-01-31".into(), day(1)),
+            ("2024-02-01".into(), day(2)),
+            ("2024-02-10".into(), day(4)),
+        ]);
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+
+        let mut report = Report::default();
+        report.add_days(&days, Some(date("2024-02-01")), Some(date("2024-02-10")));
+        assert_eq!(report.songs[&item].played, 6);
+        assert_eq!(report.songs[&item].listened, Duration::from_secs(360));
+        assert_eq!(
+            report.days.keys().copied().collect::<Vec<_>>(),
+            [date("2024-02-01"), date("2024-02-10")]
+        );
+    }
+
+    #[test]
+    fn renamed_songs_keep_their_stats() {
+        let old = Item::from(String::from("https://www.youtube.com/watch?v=UpIBKNxSeZU"));
+        let new = Item::Link(VideoLink::from_id(VideoId::new("dQw4w9WgXcQ")).into());
+        let song = |played, last_played| SongStats {
+            played,
+            last_played: Some(last_played),
+            ..Default::default()
+        };
+        let mut stats = Stats {
+            songs: HashMap::from([(old.clone(), song(3, 10)), (new.clone(), song(1, 20))]),
+        };
+        stats.rename(VideoId::new("UpIBKNxSeZU"), &new);
+        assert!(!stats.songs.contains_key(&old));
+        assert_eq!(stats.songs[&new].played, 4);
+        assert_eq!(stats.songs[&new].last_played, Some(20));
+    }
 }
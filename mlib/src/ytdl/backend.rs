@@ -0,0 +1,63 @@
+//! The youtube-dl compatible program that is run, yt-dlp unless configured otherwise.
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(default)]
+pub struct Backend {
+    /// The program, looked up in `$PATH` if it isn't a path.
+    pub binary: PathBuf,
+    /// Whether it understands `--print <field>`, otherwise `--get-<field>` is used instead.
+    pub print: bool,
+    /// Whether it can `--embed-chapters` in downloads.
+    pub embed_chapters: bool,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Self::yt_dlp()
+    }
+}
+
+impl Backend {
+    pub fn yt_dlp() -> Self {
+        Self {
+            binary: "yt-dlp".into(),
+            print: true,
+            embed_chapters: true,
+        }
+    }
+
+    /// The original youtube-dl, which has neither `--print` nor `--embed-chapters`.
+    pub fn youtube_dl() -> Self {
+        Self {
+            binary: "youtube-dl".into(),
+            print: false,
+            embed_chapters: false,
+        }
+    }
+
+    /// The arguments that make it print `field` of each video, one per line.
+    pub(crate) fn print(&self, field: &str) -> Vec<String> {
+        if self.print {
+            vec!["--print".into(), field.into()]
+        } else {
+            vec![format!("--get-{field}")]
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn print_falls_back_to_get() {
+        assert_eq!(Backend::yt_dlp().print("filename"), ["--print", "filename"]);
+        assert_eq!(Backend::youtube_dl().print("id"), ["--get-id"]);
+        let fork = serde_json::from_str::<Backend>(r#"{"binary": "/opt/yt-dlp-fork"}"#).unwrap();
+        assert!(fork.print && fork.embed_chapters);
+    }
+}
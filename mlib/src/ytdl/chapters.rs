@@ -0,0 +1,135 @@
+use std::{process::Stdio, time::Duration};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::YtdlError;
+use crate::{item::VideoLink, Error};
+
+/// A chapter extracted from a video's description.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    pub start: Duration,
+    pub title: String,
+}
+
+static TIMESTAMP: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?:^|[\s(\[])(?:(?P<h>\d{1,2}):)?(?P<m>\d{1,2}):(?P<s>[0-5]\d)(?:$|[\s)\]])")
+        .unwrap()
+});
+
+const SEPARATORS: &[char] = &[
+    '-', '–', '—', '|', ':', '.', ')', ']', '(', '[', '•', '~', '>',
+];
+
+fn parse_line(line: &str) -> Option<Chapter> {
+    let line = line.trim();
+    let captures = TIMESTAMP.captures(line)?;
+    let whole = captures.get(0).unwrap();
+    let number = |name| {
+        captures
+            .name(name)
+            .map_or(Some(0), |m| m.as_str().parse::<u64>().ok())
+    };
+    let start = Duration::from_secs(number("h")? * 3600 + number("m")? * 60 + number("s")?);
+
+    let (before, after) = (&line[..whole.start()], &line[whole.end()..]);
+    let before = before.trim_matches(|c: char| c.is_whitespace() || SEPARATORS.contains(&c));
+    let after = after.trim_matches(|c: char| c.is_whitespace() || SEPARATORS.contains(&c));
+    // the timestamp has to be at one of the ends of the line, optionally preceded by a
+    // track number, otherwise it's most likely a "at 3:20 you can hear..." kind of line.
+    let title = match (before, after) {
+        (b, a) if b.is_empty() || b.bytes().all(|c| c.is_ascii_digit()) => a,
+        (b, "") => b,
+        _ => return None,
+    };
+    if title.is_empty() {
+        return None;
+    }
+    Some(Chapter {
+        start,
+        title: title.to_string(),
+    })
+}
+
+/// Parses `hh:mm:ss Title` (or `Title hh:mm:ss`) lines in a description into chapters.
+///
+/// An empty list is returned unless at least two timestamps are found and they are in ascending
+/// order, as that's the only way to be reasonably sure the description contains a tracklist.
+pub fn parse(description: &str) -> Vec<Chapter> {
+    let chapters = description
+        .lines()
+        .filter_map(parse_line)
+        .collect::<Vec<_>>();
+    if chapters.len() < 2 || chapters.windows(2).any(|w| w[0].start >= w[1].start) {
+        return Vec::new();
+    }
+    chapters
+}
+
+/// Fetches the description of a video and extracts the chapters from it.
+pub async fn from_description(link: &VideoLink) -> Result<Vec<Chapter>, Error> {
+    let output = super::command()
+        .await
+        .arg("--get-description")
+        .arg(link.as_str())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(YtdlError::failed(
+            output.status,
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+        .into());
+    }
+    Ok(parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chapter(start: u64, title: &str) -> Chapter {
+        Chapter {
+            start: Duration::from_secs(start),
+            title: title.into(),
+        }
+    }
+
+    #[test]
+    fn tracklist() {
+        let description = "Best mix ever\n\
+            \n\
+            Tracklist:\n\
+            00:00 Intro\n\
+            1. 03:15 - Artist - Song\n\
+            (1:02:03) Last one\n";
+        assert_eq!(
+            parse(description),
+            vec![
+                chapter(0, "Intro"),
+                chapter(195, "Artist - Song"),
+                chapter(3723, "Last one"),
+            ]
+        );
+    }
+
+    #[test]
+    fn timestamp_at_the_end() {
+        let description = "Intro 0:00\nSecond 4:20";
+        assert_eq!(
+            parse(description),
+            vec![chapter(0, "Intro"), chapter(260, "Second")]
+        );
+    }
+
+    #[test]
+    fn prose_is_not_a_tracklist() {
+        assert!(parse("at 3:20 you can hear the drop and at 4:00 the other").is_empty());
+        assert!(parse("5:00 Second\n1:00 First").is_empty());
+        assert!(parse("0:00 Only one").is_empty());
+    }
+}
@@ -0,0 +1,249 @@
+//! Turning playlist and channel links into the videos in them.
+
+use std::{io, path::PathBuf, time::Duration};
+
+use base64::{engine::GeneralPurpose, Engine};
+use futures_util::{
+    future::{ready, BoxFuture},
+    stream::{self, BoxStream, Stream, StreamExt},
+};
+
+use super::{YtdlBuilder, YtdlStream};
+use crate::{
+    item::link::{Id, VideoLink},
+    Error, Item, Link, VideoId,
+};
+
+/// Where the videos of each playlist or channel are remembered, keyed by the playlist's id or
+/// the channel's link.
+pub trait ExpansionCache: Send + Sync {
+    fn get<'s>(&'s self, key: &'s str) -> BoxFuture<'s, Option<Vec<Box<VideoId>>>>;
+
+    fn put<'s>(&'s self, key: &'s str, ids: &'s [Box<VideoId>]) -> BoxFuture<'s, ()>;
+}
+
+/// Always asks yt-dlp.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoCache;
+
+impl ExpansionCache for NoCache {
+    fn get<'s>(&'s self, _: &'s str) -> BoxFuture<'s, Option<Vec<Box<VideoId>>>> {
+        Box::pin(async { None })
+    }
+
+    fn put<'s>(&'s self, _: &'s str, _: &'s [Box<VideoId>]) -> BoxFuture<'s, ()> {
+        Box::pin(async {})
+    }
+}
+
+/// Keeps expansions in the user's tmp dir, one file per playlist, for `ttl`.
+#[derive(Debug, Clone, Copy)]
+pub struct TmpCache {
+    pub ttl: Duration,
+}
+
+impl Default for TmpCache {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+const BASE64: GeneralPurpose = base64::engine::general_purpose::URL_SAFE;
+
+impl TmpCache {
+    async fn path_for(key: &str) -> PathBuf {
+        let (path, _error) = namespaced_tmp::async_impl::in_user_tmp(&format!(
+            "m_playlist_cache/{}",
+            BASE64.encode(key)
+        ))
+        .await;
+        path
+    }
+
+    async fn read(&self, key: &str) -> io::Result<Option<Vec<Box<VideoId>>>> {
+        let path = Self::path_for(key).await;
+        let age = match tokio::fs::metadata(&path).await {
+            Ok(metadata) => metadata.modified()?.elapsed().unwrap_or_default(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if age > self.ttl {
+            return Ok(None);
+        }
+        let ids = tokio::fs::read_to_string(&path).await?;
+        Ok(Some(
+            ids.lines().map(|id| VideoId::new(id).boxed()).collect(),
+        ))
+    }
+
+    async fn write(&self, key: &str, ids: &[Box<VideoId>]) -> io::Result<()> {
+        let path = Self::path_for(key).await;
+        tokio::fs::create_dir_all(&path.parent().unwrap()).await?;
+        let ids = ids.iter().map(|id| id.as_str()).collect::<Vec<_>>();
+        tokio::fs::write(path, ids.join("\n")).await
+    }
+}
+
+impl ExpansionCache for TmpCache {
+    fn get<'s>(&'s self, key: &'s str) -> BoxFuture<'s, Option<Vec<Box<VideoId>>>> {
+        Box::pin(async move {
+            self.read(key)
+                .await
+                .map_err(|e| tracing::debug!(error = ?e, key, "failed to read playlist cache"))
+                .ok()
+                .flatten()
+        })
+    }
+
+    fn put<'s>(&'s self, key: &'s str, ids: &'s [Box<VideoId>]) -> BoxFuture<'s, ()> {
+        Box::pin(async move {
+            if let Err(e) = self.write(key, ids).await {
+                tracing::error!(error = ?e, key, "failed to cache playlist");
+            }
+        })
+    }
+}
+
+/// The ids in a yt-dlp response, skipping the ones that can't be parsed.
+fn ids(stream: YtdlStream<Box<VideoId>>) -> impl Stream<Item = Box<VideoId>> {
+    stream.filter_map(|r| async {
+        r.map(|id| id.id().boxed())
+            .map_err(|e| tracing::error!(error = ?e, "failed to parse playlist item"))
+            .ok()
+    })
+}
+
+fn cache_key(link: &Link) -> Option<String> {
+    match link {
+        Link::Playlist(l) => Some(l.id().as_str().to_owned()),
+        Link::Channel(c) => Some(c.as_str().to_owned()),
+        _ => None,
+    }
+}
+
+fn request(link: &Link) -> Result<YtdlStream<Box<VideoId>>, Error> {
+    match link {
+        Link::Playlist(l) => YtdlBuilder::new(l).request_playlist(),
+        Link::Channel(c) => YtdlBuilder::new(c).request_channel(),
+        _ => unreachable!("only playlists and channels have a cache key"),
+    }
+}
+
+/// The videos in a playlist or channel, as yt-dlp sees them right now. Empty for any other
+/// link.
+pub async fn videos(link: &Link) -> Result<Vec<Box<VideoId>>, Error> {
+    match cache_key(link) {
+        Some(_) => Ok(ids(request(link)?).collect().await),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The videos `item` stands for, if it's a playlist or a channel. They come as yt-dlp finds
+/// them and are only remembered once the whole playlist went by.
+async fn expand<'c>(
+    item: &Item,
+    cache: &'c dyn ExpansionCache,
+) -> Option<BoxStream<'c, Box<VideoId>>> {
+    let Item::Link(link) = item else {
+        return None;
+    };
+    let key = cache_key(link)?;
+    if let Some(ids) = cache.get(&key).await {
+        tracing::debug!(key, "playlist expansion cached");
+        return Some(stream::iter(ids).boxed());
+    }
+    let mut found = match request(link) {
+        Ok(stream) => Box::pin(ids(stream).peekable()),
+        Err(e) => {
+            tracing::error!(error = ?e, %item, "failed to expand playlist");
+            return None;
+        }
+    };
+    if found.as_mut().peek().await.is_none() {
+        tracing::warn!(%item, "playlist is empty");
+        return None;
+    }
+    let expanded = stream::unfold(
+        (found, Vec::new(), key),
+        move |(mut found, mut seen, key)| async move {
+            match found.next().await {
+                Some(id) => {
+                    seen.push(id.boxed());
+                    Some((id, (found, seen, key)))
+                }
+                None => {
+                    cache.put(&key, &seen).await;
+                    None
+                }
+            }
+        },
+    );
+    Some(expanded.boxed())
+}
+
+/// Replaces playlists and channels with the videos in them, leaving other items alone. A
+/// playlist that can't be expanded becomes the video it was linked from, if any.
+pub fn expand_items<'c, I>(items: I, cache: &'c dyn ExpansionCache) -> impl Stream<Item = Item> + 'c
+where
+    I: IntoIterator<Item = Item>,
+    I::IntoIter: 'c,
+{
+    stream::iter(items)
+        .then(move |item| async move {
+            match expand(&item, cache).await {
+                Some(ids) => ids
+                    .map(|id| Item::Link(VideoLink::from_id(&id).into()))
+                    .boxed(),
+                None => {
+                    let item = match item {
+                        Item::Link(Link::Playlist(l)) => match l.into_video_link() {
+                            Ok(video) => Item::Link(video.into()),
+                            Err(l) => Item::Link(l.into()),
+                        },
+                        item => item,
+                    };
+                    stream::once(ready(item)).boxed()
+                }
+            }
+        })
+        .flatten()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn cached_playlists_dont_run_ytdl() {
+        struct Fixed;
+        impl ExpansionCache for Fixed {
+            fn get<'s>(&'s self, _: &'s str) -> BoxFuture<'s, Option<Vec<Box<VideoId>>>> {
+                Box::pin(async { Some(vec![VideoId::new("dQw4w9WgXcQ").boxed()]) })
+            }
+
+            fn put<'s>(&'s self, _: &'s str, _: &'s [Box<VideoId>]) -> BoxFuture<'s, ()> {
+                unreachable!("nothing was fetched")
+            }
+        }
+        let playlist = Link::try_from(
+            url::Url::parse(
+                "https://www.youtube.com/playlist?list=PL17PSucW5L7nEPyX3tqEzq_wmyYk2IkXr",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let file = Item::File("/music/song.mp3".into());
+        let items = expand_items([Item::Link(playlist), file.clone()], &Fixed)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            items,
+            [
+                Item::Link(VideoLink::from_id(VideoId::new("dQw4w9WgXcQ")).into()),
+                file
+            ]
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Keeps m from asking youtube for too much too fast, and backs off when it complains anyway.
+
+use std::time::Duration;
+
+use tokio::{sync::Mutex, time::Instant};
+
+/// How many requests can be made at once before having to wait.
+const BURST: f64 = 5.;
+
+/// How many requests per second are allowed after the burst is used up.
+const PER_SECOND: f64 = 2.;
+
+/// How many times a rate limited request is retried.
+pub(crate) const MAX_RETRIES: u32 = 4;
+
+/// What yt-dlp says when youtube is throttling us.
+const RATE_LIMIT_HINTS: &[&str] = &["HTTP Error 429", "Too Many Requests", "rate-limited"];
+
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// Takes a token, returning how long to wait before using it.
+    fn take(&mut self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * PER_SECOND).min(BURST);
+        self.refilled_at = now;
+        self.tokens -= 1.;
+        if self.tokens >= 0. {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / PER_SECOND)
+        }
+    }
+}
+
+static BUCKET: Mutex<Option<TokenBucket>> = Mutex::const_new(None);
+
+/// Waits until another request can be made.
+pub(crate) async fn acquire() {
+    let wait = {
+        let now = Instant::now();
+        BUCKET
+            .lock()
+            .await
+            .get_or_insert(TokenBucket {
+                tokens: BURST,
+                refilled_at: now,
+            })
+            .take(now)
+    };
+    if !wait.is_zero() {
+        tracing::debug!(?wait, "waiting to not get rate limited");
+        tokio::time::sleep(wait).await;
+    }
+}
+
+pub(crate) fn is_rate_limited(stderr: &str) -> bool {
+    RATE_LIMIT_HINTS.iter().any(|h| stderr.contains(h))
+}
+
+/// How long to wait before retrying for the `attempt`th time, starting at 0.
+pub(crate) fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(2) * 2u32.pow(attempt)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bursts_then_waits() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket {
+            tokens: BURST,
+            refilled_at: start,
+        };
+        for _ in 0..BURST as usize {
+            assert_eq!(bucket.take(start), Duration::ZERO);
+        }
+        assert_eq!(bucket.take(start), Duration::from_millis(500));
+        let later = start + Duration::from_secs(10);
+        assert_eq!(bucket.take(later), Duration::ZERO);
+        assert!(bucket.tokens <= BURST);
+    }
+
+    #[test]
+    fn detects_429s() {
+        assert!(is_rate_limited(
+            "ERROR: [youtube] dQw4w9WgXcQ: Unable to download webpage: HTTP Error 429: Too Many Requests"
+        ));
+        assert!(!is_rate_limited(
+            "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable"
+        ));
+        assert_eq!(backoff(0), Duration::from_secs(2));
+        assert_eq!(backoff(3), Duration::from_secs(16));
+    }
+}
@@ -0,0 +1,158 @@
+//! Everything yt-dlp knows about a video, from a single `--dump-single-json`, as opposed to the
+//! line per field protocol of [`super::YtdlBuilder::request`].
+
+use std::{process::Stdio, time::Duration};
+
+use serde::Deserialize;
+
+use super::{chapters::Chapter, YtdlError};
+use crate::{item::VideoLink, Error};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct VideoMetadata {
+    pub id: String,
+    pub title: String,
+    /// In seconds, absent for live streams.
+    #[serde(default)]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub uploader: Option<String>,
+    /// The thumbnail yt-dlp picked as the best one.
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    /// All the thumbnails, from worst to best.
+    #[serde(default)]
+    pub thumbnails: Vec<Thumbnail>,
+    /// `null` when the video has no chapters.
+    #[serde(default)]
+    pub chapters: Option<Vec<JsonChapter>>,
+    #[serde(default)]
+    pub formats: Vec<Format>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Thumbnail {
+    pub url: String,
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct JsonChapter {
+    pub start_time: f64,
+    pub end_time: f64,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Format {
+    pub format_id: String,
+    pub ext: String,
+    /// `none` for video only formats.
+    #[serde(default)]
+    pub acodec: Option<String>,
+    /// `none` for audio only formats.
+    #[serde(default)]
+    pub vcodec: Option<String>,
+    /// Average audio bitrate, in KBit/s.
+    #[serde(default)]
+    pub abr: Option<f64>,
+    #[serde(default)]
+    pub filesize: Option<u64>,
+}
+
+impl Format {
+    pub fn is_audio_only(&self) -> bool {
+        self.vcodec.as_deref() == Some("none") && self.acodec.as_deref() != Some("none")
+    }
+}
+
+impl VideoMetadata {
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+            .and_then(|d| Duration::try_from_secs_f64(d).ok())
+    }
+
+    pub fn best_thumbnail(&self) -> Option<&str> {
+        self.thumbnail
+            .as_deref()
+            .or_else(|| self.thumbnails.last().map(|t| t.url.as_str()))
+    }
+
+    /// The video's own chapters, see [`super::chapters`] for the ones in the description.
+    pub fn chapters(&self) -> Vec<Chapter> {
+        self.chapters
+            .iter()
+            .flatten()
+            .map(|c| Chapter {
+                start: Duration::try_from_secs_f64(c.start_time).unwrap_or_default(),
+                title: c.title.clone(),
+            })
+            .collect()
+    }
+}
+
+pub(super) async fn fetch(link: &VideoLink) -> Result<VideoMetadata, Error> {
+    let output = super::command()
+        .await
+        .args(["--dump-single-json", "--no-warnings"])
+        .arg(link.as_str())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(YtdlError::failed(
+            output.status,
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+        .into());
+    }
+    Ok(serde_json::from_slice(&output.stdout).map_err(YtdlError::InvalidJson)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_dump_json() {
+        let json = r#"{
+            "id": "dQw4w9WgXcQ",
+            "title": "Never Gonna Give You Up",
+            "duration": 212.0,
+            "uploader": "Rick Astley",
+            "thumbnails": [
+                {"url": "https://i.ytimg.com/vi/dQw4w9WgXcQ/default.jpg", "width": 120, "height": 90, "preference": -10},
+                {"url": "https://i.ytimg.com/vi/dQw4w9WgXcQ/maxresdefault.jpg", "preference": 0}
+            ],
+            "chapters": [
+                {"start_time": 0.0, "end_time": 30.5, "title": "Intro"},
+                {"start_time": 30.5, "end_time": 212.0, "title": "Song"}
+            ],
+            "formats": [
+                {"format_id": "251", "ext": "webm", "acodec": "opus", "vcodec": "none", "abr": 129.5},
+                {"format_id": "137", "ext": "mp4", "acodec": "none", "vcodec": "avc1", "filesize": 1024}
+            ],
+            "view_count": 1
+        }"#;
+        let metadata = serde_json::from_str::<VideoMetadata>(json).unwrap();
+        assert_eq!(metadata.duration(), Some(Duration::from_secs(212)));
+        assert_eq!(
+            metadata.best_thumbnail(),
+            Some("https://i.ytimg.com/vi/dQw4w9WgXcQ/maxresdefault.jpg")
+        );
+        assert_eq!(metadata.chapters()[1].start, Duration::from_millis(30_500));
+        assert!(metadata.formats[0].is_audio_only());
+        assert!(!metadata.formats[1].is_audio_only());
+
+        let live = r#"{"id": "x", "title": "live", "duration": null, "chapters": null}"#;
+        let live = serde_json::from_str::<VideoMetadata>(live).unwrap();
+        assert_eq!(live.duration(), None);
+        assert!(live.chapters().is_empty());
+        assert_eq!(live.best_thumbnail(), None);
+    }
+}
@@ -1,18 +1,26 @@
+pub mod backend;
+pub mod chapters;
+pub mod expand;
 mod getters;
+mod limit;
+pub mod metadata;
+pub mod probe;
 pub mod util;
 
 use std::{
-    ffi::OsStr,
+    ffi::{OsStr, OsString},
+    path::PathBuf,
     pin::Pin,
     process::{ExitStatus, Stdio},
+    sync::OnceLock,
     task::{Context, Poll},
 };
 
-use futures_util::stream::{Chunks, Stream, StreamExt};
-use pin_project::pin_project;
+use futures_util::stream::{Stream, StreamExt};
 use tokio::{
-    io::{AsyncBufReadExt, BufReader},
-    process::{Child, ChildStdout, Command},
+    io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+    process::Command,
+    sync::mpsc,
 };
 use tokio_stream::wrappers::LinesStream;
 
@@ -184,6 +192,98 @@ pub enum YtdlError {
         status_code: ExitStatus,
         stderr: String,
     },
+    #[error("downloaded file {} is not playable: {reason}", path.display())]
+    Unplayable {
+        path: std::path::PathBuf,
+        reason: String,
+    },
+    #[error("invalid json from yt-dlp: {0}")]
+    InvalidJson(serde_json::Error),
+    #[error("yt-dlp has to be logged in, give it a cookies file: {reason}")]
+    LoginRequired { reason: String },
+    #[error("youtube is rate limiting us, gave up after {retries} retries: {stderr}")]
+    RateLimited { retries: u32, stderr: String },
+}
+
+/// What yt-dlp says when a video is private, members only or age restricted.
+const LOGIN_HINTS: &[&str] = &[
+    "Sign in to confirm",
+    "Private video",
+    "members-only",
+    "available to this channel's members",
+    "Join this channel",
+    "--cookies",
+    "login required",
+    "Login required",
+];
+
+impl YtdlError {
+    /// The error for a yt-dlp process that exited with `status_code`, telling apart the videos
+    /// that need an account from other failures.
+    pub(crate) fn failed(status_code: ExitStatus, stderr: String) -> Self {
+        let login = stderr
+            .lines()
+            .find(|l| LOGIN_HINTS.iter().any(|h| l.contains(h)));
+        match login {
+            Some(line) => Self::LoginRequired {
+                reason: line.trim_start_matches("ERROR:").trim().to_string(),
+            },
+            None => Self::NonZeroStatus {
+                status_code,
+                stderr,
+            },
+        }
+    }
+}
+
+/// Options given to every yt-dlp invocation.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Config {
+    /// The program to run instead of yt-dlp.
+    pub backend: backend::Backend,
+    /// A Netscape formatted cookies file, for private or members only videos.
+    pub cookies_file: Option<PathBuf>,
+    pub extra_args: Vec<String>,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Sets the options given to yt-dlp. Has to be called before anything is requested, later calls
+/// are ignored.
+pub fn init(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+pub(crate) fn config() -> Option<&'static Config> {
+    CONFIG.get()
+}
+
+/// The configured backend, yt-dlp if [`init`] wasn't called.
+pub(crate) fn backend() -> &'static backend::Backend {
+    static DEFAULT: OnceLock<backend::Backend> = OnceLock::new();
+    match config() {
+        Some(config) => &config.backend,
+        None => DEFAULT.get_or_init(Default::default),
+    }
+}
+
+/// A command running the [`backend`] with the options from [`init`], once the rate limit allows
+/// another request.
+pub(crate) async fn command() -> Command {
+    limit::acquire().await;
+    unlimited_command()
+}
+
+/// Like [`command`], for when the caller takes care of the rate limit.
+fn unlimited_command() -> Command {
+    let mut cmd = Command::new(&backend().binary);
+    if let Some(config) = config() {
+        if let Some(cookies) = &config.cookies_file {
+            cmd.arg("--cookies").arg(cookies);
+        }
+        cmd.args(&config.extra_args);
+    }
+    cmd
 }
 
 impl<T> YtdlBuilder<T> {
@@ -200,9 +300,18 @@ impl<T> YtdlBuilder<T> {
     }
 }
 
+impl YtdlBuilder<LinkRequest<'_, VideoLink>> {
+    /// Fetches all the metadata of the video at once, as json. Unlike [`Self::request`] it
+    /// doesn't depend on the order in which yt-dlp prints each field.
+    pub async fn request_json(self) -> Result<metadata::VideoMetadata, Error> {
+        metadata::fetch(self.0 .0).await
+    }
+}
+
 impl<'l, Y, T> YtdlBuilder<T>
 where
     T: IntoResponse<Output = Y>,
+    Y: Send + 'static,
     T: YtdlParam<'l, Link = VideoLink>,
 {
     pub async fn request(self) -> Result<Ytdl<Y>, Error> {
@@ -223,6 +332,7 @@ where
 impl<'l, Y, T> YtdlBuilder<T>
 where
     T: IntoResponse<Output = Y>,
+    Y: Send + 'static,
     T: YtdlParam<'l, Link = Search>,
 {
     pub async fn search(self) -> Result<Ytdl<Y>, Error> {
@@ -248,6 +358,7 @@ where
 impl<'l, Y, T> YtdlBuilder<T>
 where
     T: IntoResponse<Output = Y>,
+    Y: Send + 'static,
     T: YtdlParam<'l, Link = PlaylistLink>,
 {
     pub fn request_playlist(&self) -> Result<YtdlStream<Y>, Error> {
@@ -259,6 +370,7 @@ where
 impl<'l, Y, T> YtdlBuilder<T>
 where
     T: IntoResponse<Output = Y>,
+    Y: Send + 'static,
     T: YtdlParam<'l, Link = ChannelLink>,
 {
     pub fn request_channel(&self) -> Result<YtdlStream<Y>, Error> {
@@ -272,60 +384,124 @@ where
     T: IntoResponse<Output = Y>,
     T: YtdlParam<'l>,
     L: AsRef<OsStr>,
+    Y: Send + 'static,
 {
-    let mut cmd = Command::new("yt-dlp");
+    let mut cmd = unlimited_command();
     cmd.arg(link);
     T::collect(&mut cmd);
     tracing::debug!(args = ?cmd.as_std().get_args(), "running ytdl");
+    let args = cmd
+        .as_std()
+        .get_args()
+        .map(OsStr::to_os_string)
+        .collect::<Vec<_>>();
+
+    let (tx, rx) = mpsc::channel(n_fields.max(16));
+    tokio::spawn(run(args, n_fields, T::response, tx));
+    Ok(YtdlStream { rx })
+}
 
-    let mut child = cmd.kill_on_drop(true).stdout(Stdio::piped()).spawn()?;
-
-    Ok(YtdlStream {
-        stream: LinesStream::new(BufReader::new(child.stdout.take().unwrap()).lines())
-            .chunks(n_fields),
-        n_fields,
-        response: T::response,
-        _child: child,
-    })
+/// Runs yt-dlp, sending each group of `n_fields` lines it prints. If youtube rate limits it before
+/// anything was sent it's tried again, a little later each time.
+async fn run<Y>(
+    args: Vec<OsString>,
+    n_fields: usize,
+    response: fn(&mut Vec<String>) -> Y,
+    tx: mpsc::Sender<Result<Ytdl<Y>, Error>>,
+) {
+    let mut attempt = 0;
+    loop {
+        limit::acquire().await;
+        let spawned = Command::new(&backend().binary)
+            .args(&args)
+            .kill_on_drop(true)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                let _ = tx.send(Err(e.into())).await;
+                return;
+            }
+        };
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let mut sent = 0;
+        let forward = async {
+            let mut chunks = LinesStream::new(BufReader::new(stdout).lines()).chunks(n_fields);
+            while let Some(lines) = chunks.next().await {
+                let item = lines
+                    .into_iter()
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(Error::from)
+                    .and_then(|mut lines| {
+                        if lines.len() == n_fields {
+                            Ok(Ytdl(response(&mut lines)))
+                        } else {
+                            Err(Error::from(YtdlError::InsufisientFields {
+                                expected: n_fields,
+                                found: lines.len(),
+                                fields: lines,
+                            }))
+                        }
+                    });
+                if tx.send(item).await.is_err() {
+                    return false;
+                }
+                sent += 1;
+            }
+            true
+        };
+        let mut error_output = String::new();
+        let (receiving, _) = tokio::join!(forward, stderr.read_to_string(&mut error_output));
+        if !receiving {
+            return;
+        }
+        let status = match child.wait().await {
+            Ok(status) => status,
+            Err(e) => {
+                let _ = tx.send(Err(e.into())).await;
+                return;
+            }
+        };
+        if status.success() || sent > 0 {
+            return;
+        }
+        if !limit::is_rate_limited(&error_output) {
+            let _ = tx
+                .send(Err(YtdlError::failed(status, error_output).into()))
+                .await;
+            return;
+        }
+        if attempt == limit::MAX_RETRIES {
+            let _ = tx
+                .send(Err(YtdlError::RateLimited {
+                    retries: attempt,
+                    stderr: error_output,
+                }
+                .into()))
+                .await;
+            return;
+        }
+        let wait = limit::backoff(attempt);
+        tracing::warn!(?wait, attempt, "rate limited by youtube, backing off");
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+    }
 }
 
+/// The results of a yt-dlp run, in the order it prints them.
 #[derive(Debug)]
-#[pin_project]
 pub struct YtdlStream<Y> {
-    #[pin]
-    stream: Chunks<LinesStream<BufReader<ChildStdout>>>,
-    n_fields: usize,
-    response: fn(&mut Vec<String>) -> Y,
-    _child: Child,
+    rx: mpsc::Receiver<Result<Ytdl<Y>, Error>>,
 }
 
 impl<Y> Stream for YtdlStream<Y> {
     type Item = Result<Ytdl<Y>, Error>;
 
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let n_fields = self.n_fields;
-        let response = self.response;
-        match self.project().stream.poll_next(cx) {
-            Poll::Ready(Some(lines)) => Poll::Ready(Some({
-                let lines = lines
-                    .into_iter()
-                    .collect::<Result<Vec<_>, _>>()
-                    .map_err(Error::from);
-                lines.and_then(|mut lines| {
-                    if lines.len() == n_fields {
-                        Ok(Ytdl(response(&mut lines)))
-                    } else {
-                        Err(Error::from(YtdlError::InsufisientFields {
-                            expected: n_fields,
-                            found: lines.len(),
-                            fields: lines,
-                        }))
-                    }
-                })
-            })),
-            Poll::Ready(None) => Poll::Ready(None),
-            Poll::Pending => Poll::Pending,
-        }
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
     }
 }
 
@@ -374,3 +550,24 @@ impl<R: getters::GetThumbnail> Ytdl<R> {
         self.0.thumbnail()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::process::ExitStatusExt;
+
+    use super::*;
+
+    #[test]
+    fn login_required_is_told_apart() {
+        let status = ExitStatus::from_raw(1 << 8);
+        let members_only = "ERROR: [youtube] abc: Join this channel to get access to members-only content like this video, and other exclusive perks.";
+        assert!(matches!(
+            YtdlError::failed(status, members_only.into()),
+            YtdlError::LoginRequired { reason } if reason.starts_with("[youtube] abc: Join")
+        ));
+        assert!(matches!(
+            YtdlError::failed(status, "ERROR: [youtube] abc: Video unavailable".into()),
+            YtdlError::NonZeroStatus { .. }
+        ));
+    }
+}
@@ -0,0 +1,66 @@
+use std::{collections::HashSet, process::Stdio};
+
+use super::YtdlError;
+use crate::{
+    item::link::{Id, VideoLink},
+    Error, VideoId,
+};
+
+/// How many videos are asked about per yt-dlp invocation.
+const BATCH_SIZE: usize = 50;
+
+/// A video yt-dlp could not find.
+#[derive(Debug)]
+pub struct Unavailable {
+    pub id: Box<VideoId>,
+    pub reason: String,
+}
+
+/// Asks yt-dlp about all the videos, in batches, and returns the ones that are no longer
+/// available, along with the reason yt-dlp gave.
+pub async fn unavailable<'i, I>(ids: I) -> Result<Vec<Unavailable>, Error>
+where
+    I: IntoIterator<Item = &'i VideoId>,
+{
+    let ids = ids.into_iter().collect::<Vec<_>>();
+    let mut dead = Vec::new();
+    for batch in ids.chunks(BATCH_SIZE) {
+        let output = super::command()
+            .await
+            .args(["--ignore-errors", "--no-warnings"])
+            .args(super::backend().print("id"))
+            .args(batch.iter().map(|id| VideoLink::from_id(id).into_string()))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .output()
+            .await?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let available = stdout.lines().map(str::trim).collect::<HashSet<_>>();
+        let reason_for = |id: &VideoId| {
+            stderr
+                .lines()
+                .find(|l| l.contains(id.as_str()))
+                .map(|l| l.trim_start_matches("ERROR:").trim().to_string())
+        };
+        let missing = batch
+            .iter()
+            .filter(|id| !available.contains(id.as_str()))
+            .map(|id| (*id, reason_for(id)))
+            .collect::<Vec<_>>();
+        // yt-dlp failing without mentioning any of the videos means it couldn't check any of
+        // them (no network, broken install, ...) rather than all of them being gone.
+        if !output.status.success()
+            && available.is_empty()
+            && missing.iter().all(|(_, r)| r.is_none())
+        {
+            return Err(YtdlError::failed(output.status, stderr.into_owned()).into());
+        }
+        dead.extend(missing.into_iter().map(|(id, reason)| Unavailable {
+            id: id.boxed(),
+            reason: reason.unwrap_or_else(|| "not returned by yt-dlp".into()),
+        }));
+    }
+    Ok(dead)
+}
@@ -1,11 +1,7 @@
-use once_cell::sync::Lazy;
-use regex::Regex;
-
-static ID: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"(v=|youtu.be/)(?P<id>[A-Za-z0-9_\-]{11})").unwrap());
+use crate::VideoId;
 
 pub fn extract_id(s: &str) -> Option<&str> {
-    Some(ID.captures(s)?.name("id").unwrap().as_str())
+    VideoId::from_any_url(s).map(VideoId::as_str)
 }
 
 #[cfg(test)]
@@ -1,16 +1,40 @@
+use std::num::NonZeroUsize;
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use clap_complete::Shell;
+use mlib::{
+    item::SearchProvider,
+    players::{EndOfQueuePolicy, PrecachePolicy},
+};
 use serde::{Deserialize, Serialize};
 
+use crate::util::date::DateRange;
+
 #[derive(Debug, Parser, Serialize, Deserialize)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
     #[arg(short, long)]
     pub socket: Option<usize>,
+    /// Send the command to every player, works with pause, set-pause, set-play, quit, vu and vd
+    #[arg(long, conflicts_with = "socket")]
+    pub all: bool,
+    /// Send the command to the player with this name, see `m play --name`
+    #[arg(long, conflicts_with_all = ["socket", "all"])]
+    pub player: Option<String>,
+    /// What to log, like RUST_LOG, also used by the daemons this command starts
+    #[arg(long, global = true)]
+    pub log: Option<String>,
+    /// How commands that only show things print them
+    #[arg(short, long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+    /// Use daemons of their own, e.g. for a test instance of m alongside the real one. Can also
+    /// be set with M_NAMESPACE
+    #[arg(long, global = true)]
+    pub namespace: Option<String>,
     #[command(subcommand)]
     pub cmd: Option<Command>,
 }
@@ -31,7 +55,10 @@ pub enum Command {
 
     /// Interactively asks the user what songs they want to play from their playlist
     #[command(alias = "play-interactive")]
-    Playlist,
+    Playlist {
+        #[command(subcommand)]
+        cmd: Option<PlaylistCmd>,
+    },
 
     /// Add a new song to the playlist
     #[command(alias = "add-song")]
@@ -40,12 +67,33 @@ pub enum Command {
     /// Append a playlist to the personal playlist
     AddPlaylist(AddPlaylist),
 
-    /// List all current categories
-    Cat,
+    /// Follow a playlist or channel, adding what's uploaded to it to the playlist. Lists the
+    /// followed ones if no link is given
+    #[command(args_conflicts_with_subcommands = true)]
+    Follow {
+        #[command(subcommand)]
+        cmd: Option<FollowCmd>,
+        link: Option<String>,
+        /// The categories its new videos are added with
+        #[arg(short, long = "category")]
+        categories: Vec<String>,
+    },
+
+    /// List all current categories, or change them in bulk
+    Cat {
+        #[command(subcommand)]
+        cmd: Option<CatCmd>,
+    },
 
     /// Shows the current playlist
     Now(Amount),
 
+    /// Show when a song will start playing and when the queue will be over
+    Eta {
+        /// The queue index of the song, as shown by `m now`
+        index: Option<usize>,
+    },
+
     /// Show the current song
     #[command(alias = "c")]
     Current {
@@ -55,15 +103,26 @@ pub enum Command {
         /// Print the filename/link instead
         #[arg(short = 'i', long, action = clap::ArgAction::Count)]
         link: u8,
+        /// Show this many of the songs that come next, instead of just one
+        #[arg(long, value_name = "N")]
+        next: Option<usize>,
     },
 
     /// Shows lyrics for the current song
     #[command(alias = "ly")]
-    Lyrics,
+    Lyrics {
+        /// Follow the song, showing each line as it's sung, if the lyrics are synced
+        #[arg(short, long)]
+        sync: bool,
+    },
 
     /// Add a category to the current song
     #[command(alias = "change-cats-to-current")]
-    ChCat, // TODO: review this
+    ChCat {
+        /// Use a named playlist instead of the main one
+        #[arg(short, long)]
+        playlist: Option<String>,
+    }, // TODO: review this
 
     /// Queue a song
     #[command(alias = "q")]
@@ -73,23 +132,47 @@ pub enum Command {
     #[command(subcommand, alias = "dq")]
     Dequeue(DeQueue),
 
+    /// Move a song to another position in the queue
+    #[command(alias = "mv")]
+    Move(Move),
+
+    /// Swap two songs in the queue
+    Swap(Swap),
+
     /// Delete a song from the playlist file
     #[command(alias = "del")]
     DeleteSong(DeleteSong),
 
     /// Deletes downloaded songs that are not in the playlist anymore
-    CleanDownloads,
+    CleanDownloads {
+        /// Also delete the least recently played songs until the download dir is under
+        /// `cache_max_bytes` from the config
+        #[arg(long)]
+        enforce_budget: bool,
+    },
+
+    /// Look for duplicated, unavailable and orphaned songs
+    #[command(alias = "check")]
+    Doctor {
+        /// Don't check if the songs are still available online
+        #[arg(long)]
+        offline: bool,
+        /// Pick a replacement for each unavailable song from a search for its name, or delete
+        /// it, or put it in the `dead` category so it isn't asked about again
+        #[arg(long, conflicts_with = "offline")]
+        fix_dead: bool,
+    },
 
     /// Toggles playlist looping
     Loop,
 
     /// Volume up
     #[command(alias = "k")]
-    Vu(Amount),
+    Vu(VolumeStep),
 
-    /// Volume up
+    /// Volume down
     #[command(alias = "j")]
-    Vd(Amount),
+    Vd(VolumeStep),
 
     /// Previous chapter in a file
     #[command(alias = "H")]
@@ -105,7 +188,18 @@ pub enum Command {
 
     /// Skip to the next file
     #[command(alias = "l")]
-    NextFile(Amount),
+    NextFile(NextFile),
+
+    /// Play the song at this position in the queue
+    Jump {
+        pos: usize,
+    },
+
+    /// Go back to the song that was playing before, even if it's not the previous one in the queue
+    BackTrack,
+
+    /// Undo a back-track
+    FwdTrack,
 
     /// Seek backward
     #[command(alias = "u", alias = "J")]
@@ -115,31 +209,167 @@ pub enum Command {
     #[command(alias = "i", alias = "K")]
     Frwd(Amount),
 
+    /// Pause the player after some time (e.g. 30m, 1h15m), or cancel the timer with "cancel"
+    Sleep {
+        when: Option<SleepFor>,
+        /// Quit the player instead of pausing it
+        #[arg(short, long)]
+        stop: bool,
+    },
+
+    /// Fade the volume in when unpausing or after large seeks
+    Fade {
+        state: OnOff,
+    },
+
+    /// Skip sponsors and other segments of youtube videos submitted to SponsorBlock, or show
+    /// whether they are being skipped
+    #[command(alias = "sb")]
+    Sponsorblock {
+        state: Option<OnOff>,
+    },
+
+    /// Skip songs that are too long or not in some categories as they start, until turned off.
+    /// Turning it off lists what was skipped
+    Focus {
+        state: Option<OnOff>,
+        /// Skip songs longer than this (e.g. 10m, 1h15m)
+        #[arg(long, value_parser = parse_duration)]
+        max: Option<Duration>,
+        /// Skip songs that aren't in this category, can be given more than once
+        #[arg(short, long = "category")]
+        categories: Vec<String>,
+    },
+
+    /// Keep the queue from running out with songs from youtube mixes, of a song or of songs in a
+    /// category, until stopped
+    Radio {
+        /// Stop the radio
+        #[arg(long, conflicts_with_all = ["category", "song"])]
+        stop: bool,
+        /// Use the mixes of songs from this category instead of a song
+        #[arg(short, long, conflicts_with = "song")]
+        category: Option<String>,
+        /// Queue more songs when fewer than this are left
+        #[arg(short, long, default_value_t = 3)]
+        threshold: usize,
+        /// A link to the song to start from, the current song if not given
+        song: Option<String>,
+    },
+
+    /// Show or set the equalizer, to a preset (flat, bass, treble, vocal or one from the config)
+    /// or to the gain in dB of each band: 31Hz 62Hz 125Hz 250Hz 500Hz 1k 2k 4k 8k 16k
+    #[command(alias = "eq")]
+    Equalizer {
+        #[arg(allow_negative_numbers = true)]
+        preset_or_bands: Vec<String>,
+    },
+
+    /// Get or set an mpv property directly, for what the other commands don't cover
+    #[command(subcommand, alias = "prop")]
+    Property(PropertyCmd),
+
+    /// Show or change what happens when the queue ends: stop, loop, quit, radio (keep going
+    /// with the youtube mix of the last song) or hook:<command>
+    EndOfQueue {
+        policy: Option<EndOfQueuePolicy>,
+    },
+
+    /// Stay open and stop once the queue ends, same as `m end-of-queue stop`
+    AutoplayOff,
+
+    /// Show or change which queued songs are downloaded before they play: off, queue (all of
+    /// them) or a number of songs after the current one
+    Precache {
+        policy: Option<PrecachePolicy>,
+    },
+
     /// Enter interactive mode
     #[command(alias = "int")]
     Interactive,
 
+    /// Follow the current player, printing a line for a status bar whenever it changes
+    Bar {
+        /// What the lines look like
+        #[arg(short, long, value_enum, default_value_t = BarFormat::Waybar)]
+        format: BarFormat,
+    },
+
+    /// Print the events of the players as they happen
+    Watch {
+        /// Print each event as a json object per line
+        #[arg(long)]
+        json: bool,
+        /// Only print changes to these properties or these events, e.g. `pause,volume,seek`
+        #[arg(long, value_delimiter = ',')]
+        only: Vec<String>,
+    },
+
     // TODO: jukebox? probably deprecated
     /// Toggle video
     ToggleVideo,
 
     /// Get all songs in the playlist, optionaly filtered by category
     Songs {
+        /// Only songs in this category, or any of its children (`rock` includes `rock/metal`)
         category: Option<String>,
+        /// Use a named playlist instead of the main one
+        #[arg(short, long)]
+        playlist: Option<String>,
+        /// Sort by how often or how recently songs were played, showing those numbers too, or by
+        /// when they were added or how long they are
+        #[arg(short, long)]
+        sort: Option<SongSort>,
+    },
+
+    /// Report on what has been listened to: top songs and categories, listening time and skip rate
+    #[command(args_conflicts_with_subcommands = true)]
+    Stats {
+        #[command(subcommand)]
+        cmd: Option<StatsCmd>,
+        /// Start of the period, as a date (2024-01-31, 2024-01), `today`, `yesterday`, `last
+        /// week`, `this month` or an amount of time ago (3d, 2w, 1mo, 2 years ago)
+        #[arg(long)]
+        since: Option<DateRange>,
+        /// End of the period, inclusive, in the same format as `--since`
+        #[arg(long)]
+        until: Option<DateRange>,
+        /// How many songs and categories to show
+        #[arg(short = 'n', long, default_value_t = 10)]
+        top: usize,
+        /// Group the listening time by day or week
+        #[arg(long, value_enum, default_value_t = StatsPeriod::Week)]
+        per: StatsPeriod,
+        /// Output JSON instead of text
+        #[arg(long)]
+        json: bool,
     },
 
     /// Save the playlist to a file to be restored later
     Dump {
         file: PathBuf,
+        #[arg(long, value_enum, default_value_t = DumpFormat::Plain)]
+        format: DumpFormat,
     },
 
-    /// Load a file of songs to play
+    /// Load a file of songs to play, like one saved with `m dump`
     Load {
         file: PathBuf,
         #[arg(short, long)]
         shuf: bool,
     },
 
+    /// Save the playlist under a name, to be restored later with `m queues load`
+    SaveQueue {
+        name: String,
+    },
+
+    /// List the saved playlists, or load or delete them
+    Queues {
+        #[command(subcommand)]
+        cmd: Option<QueuesCmd>,
+    },
+
     /// Get the socket in use
     Socket {
         #[arg(value_parser = parse_new, id = "new")]
@@ -148,18 +378,33 @@ pub enum Command {
 
     /// Shuffle
     #[command(alias = "shuf")]
-    Shuffle,
+    Shuffle {
+        /// Shuffle only the songs after the current one, putting the ones that weren't played in
+        /// a while first
+        #[arg(long)]
+        smart: bool,
+    },
 
     /// Status
     Status {
         #[arg(default_value = "players")]
         entity: EntityStatus,
+        /// Also show the pid, version and uptime of the players daemon
+        #[arg(short, long)]
+        verbose: bool,
+        /// Print the last lines the daemon of the players or downloads logged instead
+        #[arg(long, num_args = 0..=1, default_missing_value = "50", value_name = "LINES")]
+        logs: Option<usize>,
     },
 
-    /// Info
+    /// Show a song's categories, statistics, download and where it's queued, given its link or
+    /// part of its name
     Info {
         #[arg(short, long)]
         id: bool,
+        /// The song that is playing
+        #[arg(long, conflicts_with = "song")]
+        current: bool,
         song: Vec<String>,
     },
 
@@ -169,9 +414,44 @@ pub enum Command {
         shell: Shell,
     },
 
+    /// Manage the download cache
+    #[command(subcommand)]
+    Cache(CacheCmd),
+
+    /// Manage the daemons that run the players and the downloads in the background
+    #[command(subcommand)]
+    Daemon(DaemonCmd),
+
+    /// Songs the player failed to play
+    #[command(subcommand)]
+    Failed(FailedCmd),
+
+    /// The local music in `music_dir` from the config
+    #[command(subcommand)]
+    Library(LibraryCmd),
+
     /// Just download the missing songs
     Download {
         category: Option<String>,
+        /// Download the songs matching a query or a smart playlist from the config
+        #[arg(long, conflicts_with = "category")]
+        smart: Option<String>,
+        /// How many songs to download at the same time, defaults to `download_jobs` from the
+        /// config or 4
+        #[arg(short, long, conflicts_with = "bg")]
+        jobs: Option<NonZeroUsize>,
+        /// Queue the downloads in the background, see `m status downloads`
+        #[arg(long)]
+        bg: bool,
+        /// Cancel background downloads by their id
+        #[arg(long, num_args = 1.., conflicts_with_all = ["category", "smart", "what", "bg", "jobs"])]
+        cancel: Vec<u64>,
+        /// Download the song that is playing
+        #[arg(long, conflicts_with_all = ["category", "smart", "what", "cancel", "queue"])]
+        current: bool,
+        /// Download every song in the queue
+        #[arg(long, conflicts_with_all = ["category", "smart", "what", "cancel"])]
+        queue: bool,
         what: Option<Vec<String>>,
     },
 }
@@ -187,17 +467,66 @@ fn parse_new(s: &str) -> Result<(), &'static str> {
 #[derive(Debug, Clone, Parser, Serialize, Deserialize)]
 // #[structopt(global_settings = &[DisableVersion])]
 pub struct Play {
-    /// Search the song on youtube
+    /// Search the song on youtube, or somewhere else with --provider
     #[arg(short, long)]
     pub search: bool,
 
+    /// Where to search: youtube (yt), soundcloud (sc) or local, the downloaded songs
+    #[arg(long, requires = "search", default_value_t)]
+    #[serde(default)]
+    pub provider: SearchProvider,
+
     /// Whether to enable video or not
     #[arg(short, long)]
     pub video: bool,
 
-    /// Queue all songs in a category
+    /// Queue all songs in a category, including its children. Can be given more than once
     #[arg(short, long)]
-    pub category: Option<String>,
+    pub category: Vec<String>,
+
+    /// Alternate between the songs of each category instead of shuffling them all together
+    #[arg(long, requires = "category")]
+    pub interleave: bool,
+
+    /// Queue all songs matching a query (e.g. `cat:chill AND duration<5m`) or a smart
+    /// playlist from the config
+    #[arg(long, conflicts_with = "category")]
+    pub smart: Option<String>,
+
+    /// Pick songs from a named playlist instead of the main one
+    #[arg(long)]
+    pub playlist: Option<String>,
+
+    /// Use the link or file in the clipboard
+    #[arg(long, conflicts_with_all = ["what", "category", "smart", "search"])]
+    pub from_clipboard: bool,
+
+    /// Continue long songs, like mixes and podcasts, from where they were stopped last time
+    #[arg(long)]
+    #[serde(default)]
+    pub resume: bool,
+
+    /// Set the player up like a profile from the config says
+    #[arg(long)]
+    #[serde(default)]
+    pub profile: Option<String>,
+
+    /// Give the player a name, to pick it with `m --player <name>` later
+    #[arg(long)]
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// An mpv option for the new player, e.g. `ytdl-format=bestaudio`. Can be given more than
+    /// once
+    #[arg(long = "mpv-option", value_name = "NAME=VALUE", value_parser = parse_mpv_option)]
+    #[serde(default)]
+    pub mpv_options: Vec<(String, String)>,
+
+    /// Stream videos no taller than this many pixels, e.g. `480`, or only their audio with
+    /// `audio`, to use less data. Overrides `quality` in the config
+    #[arg(long, value_name = "HEIGHT|audio")]
+    #[serde(default)]
+    pub quality: Option<Quality>,
 
     /// What to play
     pub what: Vec<String>,
@@ -211,6 +540,16 @@ pub struct New {
     pub queue: bool,
     #[arg(short, long)]
     pub search: bool,
+    /// Add it to a named playlist instead of the main one
+    #[arg(short, long)]
+    pub playlist: Option<String>,
+    /// What to call it, instead of the video's title
+    #[arg(short, long)]
+    pub name: Option<String>,
+    /// Add it even if it's already in the playlist, instead of adding the categories it's
+    /// missing
+    #[arg(short, long)]
+    pub force: bool,
     pub query: String,
     pub categories: Vec<String>,
 }
@@ -221,6 +560,18 @@ pub struct AddPlaylist {
     /// Queue it too
     #[arg(short, long)]
     pub queue: bool,
+    /// Add the categories to songs that are already in the playlist
+    #[arg(long, conflicts_with_all = ["skip_existing", "ask", "force"])]
+    pub merge_categories: bool,
+    /// Leave songs that are already in the playlist untouched (the default)
+    #[arg(long, conflicts_with_all = ["ask", "force"])]
+    pub skip_existing: bool,
+    /// Ask what to do with each song that is already in the playlist
+    #[arg(long, conflicts_with = "force")]
+    pub ask: bool,
+    /// Add songs that are already in the playlist again
+    #[arg(long)]
+    pub force: bool,
     pub link: String,
     pub categories: Vec<String>,
 }
@@ -247,12 +598,32 @@ pub struct QueueOpts {
     pub notify: bool,
 
     /// Don't move in the playlist, keep it at the end
-    #[arg(short = 'm', long = "no-move")]
+    #[arg(short = 'm', long = "no-move", visible_alias = "end", conflicts_with_all = ["next", "at"])]
     pub no_move: bool,
 
+    /// Play right after the current song, before anything queued since
+    #[arg(long, conflicts_with = "at")]
+    #[serde(default)]
+    pub next: bool,
+
+    /// Put it at this position in the playlist
+    #[arg(long)]
+    #[serde(default)]
+    pub at: Option<usize>,
+
     /// Clear the queue
     #[arg(short = 'x', long = "clear")]
     pub clear: bool,
+
+    /// Queue on the current player even if a route in the config matches
+    #[arg(long = "no-route")]
+    #[serde(default)]
+    pub no_route: bool,
+
+    /// Queue each chapter of the videos as its own song, for long mixes
+    #[arg(long)]
+    #[serde(default)]
+    pub split_chapters: bool,
 }
 
 impl Deref for Queue {
@@ -276,6 +647,11 @@ pub enum DeQueue {
         /// The category to filter by
         cat: String,
     },
+    /// All songs matching a query or a smart playlist from the config
+    Smart {
+        /// The query, e.g. `cat:chill AND duration<5m`
+        query: String,
+    },
     /// The current song.
     Current,
     /// A relative index
@@ -289,6 +665,29 @@ pub enum DeQueue {
     },
 }
 
+#[derive(Debug, Clone, Copy, Parser, Serialize, Deserialize)]
+pub struct Move {
+    /// The position of the song to move, +X and -X are relative to the current song
+    #[arg(required_unless_present = "pick", allow_negative_numbers = true)]
+    pub from: Option<DeQueueIndex>,
+    /// The position it should end up in, +X and -X are relative to the current song
+    #[arg(required_unless_present = "pick", allow_negative_numbers = true)]
+    pub to: Option<DeQueueIndex>,
+    /// Pick the song, and where to put it, from the queue
+    #[arg(short, long, conflicts_with_all = ["from", "to"])]
+    pub pick: bool,
+}
+
+#[derive(Debug, Clone, Copy, Parser, Serialize, Deserialize)]
+pub struct Swap {
+    /// The position of one of the songs, +X and -X are relative to the current song
+    #[arg(allow_negative_numbers = true)]
+    pub a: DeQueueIndex,
+    /// The position of the other one
+    #[arg(allow_negative_numbers = true)]
+    pub b: DeQueueIndex,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum DeQueueIndexKind {
     Minus,
@@ -299,6 +698,20 @@ pub enum DeQueueIndexKind {
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct DeQueueIndex(pub DeQueueIndexKind, pub usize);
 
+impl DeQueueIndex {
+    /// The position in the queue, given the position of the current song.
+    pub fn resolve(self, current: usize) -> anyhow::Result<usize> {
+        let DeQueueIndex(kind, n) = self;
+        match kind {
+            DeQueueIndexKind::Plus => Ok(current + n),
+            DeQueueIndexKind::Minus => current
+                .checked_sub(n)
+                .ok_or_else(|| anyhow::anyhow!("i > {}", n)),
+            DeQueueIndexKind::Exact => Ok(n),
+        }
+    }
+}
+
 impl FromStr for DeQueueIndex {
     type Err = &'static str;
 
@@ -320,6 +733,191 @@ impl FromStr for DeQueueIndex {
     }
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ValueEnum)]
+pub enum SongSort {
+    /// Most played first
+    Plays,
+    /// Most recently played first
+    Recent,
+    /// Most recently added first, songs added before that was recorded last
+    Added,
+    /// Longest first, asking yt-dlp for the durations that aren't known yet
+    Duration,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ValueEnum)]
+pub enum StatsPeriod {
+    Day,
+    Week,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum OutputFormat {
+    /// For people, possibly as a notification
+    Text,
+    /// A json value, for scripts
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ValueEnum)]
+pub enum DumpFormat {
+    /// A song per line
+    Plain,
+    /// A song per line, each after a comment with its position, title and duration
+    Annotated,
+    /// A json object with the current position and the songs' positions, titles and durations
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ValueEnum)]
+pub enum BarFormat {
+    /// A json object per line, for waybar's custom modules with `return-type = "json"`
+    Waybar,
+    /// Plain text, for polybar's script modules with `tail = true`
+    Polybar,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ValueEnum)]
+pub enum OnOff {
+    On,
+    Off,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum SleepFor {
+    Cancel,
+    After(Duration),
+}
+
+impl FromStr for SleepFor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => return Err("expected a duration or cancel".into()),
+            "cancel" => return Ok(Self::Cancel),
+            _ => {}
+        }
+        // a bare number is a number of minutes
+        if let Ok(m) = s.parse::<u64>() {
+            return m
+                .checked_mul(60)
+                .map(|s| Self::After(Duration::from_secs(s)))
+                .ok_or_else(|| format!("{m} minutes is too long"));
+        }
+        parse_duration(s).map(Self::After)
+    }
+}
+
+/// How good the streams of new players are, given to mpv as `ytdl-format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "RawQuality", into = "String")]
+pub enum Quality {
+    /// The best video no taller than this many pixels
+    Height(u32),
+    /// Only the audio
+    Audio,
+}
+
+impl Quality {
+    fn height(h: u32) -> Result<Self, String> {
+        match h {
+            0 => Err("the height has to be more than 0".into()),
+            h => Ok(Self::Height(h)),
+        }
+    }
+
+    pub fn ytdl_format(self) -> String {
+        match self {
+            // `<=?` also takes formats whose height isn't known
+            Self::Height(h) => format!("bestvideo[height<=?{h}]+bestaudio/best[height<=?{h}]"),
+            Self::Audio => "bestaudio/best".into(),
+        }
+    }
+}
+
+impl FromStr for Quality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "audio" {
+            return Ok(Self::Audio);
+        }
+        s.strip_suffix('p')
+            .unwrap_or(s)
+            .parse()
+            .map_err(|_| format!("expected a height, like 720, or audio but got {s:?}"))
+            .and_then(Self::height)
+    }
+}
+
+impl From<Quality> for String {
+    fn from(q: Quality) -> Self {
+        match q {
+            Quality::Height(h) => h.to_string(),
+            Quality::Audio => "audio".into(),
+        }
+    }
+}
+
+/// The config can have `quality = 480` as well as `quality = "480p"`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawQuality {
+    Height(u32),
+    Name(String),
+}
+
+impl TryFrom<RawQuality> for Quality {
+    type Error = String;
+
+    fn try_from(raw: RawQuality) -> Result<Self, Self::Error> {
+        match raw {
+            RawQuality::Height(h) => Self::height(h),
+            RawQuality::Name(s) => s.parse(),
+        }
+    }
+}
+
+fn parse_mpv_option(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.into(), value.into())),
+        _ => Err(format!("expected NAME=VALUE but got {s:?}")),
+    }
+}
+
+/// Parses durations like `30m`, `1h15m`, `90s` or `7d`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    if s.is_empty() {
+        return Err("expected a duration".into());
+    }
+    let too_long = || format!("{s:?} is too long");
+    let mut total = 0u64;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(|| format!("missing unit in {s:?}"))?;
+        let n = rest[..digits]
+            .parse::<u64>()
+            .map_err(|_| format!("invalid duration: {s:?}"))?;
+        let mut chars = rest[digits..].chars();
+        let unit = match chars.next() {
+            Some('d') => 24 * 60 * 60,
+            Some('h') => 60 * 60,
+            Some('m') => 60,
+            Some('s') => 1,
+            _ => return Err(format!("invalid duration: {s:?}")),
+        };
+        total = n
+            .checked_mul(unit)
+            .and_then(|n| total.checked_add(n))
+            .ok_or_else(too_long)?;
+        rest = chars.as_str();
+    }
+    Ok(Duration::from_secs(total))
+}
+
 #[derive(Debug, Clone, Parser, Serialize, Deserialize)]
 // #[structopt(global_settings = &[DisableVersion])]
 pub struct DeleteSong {
@@ -334,6 +932,21 @@ pub struct Amount {
     pub amount: Option<i32>,
 }
 
+#[derive(Debug, Clone, Parser, Serialize, Deserialize)]
+pub struct NextFile {
+    #[command(flatten)]
+    pub amount: Amount,
+    /// Skip to the first upcoming song whose title or file matches these words instead
+    #[arg(long, conflicts_with = "amount")]
+    pub until: Option<String>,
+}
+
+/// How much to change the volume by, `volume_step` from the config by default.
+#[derive(Debug, Clone, Copy, Parser, Serialize, Deserialize)]
+pub struct VolumeStep {
+    pub amount: Option<f64>,
+}
+
 impl From<i32> for Amount {
     fn from(value: i32) -> Self {
         Self {
@@ -342,6 +955,139 @@ impl From<i32> for Amount {
     }
 }
 
+#[derive(Debug, Clone, Subcommand, Serialize, Deserialize)]
+pub enum PlaylistCmd {
+    /// Full screen editor to reorder, rename, recategorize and delete songs
+    Edit {
+        /// Edit this playlist instead of the main one
+        #[arg(short, long)]
+        playlist: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand, Serialize, Deserialize)]
+pub enum CatCmd {
+    /// Add a category to many songs at once
+    Add {
+        category: String,
+        /// The songs: some links, a query or smart playlist from the config, or a category
+        #[arg(long = "where", required = true, num_args = 1..)]
+        songs: Vec<String>,
+    },
+    /// Rename a category in every song, its children included
+    Rename { old: String, new: String },
+    /// Take a category, and its children, out of every song
+    Remove { category: String },
+}
+
+#[derive(Debug, Clone, Subcommand, Serialize, Deserialize)]
+pub enum FollowCmd {
+    /// Add the videos uploaded since the last sync to the playlist. The downloads daemon does
+    /// this every `follow_sync_hours` while it runs
+    Sync,
+    /// Stop following a playlist or channel
+    Rm { link: String },
+}
+
+#[derive(Debug, Clone, Subcommand, Serialize, Deserialize)]
+pub enum StatsCmd {
+    /// Encrypt the statistics with a newly generated key file, new statistics will be encrypted
+    /// as well
+    Lock,
+    /// Decrypt the statistics and delete the key file
+    Unlock,
+}
+
+#[derive(Debug, Clone, Subcommand, Serialize, Deserialize)]
+pub enum QueuesCmd {
+    /// Play a saved playlist
+    Load {
+        name: String,
+        #[arg(short, long)]
+        shuf: bool,
+    },
+    /// Delete saved playlists
+    Rm {
+        #[arg(required_unless_present = "older_than")]
+        names: Vec<String>,
+        /// Delete the ones saved longer ago than this, e.g. `30d`
+        #[arg(long, value_parser = parse_duration)]
+        older_than: Option<Duration>,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand, Serialize, Deserialize)]
+pub enum LibraryCmd {
+    /// Read the tags of the files that are new or changed since the last time
+    Index,
+    /// List the songs that match some words, best matches first
+    Search { words: Vec<String> },
+}
+
+#[derive(Debug, Clone, Subcommand, Serialize, Deserialize)]
+pub enum CacheCmd {
+    /// List the downloaded songs and their hashes, saving the list in the download dir
+    Manifest,
+    /// Copy the songs downloaded on another machine that are missing here
+    FetchMissing {
+        /// The download dir of the other machine, as `host:path`
+        #[arg(long)]
+        from: String,
+    },
+    /// Manage the cache of song titles
+    #[command(subcommand)]
+    Titles(TitlesCmd),
+}
+
+#[derive(Debug, Clone, Subcommand, Serialize, Deserialize)]
+pub enum DaemonCmd {
+    /// Stop a daemon, or both if none is given. Stopping the players daemon quits every player
+    Stop { daemon: Option<DaemonKind> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum DaemonKind {
+    Players,
+    Downloads,
+}
+
+#[derive(Debug, Clone, Subcommand, Serialize, Deserialize)]
+pub enum PropertyCmd {
+    /// Print the value of a property, e.g. `volume` or `metadata`
+    Get { name: String },
+    /// Set a property, e.g. `speed 1.25`. Only some properties can be set
+    Set {
+        name: String,
+        /// Parsed as json, or taken as a string if it isn't valid json
+        #[arg(allow_negative_numbers = true)]
+        value: String,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand, Serialize, Deserialize)]
+pub enum FailedCmd {
+    /// List the songs that failed and why
+    List,
+    /// Queue failed songs again, forgetting that they failed
+    Retry {
+        /// The number of the song, as shown by `m failed list`
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        n: Option<usize>,
+        /// Retry all of them
+        #[arg(long)]
+        all: bool,
+        /// Play the downloaded copy instead, if there is one
+        #[arg(long)]
+        cache: bool,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand, Serialize, Deserialize)]
+pub enum TitlesCmd {
+    /// Remove titles of songs that are neither in the playlist, nor queued, nor recently used
+    Prune,
+}
+
 #[derive(Debug, Clone, Copy, Parser, Serialize, Deserialize)]
 // #[structopt(global_settings = &[DisableVersion])]
 pub enum EntityStatus {
@@ -362,3 +1108,63 @@ impl FromStr for EntityStatus {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sleep_for_rejects_nonsense() {
+        assert!(matches!("cancel".parse(), Ok(SleepFor::Cancel)));
+        assert!(matches!(
+            "5".parse(),
+            Ok(SleepFor::After(d)) if d == Duration::from_secs(300)
+        ));
+        assert!("".parse::<SleepFor>().is_err());
+        assert!(u64::MAX.to_string().parse::<SleepFor>().is_err());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(90 * 60)));
+        assert_eq!(
+            parse_duration("2d"),
+            Ok(Duration::from_secs(2 * 24 * 60 * 60))
+        );
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration(&format!("{}d", u64::MAX / 2)).is_err());
+        assert!(parse_duration(&format!("{}s1s", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn qualities() {
+        assert_eq!("720".parse::<Quality>(), Ok(Quality::Height(720)));
+        assert_eq!("480p".parse::<Quality>(), Ok(Quality::Height(480)));
+        assert_eq!("audio".parse::<Quality>(), Ok(Quality::Audio));
+        assert!("0".parse::<Quality>().is_err());
+        assert!("0p".parse::<Quality>().is_err());
+        assert!("hd".parse::<Quality>().is_err());
+        assert!("".parse::<Quality>().is_err());
+        assert_eq!(
+            Quality::try_from(RawQuality::Name("360p".into())),
+            Ok(Quality::Height(360))
+        );
+        assert!(Quality::try_from(RawQuality::Height(0)).is_err());
+        assert_eq!(String::from(Quality::Height(1080)), "1080");
+    }
+
+    #[test]
+    fn failed_retry_takes_a_number_or_all() {
+        let retry = |args: &[&str]| -> Result<_, clap::Error> {
+            match Args::try_parse_from(["m", "failed", "retry"].iter().chain(args))?.cmd {
+                Some(Command::Failed(FailedCmd::Retry { n, all, .. })) => Ok((n, all)),
+                cmd => panic!("parsed as {cmd:?}"),
+            }
+        };
+        assert_eq!(retry(&["2"]).unwrap(), (Some(2), false));
+        assert_eq!(retry(&["--all"]).unwrap(), (None, true));
+        assert!(retry(&[]).is_err());
+        assert!(retry(&["2", "--all"]).is_err());
+    }
+}
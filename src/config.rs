@@ -1,7 +1,23 @@
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    num::{NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+    time::Duration,
+};
 
+use anyhow::Context;
 use dirs::config_dir;
+use mlib::{
+    players::{
+        DaemonSettings, DaemonTransport, EndOfQueuePolicy, PlayerProfile, PrecachePolicy,
+        VolumeCurve,
+    },
+    playlist::{query::Query, Category, Song},
+};
 use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::util::date::DateRange;
 
 #[derive(serde::Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[serde(rename_all = "lowercase")]
@@ -16,14 +32,313 @@ impl Default for DownloadFormat {
     }
 }
 
+/// A float in the config, ordered with [`f64::total_cmp`] so the config can still be compared and
+/// hashed.
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+#[serde(transparent)]
+pub struct OrderedF64(pub f64);
+
+impl PartialEq for OrderedF64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for OrderedF64 {}
+
+impl PartialOrd for OrderedF64 {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OrderedF64 {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl std::hash::Hash for OrderedF64 {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state)
+    }
+}
+
+/// Songs of a category that `m queue` always sends to the same player.
+#[derive(serde::Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Route {
+    pub category: String,
+    /// The name of the player, created if there isn't one running with this name.
+    pub player: String,
+    /// Whether the player is created with video.
+    #[serde(default)]
+    pub video: bool,
+}
+
+/// How `m play --profile <name>` sets up the player, under `[profiles.<name>]`.
+#[derive(serde::Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Profile {
+    /// Whether the player is created with video.
+    #[serde(default)]
+    pub video: bool,
+    /// How much `m vu` and `m vd` change the volume of the player by, instead of `volume_step`.
+    #[serde(default)]
+    pub volume_step: Option<OrderedF64>,
+    /// The window geometry, volume, osc and any other mpv options.
+    #[serde(flatten)]
+    pub player: PlayerProfile,
+}
+
 #[derive(serde::Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct MConfig {
     #[serde(default)]
     pub socket_base_dir: Option<PathBuf>,
+    /// Where the playlist is, `~/.config/m/playlist` by default. `$PLAYLIST` takes precedence.
+    #[serde(default)]
+    pub playlist_path: Option<PathBuf>,
+    /// Other playlists whose songs are merged into the main one, e.g. one synced from another
+    /// machine. Only the main playlist is ever written to.
+    #[serde(default)]
+    pub include_playlists: Vec<PathBuf>,
+    /// How the players daemon is reached, under `[players_transport]`. A unix socket in tmp by
+    /// default, see [`DaemonTransport`] for the others.
+    #[serde(default)]
+    pub players_transport: Option<DaemonTransport>,
     #[serde(default)]
     pub download_format: DownloadFormat,
+    /// Codec, quality and container of downloads and how they are named, under `[download]`.
+    #[serde(default)]
+    pub download: mlib::downloaded::policy::DownloadPolicy,
+    /// The most the download dir can take up, once over it the least recently played songs are
+    /// deleted by `m clean-downloads --enforce-budget` and after background downloads.
+    #[serde(default)]
+    pub cache_max_bytes: Option<u64>,
+    /// How many songs `m download` downloads at the same time.
+    #[serde(default)]
+    pub download_jobs: Option<NonZeroUsize>,
+    /// How often the downloads daemon adds the new uploads of followed playlists and channels,
+    /// in hours, 6 by default. See `m follow`.
+    #[serde(default)]
+    pub follow_sync_hours: Option<NonZeroU64>,
+    /// Regexes for the noise removed from video titles when songs are added with `m new`, like
+    /// "(Official Video)". See [`DEFAULT_TITLE_NOISE`] for the ones used when unset.
+    #[serde(default)]
+    pub title_noise: Option<Vec<String>>,
+    /// Other names for categories, e.g. `metal = "rock/metal"`.
+    #[serde(default)]
+    pub category_aliases: BTreeMap<String, String>,
+    /// Named queries, e.g. `short-chill = "cat:chill AND duration<5m"`.
+    #[serde(default)]
+    pub smart_playlists: BTreeMap<String, String>,
+    /// How much `m vu` and `m vd` change the volume by, 2 by default.
+    #[serde(default)]
+    pub volume_step: Option<OrderedF64>,
+    /// `linear` or `perceptual`, where steps get smaller at lower volumes.
+    #[serde(default)]
+    pub volume_curve: VolumeCurve,
+    /// Where notifications go: `auto`, `libnotify`, `terminal` or `stdout-json`.
+    #[serde(default)]
+    pub notifier: crate::util::notifier::Backend,
+    /// Fade in over this many milliseconds (200 to 1000) when unpausing or after large seeks.
+    /// Setting it enables fading when the first player starts, `m fade on|off` toggles it.
+    #[serde(default)]
+    pub fade_ms: Option<u64>,
+    /// What new players do once their queue ends, `quit` by default. See `m end-of-queue`.
+    #[serde(default)]
+    pub end_of_queue: Option<EndOfQueuePolicy>,
+    /// How good the streams of new players are, a height like `480` or `"audio"`, like
+    /// `m play --quality`. The best there is by default.
+    #[serde(default)]
+    pub quality: Option<crate::arg_parse::Quality>,
+    /// Which queued songs new players download before they play, the whole `"queue"` by
+    /// default. See `m precache`.
+    #[serde(default)]
+    pub precache: Option<PrecachePolicy>,
+    /// Pause other media players, like browsers, when a player starts playing or is unpaused.
+    #[serde(default)]
+    pub pause_other_players: bool,
+    /// Quit players that have been paused, or done with their queue, for this many minutes.
+    #[serde(default)]
+    pub quit_idle_after_minutes: Option<u64>,
+    /// Credentials for the services to scrobble to, under `[scrobble.lastfm]` and
+    /// `[scrobble.listenbrainz]`.
+    #[serde(default)]
+    pub scrobble: mlib::scrobble::Config,
+    /// Serve a page to control the players from a browser, under `[web_remote]`.
+    #[serde(default)]
+    pub web_remote: Option<mlib::players::web_remote::Config>,
+    /// Skip sponsors and such in youtube videos, under `[sponsorblock]`. Setting it turns skipping
+    /// on for new players, `m sponsorblock on|off` toggles it.
+    #[serde(default)]
+    pub sponsorblock: Option<mlib::sponsorblock::Config>,
+    /// Pause the players when the default audio output goes away, like when headphones are
+    /// disconnected, under `[pause_on_unplug]`.
+    #[serde(default)]
+    pub pause_on_unplug: Option<mlib::players::sink_watch::Config>,
+    /// A Netscape formatted cookies file given to yt-dlp (and mpv), for private or members only
+    /// videos. Browsers can export one, or see yt-dlp's `--cookies-from-browser`.
+    #[serde(default)]
+    pub ytdl_cookies_file: Option<PathBuf>,
+    /// Extra arguments given to every yt-dlp invocation.
+    #[serde(default)]
+    pub ytdl_extra_args: Vec<String>,
+    /// The program used instead of yt-dlp and what it supports, under `[ytdl_backend]`.
+    #[serde(default)]
+    pub ytdl_backend: mlib::ytdl::backend::Backend,
+    /// Which player `m queue` sends songs to, by category, under `[[routes]]`. The first route
+    /// that matches wins.
+    #[serde(default)]
+    pub routes: Vec<Route>,
+    /// A directory of music that is indexed with `m library index` and searched before youtube.
+    #[serde(default)]
+    pub music_dir: Option<PathBuf>,
+    /// Where to look for lyrics, in order.
+    #[serde(default)]
+    pub lyrics_providers: Option<Vec<mlib::lyrics::ProviderKind>>,
+    /// Continue long songs from where they were stopped last time, like `m play --resume`.
+    #[serde(default)]
+    pub resume_playback: bool,
+    /// Named gains for `m eq`, e.g. `loud = [4, 3, 0, 0, 0, 0, 0, 2, 3, 4]`.
+    #[serde(default)]
+    pub equalizer_presets: BTreeMap<String, Vec<OrderedF64>>,
+    /// Named ways to set up new players, e.g. `[profiles.video]` with `video = true` and
+    /// `geometry = "1280x720"`.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
 }
 
+impl MConfig {
+    /// Parses a category given by the user, expanding aliases in its first level. With
+    /// `metal = "rock/metal"`, `metal/doom` becomes `rock/metal/doom`.
+    pub fn category(&self, name: &str) -> Category {
+        let category = Category::new(name);
+        let (first, rest) = category
+            .split_once(Category::SEPARATOR)
+            .unwrap_or((&category, ""));
+        match self.category_aliases.get(first) {
+            Some(alias) => Category::new(&format!("{alias}/{rest}")),
+            None => category,
+        }
+    }
+
+    /// The providers `m lyrics` asks, all of them unless picked in the config.
+    pub fn lyrics_providers(&self) -> Vec<&'static dyn mlib::lyrics::Provider> {
+        self.lyrics_providers
+            .as_deref()
+            .unwrap_or(&mlib::lyrics::ProviderKind::ALL)
+            .iter()
+            .map(|p| p.provider())
+            .collect()
+    }
+
+    /// The gains of an equalizer preset from the config, or of one of the built in ones.
+    pub fn equalizer_preset(&self, name: &str) -> Option<Vec<f32>> {
+        if let Some(gains) = self.equalizer_presets.get(name) {
+            return Some(gains.iter().map(|g| g.0 as f32).collect());
+        }
+        let gains: &[f32] = match name {
+            "flat" => &[],
+            "bass" => &[6., 5., 4., 2., 0., 0., 0., 0., 0., 0.],
+            "treble" => &[0., 0., 0., 0., 0., 0., 2., 4., 5., 6.],
+            "vocal" => &[-2., -2., -1., 0., 2., 4., 4., 2., 0., -1.],
+            _ => return None,
+        };
+        Some(gains.to_vec())
+    }
+
+    /// A player profile from the config, by name.
+    pub fn profile(&self, name: &str) -> anyhow::Result<&Profile> {
+        self.profiles
+            .get(name)
+            .with_context(|| format!("there is no player profile called {name:?} in the config"))
+    }
+
+    /// How long fading in takes when turned on with `m fade on`.
+    pub fn fade_length(&self) -> Duration {
+        Duration::from_millis(self.fade_ms.unwrap_or(400))
+    }
+
+    /// What the players daemon is set up with, if this process becomes it.
+    pub fn daemon_settings(&self) -> DaemonSettings {
+        DaemonSettings {
+            quit_idle_after: self
+                .quit_idle_after_minutes
+                .map(|minutes| Duration::from_secs(minutes.saturating_mul(60))),
+            precache: self.precache.unwrap_or_default(),
+            pause_other_players: self.pause_other_players,
+            pause_on_unplug: self.pause_on_unplug.clone(),
+            web_remote: self.web_remote.clone(),
+            scrobble: self.scrobble.clone(),
+            sponsorblock: self.sponsorblock.clone(),
+        }
+    }
+
+    /// How much `m vu` and `m vd` change the volume by when not given an amount.
+    pub fn volume_step(&self) -> f64 {
+        self.volume_step.map_or(2., |s| s.0)
+    }
+
+    /// Where `m queue` sends `song`, if any route matches it.
+    pub fn route(&self, song: &Song) -> Option<&Route> {
+        self.routes
+            .iter()
+            .find(|r| song.is_in(&self.category(&r.category)))
+    }
+
+    pub fn ytdl(&self) -> mlib::ytdl::Config {
+        mlib::ytdl::Config {
+            backend: self.ytdl_backend.clone(),
+            cookies_file: self.ytdl_cookies_file.clone(),
+            extra_args: self.ytdl_extra_args.clone(),
+        }
+    }
+
+    /// How many songs `m download` downloads at the same time.
+    pub fn download_jobs(&self) -> NonZeroUsize {
+        self.download_jobs.unwrap_or(NonZeroUsize::new(4).unwrap())
+    }
+
+    /// The regexes of `title_noise`, or the default ones.
+    pub fn title_noise(&self) -> anyhow::Result<Vec<Regex>> {
+        let noise = match &self.title_noise {
+            Some(noise) => noise.iter().map(String::as_str).collect(),
+            None => DEFAULT_TITLE_NOISE.to_vec(),
+        };
+        noise
+            .into_iter()
+            .map(|re| Regex::new(re).with_context(|| format!("invalid title_noise regex {re:?}")))
+            .collect()
+    }
+
+    /// How often followed playlists and channels are synced, see `m follow sync`.
+    pub fn follow_sync_interval(&self) -> Duration {
+        let hours = self.follow_sync_hours.map_or(6, NonZeroU64::get);
+        Duration::from_secs(hours.saturating_mul(60 * 60))
+    }
+
+    /// Parses a smart playlist query, or looks it up by name in `smart_playlists`. Category
+    /// aliases are expanded like in [`MConfig::category`].
+    pub fn smart_query(&self, query: &str) -> anyhow::Result<Query> {
+        let source = self
+            .smart_playlists
+            .get(query)
+            .map_or(query, String::as_str);
+        let dates = |s: &str| s.parse::<DateRange>().ok().map(DateRange::to_system_time);
+        let mut query = Query::parse_with_dates(source, &dates)
+            .with_context(|| format!("invalid smart playlist query {source:?}"))?;
+        query.map_categories(&|c| self.category(c));
+        Ok(query)
+    }
+}
+
+/// Bracketed tags like "(Official Video)" or "[HD]", "| Official Audio" suffixes and a trailing
+/// "Official Music Video".
+pub const DEFAULT_TITLE_NOISE: &[&str] = &[
+    r"(?i)[(\[][^)\]]*\b(official|lyrics?|visuali[sz]er|audio|video|hd|hq|4k|m/?v)\b[^)\]]*[)\]]",
+    r"(?i)\|[^|]*\bofficial\b[^|]*$",
+    r"(?i)\bofficial (music |lyric )?(video|audio)\s*$",
+];
+
 pub static CONFIG: Lazy<MConfig> = Lazy::new(|| {
     let mut config = config::Config::builder()
         .add_source({
@@ -40,19 +355,27 @@ pub static CONFIG: Lazy<MConfig> = Lazy::new(|| {
         .expect("a valid config file")
         .try_deserialize::<MConfig>()
         .expect("a valid config file");
-    if let Some(base) = config.socket_base_dir.as_mut() {
-        *base = base
-            .iter()
-            .map(|p| {
-                if p == "~" {
-                    dirs::home_dir()
-                        .expect("can't find home dir")
-                        .into_os_string()
-                } else {
-                    p.to_owned()
-                }
-            })
-            .collect()
-    }
+    config
+        .socket_base_dir
+        .iter_mut()
+        .chain(config.playlist_path.iter_mut())
+        .chain(config.include_playlists.iter_mut())
+        .for_each(expand_home);
     config
 });
+
+/// Replaces a `~` in `path` with the home dir.
+fn expand_home(path: &mut PathBuf) {
+    *path = path
+        .iter()
+        .map(|p| {
+            if p == "~" {
+                dirs::home_dir()
+                    .expect("can't find home dir")
+                    .into_os_string()
+            } else {
+                p.to_owned()
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,43 @@
+use std::io;
+
+use mlib::players::{self, error::Error};
+
+use crate::{arg_parse::DaemonKind, download_ctl, notify};
+
+/// Whether connecting failed because the daemon isn't running.
+fn not_running(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+    )
+}
+
+pub async fn stop(daemon: Option<DaemonKind>) -> anyhow::Result<()> {
+    if daemon.map_or(true, |d| d == DaemonKind::Players) {
+        match players::stop_daemon().await {
+            Ok(()) => notify!("players daemon stopped"),
+            Err(Error::Io(e)) if not_running(&e) => notify!("players daemon isn't running"),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if daemon.map_or(true, |d| d == DaemonKind::Downloads) {
+        match download_ctl::stop_daemon().await {
+            Ok(()) => notify!("downloads daemon stopped"),
+            Err(e) if not_running(&e) => notify!("downloads daemon isn't running"),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Prints the end of the log of a daemon.
+pub async fn logs(daemon: DaemonKind, lines: usize) -> anyhow::Result<()> {
+    let tail = match daemon {
+        DaemonKind::Players => players::daemon_log_tail(lines).await?,
+        DaemonKind::Downloads => download_ctl::daemon_log_tail(lines).await?,
+    };
+    for line in tail {
+        println!("{line}");
+    }
+    Ok(())
+}
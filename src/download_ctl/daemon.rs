@@ -0,0 +1,332 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use cli_daemon::Daemon;
+use mlib::{
+    downloaded::{self, budget, Progress},
+    item::link::VideoLink,
+    playlist::Playlist,
+};
+use once_cell::sync::Lazy;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::AbortHandle,
+    time::timeout,
+};
+use tracing::{error, info};
+
+use crate::config::{DownloadFormat, CONFIG};
+
+pub type JobId = u64;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Message {
+    Queue(VideoLink),
+    Cancel(JobId),
+    List,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    Queued(JobId),
+    /// Whether there was a queued or running job to cancel.
+    Cancelled(bool),
+    Jobs(Vec<Job>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum JobState {
+    Queued,
+    Downloading(Option<Progress>),
+    Done,
+    Failed(String),
+    Cancelled,
+}
+
+impl JobState {
+    fn is_pending(&self) -> bool {
+        matches!(self, Self::Queued | Self::Downloading(_))
+    }
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Queued => f.write_str("queued"),
+            Self::Downloading(None) => f.write_str("starting"),
+            Self::Downloading(Some(p)) => {
+                write!(f, "{:.1}%", p.percent)?;
+                if let Some(eta) = &p.eta {
+                    write!(f, " ETA {eta}")?;
+                }
+                Ok(())
+            }
+            Self::Done => f.write_str("done"),
+            Self::Failed(e) => write!(f, "failed: {e}"),
+            Self::Cancelled => f.write_str("cancelled"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Job {
+    pub id: JobId,
+    pub link: VideoLink,
+    pub state: JobState,
+}
+
+#[derive(Default)]
+struct Jobs {
+    jobs: BTreeMap<JobId, Job>,
+    running: HashMap<JobId, AbortHandle>,
+    next_id: JobId,
+}
+
+impl Jobs {
+    fn queue(&mut self, link: VideoLink) -> JobId {
+        if let Some(job) = self
+            .jobs
+            .values()
+            .find(|j| j.link == link && j.state.is_pending())
+        {
+            return job.id;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.jobs.insert(
+            id,
+            Job {
+                id,
+                link,
+                state: JobState::Queued,
+            },
+        );
+        id
+    }
+
+    fn cancel(&mut self, id: JobId) -> bool {
+        match self.jobs.get_mut(&id) {
+            Some(job) if job.state.is_pending() => {
+                job.state = JobState::Cancelled;
+                if let Some(task) = self.running.remove(&id) {
+                    task.abort();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn next_queued(&self) -> Option<&Job> {
+        self.jobs
+            .values()
+            .find(|j| matches!(j.state, JobState::Queued))
+    }
+
+    fn set_state(&mut self, id: JobId, state: JobState) {
+        if let Some(job) = self.jobs.get_mut(&id) {
+            // a cancelled job may still report progress before it's aborted
+            if job.state.is_pending() {
+                job.state = state;
+            }
+        }
+    }
+
+    fn pending(&self) -> Vec<&VideoLink> {
+        self.jobs
+            .values()
+            .filter(|j| j.state.is_pending())
+            .map(|j| &j.link)
+            .collect()
+    }
+}
+
+static JOBS: Lazy<Mutex<Jobs>> = Lazy::new(Mutex::default);
+
+/// Whether followed playlists and channels are being synced, which keeps the daemon alive.
+static SYNCING: AtomicBool = AtomicBool::new(false);
+
+/// Syncs followed playlists and channels whenever it's due, while the daemon runs.
+async fn sync_follows() {
+    let every = CONFIG.follow_sync_interval();
+    loop {
+        SYNCING.store(true, Ordering::SeqCst);
+        if let Err(e) = crate::playlist_ctl::sync_follows_if_due(every).await {
+            error!(?e, "failed to sync followed playlists");
+        }
+        SYNCING.store(false, Ordering::SeqCst);
+        tokio::time::sleep(every).await;
+    }
+}
+
+/// Where the links that still have to be downloaded are kept, so that they survive the daemon
+/// being restarted.
+fn pending_file() -> Option<PathBuf> {
+    let mut path = dirs::data_dir()?;
+    path.push("m");
+    path.push("pending-downloads.json");
+    Some(path)
+}
+
+async fn load_pending() -> Vec<VideoLink> {
+    let Some(path) = pending_file() else {
+        return Vec::new();
+    };
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            error!(?e, "corrupted pending downloads file");
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn store_pending(jobs: &Jobs) {
+    let Some(path) = pending_file() else {
+        return;
+    };
+    let result = (|| {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(&path, serde_json::to_vec(&jobs.pending())?)
+    })();
+    if let Err(e) = result {
+        error!(?e, "failed to store pending downloads");
+    }
+}
+
+async fn run_job(id: JobId, link: VideoLink, dl_dir: PathBuf, wake: mpsc::UnboundedSender<()>) {
+    info!(?link, id, "starting download job");
+    let result = downloaded::download_with_progress(
+        dl_dir.clone(),
+        &link,
+        CONFIG.download_format == DownloadFormat::Audio,
+        &CONFIG.download,
+        |progress| {
+            JOBS.lock()
+                .unwrap()
+                .set_state(id, JobState::Downloading(Some(progress)))
+        },
+    )
+    .await;
+    let state = match result {
+        Ok(_) => {
+            info!(?link, "downloaded");
+            JobState::Done
+        }
+        Err(e) => {
+            let playlist = Playlist::load().await;
+            let song = playlist.as_ref().ok().map(|pl| {
+                pl.find_by_link(&link)
+                    .map(|s| s.name.as_str())
+                    .unwrap_or(link.as_str())
+            });
+            error!(?e, ?song, "error downloading link");
+            JobState::Failed(e.to_string().lines().last().unwrap_or_default().into())
+        }
+    };
+    let drained = {
+        let mut jobs = JOBS.lock().unwrap();
+        jobs.set_state(id, state);
+        jobs.running.remove(&id);
+        store_pending(&jobs);
+        jobs.pending().is_empty()
+    };
+    if drained {
+        if let Some(max_bytes) = CONFIG.cache_max_bytes {
+            if let Err(e) = budget::enforce_budget(&dl_dir, max_bytes).await {
+                error!(?e, "failed to enforce the cache budget");
+            }
+        }
+    }
+    let _ = wake.send(());
+}
+
+/// Starts queued jobs until there are as many running as allowed.
+fn schedule(dl_dir: &Path, wake: &mpsc::UnboundedSender<()>) {
+    let mut jobs = JOBS.lock().unwrap();
+    while jobs.running.len() < CONFIG.download_jobs().get() {
+        let Some(job) = jobs.next_queued() else {
+            break;
+        };
+        let (id, link) = (job.id, job.link.clone());
+        jobs.set_state(id, JobState::Downloading(None));
+        let task = tokio::spawn(run_job(id, link, dl_dir.to_owned(), wake.clone()));
+        jobs.running.insert(id, task.abort_handle());
+    }
+}
+
+const ARG_0: &str = "into-the-m-verse";
+
+pub static DAEMON: Daemon<Message, Response> = Daemon::new(ARG_0)
+    .versioned(env!("CARGO_PKG_VERSION"))
+    .protocol(1);
+
+#[tracing::instrument(name = "download-daemon", skip(bootstrapped))]
+pub async fn start_daemon<B>(bootstrapped: impl FnOnce(B)) -> anyhow::Result<()>
+where
+    B: DeserializeOwned + Default,
+{
+    let builder = match DAEMON.build_daemon_process().await {
+        None => return Ok(()),
+        Some(b) => b,
+    };
+    bootstrapped(builder.bootstrap()?.unwrap_or_default());
+
+    let dl_dir = crate::util::dl_dir().await?;
+    let (wake_send, mut wake) = mpsc::unbounded_channel();
+    let pending = load_pending().await;
+    {
+        let mut jobs = JOBS.lock().unwrap();
+        for link in pending {
+            jobs.queue(link);
+        }
+    }
+    let _ = wake_send.send(());
+    tokio::spawn(sync_follows());
+
+    let (shutdown_send, shutdown_recv) = oneshot::channel();
+
+    tokio::spawn({
+        let wake_send = wake_send.clone();
+        async move {
+            loop {
+                match timeout(Duration::from_secs(60), wake.recv()).await {
+                    Ok(Some(())) => schedule(&dl_dir, &wake_send),
+                    Err(_) if SYNCING.load(Ordering::SeqCst) => continue,
+                    Err(_) if !JOBS.lock().unwrap().pending().is_empty() => continue,
+                    Ok(None) | Err(_) => break,
+                }
+            }
+            let _ = shutdown_send.send(());
+        }
+    });
+
+    let never = builder
+        .with_shutdown(shutdown_recv)
+        .run(move |message| {
+            let wake = wake_send.clone();
+            async move {
+                let mut jobs = JOBS.lock().unwrap();
+                let response = match message {
+                    Message::Queue(l) => Response::Queued(jobs.queue(l)),
+                    Message::Cancel(id) => Response::Cancelled(jobs.cancel(id)),
+                    Message::List => return Response::Jobs(jobs.jobs.values().cloned().collect()),
+                };
+                store_pending(&jobs);
+                let _ = wake.send(());
+                response
+            }
+        })
+        .await?;
+
+    match never {}
+}
@@ -0,0 +1,476 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{self, Write},
+    num::NonZeroUsize,
+    path::Path,
+};
+
+use crate::{
+    config::{DownloadFormat, CONFIG},
+    util::session_kind::SessionKind,
+};
+
+use self::daemon::{JobState, Message, Response, DAEMON};
+use anyhow::Context;
+use crossterm::{
+    cursor::MoveToPreviousLine,
+    terminal::{self, Clear, ClearType},
+    tty::IsTty,
+    QueueableCommand,
+};
+use futures_util::StreamExt;
+use itertools::Itertools;
+use mlib::{
+    downloaded::{
+        budget, is_in_cache,
+        manager::{DownloadManager, Event},
+        manifest::{self, Manifest},
+        CheckCacheDecision, Progress,
+    },
+    item::{link::VideoLink, title_cache},
+    players,
+    playlist::{Playlist, PlaylistIds},
+    queue::Queue,
+    Item, Link,
+};
+use tokio::process::Command;
+
+mod daemon;
+
+pub use daemon::JobId;
+
+pub async fn daemon_status() -> anyhow::Result<()> {
+    let Response::Jobs(jobs) = DAEMON.exchange(Message::List).await? else {
+        anyhow::bail!("daemon should have given me the jobs");
+    };
+    if jobs.is_empty() {
+        crate::notify!("No downloads");
+        return Ok(());
+    }
+    let pending = jobs
+        .iter()
+        .filter(|j| matches!(j.state, JobState::Queued | JobState::Downloading(_)))
+        .count();
+    crate::notify!(
+        "Downloads: {pending} pending";
+        content: "{}",
+            jobs.iter()
+                .map(|j| format!("{:>3} {}  {}", j.id, j.link, j.state))
+                .format("\n")
+    );
+    Ok(())
+}
+
+/// The last `lines` lines the downloads daemon logged.
+pub async fn daemon_log_tail(lines: usize) -> io::Result<Vec<String>> {
+    DAEMON.log_tail(lines).await
+}
+
+/// Makes the downloads daemon exit, abandoning the downloads it has queued.
+pub async fn stop_daemon() -> io::Result<()> {
+    DAEMON.shutdown().await
+}
+
+pub async fn check_cache_ref(path: &Path, item: &mut Item) {
+    match mlib::downloaded::check_cache_ref(path, item).await {
+        CheckCacheDecision::Skip => {}
+        CheckCacheDecision::Download(l) => match DAEMON.exchange(Message::Queue(l)).await {
+            Ok(Response::Queued(_)) => {}
+            Ok(r) => panic!("server should have queued the download, instead: {r:?}"),
+            Err(e) => crate::error!("failed to start myself: {:?}", e),
+        },
+    }
+}
+
+/// Cancels jobs of the download daemon, a job that is downloading is stopped and resumed if
+/// queued again.
+pub async fn cancel_downloads(ids: Vec<JobId>) -> anyhow::Result<()> {
+    for id in ids {
+        match DAEMON.exchange(Message::Cancel(id)).await? {
+            Response::Cancelled(true) => crate::notify!("Cancelled download {id}"),
+            Response::Cancelled(false) => crate::error!("No pending download with id {id}"),
+            r => anyhow::bail!("daemon should have cancelled the download, instead: {r:?}"),
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the least recently played songs until the download dir is under `cache_max_bytes`.
+pub async fn enforce_budget() -> anyhow::Result<()> {
+    let Some(max_bytes) = CONFIG.cache_max_bytes else {
+        anyhow::bail!("there is no cache budget, set cache_max_bytes in the config");
+    };
+    let evicted = budget::enforce_budget(&crate::dl_dir().await?, max_bytes)
+        .await
+        .context("enforcing the cache budget")?;
+    if evicted.is_empty() {
+        crate::notify!("The download dir is within budget");
+    } else {
+        crate::notify!(
+            "Deleted {} files, freeing {} MB", evicted.len(), evicted.iter().map(|f| f.size).sum::<u64>() / 1_000_000;
+            content: "{}", evicted.iter().map(|f| f.path.display()).format("\n")
+        );
+    }
+    Ok(())
+}
+
+pub async fn cache_status() -> anyhow::Result<()> {
+    let dl_dir = crate::dl_dir().await?;
+    let dl_dir = &dl_dir;
+    let (cached, not) = Playlist::stream()
+        .await?
+        .filter_map(|r| async { r.ok() })
+        .fold((vec![], vec![]), |(mut cached, mut not), s| async move {
+            if is_in_cache(dl_dir, &s.link).await {
+                cached.push(s.name);
+            } else {
+                not.push(s.name);
+            }
+
+            (cached, not)
+        })
+        .await;
+    crate::notify!("Cache status";
+        content:
+            "   Cached: {}\nNot Cached: {}\nMissing:\n  {}",
+            cached.len(),
+            not.len(),
+            not.iter().format("\n  ")
+    );
+    Ok(())
+}
+
+pub async fn manifest() -> anyhow::Result<()> {
+    let dl_dir = crate::dl_dir().await?;
+    let manifest = Manifest::generate(&dl_dir)
+        .await
+        .context("hashing downloaded songs")?;
+    manifest
+        .save(&dl_dir.join(manifest::FILE_NAME))
+        .await
+        .context("saving manifest")?;
+    print!("{manifest}");
+    Ok(())
+}
+
+pub async fn fetch_missing(from: &str) -> anyhow::Result<()> {
+    let dl_dir = crate::dl_dir().await?;
+    let from = from.trim_end_matches('/');
+
+    let remote_manifest = tempfile::NamedTempFile::new()?;
+    let status = Command::new("rsync")
+        .arg(format!("{from}/{}", manifest::FILE_NAME))
+        .arg(remote_manifest.path())
+        .status()
+        .await?;
+    if !status.success() {
+        anyhow::bail!(
+            "failed to fetch the manifest from {from}, did you run `m cache manifest` there?"
+        );
+    }
+    let remote_manifest = Manifest::load(remote_manifest.path()).await?;
+    let missing = remote_manifest.missing_in(&dl_dir).await?;
+    if missing.is_empty() {
+        crate::notify!("Nothing to fetch");
+        return Ok(());
+    }
+
+    let mut files_from = tempfile::NamedTempFile::new()?;
+    for e in &missing {
+        writeln!(files_from, "{}", e.file_name)?;
+    }
+    files_from.flush()?;
+    crate::notify!("Fetching {} songs from {from}", missing.len());
+    let status = Command::new("rsync")
+        .arg("--partial")
+        .arg("--files-from")
+        .arg(files_from.path())
+        .arg(format!("{from}/"))
+        .arg(&dl_dir)
+        .status()
+        .await?;
+    if !status.success() {
+        crate::error!("rsync exited with {status}"; content: "verifying what was transfered");
+    }
+
+    let mut failed = vec![];
+    for e in &missing {
+        match e.verify(&dl_dir).await {
+            Ok(true) => {}
+            Ok(false) => {
+                if let Err(e) = tokio::fs::remove_file(dl_dir.join(&e.file_name)).await {
+                    tracing::error!(error = ?e, "failed to delete corrupted file");
+                }
+                failed.push(e.file_name.as_str());
+            }
+            Err(_) => failed.push(e.file_name.as_str()),
+        }
+    }
+    if failed.is_empty() {
+        crate::notify!("Fetched {} songs", missing.len());
+    } else {
+        crate::error!(
+            "{} of {} songs failed to transfer", failed.len(), missing.len();
+            content: "{}", failed.iter().format("\n")
+        );
+    }
+    Ok(())
+}
+
+/// Live view of `m download`: a line per running download with a summary below them. Finished
+/// songs are printed above and scroll away.
+#[derive(Default)]
+struct ProgressView {
+    running: BTreeMap<usize, Option<Progress>>,
+    finished: usize,
+    failed: usize,
+    drawn: u16,
+}
+
+impl ProgressView {
+    fn progress_line(link: &VideoLink, progress: Option<&Progress>, width: usize) -> String {
+        const BAR: usize = 20;
+        let line = match progress {
+            Some(p) => {
+                let filled = ((p.percent / 100. * BAR as f32) as usize).min(BAR);
+                format!(
+                    "{:>5.1}% [{}{}] {:>10} {:>12} ETA {:>8}  {link}",
+                    p.percent,
+                    "#".repeat(filled),
+                    " ".repeat(BAR - filled),
+                    p.size.as_deref().unwrap_or("?"),
+                    p.speed.as_deref().unwrap_or("?"),
+                    p.eta.as_deref().unwrap_or("?"),
+                )
+            }
+            None => format!("starting {link}"),
+        };
+        line.chars().take(width.saturating_sub(1)).collect()
+    }
+
+    fn redraw(&mut self, links: &[VideoLink], finished_line: Option<String>) -> io::Result<()> {
+        let mut out = io::stdout().lock();
+        if self.drawn > 0 {
+            out.queue(MoveToPreviousLine(self.drawn))?;
+        }
+        out.queue(Clear(ClearType::FromCursorDown))?;
+        if let Some(line) = finished_line {
+            writeln!(out, "{line}")?;
+        }
+        let width = terminal::size().map_or(80, |(w, _)| w as usize);
+        for (i, progress) in &self.running {
+            writeln!(
+                out,
+                "{}",
+                Self::progress_line(&links[*i], progress.as_ref(), width)
+            )?;
+        }
+        writeln!(
+            out,
+            "[{}/{}] {} downloading, {} failed",
+            self.finished,
+            links.len(),
+            self.running.len(),
+            self.failed
+        )?;
+        self.drawn = self.running.len() as u16 + 1;
+        out.flush()
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        let mut out = io::stdout().lock();
+        if self.drawn > 0 {
+            out.queue(MoveToPreviousLine(self.drawn))?;
+        }
+        out.queue(Clear(ClearType::FromCursorDown))?;
+        self.drawn = 0;
+        out.flush()
+    }
+}
+
+/// The last line of an error, which for youtube-dl failures is usually the useful one.
+fn last_line(error: &mlib::Error) -> String {
+    let error = error.to_string();
+    error
+        .lines()
+        .rev()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or(&error)
+        .to_string()
+}
+
+/// The videos of the songs in the queue of the chosen player, or just of the current one.
+/// Downloaded songs are played from their file, so they are mapped back to their video.
+pub async fn queued_songs(whole_queue: bool) -> anyhow::Result<Vec<Item>> {
+    let player = crate::chosen_index();
+    let items = if whole_queue {
+        Queue::load_full(&player)
+            .await
+            .context("failed getting queue")?
+            .iter()
+            .map(|s| s.item.clone())
+            .collect()
+    } else {
+        vec![Queue::link(&player)
+            .await
+            .context("failed getting the current song")?]
+    };
+    Ok(items
+        .into_iter()
+        .filter_map(|item| match item.id() {
+            Some(id) => Some(Item::Link(Link::from_video_id(id))),
+            None => {
+                tracing::warn!(%item, "not a youtube video, can't download it");
+                None
+            }
+        })
+        .collect())
+}
+
+/// Downloads the songs that aren't cached yet, `jobs` at a time. In the `background` they are
+/// queued in the download daemon instead, see `m status downloads`.
+pub async fn download(
+    items: Vec<Item>,
+    jobs: NonZeroUsize,
+    background: bool,
+) -> anyhow::Result<()> {
+    let dl_dir = crate::dl_dir().await?;
+    let mut links = Vec::new();
+    let mut cached = 0;
+    for i in items {
+        match i {
+            Item::Link(Link::Video(l)) => {
+                if is_in_cache(&dl_dir, &l).await {
+                    cached += 1;
+                } else {
+                    links.push(l);
+                }
+            }
+            Item::Link(Link::Playlist(link)) => {
+                tracing::warn!(?link, "donwloading playlists is not supported")
+            }
+            Item::Link(Link::Channel(link)) => {
+                tracing::warn!(?link, "donwloading channels is not supported")
+            }
+            Item::Link(Link::OtherPlatform(link)) => {
+                tracing::warn!(?link, "donwloading from other platforms is not supported")
+            }
+            Item::File(_) | Item::Search(_) => {}
+        }
+    }
+    if links.is_empty() {
+        crate::notify!("Nothing to download"; content: "{cached} songs already downloaded");
+        return Ok(());
+    }
+    if background {
+        let mut ids = Vec::new();
+        for l in links {
+            match DAEMON.exchange(Message::Queue(l)).await? {
+                Response::Queued(id) => ids.push(id),
+                r => anyhow::bail!("daemon should have queued the download, instead: {r:?}"),
+            }
+        }
+        crate::notify!(
+            "Queued {} downloads", ids.len();
+            content: "ids: {}", ids.iter().format(", ")
+        );
+        return Ok(());
+    }
+
+    let live = io::stdout().is_tty() && SessionKind::current().await == SessionKind::Cli;
+    let total = links.len();
+    let mut events = DownloadManager::new(
+        dl_dir,
+        CONFIG.download_format == DownloadFormat::Audio,
+        CONFIG.download.clone(),
+        jobs,
+    )
+    .run(links.clone());
+    let mut view = ProgressView::default();
+    while let Some(event) = events.recv().await {
+        match event {
+            Event::Started(i) => {
+                view.running.insert(i, None);
+                if live {
+                    view.redraw(&links, None)?;
+                } else {
+                    crate::notify!("[{}/{total}] downloading {}", i + 1, links[i]);
+                }
+            }
+            Event::Progress(i, progress) => {
+                if let Some(p) = view.running.get_mut(&i) {
+                    *p = Some(progress);
+                }
+                if live {
+                    view.redraw(&links, None)?;
+                }
+            }
+            Event::Finished(i, result) => {
+                view.running.remove(&i);
+                view.finished += 1;
+                let line = match result {
+                    Ok(()) => format!("downloaded {}", links[i]),
+                    Err(e) => {
+                        view.failed += 1;
+                        if !live {
+                            crate::error!("failed to download {}", links[i]; content: "{}", last_line(&e));
+                        }
+                        format!("failed to download {}: {}", links[i], last_line(&e))
+                    }
+                };
+                if live {
+                    view.redraw(&links, Some(line))?;
+                }
+            }
+            Event::Done(summary) => {
+                if live {
+                    view.clear()?;
+                }
+                if summary.failed == 0 {
+                    crate::notify!(
+                        "Downloaded {} songs", summary.downloaded;
+                        content: "{cached} were already downloaded"
+                    );
+                } else {
+                    crate::error!(
+                        "{} of {total} downloads failed", summary.failed;
+                        content: "downloaded {} songs, {cached} were already downloaded",
+                            summary.downloaded
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn prune_titles() -> anyhow::Result<()> {
+    let playlist = PlaylistIds::load().await?;
+    let mut queued = HashSet::new();
+    for player in players::all().await? {
+        for item in player.queue().await? {
+            if let Some(id) = item.to_item().id() {
+                queued.insert(id.as_str().to_string());
+            }
+        }
+    }
+    let removed = title_cache::prune(
+        |id| playlist.contains(id) || queued.contains(id),
+        title_cache::PRUNE_AFTER,
+    )
+    .await?;
+    crate::notify!("Removed {removed} cached titles");
+    Ok(())
+}
+
+pub use daemon::start_daemon as start_daemon_if_running_as_daemon;
+
+/// Talk to the download daemon in `namespace`, starting it there if needed.
+pub fn set_daemon_namespace(namespace: String) {
+    DAEMON.set_socket_namespace(namespace)
+}
+
+/// Set what is given to the download daemon if this process has to start it.
+pub fn set_daemon_bootstrap<B: serde::Serialize>(bootstrap: &B) {
+    DAEMON.set_bootstrap(bootstrap)
+}
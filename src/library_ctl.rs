@@ -0,0 +1,46 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use mlib::library::Library;
+
+use crate::{config::CONFIG, notify};
+
+fn music_dir() -> anyhow::Result<&'static Path> {
+    CONFIG
+        .music_dir
+        .as_deref()
+        .context("set music_dir in the config to use the library")
+}
+
+pub async fn index() -> anyhow::Result<()> {
+    let dir = music_dir()?;
+    notify!("indexing {}...", dir.display());
+    let library = Library::index(dir)
+        .await
+        .with_context(|| format!("indexing {}", dir.display()))?;
+    notify!("{} songs in the library", library.tracks.len());
+    Ok(())
+}
+
+pub async fn search(words: Vec<String>) -> anyhow::Result<()> {
+    let library = Library::load(music_dir()?).await?;
+    let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+    for track in library.search(&words) {
+        match &track.artist {
+            Some(artist) => println!("{artist} - {}", track.name()),
+            None => println!("{}", track.name()),
+        }
+        println!("    {}", track.path.display());
+    }
+    Ok(())
+}
+
+/// The song in the library that best matches `words`, if a library is configured.
+pub async fn find(words: &[String]) -> anyhow::Result<Option<PathBuf>> {
+    let Some(dir) = &CONFIG.music_dir else {
+        return Ok(None);
+    };
+    let library = Library::load(dir).await.context("loading library index")?;
+    let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+    Ok(library.find(&words).map(|t| t.path.clone()))
+}
@@ -1,35 +1,48 @@
 mod arg_parse;
 mod config;
+mod daemon_ctl;
 mod download_ctl;
+mod library_ctl;
 mod player_ctl;
 mod playlist_ctl;
 mod queue_ctl;
+mod stats_ctl;
 mod util;
 
-use arg_parse::{Args, Command, DeleteSong, EntityStatus, New};
+use arg_parse::{
+    Args, CacheCmd, CatCmd, Command, DaemonCmd, DaemonKind, DeleteSong, EntityStatus, FailedCmd,
+    FollowCmd, LibraryCmd, New, NextFile, PlaylistCmd, StatsCmd, TitlesCmd,
+};
 use clap::{CommandFactory, Parser};
-use futures_util::{future::ready, FutureExt, StreamExt, TryFutureExt, TryStreamExt};
+use futures_util::{FutureExt, StreamExt, TryStreamExt};
 use itertools::Itertools;
 use mlib::{
     downloaded::{self, clean_downloads},
-    item::link::VideoLink,
+    item::{link::VideoLink, SearchProvider},
     players::{self, PlayerIndex, PlayerLink},
-    playlist::{PartialSearchResult, Playlist, PlaylistIds},
+    playlist::{query::Query, PartialSearchResult, Playlist, PlaylistIds, Song},
     queue::Item,
     ytdl::YtdlBuilder,
     Link, Search,
 };
 use rand::seq::SliceRandom;
-use std::{process::ExitCode, sync::Mutex};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::IsTerminal,
+    path::PathBuf,
+    process::ExitCode,
+    sync::{Mutex, OnceLock},
+};
 use tokio::io;
 use tracing::dispatcher::set_global_default;
 use tracing_log::LogTracer;
-use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter, Registry};
+use tracing_subscriber::{fmt, layer::SubscriberExt, reload, EnvFilter, Registry};
 use util::session_kind::SessionKind;
 
 use crate::{
     arg_parse::{AddPlaylist, Queue},
-    config::DownloadFormat,
+    playlist_ctl::ConflictPolicy,
     util::{dl_dir, selector, with_video::with_video_env},
 };
 
@@ -51,8 +64,33 @@ async fn process_cmd(cmd: Command) -> anyhow::Result<()> {
                 }
             }
         }
-        Command::Songs { category } => playlist_ctl::songs(category).await?,
-        Command::Cat => playlist_ctl::cat().await?,
+        Command::Stats {
+            cmd: Some(StatsCmd::Lock),
+            ..
+        } => stats_ctl::lock().await?,
+        Command::Stats {
+            cmd: Some(StatsCmd::Unlock),
+            ..
+        } => stats_ctl::unlock().await?,
+        Command::Stats {
+            cmd: None,
+            since,
+            until,
+            top,
+            per,
+            json,
+        } => stats_ctl::stats(since, until, top, per, json).await?,
+        Command::Songs {
+            category,
+            playlist,
+            sort,
+        } => playlist_ctl::songs(category, playlist, sort).await?,
+        Command::Cat { cmd } => match cmd {
+            None => playlist_ctl::cat().await?,
+            Some(CatCmd::Add { category, songs }) => playlist_ctl::cat_add(category, songs).await?,
+            Some(CatCmd::Rename { old, new }) => playlist_ctl::cat_rename(old, new).await?,
+            Some(CatCmd::Remove { category }) => playlist_ctl::cat_remove(category).await?,
+        },
         Command::Quit => player_ctl::quit().await?,
         Command::SetPlay => player_ctl::resume().await?,
         Command::SetPause => player_ctl::pause().await?,
@@ -60,17 +98,48 @@ async fn process_cmd(cmd: Command) -> anyhow::Result<()> {
         Command::Vu(a) => player_ctl::vu(a).await?,
         Command::Vd(a) => player_ctl::vd(a).await?,
         Command::ToggleVideo => player_ctl::toggle_video().await?,
-        Command::NextFile(a) => player_ctl::next_file(a).await?,
+        Command::NextFile(NextFile {
+            until: Some(query), ..
+        }) => player_ctl::next_file_until(&query).await?,
+        Command::NextFile(NextFile { amount, .. }) => player_ctl::next_file(amount).await?,
         Command::PrevFile(a) => player_ctl::prev_file(a).await?,
+        Command::Jump { pos } => player_ctl::jump(pos).await?,
+        Command::BackTrack => player_ctl::history(players::Direction::Prev).await?,
+        Command::FwdTrack => player_ctl::history(players::Direction::Next).await?,
         Command::Frwd(a) => player_ctl::frwd(a).await?,
         Command::Back(a) => player_ctl::back(a).await?,
         Command::Next(a) => player_ctl::next(a).await?,
         Command::Prev(a) => player_ctl::prev(a).await?,
-        Command::Shuffle => player_ctl::shuffle().await?,
+        Command::Shuffle { smart } => player_ctl::shuffle(smart).await?,
         Command::Loop => player_ctl::toggle_loop().await?,
+        Command::Sleep { when, stop } => player_ctl::sleep(when, stop).await?,
+        Command::Fade { state } => player_ctl::fade(state).await?,
+        Command::Sponsorblock { state } => player_ctl::sponsorblock(state).await?,
+        Command::Focus {
+            state,
+            max,
+            categories,
+        } => player_ctl::focus(state, max, categories).await?,
+        Command::Radio { stop: true, .. } => player_ctl::stop_radio().await?,
+        Command::Radio {
+            stop: false,
+            category,
+            threshold,
+            song,
+        } => player_ctl::start_radio(category, song, threshold).await?,
+        Command::Equalizer { preset_or_bands } => player_ctl::equalizer(preset_or_bands).await?,
+        Command::Property(cmd) => player_ctl::property(cmd).await?,
+        Command::EndOfQueue { policy } => player_ctl::end_of_queue(policy).await?,
+        Command::AutoplayOff => {
+            player_ctl::end_of_queue(Some(players::EndOfQueuePolicy::Stop)).await?
+        }
+        Command::Precache { policy } => player_ctl::precache(policy).await?,
         Command::New(New {
             search,
             queue,
+            playlist,
+            name,
+            force,
             query: link,
             categories,
         }) => {
@@ -113,37 +182,36 @@ async fn process_cmd(cmd: Command) -> anyhow::Result<()> {
                     .map_err(|link| anyhow::anyhow!("{} is not a valid link", link))?
                     .into()
             };
-            let link = playlist_ctl::new(link, categories).await?;
+            let link = playlist_ctl::new(link, name, categories, playlist, force).await?;
             if queue {
                 queue_ctl::queue(Default::default(), Some(Item::Link(link.into()))).await?;
             }
         }
         Command::AddPlaylist(AddPlaylist {
             queue,
+            merge_categories,
+            skip_existing,
+            ask,
+            force,
             link,
             categories,
         }) => {
             let link =
                 Link::try_from(link).map_err(|s| anyhow::anyhow!("{} is not a valid link", s))?;
-            let links = playlist_ctl::add_playlist(&link, categories).await?;
-            if queue {
-                links
-                    .for_each(|r| async move {
-                        let r = ready(r)
-                            .and_then(|link| {
-                                queue_ctl::queue(Default::default(), Some(Item::Link(link.into())))
-                            })
-                            .await;
-                        if let Err(e) = r {
-                            tracing::error!("failed adding item to playlist: {:?}", e)
-                        }
-                    })
-                    .await;
+            let policy = if merge_categories {
+                ConflictPolicy::Merge
+            } else if skip_existing {
+                ConflictPolicy::Skip
+            } else if ask {
+                ConflictPolicy::Ask
+            } else if force {
+                ConflictPolicy::Force
             } else {
-                links.for_each(|_| ready(())).await;
-            }
+                ConflictPolicy::Skip
+            };
+            playlist_ctl::add_playlist(&link, categories, policy, queue).await?;
         }
-        Command::Current { link, notify } => {
+        Command::Current { link, notify, next } => {
             queue_ctl::current(
                 match link {
                     0 => queue_ctl::CurrentDisplayMode::Default,
@@ -151,11 +219,13 @@ async fn process_cmd(cmd: Command) -> anyhow::Result<()> {
                     _ => queue_ctl::CurrentDisplayMode::LinkId,
                 },
                 notify,
+                next,
             )
             .await?
         }
         Command::Now(a) => queue_ctl::now(a).await?,
-        Command::CleanDownloads => {
+        Command::Eta { index } => queue_ctl::eta(index).await?,
+        Command::CleanDownloads { enforce_budget } => {
             let ids = PlaylistIds::load().await?;
             let to_delete = clean_downloads(dl_dir().await?, &ids).await?;
             tokio::pin!(to_delete);
@@ -173,22 +243,62 @@ async fn process_cmd(cmd: Command) -> anyhow::Result<()> {
                     }
                 }
             }
+            if enforce_budget {
+                download_ctl::enforce_budget().await?;
+            }
         }
-        Command::Dump { file } => queue_ctl::dump(file).await?,
+        Command::Doctor { offline, fix_dead } => playlist_ctl::doctor(offline, fix_dead).await?,
+        Command::Dump { file, format } => queue_ctl::dump(file, format).await?,
         Command::Load { file, shuf } => queue_ctl::load(file, shuf).await?,
+        Command::SaveQueue { name } => queue_ctl::save_queue(name).await?,
+        Command::Queues { cmd } => queue_ctl::queues(cmd).await?,
         Command::Play(arg_parse::Play {
             search,
+            provider,
             what,
             category,
+            interleave,
+            smart,
+            playlist,
+            from_clipboard,
             video,
+            resume,
+            profile,
+            name,
+            mut mpv_options,
+            quality,
         }) => {
-            queue_ctl::play(
-                search_params_to_items(what, search, category).await?,
-                video || with_video_env(),
-            )
-            .await?;
+            let profile = profile
+                .map(|name| config::CONFIG.profile(&name))
+                .transpose()?;
+            let items = if from_clipboard {
+                vec![queue_ctl::clipboard_item()?]
+            } else {
+                search_params_to_items(
+                    what,
+                    search.then_some(provider),
+                    category,
+                    Mix::new(interleave),
+                    smart,
+                    playlist,
+                )
+                .await?
+            };
+            let with_video = video || with_video_env() || profile.is_some_and(|p| p.video);
+            let volume_step = profile.and_then(|p| p.volume_step);
+            let profile = profile.map(|p| p.player.clone());
+            if let Some(quality) = quality {
+                mpv_options.push(("ytdl-format".into(), quality.ytdl_format()));
+            }
+            let player = queue_ctl::play(items, with_video, profile, mpv_options, name).await?;
+            if let Some(step) = volume_step {
+                player_ctl::set_volume_step(&player, step.0).await?;
+            }
+            if resume && !config::CONFIG.resume_playback {
+                player.set_resume(true).await?;
+            }
         }
-        Command::ChCat => playlist_ctl::ch_cat().await?,
+        Command::ChCat { playlist } => playlist_ctl::ch_cat(playlist).await?,
         Command::DeleteSong(DeleteSong {
             current,
             partial_name,
@@ -197,34 +307,53 @@ async fn process_cmd(cmd: Command) -> anyhow::Result<()> {
             queue_opts,
             play_opts,
         }) => {
-            let items =
-                search_params_to_items(play_opts.what, play_opts.search, play_opts.category)
-                    .await?;
-            queue_ctl::queue(queue_opts, items).await?;
+            let items = if play_opts.from_clipboard {
+                vec![queue_ctl::clipboard_item()?]
+            } else {
+                search_params_to_items(
+                    play_opts.what,
+                    play_opts.search.then_some(play_opts.provider),
+                    play_opts.category,
+                    Mix::new(play_opts.interleave),
+                    play_opts.smart,
+                    play_opts.playlist,
+                )
+                .await?
+            };
+            if queue_opts.split_chapters {
+                queue_ctl::queue_chapters(queue_opts, items).await?;
+            } else {
+                queue_ctl::queue(queue_opts, items).await?;
+            }
         }
         Command::Dequeue(d) => queue_ctl::dequeue(d).await?,
-        Command::Playlist => queue_ctl::run_interactive_playlist().await?,
-        Command::Status { entity } => match entity {
-            EntityStatus::Players => player_ctl::status().await?,
+        Command::Move(m) => queue_ctl::move_song(m).await?,
+        Command::Swap(s) => queue_ctl::swap(s).await?,
+        Command::Playlist { cmd: None } => queue_ctl::run_interactive_playlist().await?,
+        Command::Playlist {
+            cmd: Some(PlaylistCmd::Edit { playlist }),
+        } => playlist_ctl::edit(playlist).await?,
+        Command::Status {
+            entity,
+            logs: Some(lines),
+            ..
+        } => match entity {
+            EntityStatus::Players => daemon_ctl::logs(DaemonKind::Players, lines).await?,
+            EntityStatus::Downloads => daemon_ctl::logs(DaemonKind::Downloads, lines).await?,
+            EntityStatus::Cache => anyhow::bail!("the cache has no daemon, so no logs"),
+        },
+        Command::Status {
+            entity, verbose, ..
+        } => match entity {
+            EntityStatus::Players => player_ctl::status(verbose).await?,
             EntityStatus::Cache => download_ctl::cache_status().await?,
             EntityStatus::Downloads => download_ctl::daemon_status().await?,
         },
         Command::Interactive => player_ctl::interactive().await?,
-        Command::Lyrics => {
-            dbg!(
-                selector::interative_select(
-                    &["option 1", "option 2"],
-                    [(
-                        'p',
-                        Box::new(|e, _| {
-                            async move { notify!("{}", e; force_notify: true) }.boxed()
-                        })
-                    )]
-                )
-                .await
-            )?;
-        }
-        Command::Info { id, song } => playlist_ctl::info(song, id).await?,
+        Command::Bar { format } => player_ctl::bar(format).await?,
+        Command::Watch { json, only } => player_ctl::watch(json, only).await?,
+        Command::Lyrics { sync } => player_ctl::lyrics(sync).await?,
+        Command::Info { id, current, song } => playlist_ctl::info(song, id, current).await?,
         Command::AutoComplete { shell } => {
             clap_complete::generate(
                 shell,
@@ -233,8 +362,67 @@ async fn process_cmd(cmd: Command) -> anyhow::Result<()> {
                 &mut std::io::stdout().lock(),
             );
         }
-        Command::Download { what, category } => {
-            let items = if what.is_none() && category.is_none() {
+        Command::Daemon(DaemonCmd::Stop { daemon }) => daemon_ctl::stop(daemon).await?,
+        Command::Cache(c) => match c {
+            CacheCmd::Manifest => download_ctl::manifest().await?,
+            CacheCmd::FetchMissing { from } => download_ctl::fetch_missing(&from).await?,
+            CacheCmd::Titles(TitlesCmd::Prune) => download_ctl::prune_titles().await?,
+        },
+        Command::Failed(c) => match c {
+            FailedCmd::List => player_ctl::failed_list().await?,
+            // without `n` clap requires `--all`, so `None` means all of them
+            FailedCmd::Retry { n, all: _, cache } => player_ctl::failed_retry(n, cache).await?,
+        },
+        Command::Follow {
+            cmd: Some(FollowCmd::Sync),
+            ..
+        } => playlist_ctl::sync_follows().await?,
+        Command::Follow {
+            cmd: Some(FollowCmd::Rm { link }),
+            ..
+        } => {
+            playlist_ctl::unfollow(
+                Link::try_from(link).map_err(|s| anyhow::anyhow!("{} is not a valid link", s))?,
+            )
+            .await?
+        }
+        Command::Follow {
+            cmd: None,
+            link: Some(link),
+            categories,
+        } => {
+            playlist_ctl::follow(
+                Link::try_from(link).map_err(|s| anyhow::anyhow!("{} is not a valid link", s))?,
+                categories,
+            )
+            .await?
+        }
+        Command::Follow {
+            cmd: None,
+            link: None,
+            ..
+        } => playlist_ctl::follows().await?,
+        Command::Library(c) => match c {
+            LibraryCmd::Index => library_ctl::index().await?,
+            LibraryCmd::Search { words } => library_ctl::search(words).await?,
+        },
+        Command::Download {
+            what,
+            category,
+            smart,
+            jobs,
+            bg,
+            cancel,
+            current,
+            queue,
+        } => {
+            if !cancel.is_empty() {
+                download_ctl::cancel_downloads(cancel).await?;
+                return Ok(());
+            }
+            let items = if current || queue {
+                download_ctl::queued_songs(queue).await?
+            } else if what.is_none() && category.is_none() && smart.is_none() {
                 Playlist::load()
                     .await?
                     .songs
@@ -242,50 +430,20 @@ async fn process_cmd(cmd: Command) -> anyhow::Result<()> {
                     .map(|i| Item::Link(i.link.into()))
                     .collect()
             } else {
-                search_params_to_items(what.unwrap_or_default(), false, category).await?
+                search_params_to_items(
+                    what.unwrap_or_default(),
+                    None,
+                    category.into_iter().collect(),
+                    Mix::Shuffle,
+                    smart,
+                    None,
+                )
+                .await?
             };
-            let dl_dir = dl_dir().await?;
-            let total = items.len();
-            for (idx, i) in items.into_iter().enumerate() {
-                match i {
-                    Item::Link(l) => match l {
-                        Link::Video(l) => {
-                            if !downloaded::is_in_cache(&dl_dir, &l).await {
-                                notify!("[{idx}/{total}] downloading {l}");
-                                if let Err(e) = downloaded::download(
-                                    dl_dir.clone(),
-                                    &l,
-                                    config::CONFIG.download_format == DownloadFormat::Audio,
-                                )
-                                .await
-                                {
-                                    tracing::error!(?e, "failed to download {l}");
-                                }
-                            }
-                        }
-                        Link::Playlist(link) => {
-                            tracing::warn!(?link, "donwloading playlists is not supported")
-                        }
-                        Link::Channel(link) => {
-                            tracing::warn!(?link, "donwloading channels is not supported")
-                        }
-                        Link::OtherPlatform(link) => {
-                            tracing::warn!(
-                                ?link,
-                                "donwloading from other platforms is not supported"
-                            )
-                        }
-                    },
-                    Item::File(_) => {}
-                    Item::Search(_) => {}
-                }
-            }
+            download_ctl::download(items, jobs.unwrap_or(config::CONFIG.download_jobs()), bg)
+                .await?
         }
     }
-    tracing::debug!("updating bar");
-    // TODO: move this somewhere that only runs when actual updates happen
-    util::update_bar().await?;
-
     Ok(())
 }
 
@@ -295,8 +453,20 @@ pub fn chosen_index() -> PlayerLink {
 }
 
 async fn run() -> anyhow::Result<()> {
-    download_ctl::start_daemon_if_running_as_daemon().await?;
-    players::start_daemon_if_running_as_daemon().await?;
+    mlib::ytdl::init(config::CONFIG.ytdl());
+    if let Some(path) = &config::CONFIG.playlist_path {
+        mlib::playlist::override_playlist_path(path.clone());
+    }
+    mlib::playlist::include_playlists(config::CONFIG.include_playlists.clone());
+    download_ctl::start_daemon_if_running_as_daemon(DaemonBootstrap::apply).await?;
+    if let Some(transport) = &config::CONFIG.players_transport {
+        players::set_daemon_transport(transport.clone());
+    }
+    players::start_daemon_if_running_as_daemon(
+        config::CONFIG.daemon_settings(),
+        DaemonBootstrap::apply,
+    )
+    .await?;
 
     let args = match Args::try_parse() {
         Ok(args) => args,
@@ -308,16 +478,53 @@ async fn run() -> anyhow::Result<()> {
             }
         }
     };
+    if let Some(filter) = &args.log {
+        set_log_filter(filter);
+    }
+    let bootstrap = DaemonBootstrap {
+        log_filter: args
+            .log
+            .clone()
+            .or_else(|| std::env::var(EnvFilter::DEFAULT_ENV).ok()),
+    };
+    players::set_daemon_bootstrap(&bootstrap);
+    download_ctl::set_daemon_bootstrap(&bootstrap);
+    if let Some(namespace) = args
+        .namespace
+        .clone()
+        .or_else(|| std::env::var("M_NAMESPACE").ok())
+    {
+        if let Some(players::DaemonTransport::Tcp { address, .. }) =
+            &config::CONFIG.players_transport
+        {
+            anyhow::bail!(
+                "namespaces only separate sockets, the players daemon on {address} is the same in all of them"
+            );
+        }
+        players::set_daemon_namespace(namespace.clone());
+        download_ctl::set_daemon_namespace(namespace);
+    }
+    util::output::set(args.output);
     if let Some(id) = args.socket {
         *CHOSEN_INDEX.lock().unwrap() = PlayerIndex::of(id);
     }
+    if let Some(name) = args.player {
+        let Some(index) = players::resolve_name(name.clone()).await? else {
+            anyhow::bail!("there is no player called {name}");
+        };
+        *CHOSEN_INDEX.lock().unwrap() = index;
+    }
 
     if let Some(new_base) = config::CONFIG.socket_base_dir.as_ref() {
         players::override_legacy_socket_base_dir(new_base.clone());
     }
 
     if let Some(cmd) = args.cmd {
-        process_cmd(cmd).await?;
+        if args.all {
+            player_ctl::for_all(cmd).await?;
+        } else {
+            process_cmd(cmd).await?;
+        }
     } else {
         player_ctl::interactive().await?;
     }
@@ -325,6 +532,8 @@ async fn run() -> anyhow::Result<()> {
     Ok(())
 }
 
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
 pub fn init_logger() {
     LogTracer::init().expect("Failed to set logger");
 
@@ -333,16 +542,58 @@ pub fn init_logger() {
     } else {
         EnvFilter::new("warn")
     };
+    let (env_filter, handle) = reload::Layer::new(env_filter);
+    let _ = LOG_FILTER.set(handle);
 
-    let fmt = fmt::layer().with_writer(std::io::stderr).pretty();
+    // daemons started by the cli log to a file, see `m status --logs`
+    let fmt = fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .pretty();
 
     let sub = Registry::default().with(env_filter).with(fmt);
 
     set_global_default(sub.into()).expect("Failed to set global default");
 }
 
+/// Replaces the log filter [`init_logger`] got from the environment.
+fn set_log_filter(filter: &str) {
+    let filter = match EnvFilter::try_new(filter) {
+        Ok(f) => f,
+        Err(e) => {
+            tracing::error!(?e, filter, "invalid log filter");
+            return;
+        }
+    };
+    if let Some(Err(e)) = LOG_FILTER.get().map(|h| h.reload(filter)) {
+        tracing::error!(?e, "failed to change the log filter");
+    }
+}
+
+/// What the cli gives to the daemons it starts, since they are started without arguments.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DaemonBootstrap {
+    /// The log filter the cli was using, if one was chosen.
+    log_filter: Option<String>,
+}
+
+impl DaemonBootstrap {
+    fn apply(self) {
+        tracing::debug!(bootstrap = ?self, "bootstrapping daemon");
+        if let Some(filter) = &self.log_filter {
+            set_log_filter(filter);
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    // the environment can only be changed before the runtime starts its threads
+    cli_daemon::take_bootstrap();
+    start()
+}
+
 #[tokio::main]
-async fn main() -> ExitCode {
+async fn start() -> ExitCode {
     init_logger();
     if let Err(e) = run().await {
         let mut chain = e.chain().skip(1).peekable();
@@ -362,6 +613,20 @@ async fn main() -> ExitCode {
     }
 }
 
+/// The downloaded song whose name matches all `words`.
+async fn search_downloads(words: &[String]) -> anyhow::Result<PathBuf> {
+    let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+    let mut found = downloaded::search_by_name(&dl_dir().await?, &words).await?;
+    match found.len() {
+        0 => Err(anyhow::anyhow!("no downloaded song matches")),
+        1 => Ok(found.remove(0)),
+        _ => Err(anyhow::anyhow!(
+            "too many matches:\n  {}",
+            found.iter().map(|f| f.display()).format("\n  ")
+        )),
+    }
+}
+
 fn handle_search_result<T>(r: PartialSearchResult<T>) -> anyhow::Result<T> {
     match r {
         PartialSearchResult::One(t) => Ok(t),
@@ -407,43 +672,122 @@ impl SongQuery {
         }
         Self { items, words }
     }
+
+    /// Replaces the search words with the song in the local library that matches them, if any.
+    pub async fn resolve_locally(&mut self) -> anyhow::Result<()> {
+        if self.words.is_empty() {
+            return Ok(());
+        }
+        if let Some(path) = library_ctl::find(&self.words).await? {
+            tracing::debug!(?path, words = ?self.words, "found in the library");
+            self.items.push(Item::File(path));
+            self.words.clear();
+        }
+        Ok(())
+    }
+}
+
+/// How the songs picked by category or query are ordered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mix {
+    /// All together, shuffled.
+    Shuffle,
+    /// Each category shuffled on its own, then taking one song from each in turn.
+    Interleave,
+}
+
+impl Mix {
+    fn new(interleave: bool) -> Self {
+        if interleave {
+            Self::Interleave
+        } else {
+            Self::Shuffle
+        }
+    }
 }
 
 async fn search_params_to_items(
     what: Vec<String>,
-    search: bool,
-    category: Option<String>,
+    search: Option<SearchProvider>,
+    categories: Vec<String>,
+    mix: Mix,
+    smart: Option<String>,
+    playlist: Option<String>,
 ) -> anyhow::Result<Vec<Item>> {
     tracing::debug!(?what, "parsing query");
 
-    let SongQuery { mut items, words } = SongQuery::new(what).await;
+    let mut query = SongQuery::new(what).await;
+    if search == Some(SearchProvider::YouTube) {
+        query.resolve_locally().await?;
+    }
+    let SongQuery { mut items, words } = query;
 
-    if let Some(cat) = category {
-        let cat = &cat;
-        let cat_items = Playlist::stream()
+    let mut queries = categories
+        .iter()
+        .map(|c| {
+            (
+                Query::Category(config::CONFIG.category(c)),
+                Some(playlist_ctl::category_pattern(c)),
+            )
+        })
+        .collect::<Vec<_>>();
+    if let Some(smart) = smart {
+        queries.push((config::CONFIG.smart_query(&smart)?, None));
+    }
+    if !queries.is_empty() {
+        let songs = playlist_ctl::stream(playlist.as_deref())
             .await?
             .filter_map(|s| async { s.ok() })
-            .filter_map(|s| async move {
-                s.categories
-                    .iter()
-                    .any(|c| c.contains(cat))
-                    .then_some(s.link)
-            })
-            .map(Link::Video)
-            .map(Item::Link)
             .collect::<Vec<_>>()
             .await;
-        items.extend(cat_items);
-        items.shuffle(&mut rand::rngs::OsRng);
+        let last_played =
+            playlist_ctl::last_played(&queries.iter().map(|(q, _)| q).collect::<Vec<_>>()).await?;
+        let matches = |(q, pattern): &(Query, Option<Regex>), s: &Song| {
+            q.matches_with(s, last_played(s))
+                || pattern
+                    .as_ref()
+                    .is_some_and(|p| playlist_ctl::matches_pattern(s, p))
+        };
+        let rng = &mut rand::rngs::OsRng;
+        match mix {
+            Mix::Shuffle => {
+                items.extend(
+                    songs
+                        .into_iter()
+                        .filter(|s| queries.iter().any(|q| matches(q, s)))
+                        .map(|s| Item::Link(Link::Video(s.link))),
+                );
+                items.shuffle(rng);
+            }
+            Mix::Interleave => {
+                let per_query = queries.iter().map(|q| {
+                    let mut links = songs
+                        .iter()
+                        .filter(|s| matches(q, s))
+                        .map(|s| s.link.clone())
+                        .collect::<Vec<_>>();
+                    links.shuffle(rng);
+                    links
+                });
+                items.extend(
+                    util::interleave(per_query.collect())
+                        .into_iter()
+                        .map(|l| Item::Link(Link::Video(l))),
+                );
+            }
+        }
     }
 
     if !words.is_empty() {
-        let link = if search {
-            Item::Search(Search::new(words.join(" ")))
+        let link = if let Some(provider) = search {
+            match Search::on(provider, words.join(" ")) {
+                Some(search) => Item::Search(search),
+                None => Item::File(search_downloads(&words).await?),
+            }
         } else {
             Item::Link(
                 handle_search_result(
-                    Playlist::load()
+                    playlist_ctl::load(playlist.as_deref())
                         .await?
                         .partial_name_search_mut(words.iter().map(String::as_str)),
                 )?
@@ -0,0 +1,154 @@
+use std::{pin::pin, time::Duration};
+
+use futures_util::{Stream, StreamExt};
+use mlib::players::{self, event::OwnedLibMpvEvent, PlayerLink};
+use serde_json::json;
+
+use crate::{arg_parse::BarFormat, util::DurationFmt};
+
+/// How long to wait before trying again when there is no player to follow.
+const RETRY: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+struct Status {
+    title: String,
+    paused: bool,
+    volume: f64,
+    position: Duration,
+    duration: Duration,
+}
+
+impl Status {
+    async fn current() -> Option<Self> {
+        let player = PlayerLink::current();
+        let (title, paused, volume, position, duration) = futures_util::try_join!(
+            player.media_title(),
+            player.is_paused(),
+            player.volume(),
+            player.playback_time(),
+            player.duration(),
+        )
+        .ok()?;
+        Some(Self {
+            title,
+            paused,
+            volume,
+            position: Duration::try_from_secs_f64(position).unwrap_or_default(),
+            duration: Duration::try_from_secs_f64(duration).unwrap_or_default(),
+        })
+    }
+
+    fn icon(&self) -> &'static str {
+        if self.paused {
+            "⏸"
+        } else {
+            "▶"
+        }
+    }
+}
+
+impl BarFormat {
+    fn render(self, status: Option<&Status>) -> String {
+        match (self, status) {
+            (BarFormat::Waybar, Some(s)) => json!({
+                "text": format!("{} {}", s.icon(), s.title),
+                "tooltip": format!(
+                    "{}\n{}/{} 🔉{:.0}%",
+                    s.title,
+                    DurationFmt(s.position),
+                    DurationFmt(s.duration),
+                    s.volume
+                ),
+                "class": if s.paused { "paused" } else { "playing" },
+                "percentage": if s.duration.is_zero() {
+                    0.
+                } else {
+                    (s.position.as_secs_f64() / s.duration.as_secs_f64() * 100.).round()
+                },
+            })
+            .to_string(),
+            (BarFormat::Waybar, None) => json!({ "text": "", "class": "stopped" }).to_string(),
+            (BarFormat::Polybar, Some(s)) => format!(
+                "{} {} {}/{}",
+                s.icon(),
+                s.title,
+                DurationFmt(s.position),
+                DurationFmt(s.duration)
+            ),
+            (BarFormat::Polybar, None) => String::new(),
+        }
+    }
+}
+
+/// Whether the event changes what the bar shows.
+fn relevant(event: &OwnedLibMpvEvent) -> bool {
+    match event {
+        OwnedLibMpvEvent::Seek | OwnedLibMpvEvent::FileLoaded | OwnedLibMpvEvent::Shutdown => true,
+        OwnedLibMpvEvent::PropertyChange { name, .. } => {
+            matches!(
+                name.as_str(),
+                "media-title" | "pause" | "volume" | "playlist-pos"
+            )
+        }
+        _ => false,
+    }
+}
+
+/// Waits for the next relevant event, returns false if the player went away.
+async fn changed<S>(events: &mut S) -> bool
+where
+    S: Stream<Item = std::io::Result<players::event::PlayerEvent>> + Unpin,
+{
+    loop {
+        match events.next().await {
+            Some(Ok(e)) if relevant(&e.event) => return true,
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => {
+                tracing::warn!(error = ?e, "failed to receive a player event");
+                return false;
+            }
+            None => return false,
+        }
+    }
+}
+
+/// Prints a line for a status bar whenever what's playing changes, and every second while
+/// playing so the position moves.
+pub async fn bar(format: BarFormat) -> anyhow::Result<()> {
+    let mut last = None;
+    let mut print = |status: Option<&Status>| {
+        let line = format.render(status);
+        if last.as_ref() != Some(&line) {
+            println!("{line}");
+            last = Some(line);
+        }
+    };
+    loop {
+        match players::subscribe().await {
+            Ok(events) => {
+                let mut events = pin!(events);
+                loop {
+                    let status = Status::current().await;
+                    print(status.as_ref());
+                    let playing = status.is_some_and(|s| !s.paused);
+                    let tick = async {
+                        if playing {
+                            tokio::time::sleep(Duration::from_secs(1)).await
+                        } else {
+                            std::future::pending().await
+                        }
+                    };
+                    tokio::select! {
+                        alive = changed(&mut events) => if !alive {
+                            break;
+                        },
+                        _ = tick => {}
+                    }
+                }
+            }
+            Err(e) => tracing::debug!(error = ?e, "no players to follow"),
+        }
+        print(None);
+        tokio::time::sleep(RETRY).await;
+    }
+}
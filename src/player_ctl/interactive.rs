@@ -6,7 +6,7 @@ use std::{
     time::Duration,
 };
 
-use crate::{player_ctl, util::RawMode};
+use crate::{arg_parse::VolumeStep, player_ctl, util::RawMode};
 use crossterm::{
     cursor::{self, MoveTo},
     event::{self, Event, KeyCode, KeyEvent},
@@ -100,8 +100,8 @@ async fn input_task() {
                     ('p', _) => player_ctl::cycle_pause().await,
                     ('l', Mod::NONE) => player_ctl::next_file(1).await,
                     ('h', Mod::NONE) => player_ctl::prev_file(1).await,
-                    ('j', Mod::NONE) => player_ctl::vd(2).await,
-                    ('k', Mod::NONE) => player_ctl::vu(2).await,
+                    ('j', Mod::NONE) => player_ctl::vd(VolumeStep { amount: None }).await,
+                    ('k', Mod::NONE) => player_ctl::vu(VolumeStep { amount: None }).await,
                     ('h' | 'H', Mod::SHIFT) => player_ctl::prev(1).await,
                     ('l' | 'L', Mod::SHIFT) => player_ctl::next(1).await,
                     ('j' | 'J', Mod::SHIFT) | ('u', Mod::NONE) => player_ctl::back(2).await,
@@ -134,7 +134,7 @@ async fn current_position() -> Option<PlaybackPosition> {
     );
     Some(PlaybackPosition {
         percent_position,
-        playback_time: playback_time.map(Duration::from_secs_f64),
+        playback_time: playback_time.and_then(|t| Duration::try_from_secs_f64(t).ok()),
     })
 }
 
@@ -230,14 +230,16 @@ pub async fn interactive() -> anyhow::Result<()> {
                     } => {
                         current.title = title;
                         current.chapter = None;
-                        current.duration = Duration::from_secs_f64(total_time);
+                        current.duration =
+                            Duration::try_from_secs_f64(total_time).unwrap_or_default();
                         current.next = next;
                     }
                     UiUpdate::Volume(volume) => current.volume = volume,
                     UiUpdate::Pause { is_paused } => current.playing = !is_paused,
                     UiUpdate::ChapterName { title, total_time } => {
                         current.chapter.get_or_insert_with(Default::default).1 = title;
-                        current.duration = Duration::from_secs_f64(total_time);
+                        current.duration =
+                            Duration::try_from_secs_f64(total_time).unwrap_or_default();
                     }
                     UiUpdate::ChapterNumber(index) => {
                         current.chapter.get_or_insert_with(Default::default).0 = index;
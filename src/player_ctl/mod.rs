@@ -1,13 +1,34 @@
+mod bar;
 mod interactive;
+mod watch;
 
+pub use bar::bar;
 pub use interactive::interactive;
+pub use watch::watch;
 
-use super::arg_parse::Amount;
+use super::arg_parse::{Amount, Command, OnOff, PropertyCmd, SleepFor, VolumeStep};
+
+use std::{cmp::Reverse, time::Duration};
 
 use anyhow::Context;
-use mlib::{players, queue::Queue};
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use mlib::{
+    lyrics::{self, Lyrics},
+    players::{self, Broadcast, EndOfQueuePolicy, PlayerIndex, PlayerLink},
+    queue::{Current, Queue},
+    statistics, Item,
+};
+use rand::{rngs::OsRng, Rng};
+use serde::Serialize;
 
-use crate::{chosen_index, notify};
+use crate::{
+    chosen_index,
+    config::CONFIG,
+    download_ctl::check_cache_ref,
+    notify,
+    util::{dl_dir, output, DurationFmt},
+};
 
 pub async fn resume() -> anyhow::Result<()> {
     Ok(chosen_index().resume().await?)
@@ -26,20 +47,84 @@ pub async fn cycle_pause() -> anyhow::Result<()> {
     Ok(chosen_index().cycle_pause().await?)
 }
 
-pub async fn vu<A>(amount: A) -> anyhow::Result<()>
-where
-    A: Into<Amount>,
-{
-    let Amount { amount } = amount.into();
-    Ok(chosen_index().change_volume(amount.unwrap_or(2)).await?)
+/// Where the volume step of a player created with a profile that has one is kept.
+const VOLUME_STEP: &str = "user-data/m/volume-step";
+
+/// Makes `m vu` and `m vd` change the volume of `player` by `step` when not given an amount.
+pub async fn set_volume_step(player: &PlayerLink, step: f64) -> anyhow::Result<()> {
+    player
+        .set_property_raw(VOLUME_STEP.into(), serde_json::json!(step))
+        .await?;
+    Ok(())
 }
 
-pub async fn vd<A>(amount: A) -> anyhow::Result<()>
-where
-    A: Into<Amount>,
-{
-    let Amount { amount } = amount.into();
-    Ok(chosen_index().change_volume(-amount.unwrap_or(2)).await?)
+/// How much to change the volume of `player` by when not given an amount: the step of its
+/// profile, if it was created with one that has it, or the one from the config.
+async fn volume_step(player: &PlayerLink) -> f64 {
+    match player.get_property_raw(VOLUME_STEP.into()).await {
+        Ok(step) => step.as_f64().unwrap_or_else(|| CONFIG.volume_step()),
+        Err(_) => CONFIG.volume_step(),
+    }
+}
+
+pub async fn vu(VolumeStep { amount }: VolumeStep) -> anyhow::Result<()> {
+    let player = chosen_index();
+    let step = match amount {
+        Some(step) => step,
+        None => volume_step(&player).await,
+    };
+    step_volume(player, step).await
+}
+
+pub async fn vd(VolumeStep { amount }: VolumeStep) -> anyhow::Result<()> {
+    let player = chosen_index();
+    let step = match amount {
+        Some(step) => step,
+        None => volume_step(&player).await,
+    };
+    step_volume(player, -step).await
+}
+
+async fn step_volume(player: PlayerLink, step: f64) -> anyhow::Result<()> {
+    player.step_volume(step, CONFIG.volume_curve).await?;
+    notify!("Volume: {:.0}%", player.volume().await?; replace: "volume");
+    Ok(())
+}
+
+/// Runs `cmd` on every player at once, for the commands that can be.
+pub async fn for_all(cmd: Command) -> anyhow::Result<()> {
+    let step = |VolumeStep { amount }| amount.unwrap_or_else(|| CONFIG.volume_step());
+    let command = match cmd {
+        Command::Pause => Broadcast::CyclePause,
+        Command::SetPause => Broadcast::Pause,
+        Command::SetPlay => Broadcast::Resume,
+        Command::Quit => Broadcast::Quit,
+        Command::Vu(s) => Broadcast::StepVolume {
+            delta: step(s),
+            curve: CONFIG.volume_curve,
+        },
+        Command::Vd(s) => Broadcast::StepVolume {
+            delta: -step(s),
+            curve: CONFIG.volume_curve,
+        },
+        _ => anyhow::bail!("--all only works with pause, set-pause, set-play, quit, vu and vd"),
+    };
+    let results = players::for_all(command).await?;
+    if results.is_empty() {
+        anyhow::bail!("no player is running");
+    }
+    let total = results.len();
+    let mut failed = 0;
+    for (index, result) in results {
+        if let Err(e) = result {
+            crate::error!("[{}] failed", PlayerLink::from(index); content: "{}", e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("failed on {failed} of {total} players");
+    }
+    Ok(())
 }
 
 pub async fn toggle_video() -> anyhow::Result<()> {
@@ -59,6 +144,51 @@ where
     Ok(())
 }
 
+pub async fn jump(pos: usize) -> anyhow::Result<()> {
+    Ok(chosen_index().queue_jump(pos).await?)
+}
+
+/// Skips forward to the first upcoming song that matches `query`, or a later one that matches it
+/// better.
+pub async fn next_file_until(query: &str) -> anyhow::Result<()> {
+    let player = chosen_index();
+    let queue = Queue::load_full(&player).await?;
+    let items = queue
+        .after()
+        .iter()
+        .map(|s| s.item.clone())
+        .collect::<Vec<_>>();
+    let upcoming = queue
+        .after()
+        .iter()
+        .zip(mlib::item::resolve_titles(&items).await)
+        .map(|(s, title)| (s.index, format!("{title} {}", s.item).to_lowercase()))
+        .collect::<Vec<_>>();
+    let words = query
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    let Some(pos) = best_match(&upcoming, &words) else {
+        anyhow::bail!("no upcoming song matches {query:?}");
+    };
+    Ok(player.queue_jump(pos).await?)
+}
+
+/// The position of the song that best matches all `words`, the earliest one if there's a tie.
+fn best_match(songs: &[(usize, String)], words: &[String]) -> Option<usize> {
+    songs
+        .iter()
+        .filter_map(|(pos, haystack)| {
+            let score = words
+                .iter()
+                .map(|w| mlib::library::score(haystack, w))
+                .sum::<Option<u32>>()?;
+            Some((Reverse(score), *pos))
+        })
+        .min()
+        .map(|(_, pos)| pos)
+}
+
 pub async fn prev_file<A>(amount: A) -> anyhow::Result<()>
 where
     A: Into<Amount>,
@@ -71,6 +201,77 @@ where
     Ok(())
 }
 
+/// Navigate the songs that were actually played, in the order they were played.
+pub async fn history(direction: players::Direction) -> anyhow::Result<()> {
+    let player = chosen_index();
+    let entry = match direction {
+        players::Direction::Prev => player.history_back().await?,
+        players::Direction::Next => player.history_forward().await?,
+    };
+    match entry {
+        Some(entry) => notify!(
+            "playing #{} again", entry.index;
+            content: "last played {} ago",
+            DurationFmt(entry.played_at.elapsed().unwrap_or_default())
+        ),
+        None => notify!(
+            "nothing to go {}",
+            match direction {
+                players::Direction::Prev => "back to",
+                players::Direction::Next => "forward to",
+            }
+        ),
+    }
+    Ok(())
+}
+
+pub async fn failed_list() -> anyhow::Result<()> {
+    let failed = chosen_index().failed_items().await?;
+    if failed.is_empty() {
+        println!("nothing failed to play");
+    }
+    for (i, f) in failed.iter().enumerate() {
+        println!(
+            "{i:>3}: {} ({} ago)\n     {}",
+            f.item,
+            DurationFmt(f.failed_at.elapsed().unwrap_or_default()),
+            f.reason
+        );
+    }
+    Ok(())
+}
+
+/// Queues the failed song `n` again, or all of them. With `cache` songs that were downloaded
+/// are played from the download dir. Songs are only forgotten once they are queued.
+pub async fn failed_retry(n: Option<usize>, cache: bool) -> anyhow::Result<()> {
+    let player = chosen_index();
+    let mut failed = player.failed_items().await?;
+    if let Some(n) = n {
+        if n >= failed.len() {
+            anyhow::bail!("no failed song {n}, see m failed list");
+        }
+        failed = vec![failed.swap_remove(n)];
+    }
+    if failed.is_empty() {
+        notify!("nothing to retry");
+        return Ok(());
+    }
+    let dl_dir = dl_dir().await?;
+    for players::FailedItem { item, .. } in failed {
+        let mut queued = item.clone();
+        if cache {
+            check_cache_ref(&dl_dir, &mut queued).await;
+        }
+        notify!("retrying"; content: "{}", queued);
+        player
+            .load_file(queued)
+            .await
+            .context("queueing failed song")?;
+        player.forget_failed(item).await?;
+    }
+    Ok(())
+}
+
 pub async fn frwd<A>(amount: A) -> anyhow::Result<()>
 where
     A: Into<Amount>,
@@ -107,8 +308,46 @@ where
         .await?)
 }
 
-pub async fn shuffle() -> anyhow::Result<()> {
-    Ok(players::queue_shuffle().await?)
+pub async fn shuffle(smart: bool) -> anyhow::Result<()> {
+    if !smart {
+        return Ok(players::queue_shuffle().await?);
+    }
+    let player = chosen_index();
+    let queue = Queue::load_full(&player).await?;
+    let plays = statistics::plays().await.context("loading statistics")?;
+    let last_played = queue
+        .after()
+        .iter()
+        .map(|s| plays.get(s.item.id()?)?.last)
+        .collect::<Vec<_>>();
+    let played = queue.current_idx() + 1;
+    let order = (0..played)
+        .chain(stale_first(&last_played, Utc::now(), &mut OsRng).map(|i| i + played))
+        .collect();
+    Ok(player.queue_reorder(order).await?)
+}
+
+/// A random order for songs last played at `last_played`, where the longer ago a song was played
+/// the likelier it is to come early. Songs that were never played count as played a year ago.
+fn stale_first(
+    last_played: &[Option<DateTime<Utc>>],
+    now: DateTime<Utc>,
+    rng: &mut impl Rng,
+) -> impl Iterator<Item = usize> {
+    const YEAR_IN_DAYS: f64 = 365.;
+    let mut keyed = last_played
+        .iter()
+        .enumerate()
+        .map(|(i, last)| {
+            let days = last
+                .map_or(YEAR_IN_DAYS, |t| (now - t).num_hours() as f64 / 24.)
+                .clamp(0., YEAR_IN_DAYS);
+            // weighted sampling without replacement, where the weight is `days + 1`
+            (rng.gen::<f64>().powf(1. / (days + 1.)), i)
+        })
+        .collect::<Vec<_>>();
+    keyed.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    keyed.into_iter().map(|(_, i)| i)
 }
 
 pub async fn toggle_loop() -> anyhow::Result<()> {
@@ -127,8 +366,329 @@ pub async fn toggle_loop() -> anyhow::Result<()> {
     Ok(())
 }
 
-pub async fn status() -> anyhow::Result<()> {
+pub async fn fade(state: OnOff) -> anyhow::Result<()> {
+    match state {
+        OnOff::On => {
+            let length = CONFIG.fade_length();
+            chosen_index().set_fade(Some(length)).await?;
+            notify!("fading in over {}ms", length.as_millis());
+        }
+        OnOff::Off => {
+            chosen_index().set_fade(None).await?;
+            notify!("fading disabled");
+        }
+    }
+    Ok(())
+}
+
+pub async fn sponsorblock(state: Option<OnOff>) -> anyhow::Result<()> {
+    let player = chosen_index();
+    match state {
+        None if player.sponsorblock().await? => notify!("skipping sponsorblock segments"),
+        None => notify!("not skipping sponsorblock segments"),
+        Some(OnOff::On) => {
+            player.set_sponsorblock(true).await?;
+            notify!("skipping sponsorblock segments");
+        }
+        Some(OnOff::Off) => {
+            player.set_sponsorblock(false).await?;
+            notify!("not skipping sponsorblock segments");
+        }
+    }
+    Ok(())
+}
+
+pub async fn focus(
+    state: Option<OnOff>,
+    max: Option<Duration>,
+    categories: Vec<String>,
+) -> anyhow::Result<()> {
+    let player = chosen_index();
+    match state {
+        None => match player.focus().await? {
+            Some(focus) => {
+                let mut on = vec![];
+                if let Some(max) = focus.max_duration {
+                    on.push(format!("skipping songs longer than {}", DurationFmt(max)));
+                }
+                if !focus.categories.is_empty() {
+                    on.push(format!("only playing {}", focus.categories.join(", ")));
+                }
+                notify!("focus on, {}", on.join(" and "))
+            }
+            None => notify!("focus off"),
+        },
+        Some(OnOff::On) => {
+            if max.is_none() && categories.is_empty() {
+                anyhow::bail!("nothing to focus on, pass --max and/or --category");
+            }
+            let focus = players::Focus {
+                max_duration: max,
+                categories: categories
+                    .iter()
+                    .map(|c| CONFIG.category(c).to_string())
+                    .collect(),
+            };
+            player.set_focus(Some(focus)).await?;
+            notify!("focus on");
+        }
+        Some(OnOff::Off) => {
+            let skipped = player.set_focus(None).await?;
+            notify!("focus off, skipped {} songs", skipped.len());
+            for s in skipped {
+                println!("{} ({})", s.item.fetch_item_title().await, s.reason);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn start_radio(
+    category: Option<String>,
+    song: Option<String>,
+    threshold: usize,
+) -> anyhow::Result<()> {
+    let player = chosen_index();
+    let seed = match (category, song) {
+        (Some(category), _) => players::RadioSeed::Category(CONFIG.category(&category).to_string()),
+        (None, Some(song)) => players::RadioSeed::Song(Item::from(song)),
+        (None, None) => players::RadioSeed::Song(Item::from_mpv_filename(player.filename().await?)),
+    };
+    let on = match &seed {
+        players::RadioSeed::Song(song) => song.fetch_item_title().await,
+        players::RadioSeed::Category(category) => category.clone(),
+    };
+    player
+        .start_radio(players::Radio { seed, threshold })
+        .await?;
+    notify!("radio on, playing songs like {on}");
+    Ok(())
+}
+
+pub async fn stop_radio() -> anyhow::Result<()> {
+    if chosen_index().stop_radio().await? {
+        notify!("radio off");
+    } else {
+        notify!("radio wasn't on");
+    }
+    Ok(())
+}
+
+pub async fn equalizer(preset_or_bands: Vec<String>) -> anyhow::Result<()> {
+    let player = chosen_index();
+    let bands = match preset_or_bands.as_slice() {
+        [] => {
+            let bands = player.get_equalizer().await?;
+            if bands.is_empty() {
+                notify!("equalizer off");
+            }
+            for (freq, gain) in players::EQUALIZER_BANDS.iter().zip(bands) {
+                println!("{freq:>5}Hz {gain:+5.1}dB");
+            }
+            return Ok(());
+        }
+        [preset] if preset.parse::<f32>().is_err() => CONFIG
+            .equalizer_preset(preset)
+            .with_context(|| format!("no equalizer preset called {preset}"))?,
+        bands => bands
+            .iter()
+            .map(|b| {
+                b.parse::<f32>()
+                    .with_context(|| format!("invalid gain: {b}"))
+            })
+            .collect::<anyhow::Result<_>>()?,
+    };
+    player.set_equalizer(bands).await?;
+    notify!("equalizer set");
+    Ok(())
+}
+
+pub async fn property(cmd: PropertyCmd) -> anyhow::Result<()> {
+    let player = chosen_index();
+    match cmd {
+        PropertyCmd::Get { name } => match player.get_property_raw(name).await? {
+            serde_json::Value::String(s) if !output::is_json() => println!("{s}"),
+            value => output::json(&value)?,
+        },
+        PropertyCmd::Set { name, value } => {
+            let value = serde_json::from_str(&value).unwrap_or(serde_json::Value::String(value));
+            player.set_property_raw(name, value).await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn end_of_queue(policy: Option<players::EndOfQueuePolicy>) -> anyhow::Result<()> {
+    let player = chosen_index();
+    match policy {
+        Some(policy) => {
+            player.set_end_of_queue(policy.clone()).await?;
+            notify!("once the queue ends: {policy}");
+        }
+        None => notify!("once the queue ends: {}", player.end_of_queue().await?),
+    }
+    Ok(())
+}
+
+pub async fn precache(policy: Option<players::PrecachePolicy>) -> anyhow::Result<()> {
+    let player = chosen_index();
+    let policy = match policy {
+        Some(policy) => {
+            player.set_precache(policy).await?;
+            policy
+        }
+        None => player.precache().await?,
+    };
+    match policy {
+        players::PrecachePolicy::Off => notify!("not downloading queued songs"),
+        players::PrecachePolicy::Next(n) => notify!("downloading the next {n} songs"),
+        players::PrecachePolicy::Queue => notify!("downloading the whole queue"),
+    }
+    Ok(())
+}
+
+pub async fn sleep(when: Option<SleepFor>, stop: bool) -> anyhow::Result<()> {
+    let player = chosen_index();
+    match when {
+        Some(SleepFor::Cancel) => {
+            if player.cancel_sleep_timer().await? {
+                notify!("sleep timer canceled");
+            } else {
+                notify!("no sleep timer running");
+            }
+        }
+        Some(SleepFor::After(after)) => {
+            let action = if stop {
+                players::StopOrPause::Stop
+            } else {
+                players::StopOrPause::Pause
+            };
+            player.set_sleep_timer(after, action).await?;
+            notify!("sleeping in {}", DurationFmt(after));
+        }
+        None => match player.sleep_timer_status().await? {
+            Some(timer) => notify!(
+                "{} in {}",
+                match timer.action {
+                    players::StopOrPause::Stop => "stopping",
+                    players::StopOrPause::Pause => "pausing",
+                },
+                DurationFmt(timer.remaining)
+            ),
+            None => notify!("no sleep timer running"),
+        },
+    }
+    Ok(())
+}
+
+/// The current song's title and lyrics, if they can be found.
+async fn current_lyrics(player: &PlayerLink) -> anyhow::Result<(String, Option<Lyrics>)> {
+    let (title, duration) = futures_util::try_join!(player.media_title(), player.duration())?;
+    let query = lyrics::Query::from_media_title(&title, Duration::try_from_secs_f64(duration).ok());
+    let lyrics = lyrics::fetch(&query, &CONFIG.lyrics_providers())
+        .await
+        .with_context(|| format!("fetching lyrics for {title}"))?;
+    Ok((title, lyrics))
+}
+
+pub async fn lyrics(sync: bool) -> anyhow::Result<()> {
+    let player = chosen_index();
+    if !sync {
+        match current_lyrics(&player).await? {
+            (_, Some(lyrics)) => print!("{lyrics}"),
+            (title, None) => notify!("no lyrics found for {title}"),
+        }
+        return Ok(());
+    }
+    let mut current = None;
+    let mut shown = None;
+    loop {
+        let title = player.media_title().await?;
+        if current.as_ref().map(|(t, _)| t) != Some(&title) {
+            let (title, lyrics) = current_lyrics(&player).await?;
+            println!("\n# {title}\n");
+            match &lyrics {
+                Some(Lyrics::Plain(text)) => println!("{text}"),
+                Some(Lyrics::Synced(_)) => {}
+                None => println!("no lyrics found"),
+            }
+            current = Some((title, lyrics));
+            shown = None;
+        }
+        if let Some((_, Some(lyrics @ Lyrics::Synced(lines)))) = &current {
+            let at = Duration::try_from_secs_f64(player.playback_time().await?).unwrap_or_default();
+            let line = lyrics.line_at(at);
+            if line != shown {
+                if let Some(i) = line {
+                    println!("{}", lines[i].text);
+                }
+                shown = line;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+pub async fn status(verbose: bool) -> anyhow::Result<()> {
+    #[derive(Serialize)]
+    struct Daemon {
+        #[serde(flatten)]
+        pong: cli_daemon::Pong,
+        version: Option<String>,
+        metrics: cli_daemon::Metrics,
+    }
+    #[derive(Serialize)]
+    struct Status {
+        player: PlayerIndex,
+        name: Option<String>,
+        idle_for: Option<Duration>,
+        current: Current,
+        queue_size: usize,
+        last_queue: Option<usize>,
+        end_of_queue: EndOfQueuePolicy,
+    }
+    let daemon = if verbose {
+        let (pong, version, metrics) = futures_util::try_join!(
+            players::ping_daemon(),
+            players::daemon_version(),
+            players::daemon_metrics()
+        )
+        .context("asking the players daemon")?;
+        Some(Daemon {
+            pong,
+            version,
+            metrics,
+        })
+    } else {
+        None
+    };
+    if let Some(daemon) = daemon.as_ref().filter(|_| !output::is_json()) {
+        let total = daemon.metrics.total();
+        let by_kind = daemon
+            .metrics
+            .messages
+            .iter()
+            .sorted_by_key(|(_, count)| std::cmp::Reverse(count.handled))
+            .map(|(kind, count)| match count.failed {
+                0 => format!("   {kind}: {}", count.handled),
+                failed => format!("   {kind}: {} ({failed} failed)", count.handled),
+            })
+            .format("\n");
+        notify!(
+            "players daemon";
+            content: " §b    pid:§r {}\n §bversion:§r {}\n §b uptime:§r {}\n §brequests:§r {} ({} failed, {} malformed) from {} connections\n{}",
+                daemon.pong.pid,
+                daemon.version.as_deref().unwrap_or("unknown"),
+                DurationFmt(daemon.pong.uptime),
+                total.handled,
+                total.failed,
+                daemon.metrics.malformed,
+                daemon.metrics.connections,
+                by_kind,
+        );
+    }
     let all = players::all().await?;
+    let mut statuses = Vec::new();
     for player in all {
         let current = Queue::current(&player, mlib::queue::CurrentOptions::None)
             .await
@@ -141,20 +701,102 @@ pub async fn status() -> anyhow::Result<()> {
         let last_queue = player
             .last_queue()
             .await
-            .with_context(|| format!("[{player}] fetching last queue"))?
+            .with_context(|| format!("[{player}] fetching last queue"))?;
+
+        let end_of_queue = player
+            .end_of_queue()
+            .await
+            .with_context(|| format!("[{player}] fetching end of queue policy"))?;
+
+        let name = player
+            .name()
+            .await
+            .with_context(|| format!("[{player}] fetching name"))?;
+
+        let idle_for = player
+            .idle_for()
+            .await
+            .with_context(|| format!("[{player}] fetching idle time"))?;
+
+        if output::is_json() {
+            statuses.push(Status {
+                player: player.index(),
+                name,
+                idle_for,
+                current,
+                queue_size,
+                last_queue,
+                end_of_queue,
+            });
+            continue;
+        }
+        let last_queue = last_queue
             .map(|l| format!(" (last queued {l})"))
             .unwrap_or_default();
 
+        let name = name.map(|n| format!(" ({n})")).unwrap_or_default();
+        let idle_for = idle_for
+            .map(|d| format!("\n §b idle:§r {}", DurationFmt(d)))
+            .unwrap_or_default();
         notify!(
-            "{player}";
-            content: " §btitle:§r {}\n §b meta:§r {:.0}% {}\n §bqueue:§r {}/{}{}",
+            "{player}{name}";
+            content: " §btitle:§r {}\n §b meta:§r {:.0}% {}\n §bqueue:§r {}/{}{}\n §b  end:§r {}{}",
                 current.title,
                 current.progress.as_ref().map(ToString::to_string).unwrap_or_else(|| String::from("none")),
                 if current.playing { ">" } else { "||" },
                 current.index,
                 queue_size.saturating_sub(1),
                 last_queue,
+                end_of_queue,
+                idle_for,
         );
     }
+    if output::is_json() {
+        match daemon {
+            Some(daemon) => output::json(&serde_json::json!({
+                "daemon": daemon,
+                "players": statuses,
+            }))?,
+            None => output::json(&statuses)?,
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn best_match_prefers_better_then_closer() {
+        let songs = [
+            (3, "a bohemian rhapsody queen".to_owned()),
+            (4, "the show must go on queen".to_owned()),
+            (5, "somebody to love queen".to_owned()),
+        ];
+        let words = |q: &str| q.split_whitespace().map(str::to_owned).collect::<Vec<_>>();
+        assert_eq!(best_match(&songs, &words("queen")), Some(3));
+        assert_eq!(best_match(&songs, &words("queen show")), Some(4));
+        // "rhapsody" only has the letters in order, "somebody" has the whole word
+        assert_eq!(best_match(&songs, &words("body")), Some(5));
+        assert_eq!(best_match(&songs, &words("abba")), None);
+    }
+
+    #[test]
+    fn stale_first_front_loads_songs_not_played_in_a_while() {
+        use rand::{rngs::StdRng, SeedableRng};
+        let now = Utc::now();
+        let last_played = [Some(now), None, Some(now - chrono::Duration::days(1))];
+        let stale_first_count = (0..100)
+            .filter(|seed| {
+                let order = stale_first(&last_played, now, &mut StdRng::seed_from_u64(*seed));
+                order.collect::<Vec<_>>()[0] == 1
+            })
+            .count();
+        assert!(stale_first_count > 90, "{stale_first_count}");
+
+        let mut order = stale_first(&last_played, now, &mut OsRng).collect::<Vec<_>>();
+        order.sort();
+        assert_eq!(order, [0, 1, 2]);
+    }
+}
@@ -0,0 +1,104 @@
+use std::pin::pin;
+
+use futures_util::StreamExt;
+use mlib::players::{
+    self,
+    event::{OwnedLibMpvEvent, OwnedMpvNode, PlayerEvent},
+};
+
+/// What `--only` matches against: the property for property changes, the event otherwise.
+fn name(event: &OwnedLibMpvEvent) -> String {
+    if let OwnedLibMpvEvent::PropertyChange { name, .. } = event {
+        return name.clone();
+    }
+    let variant = match serde_json::to_value(event) {
+        Ok(serde_json::Value::String(s)) => s,
+        Ok(serde_json::Value::Object(o)) => {
+            o.into_iter().next().map(|(k, _)| k).unwrap_or_default()
+        }
+        _ => String::new(),
+    };
+    kebab_case(&variant)
+}
+
+fn kebab_case(s: &str) -> String {
+    let mut kebab = String::with_capacity(s.len());
+    for (i, c) in s.char_indices() {
+        if c.is_uppercase() && i > 0 {
+            kebab.push('-');
+        }
+        kebab.extend(c.to_lowercase());
+    }
+    kebab
+}
+
+fn value(node: &OwnedMpvNode) -> String {
+    match node {
+        OwnedMpvNode::String(s) | OwnedMpvNode::OsdString(s) => s.clone(),
+        OwnedMpvNode::Flag(b) => b.to_string(),
+        OwnedMpvNode::Int64(i) => i.to_string(),
+        OwnedMpvNode::Double(d) => d.to_string(),
+        OwnedMpvNode::None => "none".into(),
+        OwnedMpvNode::Invalid(e) => format!("invalid ({e:?})"),
+        OwnedMpvNode::Array(_) | OwnedMpvNode::Map(_) => {
+            serde_json::to_string(node).unwrap_or_default()
+        }
+    }
+}
+
+fn human(event: &PlayerEvent) -> String {
+    let PlayerEvent {
+        player_index,
+        event,
+        ..
+    } = event;
+    let payload = match event {
+        OwnedLibMpvEvent::PropertyChange { name, change, .. } => {
+            return format!("[{player_index}] {name} = {}", value(change));
+        }
+        event => match serde_json::to_value(event) {
+            Ok(serde_json::Value::Object(o)) => o.into_iter().next().map(|(_, v)| v),
+            _ => None,
+        },
+    };
+    match payload {
+        Some(payload) => format!("[{player_index}] {} {payload}", name(event)),
+        None => format!("[{player_index}] {}", name(event)),
+    }
+}
+
+/// Prints the events of every player until the daemon goes away.
+pub async fn watch(json: bool, only: Vec<String>) -> anyhow::Result<()> {
+    let mut events = pin!(players::subscribe().await?);
+    while let Some(event) = events.next().await {
+        let event = event?;
+        if !only.is_empty() && !only.contains(&name(&event.event)) {
+            continue;
+        }
+        if json {
+            println!("{}", serde_json::to_string(&event)?);
+        } else {
+            println!("{}", human(&event));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn events_are_named_like_mpv() {
+        assert_eq!(name(&OwnedLibMpvEvent::FileLoaded), "file-loaded");
+        assert_eq!(name(&OwnedLibMpvEvent::EndFile(0)), "end-file");
+        assert_eq!(
+            name(&OwnedLibMpvEvent::PropertyChange {
+                name: "pause".into(),
+                change: OwnedMpvNode::Flag(true),
+                reply_userdata: 0,
+            }),
+            "pause"
+        );
+    }
+}
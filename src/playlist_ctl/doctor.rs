@@ -0,0 +1,174 @@
+use crate::{
+    config::CONFIG,
+    error, notify,
+    util::{dl_dir, selector},
+};
+use futures_util::TryStreamExt;
+use itertools::Itertools;
+use mlib::{
+    downloaded,
+    item::link::{Id, VideoLink},
+    playlist::Playlist,
+    statistics,
+    ytdl::{
+        probe::{self, Unavailable},
+        YtdlBuilder,
+    },
+    Search,
+};
+
+/// The category songs that are no longer available are put in by `m doctor --fix-dead`, when
+/// no replacement is picked for them.
+const DEAD: &str = "dead";
+
+/// Looks for duplicated, unavailable and orphaned songs and prints what can be done about them.
+/// With `fix_dead`, asks what to do with each unavailable song.
+pub async fn doctor(offline: bool, fix_dead: bool) -> anyhow::Result<()> {
+    let playlist = Playlist::load().await?;
+    let mut problems = 0;
+
+    let duplicates = playlist.duplicates();
+    if !duplicates.is_empty() {
+        println!("duplicated songs (remove the extra entries with `m playlist edit`):");
+        for group in &duplicates {
+            let kind = if group.iter().map(|s| s.link.as_str()).all_equal() {
+                "same link"
+            } else {
+                "different links"
+            };
+            println!("  {} ({kind})", group[0].link.id().as_str());
+            for song in group {
+                println!("    {} :: {}", song.name, song.link);
+            }
+        }
+        problems += duplicates.len();
+    }
+
+    if !offline {
+        let ids = playlist
+            .songs
+            .iter()
+            .map(|s| s.link.id())
+            .unique()
+            .collect_vec();
+        notify!("checking if {} songs are still available", ids.len());
+        let unavailable = probe::unavailable(ids).await?;
+        if !unavailable.is_empty() && fix_dead {
+            fix(&unavailable).await?;
+        } else if !unavailable.is_empty() {
+            println!(
+                "unavailable songs (remove them with `m delete-song` or replace them with `m doctor --fix-dead`):"
+            );
+            for dead in &unavailable {
+                let name = playlist
+                    .songs
+                    .iter()
+                    .find(|s| s.link.id() == &*dead.id)
+                    .map_or("", |s| s.name.as_str());
+                println!("  {} :: {}: {}", name, dead.id.as_str(), dead.reason);
+            }
+            problems += unavailable.len();
+        }
+    }
+
+    let orphans = downloaded::orphans(&dl_dir().await?, &playlist).await?;
+    if !orphans.is_empty() {
+        println!(
+            "downloaded files of songs not in the playlist (delete them with `m clean-downloads`):"
+        );
+        for file in &orphans {
+            println!("  {}", file.display());
+        }
+        problems += orphans.len();
+    }
+
+    match problems {
+        0 => println!("no problems found"),
+        n => println!("{n} problems found"),
+    }
+    Ok(())
+}
+
+/// Asks, for each unavailable song, whether to replace it with one of the results of searching
+/// for its name, to delete it or to mark it as dead. Replacements keep the song's name,
+/// categories and statistics.
+async fn fix(unavailable: &[Unavailable]) -> anyhow::Result<()> {
+    const MARK: &str = "mark as dead";
+    const DELETE: &str = "delete";
+    const SKIP: &str = "skip";
+
+    let dead = CONFIG.category(DEAD);
+    let lock = Playlist::lock().await?;
+    let mut playlist = Playlist::load().await?;
+    let (mut marked, mut deleted) = (0, 0);
+    let mut renames = Vec::new();
+    for gone in unavailable {
+        let Some(song) = playlist.find_song_mut(|s| s.link.id() == &*gone.id) else {
+            continue;
+        };
+        if song.categories.contains(&dead) {
+            continue;
+        }
+        if let Err(e) = song.ensure_editable() {
+            error!("can't fix {}", song.name; content: "{}", e);
+            continue;
+        }
+        notify!("searching for a replacement of {}", song.name);
+        let search = async {
+            YtdlBuilder::new(&Search::multiple(song.name.clone(), 5))
+                .get_title()
+                .search_multiple()?
+                .try_collect::<Vec<_>>()
+                .await
+        };
+        let results = match search.await {
+            Ok(results) => results,
+            Err(e) => {
+                error!("failed to search for a replacement of {}", song.name; content: "{}", e);
+                continue;
+            }
+        };
+        let options = results
+            .iter()
+            .map(|r| format!("{} :: {}", r.title_ref(), r.id().as_str()))
+            .chain([MARK, DELETE, SKIP].map(String::from))
+            .collect::<Vec<_>>();
+        let prompt = format!("{} is gone ({})", song.name, gone.reason);
+        let choice = selector::selector(&options, &prompt, options.len()).await?;
+        match choice.as_deref() {
+            Some(MARK) => {
+                song.categories.push(dead.clone());
+                marked += 1;
+            }
+            Some(DELETE) => {
+                song.delete();
+                deleted += 1;
+            }
+            Some(SKIP) | None => {}
+            Some(pick) => {
+                let Some(new) = results.iter().zip(&options).find(|(_, o)| *o == pick) else {
+                    continue;
+                };
+                let new = new.0.id();
+                song.link = VideoLink::from_id(new);
+                renames.push((&*gone.id, new.boxed()));
+            }
+        }
+    }
+    lock.save(&playlist).await?;
+    // only once the playlist has the new links, so statistics never point at songs that aren't
+    // in it
+    for (from, to) in &renames {
+        statistics::rename_song(from, to).await?;
+    }
+    let replaced = renames.len();
+    notify!(
+        "unavailable songs fixed";
+        content: "{} replaced, {} marked as dead, {} deleted, {} left",
+        replaced,
+        marked,
+        deleted,
+        unavailable.len() - replaced - marked - deleted
+    );
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use futures_util::StreamExt;
+use mlib::{playlist::Playlist, ytdl::YtdlBuilder};
+
+use crate::notify;
+use tracing::error;
+
+/// How long to wait before asking again for the duration of a song whose lookup failed.
+const RETRY_FAILED_AFTER: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Asks yt-dlp for the durations of the songs that don't have one, returning how many were
+/// found. Songs of included playlists are skipped, since they aren't saved with this playlist,
+/// and so are songs whose lookup failed recently.
+pub async fn fill_durations(playlist: &mut Playlist) -> usize {
+    let mut failed = load_failed().await;
+    let now = unix_secs(SystemTime::now());
+    failed.retain(|_, at| now.saturating_sub(*at) < RETRY_FAILED_AFTER.as_secs());
+    let unknown = playlist
+        .songs
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.time == 0)
+        .filter(|(_, s)| playlist.included_from(s).is_none())
+        .filter(|(_, s)| !failed.contains_key(s.link.id().as_str()))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    if unknown.is_empty() {
+        return 0;
+    }
+    notify!("getting the duration of {} songs", unknown.len());
+    let songs = &playlist.songs;
+    let durations = futures_util::stream::iter(unknown)
+        .map(|i| async move {
+            let song = &songs[i];
+            match YtdlBuilder::new(&song.link).get_duration().request().await {
+                Ok(r) => (i, Some(r.duration().as_secs())),
+                Err(e) => {
+                    error!(?e, song = %song.name, "failed to get the duration");
+                    (i, None)
+                }
+            }
+        })
+        .buffer_unordered(8)
+        .collect::<Vec<_>>()
+        .await;
+    let mut found = 0;
+    for (i, duration) in durations {
+        let song = &mut playlist.songs[i];
+        match duration {
+            Some(duration) => {
+                song.time = duration;
+                found += 1;
+            }
+            None => {
+                failed.insert(song.link.id().as_str().to_owned(), now);
+            }
+        }
+    }
+    store_failed(&failed);
+    found
+}
+
+/// Where the songs whose duration couldn't be found are remembered, with when it was tried.
+fn failed_file() -> Option<PathBuf> {
+    let mut path = dirs::cache_dir()?;
+    path.push("m");
+    path.push("unknown-durations.json");
+    Some(path)
+}
+
+async fn load_failed() -> HashMap<String, u64> {
+    let Some(path) = failed_file() else {
+        return HashMap::new();
+    };
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            error!(?e, "corrupted unknown durations file");
+            HashMap::new()
+        }),
+        Err(_) => HashMap::new(),
+    }
+}
+
+fn store_failed(failed: &HashMap<String, u64>) {
+    let Some(path) = failed_file() else {
+        return;
+    };
+    let result = (|| {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(&path, serde_json::to_vec(failed)?)
+    })();
+    if let Err(e) = result {
+        error!(?e, "failed to store unknown durations");
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
@@ -0,0 +1,316 @@
+use std::io::{self, stdout, Write};
+
+use crate::{config::CONFIG, util::RawMode};
+use anyhow::Context;
+use crossterm::{
+    cursor::{Hide, MoveTo, MoveToNextLine, Show},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers as Mod},
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+    QueueableCommand,
+};
+use itertools::Itertools;
+use mlib::playlist::{Playlist, PlaylistLock, Song};
+
+const HELP: &str =
+    "j/k: select  J/K: move  r: rename  c: add category  x: remove category  d: delete  w: save  q: quit";
+
+struct AlternateScreen;
+impl AlternateScreen {
+    fn enter() -> io::Result<Self> {
+        stdout()
+            .lock()
+            .queue(EnterAlternateScreen)?
+            .queue(Hide)?
+            .flush()?;
+        Ok(Self)
+    }
+}
+impl Drop for AlternateScreen {
+    fn drop(&mut self) {
+        let _ = stdout()
+            .lock()
+            .queue(Show)
+            .and_then(|s| s.queue(LeaveAlternateScreen));
+        let _ = stdout().flush();
+    }
+}
+
+enum Input {
+    Key(char, Mod),
+    Code(KeyCode),
+    Resize,
+}
+
+fn next_input() -> io::Result<Input> {
+    loop {
+        match event::read()? {
+            Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind,
+                ..
+            }) if kind != KeyEventKind::Release => {
+                return Ok(match code {
+                    KeyCode::Char(c) => Input::Key(c, modifiers),
+                    code => Input::Code(code),
+                })
+            }
+            Event::Resize(..) => return Ok(Input::Resize),
+            _ => {}
+        }
+    }
+}
+
+struct Editor {
+    playlist: Playlist,
+    selected: usize,
+    offset: usize,
+    dirty: bool,
+    status: Option<String>,
+}
+
+impl Editor {
+    fn selected(&mut self) -> Option<&mut Song> {
+        self.playlist.songs.get_mut(self.selected)
+    }
+
+    /// The selected song if it can be edited, otherwise why it can't is shown in the status bar.
+    fn editable(&mut self) -> Option<&mut Song> {
+        let song = self.playlist.songs.get(self.selected)?;
+        if let Err(e) = self.playlist.ensure_editable(song) {
+            self.status = Some(e.to_string());
+            return None;
+        }
+        self.selected()
+    }
+
+    fn draw(&mut self, prompt: Option<&str>) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let rows = usize::from(height.saturating_sub(1)).max(1);
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + rows {
+            self.offset = self.selected + 1 - rows;
+        }
+        let mut out = stdout().lock();
+        out.queue(MoveTo(0, 0))?.queue(Clear(ClearType::All))?;
+        for (i, song) in self
+            .playlist
+            .songs
+            .iter()
+            .enumerate()
+            .skip(self.offset)
+            .take(rows)
+        {
+            let line = format!(
+                "{} {} [{}]",
+                if i == self.selected { '>' } else { ' ' },
+                song.name,
+                song.categories.iter().format(", ")
+            );
+            let line = line.chars().take(width.into()).collect::<String>();
+            if i == self.selected {
+                out.queue(SetAttribute(Attribute::Reverse))?
+                    .queue(Print(line))?
+                    .queue(SetAttribute(Attribute::Reset))?;
+            } else {
+                out.queue(Print(line))?;
+            }
+            out.queue(MoveToNextLine(1))?;
+        }
+        let status = match (prompt, &self.status) {
+            (Some(prompt), _) => prompt,
+            (None, Some(status)) => status,
+            (None, None) => HELP,
+        };
+        out.queue(MoveTo(0, height.saturating_sub(1)))?
+            .queue(Print(status.chars().take(width.into()).collect::<String>()))?
+            .flush()
+    }
+
+    /// Reads a line of text in the status bar. Returns `None` if the user pressed escape.
+    fn prompt(&mut self, question: &str, initial: &str) -> io::Result<Option<String>> {
+        let mut answer = initial.to_string();
+        loop {
+            self.draw(Some(&format!("{question}{answer}")))?;
+            match next_input()? {
+                Input::Key('c' | 'd', Mod::CONTROL) | Input::Code(KeyCode::Esc) => return Ok(None),
+                Input::Key('u', Mod::CONTROL) => answer.clear(),
+                Input::Key(c, Mod::NONE | Mod::SHIFT) => answer.push(c),
+                Input::Code(KeyCode::Backspace) => {
+                    answer.pop();
+                }
+                Input::Code(KeyCode::Enter) => return Ok(Some(answer.trim().to_string())),
+                Input::Key(..) | Input::Code(_) | Input::Resize => {}
+            }
+        }
+    }
+
+    fn confirm(&mut self, question: &str) -> io::Result<bool> {
+        self.draw(Some(&format!("{question} [y/N]")))?;
+        Ok(matches!(next_input()?, Input::Key('y' | 'Y', _)))
+    }
+
+    fn swap(&mut self, with: usize) {
+        if with < self.playlist.songs.len() && self.selected < self.playlist.songs.len() {
+            self.playlist.songs.swap(self.selected, with);
+            self.selected = with;
+            self.dirty = true;
+        }
+    }
+}
+
+/// What the playlist's file holds, to tell whether someone else changed it.
+async fn contents(lock: &PlaylistLock) -> anyhow::Result<Vec<u8>> {
+    match tokio::fs::read(lock.path()).await {
+        Ok(contents) => Ok(contents),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).context("reading playlist"),
+    }
+}
+
+/// Saves the playlist unless someone else changed it since it was `loaded`, in which case saving
+/// would throw their changes away. Returns what the file holds after saving.
+async fn save(
+    playlist: &Playlist,
+    name: Option<&str>,
+    loaded: &[u8],
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let lock = super::lock(name).await?;
+    if contents(&lock).await? != loaded {
+        return Ok(None);
+    }
+    lock.save(playlist).await.context("saving playlist")?;
+    contents(&lock).await.map(Some)
+}
+
+/// Full screen editor to reorder, rename, recategorize and delete the songs in a playlist.
+pub async fn edit(playlist: Option<String>) -> anyhow::Result<()> {
+    let (mut loaded, loaded_playlist) = {
+        let lock = super::lock(playlist.as_deref()).await?;
+        (
+            contents(&lock).await?,
+            super::load(playlist.as_deref()).await?,
+        )
+    };
+    let mut editor = Editor {
+        playlist: loaded_playlist,
+        selected: 0,
+        offset: 0,
+        dirty: false,
+        status: None,
+    };
+    {
+        let _raw = RawMode::enable()?;
+        let _screen = AlternateScreen::enter()?;
+        loop {
+            editor.draw(None)?;
+            let input = next_input()?;
+            editor.status = None;
+            match input {
+                Input::Key('q', Mod::NONE) | Input::Key('c', Mod::CONTROL)
+                    if !editor.dirty || editor.confirm("discard unsaved changes?")? =>
+                {
+                    break
+                }
+                Input::Key('j', Mod::NONE) | Input::Code(KeyCode::Down) => {
+                    editor.selected =
+                        (editor.selected + 1).min(editor.playlist.songs.len().saturating_sub(1));
+                }
+                Input::Key('k', Mod::NONE) | Input::Code(KeyCode::Up) => {
+                    editor.selected = editor.selected.saturating_sub(1);
+                }
+                Input::Key('g', Mod::NONE) | Input::Code(KeyCode::Home) => editor.selected = 0,
+                Input::Key('G', _) | Input::Code(KeyCode::End) => {
+                    editor.selected = editor.playlist.songs.len().saturating_sub(1);
+                }
+                Input::Key('J', _) => editor.swap(editor.selected + 1),
+                Input::Key('K', _) => {
+                    if let Some(above) = editor.selected.checked_sub(1) {
+                        editor.swap(above)
+                    }
+                }
+                Input::Key('r', Mod::NONE) => {
+                    let Some(name) = editor.editable().map(|s| s.name.clone()) else {
+                        continue;
+                    };
+                    if let Some(new) = editor.prompt("rename to: ", &name)? {
+                        if !new.is_empty() && new != name {
+                            editor.selected().unwrap().name = new;
+                            editor.dirty = true;
+                        }
+                    }
+                }
+                Input::Key('c', Mod::NONE) => {
+                    if editor.editable().is_none() {
+                        continue;
+                    }
+                    if let Some(category) = editor.prompt("add category: ", "")? {
+                        if !category.is_empty()
+                            && editor
+                                .selected()
+                                .unwrap()
+                                .categories
+                                .push(CONFIG.category(&category))
+                                .is_none()
+                        {
+                            editor.dirty = true;
+                        }
+                    }
+                }
+                Input::Key('x', Mod::NONE) => {
+                    let Some(song) = editor.editable() else {
+                        continue;
+                    };
+                    let initial = match &song.categories[..] {
+                        [only] => only.to_string(),
+                        _ => String::new(),
+                    };
+                    if let Some(category) = editor.prompt("remove category: ", &initial)? {
+                        if editor
+                            .selected()
+                            .unwrap()
+                            .categories
+                            .remove(&CONFIG.category(&category))
+                        {
+                            editor.dirty = true;
+                        } else {
+                            editor.status = Some(format!("no category named {category:?}"));
+                        }
+                    }
+                }
+                Input::Key('d', Mod::NONE) | Input::Code(KeyCode::Delete) => {
+                    let Some(name) = editor.editable().map(|s| s.name.clone()) else {
+                        continue;
+                    };
+                    if editor.confirm(&format!("delete {name}?"))? {
+                        editor.playlist.songs.remove(editor.selected);
+                        editor.selected = editor
+                            .selected
+                            .min(editor.playlist.songs.len().saturating_sub(1));
+                        editor.dirty = true;
+                    }
+                }
+                Input::Key('w', Mod::NONE) => {
+                    match save(&editor.playlist, playlist.as_deref(), &loaded).await? {
+                        Some(saved) => {
+                            loaded = saved;
+                            editor.dirty = false;
+                            editor.status = Some("saved".into());
+                        }
+                        None => {
+                            editor.status = Some(
+                                "not saved: the playlist was changed since it was opened, \
+                                 quit and edit it again"
+                                    .into(),
+                            )
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
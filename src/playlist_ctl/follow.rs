@@ -0,0 +1,93 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use itertools::Itertools;
+use mlib::{follows::Follows, playlist::Playlist, Link};
+
+use crate::{config::CONFIG, error, notify, util::output};
+
+pub async fn follow(link: Link, categories: Vec<String>) -> anyhow::Result<()> {
+    let categories = categories.iter().map(|c| CONFIG.category(c)).collect();
+    let mut follows = Follows::load().await?;
+    notify!("following {}...", link);
+    follows
+        .follow(link.clone(), categories)
+        .await
+        .with_context(|| format!("following {link}"))?;
+    follows.save().await?;
+    notify!("following {}", link);
+    Ok(())
+}
+
+pub async fn unfollow(link: Link) -> anyhow::Result<()> {
+    let mut follows = Follows::load().await?;
+    if follows.unfollow(link.clone())? {
+        follows.save().await?;
+        notify!("unfollowed {}", link);
+    } else {
+        error!("{} is not followed", link);
+    }
+    Ok(())
+}
+
+pub async fn follows() -> anyhow::Result<()> {
+    let follows = Follows::load().await?;
+    if output::is_json() {
+        return output::json(&follows.follows);
+    }
+    for f in &follows.follows {
+        println!("{} [{}]", f.link, f.categories.iter().format(", "));
+    }
+    Ok(())
+}
+
+/// Adds the videos uploaded to the followed playlists and channels since the last sync to the
+/// playlist, with the categories they were followed with.
+pub async fn sync_follows() -> anyhow::Result<()> {
+    let mut follows = Follows::load().await?;
+    let uploads = follows.sync().await;
+    let playlist = Playlist::load().await?;
+    for upload in uploads {
+        let mut added = Vec::new();
+        for link in upload.videos {
+            if playlist.find_by_link(&link).is_some() {
+                follows.see(&upload.from, &link);
+                continue;
+            }
+            let categories = upload.categories.iter().cloned().collect();
+            // failed uploads aren't marked as seen, so they are tried again on the next sync
+            let song = match super::fetch_song(link.clone(), categories).await {
+                Ok(song) => song,
+                Err(e) => {
+                    tracing::error!(?e, "failed to fetch new upload");
+                    continue;
+                }
+            };
+            Playlist::add_song(&song).await?;
+            follows.see(&upload.from, &link);
+            added.push(song.name);
+        }
+        if !added.is_empty() {
+            notify!(
+                "{} new from {}", added.len(), upload.from;
+                content: "{}", added.iter().format("\n")
+            );
+        }
+    }
+    follows.save().await?;
+    Ok(())
+}
+
+/// Syncs if it has been longer than `every` since the last time.
+pub async fn sync_follows_if_due(every: Duration) -> anyhow::Result<()> {
+    let follows = Follows::load().await?;
+    let due = match follows.last_sync {
+        _ if follows.follows.is_empty() => false,
+        Some(last) => SystemTime::now().duration_since(last).unwrap_or_default() >= every,
+        None => true,
+    };
+    if due {
+        sync_follows().await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,965 @@
+mod doctor;
+mod duration;
+mod edit;
+mod follow;
+
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    fmt,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use crate::arg_parse::SongSort;
+use crate::config::CONFIG;
+use crate::util::{self, output, selector, DurationFmt};
+use crate::{error, notify};
+use anyhow::{bail, Context};
+use chrono::{DateTime, Local, Utc};
+use futures_util::TryStreamExt;
+use futures_util::{future::Either, Stream};
+use itertools::Itertools;
+use mlib::item::link::{Id, VideoLink};
+use mlib::players::{self, PlayerIndex, PlayerLink};
+use mlib::playlist::{PartialSearchResult, PlaylistIndex};
+use mlib::Item;
+use mlib::{
+    downloaded,
+    playlist::{self, query::Query, Category, Playlist, PlaylistLock, PlaylistSet, Song},
+    queue::Queue,
+    statistics,
+    ytdl::YtdlBuilder,
+    Link, VideoId,
+};
+use regex::Regex;
+use serde::Serialize;
+
+pub use doctor::doctor;
+pub use edit::edit;
+pub use follow::{follow, follows, sync_follows, sync_follows_if_due, unfollow};
+
+/// Loads the named playlist, or the main playlist if no name is given.
+pub async fn load(name: Option<&str>) -> anyhow::Result<Playlist> {
+    Ok(match name {
+        Some(name) => PlaylistSet::open()?
+            .load(name)
+            .await
+            .with_context(|| format!("loading playlist {name}"))?,
+        None => Playlist::load().await?,
+    })
+}
+
+/// Streams the named playlist, or the main playlist if no name is given.
+pub async fn stream(
+    name: Option<&str>,
+) -> anyhow::Result<impl Stream<Item = Result<Song, impl std::error::Error>>> {
+    Ok(match name {
+        Some(name) => Either::Left(
+            PlaylistSet::open()?
+                .stream(name)
+                .await
+                .with_context(|| format!("loading playlist {name}"))?,
+        ),
+        None => Either::Right(Playlist::stream().await?),
+    })
+}
+
+/// Takes the lock of the named playlist, or of the main playlist if no name is given. It's held
+/// from before loading the playlist until it's saved, so that changes made by others in between
+/// aren't lost.
+async fn lock(name: Option<&str>) -> anyhow::Result<PlaylistLock> {
+    Ok(match name {
+        Some(name) => PlaylistSet::open()?.lock(name).await?,
+        None => Playlist::lock().await?,
+    })
+}
+
+pub async fn songs(
+    category: Option<String>,
+    playlist_name: Option<String>,
+    sort: Option<SongSort>,
+) -> anyhow::Result<()> {
+    let category = category
+        .as_deref()
+        .map(|c| (CONFIG.category(c), category_pattern(c)));
+    let mut playlist = load(playlist_name.as_deref()).await?;
+    if matches!(sort, Some(SongSort::Duration)) && playlist.songs.iter().any(|s| s.time == 0) {
+        let lock = lock(playlist_name.as_deref()).await?;
+        playlist = load(playlist_name.as_deref()).await?;
+        if duration::fill_durations(&mut playlist).await > 0 {
+            lock.save(&playlist).await?;
+        }
+    }
+
+    let filter = |s: &Song| match category {
+        Some((ref category, ref pattern)) => s.is_in(category) || matches_pattern(s, pattern),
+        None => true,
+    };
+    let mut songs = playlist
+        .songs
+        .into_iter()
+        .filter(filter)
+        .collect::<Vec<_>>();
+    let plays = match sort {
+        None => {
+            if output::is_json() {
+                return output::json(&songs.iter().map(SongJson::from).collect::<Vec<_>>());
+            }
+            for Song { name, link, .. } in songs {
+                println!("{} :: {}", link, name);
+            }
+            return Ok(());
+        }
+        Some(SongSort::Added | SongSort::Duration) => {
+            match sort {
+                Some(SongSort::Added) => songs.sort_by_key(|s| Reverse(s.added)),
+                // songs whose duration couldn't be found have a duration of 0, so they come last
+                _ => songs.sort_by_key(|s| Reverse(s.time)),
+            }
+            if output::is_json() {
+                return output::json(&songs.iter().map(SongJson::from).collect::<Vec<_>>());
+            }
+            for song in songs {
+                let column = match sort {
+                    Some(SongSort::Added) => Added(song.added).to_string(),
+                    _ if song.time == 0 => "unknown".to_owned(),
+                    _ => DurationFmt(Duration::from_secs(song.time)).to_string(),
+                };
+                println!("{:16}  {} :: {}", column, song.link, song.name);
+            }
+            return Ok(());
+        }
+        Some(SongSort::Plays | SongSort::Recent) => {
+            statistics::plays().await.context("loading statistics")?
+        }
+    };
+    let mut songs = songs
+        .into_iter()
+        .map(|s| (plays.get(s.link.id()).copied().unwrap_or_default(), s))
+        .collect::<Vec<_>>();
+    match sort {
+        Some(SongSort::Plays) => songs.sort_by_key(|(p, _)| Reverse(p.count)),
+        _ => songs.sort_by_key(|(p, _)| Reverse(p.last)),
+    }
+    if output::is_json() {
+        let songs = songs
+            .iter()
+            .map(|(plays, song)| SongJson::from(song).with_plays(*plays))
+            .collect::<Vec<_>>();
+        return output::json(&songs);
+    }
+    for (plays, Song { name, link, .. }) in songs {
+        println!(
+            "{:5}  {:16}  {} :: {}",
+            plays.count,
+            LastPlayed(plays.last),
+            link,
+            name
+        );
+    }
+    Ok(())
+}
+
+/// A song as printed with `--output json`.
+#[derive(Serialize)]
+struct SongJson<'s> {
+    name: &'s str,
+    link: &'s str,
+    categories: Vec<&'s Category>,
+    /// In seconds, 0 if it isn't known.
+    duration: u64,
+    added: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    plays: Option<PlaysJson>,
+    #[serde(flatten)]
+    whereabouts: Option<WhereaboutsJson>,
+}
+
+#[derive(Serialize)]
+struct PlaysJson {
+    plays: u64,
+    skips: u64,
+    last_played: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct WhereaboutsJson {
+    cached: Option<CachedJson>,
+    queued: Vec<QueuedJson>,
+}
+
+#[derive(Serialize)]
+struct CachedJson {
+    path: PathBuf,
+    bytes: u64,
+}
+
+#[derive(Serialize)]
+struct QueuedJson {
+    player: PlayerIndex,
+    position: usize,
+}
+
+impl<'s> From<&'s Song> for SongJson<'s> {
+    fn from(song: &'s Song) -> Self {
+        Self {
+            name: &song.name,
+            link: song.link.as_str(),
+            categories: song.categories.iter().collect(),
+            duration: song.time,
+            added: song.added.map(DateTime::from),
+            plays: None,
+            whereabouts: None,
+        }
+    }
+}
+
+impl SongJson<'_> {
+    fn with_plays(self, plays: statistics::Plays) -> Self {
+        Self {
+            plays: Some(PlaysJson {
+                plays: plays.count,
+                skips: plays.skipped,
+                last_played: plays.last,
+            }),
+            ..self
+        }
+    }
+
+    fn with_whereabouts(self, whereabouts: Whereabouts) -> Self {
+        let this = match whereabouts.plays {
+            Some(plays) => self.with_plays(plays),
+            None => self,
+        };
+        Self {
+            whereabouts: Some(WhereaboutsJson {
+                cached: whereabouts
+                    .cached
+                    .map(|(path, bytes)| CachedJson { path, bytes }),
+                queued: whereabouts
+                    .queued
+                    .into_iter()
+                    .map(|(player, position)| QueuedJson { player, position })
+                    .collect(),
+            }),
+            ..this
+        }
+    }
+}
+
+/// Where a song can be found besides the playlist.
+struct Whereabouts {
+    /// How often it was played, `None` if there are no statistics.
+    plays: Option<statistics::Plays>,
+    /// The downloaded file and its size.
+    cached: Option<(PathBuf, u64)>,
+    /// The players that have it queued, and where.
+    queued: Vec<(PlayerIndex, usize)>,
+}
+
+impl Whereabouts {
+    async fn of(id: &VideoId) -> Self {
+        let cached = match crate::dl_dir().await {
+            Ok(dl_dir) => {
+                match downloaded::search_cache_for(&dl_dir, &VideoLink::from_id(id)).await {
+                    Ok(Some(path)) => {
+                        let bytes = tokio::fs::metadata(&path).await.map_or(0, |m| m.len());
+                        Some((path, bytes))
+                    }
+                    Ok(None) => None,
+                    Err(e) => {
+                        tracing::error!(error = ?e, "failed to search the download dir");
+                        None
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::error!(error = ?e, "failed to find the download dir");
+                None
+            }
+        };
+        let mut queued = Vec::new();
+        // asking for the players would start the daemon, which isn't worth it to find nothing
+        let all = async {
+            players::ping_daemon().await?;
+            players::all().await
+        };
+        match all.await {
+            Ok(all) => {
+                for player in all {
+                    let Ok(queue) = player.queue().await else {
+                        continue;
+                    };
+                    queued.extend(
+                        queue
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, i)| i.to_item().id() == Some(id))
+                            .map(|(pos, _)| (player.index(), pos)),
+                    );
+                }
+            }
+            Err(e) => tracing::debug!(error = ?e, "no players to look for the song in"),
+        }
+        Self {
+            plays: plays(id).await,
+            cached,
+            queued,
+        }
+    }
+}
+
+impl fmt::Display for Whereabouts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(plays) = self.plays {
+            write!(
+                f,
+                "\n§bplays:§r {}, skipped {} (last played: {})",
+                plays.count,
+                plays.skipped,
+                LastPlayed(plays.last)
+            )?;
+        }
+        match &self.cached {
+            Some((path, bytes)) => write!(
+                f,
+                "\n§bdownloaded:§r {} ({} MB)",
+                path.display(),
+                bytes / 1_000_000
+            )?,
+            None => write!(f, "\n§bdownloaded:§r no")?,
+        }
+        if self.queued.is_empty() {
+            write!(f, "\n§bqueued:§r no")?;
+        } else {
+            write!(
+                f,
+                "\n§bqueued:§r {}",
+                self.queued
+                    .iter()
+                    .map(|(player, pos)| format!("{} at {pos}", PlayerLink::from(*player)))
+                    .format(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// When a song was added, for `m songs --sort added`.
+struct Added(Option<SystemTime>);
+
+impl fmt::Display for Added {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(t) => f.pad(
+                &DateTime::<Local>::from(t)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string(),
+            ),
+            None => f.pad("unknown"),
+        }
+    }
+}
+
+struct LastPlayed(Option<DateTime<Utc>>);
+
+impl fmt::Display for LastPlayed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(t) => f.pad(&t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()),
+            None => f.pad("never"),
+        }
+    }
+}
+
+/// Categories given by the user were matched as regexes before they were hierarchical, so they
+/// still are, on top of matching their children. `m songs 'ro.k'` and `m play -c roc` keep
+/// working. Names that aren't valid regexes are matched literally.
+pub fn category_pattern(category: &str) -> Regex {
+    Regex::new(category).unwrap_or_else(|_| {
+        Regex::new(&regex::escape(category)).expect("escaped regexes are valid")
+    })
+}
+
+/// Whether any of the song's categories matches a [`category_pattern`].
+pub fn matches_pattern(song: &Song, pattern: &Regex) -> bool {
+    song.categories.iter().any(|c| pattern.is_match(c.as_str()))
+}
+
+/// When each song was last played, for matching queries that use it. Statistics are only
+/// loaded if one of the queries needs them.
+pub async fn last_played(
+    queries: &[&Query],
+) -> anyhow::Result<impl Fn(&Song) -> Option<SystemTime>> {
+    let plays = if queries.iter().any(|q| q.uses_plays()) {
+        Some(statistics::plays().await.context("loading statistics")?)
+    } else {
+        None
+    };
+    Ok(move |song: &Song| {
+        plays
+            .as_ref()?
+            .get(song.link.id())?
+            .last
+            .map(SystemTime::from)
+    })
+}
+
+/// How often a song was played, `None` if there are no statistics.
+async fn plays(id: &VideoId) -> Option<statistics::Plays> {
+    match statistics::plays().await {
+        Ok(plays) => Some(plays.get(id).copied().unwrap_or_default()),
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to load statistics");
+            None
+        }
+    }
+}
+
+pub async fn cat() -> anyhow::Result<()> {
+    let playlist = Playlist::load().await?;
+    let mut cat = playlist.categories().collect::<Vec<_>>();
+    cat.sort_unstable_by_key(|(_, count)| *count);
+    if output::is_json() {
+        #[derive(Serialize)]
+        struct CategoryJson<'c> {
+            category: &'c str,
+            count: usize,
+        }
+        let cat = cat
+            .iter()
+            .map(|&(category, count)| CategoryJson { category, count })
+            .collect::<Vec<_>>();
+        return output::json(&cat);
+    }
+    for (c, count) in cat {
+        println!("{:5}  {}", count, c);
+    }
+    Ok(())
+}
+
+/// The songs `m cat add --where` picks, given either links or a query, smart playlist or
+/// category.
+async fn picked(songs: &[String]) -> anyhow::Result<Box<dyn Fn(&Song) -> bool>> {
+    let links = songs
+        .iter()
+        .map(|s| VideoLink::try_from(s.clone()))
+        .collect::<Result<Vec<_>, _>>();
+    if let Ok(links) = links {
+        let ids = links.iter().map(|l| l.id().boxed()).collect::<HashSet<_>>();
+        return Ok(Box::new(move |s| ids.contains(s.link.id())));
+    }
+    let source = songs.join(" ");
+    let query =
+        if source.contains([':', '<', '>', '=']) || CONFIG.smart_playlists.contains_key(&source) {
+            CONFIG.smart_query(&source)?
+        } else {
+            Query::Category(CONFIG.category(&source))
+        };
+    let last_played = last_played(&[&query]).await?;
+    Ok(Box::new(move |s| query.matches_with(s, last_played(s))))
+}
+
+/// Saves the playlist if any songs were `changed` and says which.
+async fn save_changed(
+    lock: PlaylistLock,
+    playlist: &Playlist,
+    what: String,
+    changed: Vec<String>,
+) -> anyhow::Result<()> {
+    if changed.is_empty() {
+        notify!("{}: no songs changed", what);
+        return Ok(());
+    }
+    lock.save(playlist).await?;
+    notify!(
+        "{}: {} songs changed", what, changed.len();
+        content: "{}", changed.iter().format("\n")
+    );
+    Ok(())
+}
+
+fn names(songs: Vec<&Song>) -> Vec<String> {
+    songs.into_iter().map(|s| s.name.clone()).collect()
+}
+
+pub async fn cat_add(category: String, songs: Vec<String>) -> anyhow::Result<()> {
+    let category = CONFIG.category(&category);
+    let picked = picked(&songs).await?;
+    let lock = Playlist::lock().await?;
+    let mut playlist = Playlist::load().await?;
+    let changed = names(playlist.add_category(&category, picked)?);
+    save_changed(lock, &playlist, format!("added {category}"), changed).await
+}
+
+pub async fn cat_rename(old: String, new: String) -> anyhow::Result<()> {
+    let (old, new) = (CONFIG.category(&old), CONFIG.category(&new));
+    let lock = Playlist::lock().await?;
+    let mut playlist = Playlist::load().await?;
+    let changed = names(playlist.rename_category(&old, &new)?);
+    save_changed(lock, &playlist, format!("renamed {old} to {new}"), changed).await
+}
+
+pub async fn cat_remove(category: String) -> anyhow::Result<()> {
+    let category = CONFIG.category(&category);
+    let lock = Playlist::lock().await?;
+    let mut playlist = Playlist::load().await?;
+    let changed = names(playlist.remove_category(&category)?);
+    save_changed(lock, &playlist, format!("removed {category}"), changed).await
+}
+
+/// Adds a video to the playlist. Unless `name` is given, it's named after the video's title,
+/// without the noise matched by the `title_noise` regexes from the config.
+///
+/// If the video is already in the playlist the categories it's missing are added to it
+/// instead, unless `force` is set, in which case it's added again.
+pub async fn new(
+    link: Link,
+    name: Option<String>,
+    categories: Vec<String>,
+    playlist: Option<String>,
+    force: bool,
+) -> anyhow::Result<VideoLink> {
+    let link = link
+        .into_video()
+        .map_err(|link| anyhow::anyhow!("{} is not a video link", link))?;
+    let already_there = match &playlist {
+        Some(name) => PlaylistSet::open()?.contains_song(name, link.id()).await?,
+        None => Playlist::contains_song(link.id()).await?,
+    };
+    if already_there && !force {
+        merge_categories(&link, &categories, playlist.as_deref()).await?;
+        return Ok(link);
+    }
+    notify!("Fetching song info");
+    let mut short = link.clone();
+    short.shorten();
+    let metadata = YtdlBuilder::new(&link).request_json().await?;
+    let song = Song {
+        time: metadata.duration().map_or(0, |d| d.as_secs()),
+        added: Some(SystemTime::now()),
+        link: short,
+        name: match name {
+            Some(name) => name,
+            None => util::clean_title(&metadata.title, &CONFIG.title_noise()?),
+        },
+        categories: categories.iter().map(|c| CONFIG.category(c)).collect(),
+    };
+    match &playlist {
+        Some(name) => PlaylistSet::open()?.add_song(name, &song).await?,
+        None => Playlist::add_song(&song).await?,
+    }
+    notify!("Song added"; content: "{}", song);
+    Ok(link)
+}
+
+/// Adds the categories a song that is already in the playlist is missing.
+async fn merge_categories(
+    link: &VideoLink,
+    categories: &[String],
+    playlist_name: Option<&str>,
+) -> anyhow::Result<()> {
+    let lock = lock(playlist_name).await?;
+    let mut playlist = load(playlist_name).await?;
+    let Some(mut song) = playlist.find_song_mut(|s| s.link.id() == link.id()) else {
+        // the id was found in the file, but only as part of some other song's name
+        bail!("{link} is in the playlist but it couldn't be found, try with --force");
+    };
+    let missing = categories
+        .iter()
+        .map(|c| CONFIG.category(c))
+        .filter(|c| !song.categories.contains(c))
+        .collect::<Vec<_>>();
+    let name = song.name.clone();
+    if missing.is_empty() {
+        error!("Song already in playlist"; content: "{}", name);
+        return Ok(());
+    }
+    // only say categories were added once they are saved, which never happens to songs of
+    // included playlists
+    if let Err(e) = song.ensure_editable() {
+        error!(
+            "Song already in playlist";
+            content: "{} not added: {}", missing.iter().format(", "), e
+        );
+        return Ok(());
+    }
+    for c in &missing {
+        song.categories.push(c.clone());
+    }
+    lock.save(&playlist).await?;
+    notify!(
+        "Song already in playlist";
+        content: "added {} to {}", missing.iter().format(", "), name
+    );
+    Ok(())
+}
+
+/// What to do with the songs of a playlist that are already in the personal playlist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Leave them untouched.
+    #[default]
+    Skip,
+    /// Add the missing categories to them.
+    Merge,
+    /// Ask the user, for each one, which of the above to do.
+    Ask,
+    /// Add them again.
+    Force,
+}
+
+struct Conflict {
+    link: VideoLink,
+    name: String,
+    missing: Vec<Category>,
+}
+
+async fn resolve_conflicts(
+    conflicts: Vec<Conflict>,
+    policy: ConflictPolicy,
+) -> anyhow::Result<Vec<Conflict>> {
+    if policy != ConflictPolicy::Ask || conflicts.is_empty() {
+        return Ok(match policy {
+            ConflictPolicy::Skip | ConflictPolicy::Force => Vec::new(),
+            _ => conflicts,
+        });
+    }
+    println!(
+        "{} songs are already in the playlist without these categories:",
+        conflicts.len()
+    );
+    for c in &conflicts {
+        println!("  {} (missing: {})", c.name, c.missing.iter().format(", "));
+    }
+    let mut merge = Vec::new();
+    let mut default = None;
+    for c in conflicts {
+        let choice = match default {
+            Some(choice) => choice,
+            None => {
+                const OPTIONS: [&str; 4] = ["merge", "skip", "merge all", "skip all"];
+                let prompt = format!("{} (missing: {})", c.name, c.missing.iter().format(", "));
+                match selector::selector(OPTIONS, &prompt, OPTIONS.len())
+                    .await?
+                    .as_deref()
+                {
+                    Some("merge") => true,
+                    Some("merge all") => *default.insert(true),
+                    Some("skip all") | None => *default.insert(false),
+                    _ => false,
+                }
+            }
+        };
+        if choice {
+            merge.push(c);
+        }
+    }
+    Ok(merge)
+}
+
+/// What `m add-playlist` did with each video of the playlist.
+#[derive(Debug, Default, Serialize)]
+struct AddPlaylistReport {
+    added: Vec<String>,
+    merged: Vec<String>,
+    skipped: Vec<String>,
+    failed: Vec<String>,
+}
+
+pub async fn add_playlist(
+    link: &Link,
+    categories: Vec<String>,
+    policy: ConflictPolicy,
+    queue: bool,
+) -> anyhow::Result<()> {
+    let link = match link.as_playlist() {
+        Some(s) => s,
+        None => return Err(anyhow::anyhow!("Not a playlist link")),
+    };
+    tracing::debug!("loading playlist");
+    let categories = categories
+        .iter()
+        .map(|c| CONFIG.category(c))
+        .collect::<Vec<_>>();
+    let playlist = Playlist::load().await?;
+    let mut id_stream = std::pin::pin!(YtdlBuilder::new(link).request_playlist()?);
+    let mut report = AddPlaylistReport::default();
+    let mut seen = HashSet::new();
+    let mut conflicts = Vec::new();
+    while let Some(b) = id_stream.try_next().await? {
+        let link = VideoLink::from_id(b.id());
+        if !seen.insert(link.id().boxed()) {
+            // the same video more than once in the playlist being added
+            continue;
+        }
+        match playlist.find_by_link(&link) {
+            Some(song) if policy != ConflictPolicy::Force => {
+                let missing = categories
+                    .iter()
+                    .filter(|c| !song.categories.contains(c))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if missing.is_empty() {
+                    report.skipped.push(format!("{} ({link})", song.name));
+                } else {
+                    conflicts.push(Conflict {
+                        link,
+                        name: song.name.clone(),
+                        missing,
+                    });
+                }
+                continue;
+            }
+            _ => {}
+        }
+        let r = async {
+            let song = add_song(link.clone(), categories.iter().cloned().collect()).await?;
+            report.added.push(format!("{} ({link})", song.name));
+            if queue {
+                crate::queue_ctl::queue(Default::default(), Some(Item::Link(link.clone().into())))
+                    .await?;
+            }
+            anyhow::Ok(())
+        };
+        if let Err(e) = r.await {
+            tracing::error!("failed adding item to playlist: {:?}", e);
+            report.failed.push(link.to_string());
+        }
+    }
+    let described = |c: &Conflict| format!("{} ({})", c.name, c.link);
+    let conflicting = conflicts.iter().map(described).collect::<Vec<_>>();
+    let merge = resolve_conflicts(conflicts, policy).await?;
+    let picked = merge.iter().map(described).collect::<Vec<_>>();
+    report
+        .skipped
+        .extend(conflicting.into_iter().filter(|c| !picked.contains(c)));
+    if !merge.is_empty() {
+        // reload, songs were appended to the file since it was first loaded
+        let lock = Playlist::lock().await?;
+        let mut playlist = Playlist::load().await?;
+        let mut merged = Vec::new();
+        for c in &merge {
+            let Some(mut song) = playlist.find_song_mut(|s| s.link.id() == c.link.id()) else {
+                continue;
+            };
+            if let Err(e) = song.ensure_editable() {
+                tracing::error!("failed merging categories: {:?}", e);
+                report.failed.push(described(c));
+                continue;
+            }
+            for cat in &c.missing {
+                song.categories.push(cat.clone());
+            }
+            merged.push(described(c));
+        }
+        lock.save(&playlist).await?;
+        report.merged = merged;
+    }
+    if output::is_json() {
+        return output::json(&report);
+    }
+    let summary = [
+        ("added", &report.added),
+        ("merged", &report.merged),
+        ("skipped", &report.skipped),
+        ("failed", &report.failed),
+    ];
+    notify!(
+        "playlist added";
+        content: "{}",
+        summary
+            .iter()
+            .map(|(what, songs)| format!("{} {}", songs.len(), what))
+            .format(", ")
+    );
+    for (what, songs) in summary {
+        for song in songs {
+            println!("{what}: {song}");
+        }
+    }
+    Ok(())
+}
+
+pub async fn ch_cat(playlist_name: Option<String>) -> anyhow::Result<()> {
+    let current = Queue::link(PlayerLink::current()).await?;
+    let lock = lock(playlist_name.as_deref()).await?;
+    let mut playlist = load(playlist_name.as_deref()).await?;
+    let current = current
+        .id()
+        .ok_or_else(|| anyhow::anyhow!("current song is not identified"))?;
+
+    let mut current = match playlist.find_song_mut(|s| s.link.id() == current) {
+        Some(c) => c,
+        None => return Err(anyhow::anyhow!("current song not in playlist")),
+    };
+    current.ensure_editable()?;
+
+    while let Some(new_cat) = selector::selector(
+        current.categories.iter(),
+        "Category name? (Esq to quit)",
+        current.categories.len(),
+    )
+    .await?
+    {
+        if let Some(old_cat) = current.categories.push(CONFIG.category(&new_cat)) {
+            current.categories.remove(&old_cat);
+        }
+    }
+    lock.save(&playlist).await?;
+    Ok(())
+}
+
+pub async fn delete_song(current: bool, partial_name: Vec<String>) -> anyhow::Result<()> {
+    let lock = Playlist::lock().await?;
+    let mut playlist = Playlist::load().await?;
+    let idx = if current {
+        let current = Queue::link(PlayerLink::current()).await?;
+        let current = current
+            .id()
+            .ok_or_else(|| anyhow::anyhow!("current song is not identified"))?;
+        playlist.find_song_mut(|s| s.link.id() == current).into()
+    } else if !partial_name.is_empty() {
+        playlist.partial_name_search_mut(partial_name.iter().map(String::as_str))
+    } else {
+        unreachable!()
+    };
+    let song = super::handle_search_result(idx)?;
+    song.ensure_editable()?;
+    let deleted = song.delete();
+    lock.save(&playlist).await?;
+    notify!("song deleted"; content: "{}", deleted);
+    Ok(())
+}
+
+async fn add_song(link: VideoLink, categories: HashSet<Category>) -> anyhow::Result<Song> {
+    let song = fetch_song(link, categories).await?;
+    Playlist::add_song(&song).await?;
+    notify!("Song added"; content: "{}", song);
+    Ok(song)
+}
+
+async fn fetch_song(mut link: VideoLink, categories: HashSet<Category>) -> anyhow::Result<Song> {
+    let b = YtdlBuilder::new(&link)
+        .get_title()
+        .get_duration()
+        .request()
+        .await?;
+    link.shorten();
+    let song = Song {
+        time: b.duration().as_secs(),
+        added: Some(SystemTime::now()),
+        link,
+        name: b.title(),
+        categories: categories.into_iter().collect(),
+    };
+    Ok(song)
+}
+
+pub(crate) async fn info(song: Vec<String>, just_id: bool, current: bool) -> anyhow::Result<()> {
+    let playlist = playlist::Playlist::load().await?;
+    let item = if current {
+        Queue::link(&crate::chosen_index())
+            .await
+            .context("failed getting the current song")?
+    } else {
+        Item::from(song.join(" "))
+    };
+    let in_playlist = item
+        .id()
+        .and_then(|id| playlist.find_by_link(&VideoLink::from_id(id)));
+    let found = match in_playlist {
+        Some(s) => PartialSearchResult::One(s),
+        None if current => PartialSearchResult::None,
+        None => {
+            let words = song
+                .iter()
+                .map(String::as_str)
+                .flat_map(|s| s.split_whitespace());
+            playlist
+                .partial_name_search(words)
+                .map(PlaylistIndex::into_song)
+        }
+    };
+
+    match found {
+        PartialSearchResult::None => {
+            if just_id {
+                match item.id() {
+                    Some(id) if output::is_json() => output::json(id.as_str())?,
+                    Some(id) => println!("{}", id.as_str()),
+                    None => error!("song doens't have an id"),
+                }
+                return Ok(());
+            }
+            let vid = match &item {
+                Item::Link(Link::Video(l)) => YtdlBuilder::new(l).get_title().request().await?,
+                Item::Search(s) => YtdlBuilder::new(s).get_title().search().await?,
+                i => {
+                    let Some(id) = i.id() else {
+                        bail!("info for {i} not suported");
+                    };
+                    YtdlBuilder::new(&VideoLink::from_id(id))
+                        .get_title()
+                        .request()
+                        .await?
+                }
+            };
+            let extra = Whereabouts::of(vid.id()).await;
+            if output::is_json() {
+                let link = format!("http://youtu.be/{}", vid.id().as_str());
+                let json = SongJson {
+                    name: vid.title_ref(),
+                    link: &link,
+                    categories: vec![],
+                    duration: 0,
+                    added: None,
+                    plays: None,
+                    whereabouts: None,
+                };
+                return output::json(&json.with_whereabouts(extra));
+            }
+            notify!(
+                "song info:";
+                content:
+                    "§bname:§r {}\n§blink:§r http://youtu.be/{}{}",
+                    vid.title_ref(),
+                    vid.id().as_str(),
+                    extra,
+            )
+        }
+        PartialSearchResult::One(s) => {
+            if just_id {
+                if output::is_json() {
+                    return output::json(s.link.id().as_str());
+                }
+                println!("{}", s.link.id().as_str());
+                return Ok(());
+            }
+            let extra = Whereabouts::of(s.link.id()).await;
+            if output::is_json() {
+                return output::json(&SongJson::from(s).with_whereabouts(extra));
+            }
+            notify!(
+                "song info:";
+                content:
+                    "§bname:§r {}\n§blink:§r {}\n§bcategories:§r {}{}",
+                    s.name,
+                    s.link,
+                    s.categories.iter().format(" | "),
+                    extra
+            );
+        }
+        PartialSearchResult::Many(m) if output::is_json() => {
+            bail!("too many matches: {}", m.iter().format(", "));
+        }
+        PartialSearchResult::Many(m) => {
+            notify!(
+                "too many matches:";
+                content: " - {}", m.iter().format("\n - ")
+            );
+        }
+    }
+    Ok(())
+}
@@ -1,40 +1,52 @@
 use crate::{
-    arg_parse::{Amount, DeQueue, DeQueueIndex, QueueOpts},
+    arg_parse::{Amount, DeQueue, DumpFormat, Move, QueueOpts, QueuesCmd, Swap},
+    config::{Route, CONFIG},
     download_ctl::check_cache_ref,
-    notify,
-    util::{dl_dir, selector::selector, with_video::with_video_env, DisplayEither, DurationFmt},
+    error, notify, playlist_ctl,
+    util::{
+        dl_dir, output, selector::selector, with_video::with_video_env, DisplayEither, DurationFmt,
+    },
 };
 
-use std::{collections::HashSet, io::Write, path::PathBuf, pin::pin};
+use std::{
+    collections::HashSet,
+    io::Write,
+    path::PathBuf,
+    pin::pin,
+    time::{Duration, SystemTime},
+};
 
 use anyhow::{bail, Context};
+use chrono::{DateTime, Local, Utc};
 use futures_util::{
-    future::ready,
-    stream::{self, BoxStream, FuturesUnordered},
-    Stream, StreamExt, TryStreamExt,
+    stream::{self, FuturesUnordered},
+    StreamExt,
 };
 use itertools::Itertools;
 use mlib::{
-    item::{
-        link::{ChannelLink, VideoLink},
-        PlaylistLink,
+    downloaded::thumbnails,
+    item::link::VideoLink,
+    players::{
+        self, error::MpvError, PlayerLink, PlayerProfile, QueuePlacement, SmartQueueOpts,
+        SmartQueueSummary,
     },
-    players::{self, error::MpvError, PlayerLink, SmartQueueOpts, SmartQueueSummary},
-    playlist::Playlist,
-    queue::{Current, Item, Queue},
-    ytdl::YtdlBuilder,
-    Error, Link, Search, VideoId,
+    playlist::{query::Query, Category, Playlist, Song},
+    queue::{Current, Eta, Item, Queue, Snapshot},
+    ytdl::{
+        chapters::{self, Chapter},
+        expand::{self, TmpCache},
+        YtdlBuilder,
+    },
+    Link, Search,
 };
 use rand::{prelude::SliceRandom, rngs};
-use serde::Deserialize;
-use tokio::io::BufReader;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use tokio::{
     fs::File,
-    io::{AsyncBufReadExt, AsyncWriteExt, BufWriter},
+    io::{AsyncWriteExt, BufWriter},
     process::Command as Fork,
 };
-use tokio_stream::wrappers::LinesStream;
-use tracing::debug;
 
 pub enum CurrentDisplayMode {
     Default,
@@ -42,13 +54,23 @@ pub enum CurrentDisplayMode {
     LinkId,
 }
 
-pub async fn current(mode: CurrentDisplayMode, notify: bool) -> anyhow::Result<()> {
+pub async fn current(
+    mode: CurrentDisplayMode,
+    notify: bool,
+    next: Option<usize>,
+) -> anyhow::Result<()> {
     match mode {
         CurrentDisplayMode::Default => {
-            let current =
-                Queue::current(PlayerLink::current(), mlib::queue::CurrentOptions::GetNext)
-                    .await
-                    .context("loading the current queue")?;
+            let options = match next {
+                Some(n) => mlib::queue::CurrentOptions::GetUpcoming(n),
+                None => mlib::queue::CurrentOptions::GetNext,
+            };
+            let current = Queue::current(PlayerLink::current(), options)
+                .await
+                .context("loading the current queue")?;
+            if output::is_json() {
+                return output::json(&current);
+            }
 
             display_current(&current, notify).await
         }
@@ -57,18 +79,19 @@ pub async fn current(mode: CurrentDisplayMode, notify: bool) -> anyhow::Result<(
                 .await
                 .context("loading the queue to fetch the link")?;
             tracing::debug!("{:?}", link);
-            match mode {
+            let link = match mode {
                 CurrentDisplayMode::Default => unreachable!(),
-                CurrentDisplayMode::Link => notify!("{}", link),
-                CurrentDisplayMode::LinkId => {
-                    notify!(
-                        "{}",
-                        link.id()
-                            .ok_or_else(|| anyhow::anyhow!("no id for this video"))?
-                            .as_str()
-                    )
-                }
+                CurrentDisplayMode::Link => link.to_string(),
+                CurrentDisplayMode::LinkId => link
+                    .id()
+                    .ok_or_else(|| anyhow::anyhow!("no id for this video"))?
+                    .as_str()
+                    .to_owned(),
+            };
+            if output::is_json() {
+                return output::json(&link);
             }
+            notify!("{}", link);
             Ok(())
         }
     }
@@ -87,19 +110,30 @@ pub async fn display_current(current: &Current, notify: bool) -> anyhow::Result<
         }
         None => current.title.clone(),
     };
+    let by = match (&current.track.uploader, &current.track.album) {
+        (Some(uploader), Some(album)) => format!("\n§bBy§r: {uploader} ({album})"),
+        (Some(uploader), None) => format!("\n§bBy§r: {uploader}"),
+        (None, _) => String::new(),
+    };
     let current_categories = if current.categories.is_empty() {
         String::new()
     } else {
         format!("\n\n| {} |", current.categories.iter().join(" | "))
     };
-    let up_next = if let Some(next) = current.next.clone() {
+    let up_next = if current.upcoming.len() > 1 {
+        format!(
+            "\n\n=== UP NEXT ===\n{}",
+            current.upcoming.iter().join("\n")
+        )
+    } else if let Some(next) = current.next.clone() {
         format!("\n\n=== UP NEXT ===\n{next}")
     } else {
         String::new()
     };
     notify!("Now Playing";
-        content: "{}\n{}🔉{:.0}% | <{}{}> {:.0}%\n          {}/{}{}{}",
+        content: "{}{}\n{}🔉{:.0}% | <{}{}> {:.0}%\n          {}/{}{}{}",
         song,
+        by,
         if current.playing { ">" } else { "||" },
         current.volume,
         plus,
@@ -126,22 +160,80 @@ pub async fn now(Amount { amount }: Amount) -> anyhow::Result<()> {
     .await
     .context("failed getting queue")?;
     let current = queue.current_idx();
-    stream::iter(queue.iter())
-        .map(|i| {
-            debug!("translating queue item: {i:?}");
-            async { (i.index, i.item.fetch_item_title().await) }
-        })
-        .buffered(8)
-        .for_each(|(index, s)| async move {
-            static SEPERATORS: [&str; 2] = ["   ", "==>"];
-            println!(
-                "{:2} {} {}",
+    let items = queue.iter().map(|i| i.item.clone()).collect::<Vec<_>>();
+    let mut titles =
+        pin!(stream::iter(queue.iter().map(|i| i.index)).zip(mlib::item::stream_titles(&items)));
+    if output::is_json() {
+        #[derive(Serialize)]
+        struct Entry {
+            index: usize,
+            title: String,
+            current: bool,
+        }
+        let entries = titles
+            .map(|(index, title)| Entry {
                 index,
-                SEPERATORS[(index == current) as usize],
-                s
-            )
-        })
+                title,
+                current: index == current,
+            })
+            .collect::<Vec<_>>()
+            .await;
+        return output::json(&entries);
+    }
+    while let Some((index, s)) = titles.next().await {
+        static SEPERATORS: [&str; 2] = ["   ", "==>"];
+        println!(
+            "{:2} {} {}",
+            index,
+            SEPERATORS[(index == current) as usize],
+            s
+        )
+    }
+    Ok(())
+}
+
+pub async fn eta(index: Option<usize>) -> anyhow::Result<()> {
+    let player = PlayerLink::current();
+    let queue = Queue::load_full(player)
+        .await
+        .context("failed getting queue")?;
+    let current = queue.current_idx();
+    let upcoming = queue.after();
+    let target = match index {
+        Some(i) if i <= current => bail!("song {i} is not after the current song"),
+        Some(i) if i - current > upcoming.len() => bail!("there is no song {i} in the queue"),
+        Some(i) => Some(i - current - 1),
+        None => None,
+    };
+    let (duration, playback_time) =
+        futures_util::try_join!(player.duration(), player.playback_time())?;
+    let remaining = Duration::try_from_secs_f64(duration - playback_time).unwrap_or_default();
+    let durations = stream::iter(upcoming)
+        .map(|s| s.item.fetch_item_duration())
+        .buffered(8)
+        .collect::<Vec<_>>()
         .await;
+    let eta = Eta::compute(remaining, &durations, target.unwrap_or(upcoming.len()));
+    let at = |d: Duration| {
+        let d = chrono::Duration::from_std(d).unwrap_or_default();
+        (chrono::Local::now() + d).format("%H:%M")
+    };
+    if let Some(target) = target {
+        println!(
+            "{} starts in {} (at {})",
+            upcoming[target].item.fetch_item_title().await,
+            DurationFmt(eta.until_target),
+            at(eta.until_target),
+        );
+    }
+    println!(
+        "the queue ends in {} (at {})",
+        DurationFmt(eta.until_end),
+        at(eta.until_end)
+    );
+    if eta.unknown > 0 {
+        println!("{} songs of unknown duration were not counted", eta.unknown);
+    }
     Ok(())
 }
 
@@ -151,13 +243,104 @@ where
     I::IntoIter: ExactSizeIterator,
 {
     tracing::debug!(options = ?q, "queueing songs");
+    if q.no_route || CONFIG.routes.is_empty() {
+        return queue_on_current(q, items).await;
+    }
+    let (routed, rest) = route(items).await?;
+    let mut last = None;
+    for (route, items) in routed {
+        last = Some(queue_routed(&q, route, items).await?);
+    }
+    match last {
+        Some(player) if rest.is_empty() => Ok(player),
+        _ => queue_on_current(q, rest).await,
+    }
+}
+
+/// Splits the items that match a route in the config, grouped by route, from the ones that don't.
+async fn route<I>(items: I) -> anyhow::Result<(Vec<(&'static Route, Vec<Item>)>, Vec<Item>)>
+where
+    I: IntoIterator<Item = Item>,
+{
+    let playlist = Playlist::load().await?;
+    let mut routed = Vec::<(&Route, Vec<Item>)>::new();
+    let mut rest = Vec::new();
+    for item in items {
+        let route = match &item {
+            Item::Link(l) => l
+                .as_video()
+                .and_then(|v| playlist.find_by_link(v))
+                .and_then(|song| CONFIG.route(song)),
+            _ => None,
+        };
+        match route {
+            Some(route) => match routed.iter_mut().find(|(r, _)| std::ptr::eq(*r, route)) {
+                Some((_, items)) => items.push(item),
+                None => routed.push((route, vec![item])),
+            },
+            None => rest.push(item),
+        }
+    }
+    Ok((routed, rest))
+}
+
+/// Queues `items` on the player the route names, starting it if it isn't running.
+async fn queue_routed(
+    q: &crate::arg_parse::QueueOpts,
+    route: &Route,
+    items: Vec<Item>,
+) -> anyhow::Result<PlayerLink> {
+    for player in players::all().await? {
+        if player.name().await?.as_deref() == Some(&route.player) {
+            tracing::debug!(player = route.player, "routing {} songs", items.len());
+            return queue_on(q, player, items).await;
+        }
+    }
+    tracing::debug!(player = route.player, "starting a player for the route");
+    start(
+        items,
+        route.video,
+        None,
+        vec![],
+        true,
+        Some(route.player.clone()),
+    )
+    .await
+}
+
+async fn queue_on_current<I>(q: crate::arg_parse::QueueOpts, items: I) -> anyhow::Result<PlayerLink>
+where
+    I: IntoIterator<Item = Item>,
+    I::IntoIter: ExactSizeIterator,
+{
     let player = match players::current().await? {
         Some(index) => PlayerLink::of(index),
         None => {
             tracing::debug!("no mpv instance, starting a new one");
-            return play(items, with_video_env()).await;
+            return play(items, with_video_env(), None, vec![], None).await;
         }
     };
+    queue_on(&q, player, items).await
+}
+
+fn placement(q: &crate::arg_parse::QueueOpts) -> QueuePlacement {
+    match (q.no_move, q.next, q.at) {
+        (true, _, _) => QueuePlacement::End,
+        (_, true, _) => QueuePlacement::Next,
+        (_, _, Some(at)) => QueuePlacement::At(at),
+        _ => QueuePlacement::Smart,
+    }
+}
+
+async fn queue_on<I>(
+    q: &crate::arg_parse::QueueOpts,
+    player: PlayerLink,
+    items: I,
+) -> anyhow::Result<PlayerLink>
+where
+    I: IntoIterator<Item = Item>,
+    I::IntoIter: ExactSizeIterator,
+{
     tracing::debug!("found a player: {player:?}");
     if q.clear {
         notify!("Clearing playlist...");
@@ -171,8 +354,11 @@ where
     let mut notify_tasks = FuturesUnordered::new();
     let items = items.into_iter();
     let item_count = items.len();
-    let mut expanded_items = pin!(expand_playlists(items).inspect(|_| n_targets += 1));
+    let mut expanded_items =
+        pin!(expand::expand_items(items, &PLAYLIST_CACHE).inspect(|_| n_targets += 1));
     let dl_dir = dl_dir().await?;
+    let mut next_placement = placement(q);
+    let mut last_moved_to = None;
     while let Some(mut item) = expanded_items.next().await {
         check_cache_ref(&dl_dir, &mut item).await;
         print!("Queuing song: {} ... ", item);
@@ -182,9 +368,16 @@ where
             moved_to,
             current,
         } = player
-            .smart_queue(item.clone(), SmartQueueOpts { no_move: q.no_move })
+            .smart_queue(
+                item.clone(),
+                SmartQueueOpts {
+                    placement: next_placement,
+                },
+            )
             .await
             .context("when queueing")?;
+        next_placement = next_placement.after(moved_to);
+        last_moved_to = Some(moved_to);
 
         if from != moved_to {
             println!("success");
@@ -209,6 +402,12 @@ where
             }
         })
         .await;
+    // songs queued normally afterwards go after the whole batch, not just after its first song
+    if let (QueuePlacement::Next, Some(last)) = (placement(q), last_moved_to) {
+        if player.last_queue().await?.map_or(true, |l| l < last) {
+            player.last_queue_set(last).await?;
+        }
+    }
     if n_targets > 5 {
         tracing::info!("reseting queue because got {} targets", n_targets);
         player
@@ -219,51 +418,109 @@ where
     Ok(player)
 }
 
+/// The mpv options that play only the `i`th of `chapters`, until the next one starts.
+fn chapter_options(chapters: &[Chapter], i: usize) -> Vec<(String, String)> {
+    let mut options = vec![
+        ("start".into(), chapters[i].start.as_secs_f64().to_string()),
+        ("force-media-title".into(), chapters[i].title.clone()),
+    ];
+    if let Some(next) = chapters.get(i + 1) {
+        options.push(("end".into(), next.start.as_secs_f64().to_string()));
+    }
+    options
+}
+
+/// Queues each chapter of the videos as its own song, so that going to the next or previous song
+/// moves between the songs of a mix. The chapters come from the video or, if it has none, from
+/// its description.
+pub async fn queue_chapters(q: QueueOpts, items: Vec<Item>) -> anyhow::Result<()> {
+    let (player, mut at) = match players::current().await? {
+        Some(index) => {
+            let player = PlayerLink::of(index);
+            if q.clear {
+                player.queue_clear().await.context("clearing queue")?;
+            }
+            if q.reset || q.clear {
+                player.last_queue_clear().await.context("resetting queue")?;
+            }
+            let current = player.queue_pos().await?;
+            let at = match placement(&q) {
+                QueuePlacement::End => None,
+                QueuePlacement::Next => Some(current + 1),
+                QueuePlacement::At(at) => Some(at),
+                QueuePlacement::Smart => {
+                    Some(player.last_queue().await?.unwrap_or(current).max(current) + 1)
+                }
+            };
+            (player, at)
+        }
+        None => (play([], with_video_env(), None, vec![], None).await?, None),
+    };
+    let dl_dir = dl_dir().await?;
+    for item in items {
+        let Some(link) = item.id().map(VideoLink::from_id) else {
+            error!("{} isn't a youtube video, it has no chapters", item);
+            continue;
+        };
+        notify!("getting the chapters of {}", link);
+        let metadata = YtdlBuilder::new(&link).request_json().await?;
+        let mut chapters = metadata.chapters();
+        if chapters.is_empty() {
+            chapters = chapters::from_description(&link).await?;
+        }
+        if chapters.is_empty() {
+            error!("{} has no chapters", metadata.title);
+            continue;
+        }
+        let mut item = Item::Link(link.into());
+        check_cache_ref(&dl_dir, &mut item).await;
+        for i in 0..chapters.len() {
+            player
+                .load_file_with_options(item.clone(), chapter_options(&chapters, i), at)
+                .await
+                .context("when queueing")?;
+            at = at.map(|at| at + 1);
+        }
+        notify!("queued {} songs", chapters.len(); content: "from {}", metadata.title);
+    }
+    if let (QueuePlacement::Smart, Some(at)) = (placement(&q), at) {
+        player.last_queue_set(at - 1).await?;
+    }
+    Ok(())
+}
+
 async fn notify(item: Item, current: usize, target: usize) -> anyhow::Result<()> {
     let img = tempfile::Builder::new().suffix(".png").tempfile()?;
     let (img_file, img_path) = img.into_parts();
     tracing::debug!("image tmp path: {}", img_path.display());
-    let title = match item {
-        Item::Link(l) => {
-            macro_rules! handle {
-                ($thumbnail:expr, $title:expr) => {{
-                    let thumbnail = $thumbnail;
-                    tracing::debug!("thumbnail: {}", thumbnail);
-                    let thumb = reqwest::get(thumbnail).await?;
-                    let mut byte_stream = thumb.bytes_stream();
-                    let mut img_file = BufWriter::new(File::from(img_file));
-                    while let Some(chunk) = byte_stream.next().await.transpose()? {
-                        img_file.write_all(&chunk).await?;
-                    }
-                    img_file.flush().await?;
-
-                    $title
-                }};
+    let (title, thumbnail) = match item {
+        Item::Link(l) => match l.into_video() {
+            Ok(v) => {
+                let thumbnail = thumbnails::get_or_fetch(&dl_dir().await?, v.id()).await?;
+                (v.resolve_link().await, thumbnail)
             }
-            match l.into_video() {
-                Ok(v) => {
-                    let b = YtdlBuilder::new(&v)
-                        .get_title()
-                        .get_thumbnail()
-                        .request()
-                        .await?;
-                    handle!(b.thumbnail(), b.title())
+            Err(pl) => {
+                let Some(pl) = pl.as_playlist() else {
+                    return Ok(());
+                };
+                let b = YtdlBuilder::new(pl)
+                    .get_title()
+                    .get_thumbnail()
+                    .request_playlist()?
+                    .next()
+                    .await
+                    .ok_or_else(|| anyhow::anyhow!("playlist was emtpy"))??;
+                tracing::debug!("thumbnail: {}", b.thumbnail());
+                let thumb = reqwest::get(b.thumbnail()).await?;
+                let mut byte_stream = thumb.bytes_stream();
+                let mut img_file = BufWriter::new(File::from(img_file));
+                while let Some(chunk) = byte_stream.next().await.transpose()? {
+                    img_file.write_all(&chunk).await?;
                 }
-                Err(pl) => match pl.as_playlist() {
-                    Some(pl) => {
-                        let b = YtdlBuilder::new(pl)
-                            .get_title()
-                            .get_thumbnail()
-                            .request_playlist()?
-                            .next()
-                            .await
-                            .ok_or_else(|| anyhow::anyhow!("playlist was emtpy"))??;
-                        handle!(b.thumbnail(), b.title())
-                    }
-                    None => handle!("", String::from("url")),
-                },
+                img_file.flush().await?;
+                (b.title(), img_path.to_path_buf())
             }
-        }
+        },
         Item::File(f) => {
             let mut ffmpeg = Fork::new("ffmpeg")
                 .args(["-y", "-loglevel", "error", "-hide_banner", "-vsync", "2"])
@@ -296,7 +553,7 @@ async fn notify(item: Item, current: usize, target: usize) -> anyhow::Result<()>
                 .title;
 
             ffmpeg.wait().await?;
-            title
+            (title, img_path.to_path_buf())
         }
         _ => return Ok(()),
     };
@@ -304,7 +561,7 @@ async fn notify(item: Item, current: usize, target: usize) -> anyhow::Result<()>
     tracing::debug!("image scaled tmp path: {}", scaled.path().display());
     Fork::new("convert")
         .args(["-scale", "x64", "--"])
-        .arg(&img_path)
+        .arg(&thumbnail)
         .arg(scaled.path())
         .spawn()?
         .wait()
@@ -347,73 +604,292 @@ pub async fn dequeue(d: crate::arg_parse::DeQueue) -> anyhow::Result<()> {
             let to_remove = player.queue_pos().await?;
             player.queue_remove(to_remove).await?;
         }
-        DeQueue::N {
-            i: DeQueueIndex(kind, n),
-        } => {
-            let to_remove = match kind {
-                crate::arg_parse::DeQueueIndexKind::Plus => {
-                    let current = player.queue_pos().await?;
-                    current + n
-                }
-                crate::arg_parse::DeQueueIndexKind::Minus => {
-                    let current = player.queue_pos().await?;
-                    current
-                        .checked_sub(n)
-                        .ok_or_else(|| anyhow::anyhow!("i > {}", n))?
-                }
-                crate::arg_parse::DeQueueIndexKind::Exact => n,
-            };
-            player.queue_remove(to_remove).await?;
+        DeQueue::N { i } => {
+            let current = player.queue_pos().await?;
+            player.queue_remove(i.resolve(current)?).await?;
         }
         DeQueue::Cat { cat } => {
-            let cat = &cat;
-            let playlist = Playlist::stream()
-                .await
-                .context("getting playlist file")?
-                .filter_map(|s| async { s.ok() })
-                .filter_map(
-                    |s| async move { s.categories.iter().any(|c| c.contains(cat)).then_some(s) },
-                )
-                .map(|s| s.link.id().to_string())
-                .collect::<HashSet<_>>()
-                .await;
-            let queue = Queue::load_full(player)
-                .await
-                .context("loading current queue")?;
+            let query = Query::Category(CONFIG.category(&cat));
+            let pattern = playlist_ctl::category_pattern(&cat);
+            dequeue_matching(player, &query, Some(&pattern)).await?
+        }
+        DeQueue::Smart { query } => {
+            dequeue_matching(player, &CONFIG.smart_query(&query)?, None).await?
+        }
+    }
+    Ok(())
+}
 
-            for index in queue.iter().rev().filter_map(|s| {
-                s.item
-                    .id()
-                    .filter(|id| playlist.contains(id.as_str()))
-                    .map(|_| s.index)
-            }) {
-                print!("removing {}... ", index);
-                std::io::stdout().flush()?;
-                player.queue_remove(index).await?;
-                println!(" success");
+/// Removes the songs of the playlist that match the `query`, or whose categories match the
+/// [`category_pattern`](playlist_ctl::category_pattern), from the queue.
+async fn dequeue_matching(
+    player: &PlayerLink,
+    query: &Query,
+    pattern: Option<&Regex>,
+) -> anyhow::Result<()> {
+    let last_played = &playlist_ctl::last_played(&[query]).await?;
+    let matches = |s: &Song| {
+        query.matches_with(s, last_played(s))
+            || pattern.is_some_and(|p| playlist_ctl::matches_pattern(s, p))
+    };
+    let playlist = Playlist::stream()
+        .await
+        .context("getting playlist file")?
+        .filter_map(|s| async { s.ok() })
+        .filter_map(|s| std::future::ready(matches(&s).then_some(s)))
+        .map(|s| s.link.id().to_string())
+        .collect::<HashSet<_>>()
+        .await;
+    let queue = Queue::load_full(player)
+        .await
+        .context("loading current queue")?;
+
+    for index in queue.iter().rev().filter_map(|s| {
+        s.item
+            .id()
+            .filter(|id| playlist.contains(id.as_str()))
+            .map(|_| s.index)
+    }) {
+        print!("removing {}... ", index);
+        std::io::stdout().flush()?;
+        player.queue_remove(index).await?;
+        println!(" success");
+    }
+    Ok(())
+}
+
+pub async fn move_song(Move { from, to, pick }: Move) -> anyhow::Result<()> {
+    let player = PlayerLink::current();
+    let queue = Queue::load_full(player)
+        .await
+        .context("failed getting queue")?;
+    let mut items = queue.iter().map(|s| s.item.clone()).collect::<Vec<_>>();
+    let current = queue.current_idx();
+    // every title is only needed to pick, otherwise just the ones shown at the end
+    let mut titles = None;
+    let (from, to) = match (from, to) {
+        (Some(from), Some(to)) if !pick => (from.resolve(current)?, to.resolve(current)?),
+        _ => {
+            let all = titles.insert(mlib::item::resolve_titles(&items).await);
+            match pick_move(all).await? {
+                Some(picked) => picked,
+                None => return Ok(()),
             }
         }
+    };
+    if from >= items.len() || to >= items.len() {
+        bail!("the queue only has {} songs", items.len());
+    }
+    if from != to {
+        // mpv puts the song before `to`, which is one too early when moving it forward.
+        let target = if from < to { to + 1 } else { to };
+        player.queue_move(from, target).await?;
+    }
+    let moved = items.remove(from);
+    items.insert(to, moved);
+    let current = moved_index(current, from, to);
+    let shown = to.saturating_sub(3)..items.len().min(to.saturating_sub(3) + 7);
+    let titles = match titles {
+        Some(mut titles) => {
+            let moved = titles.remove(from);
+            titles.insert(to, moved);
+            titles.drain(shown.clone()).collect()
+        }
+        None => mlib::item::resolve_titles(&items[shown.clone()]).await,
+    };
+    for (index, title) in shown.zip(titles) {
+        static SEPERATORS: [&str; 3] = ["   ", "==>", "-->"];
+        let sep = if index == current {
+            1
+        } else if index == to {
+            2
+        } else {
+            0
+        };
+        println!("{:2} {} {}", index, SEPERATORS[sep], title);
     }
     Ok(())
 }
 
-pub async fn dump(file: PathBuf) -> anyhow::Result<()> {
+/// Asks which song to move and where to, using the titles of the songs in the queue.
+async fn pick_move(titles: &[String]) -> anyhow::Result<Option<(usize, usize)>> {
+    let entries = titles
+        .iter()
+        .enumerate()
+        .map(|(i, t)| format!("{i:2} {t}"))
+        .collect::<Vec<_>>();
+    let index_of = |picked: String| {
+        picked
+            .split_whitespace()
+            .next()
+            .and_then(|i| i.parse::<usize>().ok())
+            .ok_or_else(|| anyhow::anyhow!("{picked:?} is not a song in the queue"))
+    };
+    let Some(from) = selector(&entries, "Move which song?", entries.len()).await? else {
+        return Ok(None);
+    };
+    let from = index_of(from)?;
+    let Some(to) = selector(&entries, "To where?", entries.len()).await? else {
+        return Ok(None);
+    };
+    Ok(Some((from, index_of(to)?)))
+}
+
+pub async fn swap(Swap { a, b }: Swap) -> anyhow::Result<()> {
+    let player = crate::chosen_index();
+    let current = player.queue_pos().await?;
+    let (a, b) = (a.resolve(current)?, b.resolve(current)?);
+    let len = player.queue_size().await?;
+    if a >= len || b >= len {
+        bail!("the queue only has {len} songs");
+    }
+    player.queue_swap(a, b).await?;
+    Ok(())
+}
+
+/// Where the song at `index` ends up after the one at `from` is moved to `to`.
+fn moved_index(index: usize, from: usize, to: usize) -> usize {
+    if index == from {
+        to
+    } else if from < index && index <= to {
+        index - 1
+    } else if to <= index && index < from {
+        index + 1
+    } else {
+        index
+    }
+}
+
+/// A song of a queue dumped with `--format json`.
+#[derive(Serialize, Deserialize)]
+struct DumpedSong {
+    #[serde(default)]
+    index: usize,
+    item: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    duration: Option<Duration>,
+}
+
+/// A queue dumped with `--format json`.
+#[derive(Serialize, Deserialize)]
+struct DumpedQueue {
+    #[serde(default)]
+    current: usize,
+    songs: Vec<DumpedSong>,
+}
+
+pub async fn dump(file: PathBuf, format: DumpFormat) -> anyhow::Result<()> {
     let q = Queue::load_full(PlayerLink::current()).await?;
     let mut file = BufWriter::new(File::create(file).await?);
-    for s in q.iter() {
-        file.write_all(s.item.as_bytes()).await?;
-        file.write_all(b"\n").await?;
+    if let DumpFormat::Plain = format {
+        for s in q.iter() {
+            file.write_all(s.item.as_bytes()).await?;
+            file.write_all(b"\n").await?;
+        }
+        file.flush().await?;
+        return Ok(());
     }
+    let items = q.iter().map(|s| s.item.clone()).collect::<Vec<_>>();
+    let (titles, durations) = futures_util::join!(
+        mlib::item::resolve_titles(&items),
+        stream::iter(&items)
+            .map(Item::fetch_item_duration)
+            .buffered(8)
+            .collect::<Vec<_>>()
+    );
+    let queue = DumpedQueue {
+        current: q.current_idx(),
+        songs: q
+            .iter()
+            .zip(titles)
+            .zip(durations)
+            .map(|((s, title), duration)| {
+                let Ok(item) = <&str>::try_from(&s.item) else {
+                    bail!(
+                        "{} isn't valid utf-8, only --format plain can dump it",
+                        s.item.as_bytes().escape_ascii()
+                    );
+                };
+                Ok(DumpedSong {
+                    index: s.index,
+                    item: item.to_owned(),
+                    title,
+                    duration,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?,
+    };
+    let contents = match format {
+        DumpFormat::Json => serde_json::to_string_pretty(&queue)? + "\n",
+        _ => annotated(&queue),
+    };
+    file.write_all(contents.as_bytes()).await?;
     file.flush().await?;
     Ok(())
 }
 
+/// What the first line of a queue dumped with `--format annotated` has after the number of songs.
+const ANNOTATED_HEADER: &str = " songs, the current one is ";
+
+/// Writes a queue as its songs, each after a comment saying what it is.
+fn annotated(queue: &DumpedQueue) -> String {
+    use std::fmt::Write;
+    let mut out = format!(
+        "# {}{ANNOTATED_HEADER}{}\n",
+        queue.songs.len(),
+        queue.current
+    );
+    for s in &queue.songs {
+        let marker = if s.index == queue.current {
+            "==>"
+        } else {
+            "   "
+        };
+        let _ = write!(
+            out,
+            "# {:2} {marker} {}",
+            s.index,
+            s.title.replace('\n', " ")
+        );
+        if let Some(d) = s.duration {
+            let _ = write!(out, " ({})", DurationFmt(d));
+        }
+        let _ = writeln!(out, "\n{}", s.item);
+    }
+    out
+}
+
+/// Reads the songs of a file saved with `m dump`, in any format. Every line of a plain dump is a
+/// song, while blank lines and lines starting with `#` are skipped in an annotated one.
+fn parse_dump(contents: &str) -> anyhow::Result<Vec<Item>> {
+    if contents.trim_start().starts_with('{') {
+        let queue =
+            serde_json::from_str::<DumpedQueue>(contents).context("failed to parse json dump")?;
+        return Ok(queue
+            .songs
+            .into_iter()
+            .map(|s| Item::from(s.item))
+            .collect());
+    }
+    let annotated = contents
+        .lines()
+        .next()
+        .is_some_and(|l| l.starts_with("# ") && l.contains(ANNOTATED_HEADER));
+    Ok(contents
+        .lines()
+        .filter(|l| !annotated || !(l.is_empty() || l.starts_with('#')))
+        .map(|l| Item::from(l.to_owned()))
+        .collect())
+}
+
 pub async fn load(file: PathBuf, shuf: bool) -> anyhow::Result<()> {
-    let mut items = LinesStream::new(BufReader::new(File::open(file).await?).lines())
-        .map_ok(Item::from)
-        .try_collect::<Vec<_>>()
-        .await?;
+    let items = parse_dump(&tokio::fs::read_to_string(file).await?)?;
+    queue_looping(items, shuf).await
+}
 
+async fn queue_looping(mut items: Vec<Item>, shuf: bool) -> anyhow::Result<()> {
     if shuf {
         items.shuffle(&mut rngs::OsRng);
     }
@@ -427,15 +903,92 @@ pub async fn load(file: PathBuf, shuf: bool) -> anyhow::Result<()> {
     Ok(())
 }
 
+pub async fn save_queue(name: String) -> anyhow::Result<()> {
+    let snapshot = Snapshot::save(PlayerLink::current(), &name).await?;
+    println!("saved {} songs as {}", snapshot.items, snapshot.name);
+    Ok(())
+}
+
+/// A saved queue as printed with `--output json`.
+#[derive(Serialize)]
+struct SnapshotJson<'s> {
+    name: &'s str,
+    items: usize,
+    saved_at: DateTime<Utc>,
+}
+
+pub async fn queues(cmd: Option<QueuesCmd>) -> anyhow::Result<()> {
+    match cmd {
+        None => {
+            let snapshots = Snapshot::list().await?;
+            if output::is_json() {
+                return output::json(
+                    &snapshots
+                        .iter()
+                        .map(|s| SnapshotJson {
+                            name: &s.name,
+                            items: s.items,
+                            saved_at: s.saved_at.into(),
+                        })
+                        .collect::<Vec<_>>(),
+                );
+            }
+            let width = snapshots.iter().map(|s| s.name.len()).max().unwrap_or(0);
+            for s in snapshots {
+                println!(
+                    "{:width$}  {:>5} songs  saved {}",
+                    s.name,
+                    s.items,
+                    DateTime::<Local>::from(s.saved_at).format("%Y-%m-%d %H:%M"),
+                );
+            }
+        }
+        Some(QueuesCmd::Load { name, shuf }) => {
+            queue_looping(Snapshot::load(&name).await?, shuf).await?
+        }
+        Some(QueuesCmd::Rm { names, older_than }) => {
+            for name in names {
+                Snapshot::delete(&name).await?;
+                println!("deleted {name}");
+            }
+            if let Some(older_than) = older_than {
+                let before = SystemTime::now()
+                    .checked_sub(older_than)
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                for s in Snapshot::prune(before).await? {
+                    println!("deleted {}", s.name);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 pub async fn play(
     items: impl IntoIterator<Item = Item>,
     with_video: bool,
+    profile: Option<PlayerProfile>,
+    mpv_options: Vec<(String, String)>,
+    name: Option<String>,
+) -> anyhow::Result<PlayerLink> {
+    start(items, with_video, profile, mpv_options, false, name).await
+}
+
+/// Starts a new player, called `name` if given. One started in the `background` doesn't pause
+/// the previous one nor becomes the current player, unless there is no other.
+async fn start(
+    items: impl IntoIterator<Item = Item>,
+    with_video: bool,
+    profile: Option<PlayerProfile>,
+    mut mpv_options: Vec<(String, String)>,
+    background: bool,
+    name: Option<String>,
 ) -> anyhow::Result<PlayerLink> {
     let dl_dir = match dl_dir().await {
         Ok(d) => Some(d),
         Err(_) => None,
     };
-    let items = expand_playlists(items)
+    let items = expand::expand_items(items, &PLAYLIST_CACHE)
         .map(|mut i| async {
             if let Some(dl_dir) = &dl_dir {
                 check_cache_ref(dl_dir, &mut i).await;
@@ -448,17 +1001,55 @@ pub async fn play(
 
     tracing::info!("playing {:?}", items);
 
-    tracing::info!("pausing previous mpv instance");
-    match players::pause().await {
-        Err(players::Error::Mpv(MpvError::NoMpvInstance)) => {}
-        Err(e) => {
-            crate::error!("failed to pause previous player"; content: "{:?}", e);
+    // the config's quality is only a default, profiles and --mpv-option pick their own
+    if let Some(quality) = CONFIG.quality {
+        let picked = mpv_options.iter().any(|(name, _)| name == "ytdl-format")
+            || profile
+                .as_ref()
+                .is_some_and(|p| p.mpv_options.contains_key("ytdl-format"));
+        if !picked {
+            mpv_options.push(("ytdl-format".into(), quality.ytdl_format()));
+        }
+    }
+
+    if !background {
+        tracing::info!("pausing previous mpv instance");
+        match players::pause().await {
+            Err(players::Error::Mpv(MpvError::NoMpvInstance)) => {}
+            Err(e) => {
+                crate::error!("failed to pause previous player"; content: "{:?}", e);
+            }
+            Ok(_) => {}
         }
-        Ok(_) => {}
     }
 
-    let index = players::create(items.iter(), with_video).await?;
-    Ok(index.into())
+    let player = PlayerLink::from(
+        players::create(
+            items.iter(),
+            with_video,
+            profile,
+            mpv_options,
+            background,
+            name,
+        )
+        .await?,
+    );
+    if CONFIG.fade_ms.is_some() && players::all().await?.len() == 1 {
+        if let Err(e) = player.set_fade(Some(CONFIG.fade_length())).await {
+            crate::error!("failed to enable fading"; content: "{:?}", e);
+        }
+    }
+    if CONFIG.resume_playback {
+        if let Err(e) = player.set_resume(true).await {
+            crate::error!("failed to turn on resuming"; content: "{:?}", e);
+        }
+    }
+    if let Some(policy) = &CONFIG.end_of_queue {
+        if let Err(e) = player.set_end_of_queue(policy.clone()).await {
+            crate::error!("failed to set the end of queue policy"; content: "{:?}", e);
+        }
+    }
+    Ok(player)
 }
 
 pub async fn run_interactive_playlist() -> anyhow::Result<()> {
@@ -511,13 +1102,13 @@ pub async fn run_interactive_playlist() -> anyhow::Result<()> {
             )
             .await?;
             let category = match category {
-                Some(c) => c,
+                Some(c) => Category::new(&c),
                 None => return Ok(()),
             };
             playlist
                 .songs
                 .into_iter()
-                .filter(|s| s.categories.contains(&category))
+                .filter(|s| s.is_in(&category))
                 .map(|l| Item::Link(l.link.into()))
                 .collect()
         }
@@ -527,7 +1118,7 @@ pub async fn run_interactive_playlist() -> anyhow::Result<()> {
         _ => return Ok(()),
     };
 
-    vids = expand_playlists(vids).collect().await;
+    vids = expand::expand_items(vids, &PLAYLIST_CACHE).collect().await;
 
     let loop_list = vids.len() > 1;
     if loop_list {
@@ -550,6 +1141,20 @@ pub async fn run_interactive_playlist() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Reads a link or a path to a file from the clipboard.
+pub fn clipboard_item() -> anyhow::Result<Item> {
+    let contents = get_clipboard_contents()?;
+    let contents = contents.trim();
+    if let Ok(link) = Link::try_from(contents.to_string()) {
+        return Ok(Item::Link(link));
+    }
+    let path = PathBuf::from(contents);
+    if path.is_file() {
+        return Ok(Item::File(path));
+    }
+    bail!("clipboard doesn't contain a link or a path to a file: {contents:?}")
+}
+
 fn get_clipboard_contents() -> anyhow::Result<String> {
     use arboard::Clipboard;
     use wl_clipboard_rs::paste::{get_contents, ClipboardType, Error, MimeType, Seat};
@@ -577,73 +1182,83 @@ fn get_clipboard_contents() -> anyhow::Result<String> {
     }
 }
 
-fn expand_playlists<I: IntoIterator<Item = Item>>(items: I) -> impl Stream<Item = Item> {
-    use mlib::ytdl::YtdlStream;
+/// Playlists are remembered for an hour, so queueing the same one again doesn't ask yt-dlp.
+static PLAYLIST_CACHE: TmpCache = TmpCache {
+    ttl: Duration::from_secs(60 * 60),
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    async fn expand(
-        stream: YtdlStream<Box<VideoId>>,
-    ) -> Result<Option<BoxStream<'static, Item>>, Error> {
-        let s = stream
-            .filter_map(|r| async {
-                match r {
-                    Ok(x) => Some(x),
-                    Err(e) => {
-                        crate::error!(
-                            "failed to parse playlist item when expanding playlist: {:?}",
-                            e
-                        );
-                        None
-                    }
-                }
-            })
-            .map(|b| VideoLink::from_id(b.id()));
-        let mut s = Box::pin(s.map(|l| Item::Link(l.into())).peekable());
-        if s.as_mut().peek().await.is_none() {
-            tracing::warn!("playlist is empty");
-            Ok(None)
-        } else {
-            Ok(Some(s))
-        }
+    #[test]
+    fn chapters_end_where_the_next_starts() {
+        let chapter = |start, title: &str| Chapter {
+            start: Duration::from_secs(start),
+            title: title.into(),
+        };
+        let chapters = [chapter(0, "intro"), chapter(95, "a, b"), chapter(300, "c")];
+        let option = |i, name: &str| {
+            chapter_options(&chapters, i)
+                .into_iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v)
+        };
+        assert_eq!(option(1, "start").as_deref(), Some("95"));
+        assert_eq!(option(1, "end").as_deref(), Some("300"));
+        assert_eq!(option(1, "force-media-title").as_deref(), Some("a, b"));
+        assert_eq!(option(2, "end"), None);
     }
 
-    async fn expand_playlist(
-        l: &'_ PlaylistLink,
-    ) -> Result<Option<BoxStream<'static, Item>>, Error> {
-        expand(YtdlBuilder::new(l).request_playlist()?).await
+    #[test]
+    fn moving_shifts_the_songs_in_between() {
+        // 0 1 2 3 4 -> 0 2 3 1 4
+        assert_eq!(moved_index(1, 1, 3), 3);
+        assert_eq!(moved_index(2, 1, 3), 1);
+        assert_eq!(moved_index(4, 1, 3), 4);
+        // 0 1 2 3 4 -> 0 3 1 2 4
+        assert_eq!(moved_index(1, 3, 1), 2);
+        assert_eq!(moved_index(0, 3, 1), 0);
     }
 
-    async fn expand_channel(l: &ChannelLink) -> Result<Option<BoxStream<'static, Item>>, Error> {
-        expand(YtdlBuilder::new(l).request_channel()?).await
+    #[test]
+    fn annotated_dumps_load_like_plain_ones() {
+        let queue = DumpedQueue {
+            current: 1,
+            songs: vec![
+                DumpedSong {
+                    index: 0,
+                    item: "https://youtu.be/dQw4w9WgXcQ".into(),
+                    title: "# not a comment".into(),
+                    duration: Some(Duration::from_secs(213)),
+                },
+                DumpedSong {
+                    index: 1,
+                    item: "/music/song.mp3".into(),
+                    title: "song".into(),
+                    duration: None,
+                },
+            ],
+        };
+        let plain = "https://youtu.be/dQw4w9WgXcQ\n/music/song.mp3\n";
+        let expected = parse_dump(plain).unwrap();
+        assert_eq!(expected.len(), 2);
+        assert_eq!(parse_dump(&annotated(&queue)).unwrap(), expected);
+        assert_eq!(
+            parse_dump(&serde_json::to_string(&queue).unwrap()).unwrap(),
+            expected
+        );
     }
 
-    fn single(l: impl Into<Item>) -> BoxStream<'static, Item> {
-        Box::pin(stream::once(ready(l.into())))
+    #[test]
+    fn plain_dumps_load_every_line_as_is() {
+        let plain = "#1 hit.mp3\n  spaced out.mp3\n";
+        assert_eq!(
+            parse_dump(plain).unwrap(),
+            [
+                Item::from("#1 hit.mp3".to_owned()),
+                Item::from("  spaced out.mp3".to_owned())
+            ]
+        );
     }
-
-    stream::iter(items)
-        .then(move |i| async {
-            let expanded = match &i {
-                Item::Link(l) => match l {
-                    Link::Playlist(l) => expand_playlist(l)
-                        .await
-                        .map(|opt_items| {
-                            opt_items.or_else(|| {
-                                l.clone()
-                                    .into_video_link()
-                                    .map(Link::from)
-                                    .map(Item::from)
-                                    .map(single)
-                                    .ok()
-                            })
-                        })
-                        .ok(),
-                    Link::Channel(c) => expand_channel(c).await.ok(),
-                    _ => None,
-                },
-                _ => None,
-            }
-            .flatten();
-            expanded.unwrap_or_else(|| single(i))
-        })
-        .flatten()
 }
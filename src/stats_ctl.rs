@@ -0,0 +1,226 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Context;
+use chrono::{Datelike, NaiveDate};
+use itertools::Itertools;
+use mlib::{
+    playlist::{Category, Playlist, Song},
+    statistics::{self, Totals},
+    Item,
+};
+use serde::Serialize;
+
+use crate::{
+    arg_parse::StatsPeriod,
+    notify,
+    util::{date::DateRange, output, DurationFmt},
+};
+
+#[derive(Serialize)]
+struct SongReport {
+    name: String,
+    item: String,
+    played: u64,
+    skipped: u64,
+    listened_secs: u64,
+}
+
+#[derive(Serialize)]
+struct CategoryReport {
+    category: Category,
+    played: u64,
+    listened_secs: u64,
+}
+
+#[derive(Serialize)]
+struct PeriodReport {
+    period: String,
+    played: u64,
+    listened_secs: u64,
+}
+
+#[derive(Serialize)]
+struct Report {
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+    played: u64,
+    skipped: u64,
+    skip_rate: f64,
+    listened_secs: u64,
+    top_songs: Vec<SongReport>,
+    top_categories: Vec<CategoryReport>,
+    listening: Vec<PeriodReport>,
+}
+
+fn period_of(day: NaiveDate, per: StatsPeriod) -> String {
+    match per {
+        StatsPeriod::Day => day.format("%Y-%m-%d").to_string(),
+        StatsPeriod::Week => {
+            let week = day.iso_week();
+            format!("{}-W{:02}", week.year(), week.week())
+        }
+    }
+}
+
+fn build(
+    stats: statistics::Report,
+    playlist: &Playlist,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+    top: usize,
+    per: StatsPeriod,
+) -> Report {
+    let by_id = playlist
+        .songs
+        .iter()
+        .map(|s| (s.link.id(), s))
+        .collect::<HashMap<_, _>>();
+    let song_of = |item: &Item| -> Option<&Song> { by_id.get(item.id()?).copied() };
+
+    let mut total = Totals::default();
+    let mut categories = HashMap::<&Category, Totals>::new();
+    for (item, totals) in &stats.songs {
+        total += *totals;
+        for category in song_of(item).into_iter().flat_map(|s| s.categories.iter()) {
+            *categories.entry(category).or_default() += *totals;
+        }
+    }
+
+    let top_songs = stats
+        .songs
+        .iter()
+        .sorted_by_key(|(_, t)| std::cmp::Reverse((t.played, t.listened)))
+        .take(top)
+        .map(|(item, t)| SongReport {
+            name: song_of(item).map_or_else(|| item.to_string(), |s| s.name.clone()),
+            item: item.to_string(),
+            played: t.played,
+            skipped: t.skipped,
+            listened_secs: t.listened.as_secs(),
+        })
+        .collect();
+    let top_categories = categories
+        .into_iter()
+        .sorted_by_key(|(c, t)| (std::cmp::Reverse((t.played, t.listened)), *c))
+        .take(top)
+        .map(|(category, t)| CategoryReport {
+            category: category.clone(),
+            played: t.played,
+            listened_secs: t.listened.as_secs(),
+        })
+        .collect();
+    let listening = stats
+        .days
+        .iter()
+        .chunk_by(|(day, _)| period_of(**day, per))
+        .into_iter()
+        .map(|(period, days)| {
+            let mut totals = Totals::default();
+            for (_, t) in days {
+                totals += *t;
+            }
+            PeriodReport {
+                period,
+                played: totals.played,
+                listened_secs: totals.listened.as_secs(),
+            }
+        })
+        .collect();
+
+    Report {
+        since,
+        until,
+        played: total.played,
+        skipped: total.skipped,
+        skip_rate: total.skip_rate(),
+        listened_secs: total.listened.as_secs(),
+        top_songs,
+        top_categories,
+        listening,
+    }
+}
+
+fn print(report: &Report) {
+    let secs = |s| DurationFmt(Duration::from_secs(s)).to_string();
+    println!(
+        "listened for {} over {} plays, {:.0}% skipped",
+        secs(report.listened_secs),
+        report.played,
+        report.skip_rate * 100.
+    );
+    println!("\ntop songs");
+    for s in &report.top_songs {
+        println!("{:5} {:>9}  {}", s.played, secs(s.listened_secs), s.name);
+    }
+    println!("\ntop categories");
+    for c in &report.top_categories {
+        println!(
+            "{:5} {:>9}  {}",
+            c.played,
+            secs(c.listened_secs),
+            c.category
+        );
+    }
+    println!("\nlistening time");
+    for p in &report.listening {
+        println!(
+            "{:10} {:>9}  {} plays",
+            p.period,
+            secs(p.listened_secs),
+            p.played
+        );
+    }
+}
+
+pub async fn stats(
+    since: Option<DateRange>,
+    until: Option<DateRange>,
+    top: usize,
+    per: StatsPeriod,
+    json: bool,
+) -> anyhow::Result<()> {
+    let (since, until) = (since.map(|d| d.first_day()), until.map(|d| d.last_day()));
+    if let (Some(since), Some(until)) = (since, until) {
+        anyhow::ensure!(
+            since <= until,
+            "--since ({since}) is after --until ({until})"
+        );
+    }
+    let stats = statistics::report(since, until)
+        .await
+        .context("loading statistics")?;
+    let playlist = Playlist::load().await.context("loading playlist")?;
+    let report = build(stats, &playlist, since, until, top, per);
+    if json || output::is_json() {
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &report)?;
+        println!();
+    } else {
+        print(&report);
+    }
+    Ok(())
+}
+
+pub async fn lock() -> anyhow::Result<()> {
+    let files = match statistics::lock().await {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            anyhow::bail!("statistics are already locked")
+        }
+        r => r.context("encrypting statistics")?,
+    };
+    notify!(
+        "encrypted {files} statistics files";
+        content: "key: {}", mlib::encryption::key_path()?.display()
+    );
+    Ok(())
+}
+
+pub async fn unlock() -> anyhow::Result<()> {
+    match statistics::unlock()
+        .await
+        .context("decrypting statistics")?
+    {
+        None => notify!("statistics weren't locked"),
+        Some(files) => notify!("decrypted {files} statistics files"),
+    }
+    Ok(())
+}
@@ -0,0 +1,343 @@
+//! Dates and times given on the command line, either absolute (`2024-01-31`, `2024-01`,
+//! `2024-01-31T18:00`) or relative to now (`today`, `3d`, `2 weeks ago`, `last month`).
+//!
+//! Every date is a range of time: `2024-01` is the whole month and `3d` the whole day three days
+//! ago. Used as a lower bound the start of the range is taken and as an upper bound the end, so
+//! `--since 'last month' --until 'last month'` covers the whole month.
+
+use std::{fmt, ops::Range, str::FromStr, time::SystemTime};
+
+use chrono::{
+    DateTime, Datelike, Days, Duration, Local, Months, NaiveDate, NaiveDateTime, NaiveTime,
+    TimeZone,
+};
+use serde::{Deserialize, Serialize};
+
+/// A range of local time, `end` is exclusive. Exact times have `start == end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DateRange {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+impl fmt::Display for DateRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start.format("%Y-%m-%d %H:%M:%S"))
+        } else if self.first_day() == self.last_day() {
+            write!(f, "{}", self.first_day())
+        } else {
+            write!(f, "{} to {}", self.first_day(), self.last_day())
+        }
+    }
+}
+
+enum Unit {
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl Unit {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "h" | "hour" | "hours" => Self::Hour,
+            "d" | "day" | "days" => Self::Day,
+            "w" | "week" | "weeks" => Self::Week,
+            "mo" | "month" | "months" => Self::Month,
+            "y" | "year" | "years" => Self::Year,
+            _ => return None,
+        })
+    }
+}
+
+fn midnight(day: NaiveDate) -> NaiveDateTime {
+    day.and_time(NaiveTime::MIN)
+}
+
+impl DateRange {
+    fn at(time: NaiveDateTime) -> Self {
+        Self {
+            start: time,
+            end: time,
+        }
+    }
+
+    fn days(first: NaiveDate, days: u64) -> Option<Self> {
+        Some(Self {
+            start: midnight(first),
+            end: midnight(first.checked_add_days(Days::new(days))?),
+        })
+    }
+
+    fn day(day: NaiveDate) -> Option<Self> {
+        Self::days(day, 1)
+    }
+
+    fn month(first: NaiveDate) -> Option<Self> {
+        Some(Self {
+            start: midnight(first),
+            end: midnight(first.checked_add_months(Months::new(1))?),
+        })
+    }
+
+    fn year(year: i32) -> Option<Self> {
+        Some(Self {
+            start: midnight(NaiveDate::from_ymd_opt(year, 1, 1)?),
+            end: midnight(NaiveDate::from_ymd_opt(year.checked_add(1)?, 1, 1)?),
+        })
+    }
+
+    /// Parses `s` relative to `now`.
+    pub fn parse_at(s: &str, now: NaiveDateTime) -> Result<Self, String> {
+        let invalid =
+            || format!("invalid date {s:?}, expected e.g. 2024-01-31, today, 3d, 2w or last month");
+        let too_far = || format!("{s:?} is too far away");
+
+        let trimmed = s.trim();
+        let lower = trimmed.to_lowercase();
+        let today = now.date();
+        match lower.as_str() {
+            "now" => return Ok(Self::at(now)),
+            "today" => return Self::day(today).ok_or_else(too_far),
+            "yesterday" => return today.pred_opt().and_then(Self::day).ok_or_else(too_far),
+            _ => {}
+        }
+
+        if let Some((which, unit)) = lower.split_once(char::is_whitespace) {
+            let back = match which {
+                "this" => Some(0),
+                "last" => Some(1),
+                _ => None,
+            };
+            if let Some(back) = back {
+                let week_start = today.week(chrono::Weekday::Mon).first_day();
+                let first_of_month = today.with_day(1).unwrap();
+                let range = match unit.trim() {
+                    "week" => week_start
+                        .checked_sub_days(Days::new(7 * back))
+                        .and_then(|d| Self::days(d, 7)),
+                    "month" => first_of_month
+                        .checked_sub_months(Months::new(back as u32))
+                        .and_then(Self::month),
+                    "year" => Self::year(today.year() - back as i32),
+                    _ => return Err(invalid()),
+                };
+                return range.ok_or_else(too_far);
+            }
+        }
+
+        for format in [
+            "%Y-%m-%dT%H:%M:%S",
+            "%Y-%m-%dT%H:%M",
+            "%Y-%m-%d %H:%M:%S",
+            "%Y-%m-%d %H:%M",
+        ] {
+            if let Ok(time) = NaiveDateTime::parse_from_str(trimmed, format) {
+                return Ok(Self::at(time));
+            }
+        }
+        if let Ok(day) = NaiveDate::parse_from_str(&lower, "%Y-%m-%d") {
+            return Self::day(day).ok_or_else(too_far);
+        }
+        if let Ok(first) = NaiveDate::parse_from_str(&format!("{lower}-01"), "%Y-%m-%d") {
+            return Self::month(first).ok_or_else(too_far);
+        }
+        if lower.len() == 4 {
+            if let Ok(year) = lower.parse() {
+                return Self::year(year).ok_or_else(too_far);
+            }
+        }
+
+        let relative = lower.strip_suffix("ago").unwrap_or(&lower).trim_end();
+        let digits = relative
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let (n, unit) = relative.split_at(digits);
+        let n = n.parse::<u64>().map_err(|_| invalid())?;
+        let unit = Unit::parse(unit.trim_start()).ok_or_else(invalid)?;
+        let day = |day: Option<NaiveDate>| day.and_then(Self::day).ok_or_else(too_far);
+        match unit {
+            Unit::Hour => i64::try_from(n)
+                .ok()
+                .and_then(Duration::try_hours)
+                .and_then(|d| now.checked_sub_signed(d))
+                .map(Self::at)
+                .ok_or_else(too_far),
+            Unit::Day => day(today.checked_sub_days(Days::new(n))),
+            Unit::Week => day(n
+                .checked_mul(7)
+                .and_then(|n| today.checked_sub_days(Days::new(n)))),
+            Unit::Month => day(u32::try_from(n)
+                .ok()
+                .and_then(|n| today.checked_sub_months(Months::new(n)))),
+            Unit::Year => day(u32::try_from(n)
+                .ok()
+                .and_then(|n| n.checked_mul(12))
+                .and_then(|n| today.checked_sub_months(Months::new(n)))),
+        }
+    }
+
+    /// The first day of the range, for a lower bound.
+    pub fn first_day(&self) -> NaiveDate {
+        self.start.date()
+    }
+
+    /// The last day of the range, for an inclusive upper bound.
+    pub fn last_day(&self) -> NaiveDate {
+        if self.end > self.start {
+            (self.end - Duration::nanoseconds(1)).date()
+        } else {
+            self.start.date()
+        }
+    }
+
+    pub fn to_system_time(self) -> Range<SystemTime> {
+        let system_time = |t: NaiveDateTime| {
+            Local
+                .from_local_datetime(&t)
+                .earliest()
+                .map(SystemTime::from)
+                // local times skipped by DST changes
+                .unwrap_or_else(|| {
+                    DateTime::<chrono::Utc>::from_naive_utc_and_offset(t, chrono::Utc).into()
+                })
+        };
+        system_time(self.start)..system_time(self.end)
+    }
+}
+
+impl FromStr for DateRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_at(s, Local::now().naive_local())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    /// A Wednesday.
+    fn now() -> NaiveDateTime {
+        time("2024-01-03 15:30")
+    }
+
+    fn days(s: &str) -> (NaiveDate, NaiveDate) {
+        let range = DateRange::parse_at(s, now()).unwrap();
+        (range.first_day(), range.last_day())
+    }
+
+    fn day(s: &str) -> NaiveDate {
+        let (first, last) = days(s);
+        assert_eq!(first, last, "{s} should be a single day");
+        first
+    }
+
+    #[test]
+    fn named_days() {
+        assert_eq!(day("today"), date("2024-01-03"));
+        assert_eq!(day("Yesterday"), date("2024-01-02"));
+        assert_eq!(
+            DateRange::parse_at("now", now()).unwrap(),
+            DateRange::at(now())
+        );
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(day("0d"), date("2024-01-03"));
+        assert_eq!(day("3d"), date("2023-12-31"));
+        assert_eq!(day("3 days ago"), date("2023-12-31"));
+        assert_eq!(day("1day"), date("2024-01-02"));
+        assert_eq!(day("2w"), date("2023-12-20"));
+        assert_eq!(day("1 week ago"), date("2023-12-27"));
+        assert_eq!(day("1mo"), date("2023-12-03"));
+        assert_eq!(day("2 months"), date("2023-11-03"));
+        assert_eq!(day("1y"), date("2023-01-03"));
+        assert_eq!(
+            DateRange::parse_at("5h", now()).unwrap(),
+            DateRange::at(time("2024-01-03 10:30"))
+        );
+        assert_eq!(
+            DateRange::parse_at("16 hours ago", now()).unwrap(),
+            DateRange::at(time("2024-01-02 23:30"))
+        );
+    }
+
+    #[test]
+    fn calendar_periods() {
+        assert_eq!(days("this week"), (date("2024-01-01"), date("2024-01-07")));
+        assert_eq!(days("last week"), (date("2023-12-25"), date("2023-12-31")));
+        assert_eq!(days("this month"), (date("2024-01-01"), date("2024-01-31")));
+        assert_eq!(days("last month"), (date("2023-12-01"), date("2023-12-31")));
+        assert_eq!(days("LAST  year"), (date("2023-01-01"), date("2023-12-31")));
+        assert_eq!(days("this year"), (date("2024-01-01"), date("2024-12-31")));
+    }
+
+    #[test]
+    fn absolute() {
+        assert_eq!(day("2024-01-31"), date("2024-01-31"));
+        assert_eq!(days("2024-02"), (date("2024-02-01"), date("2024-02-29")));
+        assert_eq!(days("2023"), (date("2023-01-01"), date("2023-12-31")));
+        assert_eq!(
+            DateRange::parse_at("2024-01-31T18:05", now()).unwrap(),
+            DateRange::at(time("2024-01-31 18:05"))
+        );
+        assert_eq!(
+            DateRange::parse_at("2024-01-31 18:05:30", now()).unwrap(),
+            DateRange::at(time("2024-01-31 18:05") + Duration::seconds(30))
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        for s in [
+            "",
+            "d",
+            "3",
+            "3x",
+            "-3d",
+            "3 d ago ago",
+            "next week",
+            "last decade",
+            "2024-13-01",
+            "2024-02-30",
+            "tomorrow",
+        ] {
+            assert!(
+                DateRange::parse_at(s, now()).is_err(),
+                "{s:?} should be invalid"
+            );
+        }
+        assert!(DateRange::parse_at("99999999999d", now())
+            .unwrap_err()
+            .contains("too far"));
+        assert!(DateRange::parse_at("99999999999h", now())
+            .unwrap_err()
+            .contains("too far"));
+    }
+
+    #[test]
+    fn ranges_are_half_open() {
+        let range = DateRange::parse_at("2024-01-31", now())
+            .unwrap()
+            .to_system_time();
+        let end = DateRange::parse_at("2024-02-01", now())
+            .unwrap()
+            .to_system_time();
+        assert_eq!(range.end, end.start);
+        assert!(range.start < range.end);
+    }
+}
@@ -1,11 +1,17 @@
+pub mod date;
+pub mod notifier;
 pub mod notify;
+pub mod output;
 pub mod selector;
 pub mod session_kind;
 pub mod with_video;
 
 use mlib::item::link::VideoLink;
 use mlib::VideoId;
+use regex::Regex;
+use std::collections::HashSet;
 use std::fmt::Display;
+use std::hash::Hash;
 use std::io;
 use std::path::PathBuf;
 use std::time::Duration;
@@ -58,24 +64,6 @@ pub async fn dl_dir() -> anyhow::Result<PathBuf> {
     .cloned()
 }
 
-pub async fn update_bar() -> io::Result<()> {
-    let mut update_panel = dirs::config_dir()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "config dir not found"))?;
-    update_panel.push("m");
-    update_panel.push("update_panel.sh");
-    tracing::debug!(
-        "checking if update panel script (at {}) exists",
-        update_panel.display()
-    );
-    let metadata = tokio::fs::metadata(&update_panel).await;
-    tracing::debug!("metadata check for script {:?}", metadata);
-    if metadata.is_ok() {
-        Command::new("sh").arg(update_panel).spawn()?.wait().await?;
-    }
-
-    Ok(())
-}
-
 pub async fn preview_video(l: &VideoId) -> anyhow::Result<()> {
     Command::new("mpv")
         .args(["--start=20", "--geometry=820x466", "--no-terminal"])
@@ -101,3 +89,79 @@ impl Drop for RawMode {
         }
     }
 }
+
+/// Takes one element from each list in turn until they are all exhausted, skipping elements
+/// that were already taken from an earlier list.
+pub fn interleave<T: Clone + Eq + Hash>(lists: Vec<Vec<T>>) -> Vec<T> {
+    let mut seen = HashSet::new();
+    let mut lists = lists.into_iter().map(Vec::into_iter).collect::<Vec<_>>();
+    let mut out = Vec::new();
+    while !lists.is_empty() {
+        lists.retain_mut(|list| match list.find(|x| !seen.contains(x)) {
+            Some(x) => {
+                seen.insert(x.clone());
+                out.push(x);
+                true
+            }
+            None => false,
+        });
+    }
+    out
+}
+
+/// Removes whatever matches `noise` from a video's title, like "(Official Video)", along with
+/// the spaces and separators that are left dangling.
+pub fn clean_title(title: &str, noise: &[Regex]) -> String {
+    let mut cleaned = title.to_owned();
+    for re in noise {
+        cleaned = re.replace_all(&cleaned, " ").into_owned();
+    }
+    let cleaned = cleaned
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches(|c: char| c.is_whitespace() || matches!(c, '-' | '|' | ':'))
+        .to_owned();
+    if cleaned.is_empty() {
+        title.trim().to_owned()
+    } else {
+        cleaned
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interleave_alternates_and_skips_repeats() {
+        let mixed = interleave(vec![vec![1, 2, 3, 4], vec![5, 2, 6], vec![]]);
+        assert_eq!(mixed, [1, 5, 2, 6, 3, 4]);
+    }
+
+    #[test]
+    fn clean_title_strips_noise() {
+        let noise = crate::config::DEFAULT_TITLE_NOISE
+            .iter()
+            .map(|re| Regex::new(re).unwrap())
+            .collect::<Vec<_>>();
+        let clean = |title| clean_title(title, &noise);
+        assert_eq!(
+            clean("Daft Punk - Around The World (Official Video)"),
+            "Daft Punk - Around The World"
+        );
+        assert_eq!(
+            clean("Kendrick Lamar - HUMBLE. [Official Music Video] [HD]"),
+            "Kendrick Lamar - HUMBLE."
+        );
+        assert_eq!(
+            clean("Nirvana - Lithium (Remastered 2021) | Official Audio"),
+            "Nirvana - Lithium (Remastered 2021)"
+        );
+        assert_eq!(
+            clean("Video Killed the Radio Star"),
+            "Video Killed the Radio Star"
+        );
+        assert_eq!(clean("(Official Video)"), "(Official Video)");
+    }
+}
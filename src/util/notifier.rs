@@ -0,0 +1,224 @@
+//! Where [`Notify`]s end up, picked with `notifier` in the config.
+
+use std::{
+    collections::HashMap,
+    io::{self, stdout, StdoutLock, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::CONFIG,
+    util::{
+        notify::{triplets, Notify},
+        session_kind::SessionKind,
+    },
+};
+use crossterm::{
+    cursor::MoveToNextLine,
+    style::{Attribute, Color, Print, SetAttribute, SetForegroundColor},
+    tty::IsTty,
+    QueueableCommand,
+};
+use serde::{Deserialize, Serialize};
+use zbus::zvariant::Value;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// The terminal when running in one, desktop notifications otherwise.
+    #[default]
+    Auto,
+    /// Desktop notifications, through the notification daemon's dbus interface.
+    Libnotify,
+    Terminal,
+    /// A json object per line on stdout, for status bars and scripts.
+    StdoutJson,
+}
+
+pub async fn send(n: &Notify<'_>) -> io::Result<()> {
+    match CONFIG.notifier {
+        Backend::Auto => match SessionKind::current().await {
+            SessionKind::Cli if !n.force_notify => terminal(n),
+            _ => desktop(n).await,
+        },
+        Backend::Libnotify => desktop(n).await,
+        Backend::Terminal => terminal(n),
+        Backend::StdoutJson => json(n),
+    }
+}
+
+/// The text without the `§` color markers.
+fn plain(s: &str) -> String {
+    triplets(s).map(|(s, _)| s).collect()
+}
+
+fn terminal(n: &Notify<'_>) -> io::Result<()> {
+    trait BoolPaint {
+        fn paint<T: crossterm::Command>(self, stdout: &mut StdoutLock, cmd: T) -> io::Result<()>;
+    }
+    impl BoolPaint for bool {
+        fn paint<T: crossterm::Command>(self, stdout: &mut StdoutLock, cmd: T) -> io::Result<()> {
+            self.then(|| stdout.queue(cmd))
+                .map(|_| Ok(()))
+                .unwrap_or(Ok(()))
+        }
+    }
+    fn print(stdout: &mut StdoutLock, s: &str) -> io::Result<()> {
+        if crossterm::terminal::is_raw_mode_enabled()? {
+            for line in s.split_inclusive('\n') {
+                if line.ends_with('\n') {
+                    stdout.queue(Print(&line[..(line.len().saturating_sub(1))]))?;
+                    stdout.queue(MoveToNextLine(1))?;
+                } else {
+                    stdout.queue(Print(line))?;
+                }
+            }
+        } else {
+            stdout.write_all(s.as_bytes())?;
+        }
+        Ok(())
+    }
+    let stdout = stdout();
+    let mut stdout = stdout.lock();
+    let is_tty = stdout.is_tty();
+    is_tty.paint(&mut stdout, SetAttribute(Attribute::Bold))?;
+    if n.error {
+        is_tty.paint(&mut stdout, SetForegroundColor(Color::Red))?;
+        stdout.queue(Print("Error: "))?;
+        is_tty.paint(&mut stdout, SetForegroundColor(Color::Reset))?;
+    }
+    for (s, c) in triplets(&n.title) {
+        print(&mut stdout, s)?;
+        if let Some(x) = match c {
+            "b" => Some(Color::Blue),
+            "w" => Some(Color::White),
+            "r" => Some(Color::Reset),
+            _ => None,
+        } {
+            is_tty.paint(&mut stdout, SetForegroundColor(x))?;
+        }
+    }
+    if crossterm::terminal::is_raw_mode_enabled()? {
+        stdout.queue(MoveToNextLine(1))?;
+    } else {
+        stdout.write_all(b"\n")?;
+    }
+    is_tty.paint(&mut stdout, SetAttribute(Attribute::Reset))?;
+    if let Some(content) = &n.content {
+        for (s, c) in triplets(content) {
+            print(&mut stdout, s)?;
+            if let Some(x) = match c {
+                "b" => Some(Color::Blue),
+                "w" => Some(Color::White),
+                "r" => Some(Color::Reset),
+                _ => None,
+            } {
+                is_tty.paint(&mut stdout, SetForegroundColor(x))?;
+            }
+        }
+        if crossterm::terminal::is_raw_mode_enabled()? {
+            stdout.queue(MoveToNextLine(1))?;
+        } else {
+            stdout.write_all(b"\n")?;
+        }
+    }
+    stdout.flush()
+}
+
+fn json(n: &Notify<'_>) -> io::Result<()> {
+    #[derive(Serialize)]
+    struct Notification<'n> {
+        title: String,
+        content: Option<String>,
+        error: bool,
+        image: Option<&'n Path>,
+        replace: Option<&'static str>,
+    }
+    let notification = Notification {
+        title: plain(&n.title),
+        content: n.content.as_deref().map(plain),
+        error: n.error,
+        image: n.img,
+        replace: n.replace,
+    };
+    let mut stdout = stdout().lock();
+    serde_json::to_writer(&mut stdout, &notification)?;
+    stdout.write_all(b"\n")?;
+    stdout.flush()
+}
+
+/// Desktop notifications, falling back to the terminal if there is no notification daemon.
+async fn desktop(n: &Notify<'_>) -> io::Result<()> {
+    if let Err(e) = libnotify(n).await {
+        tracing::warn!(error = ?e, "failed to send a desktop notification");
+        return terminal(n);
+    }
+    Ok(())
+}
+
+/// Where the id of the last notification sent with `tag` is kept, since each command is a new
+/// process.
+async fn id_file(tag: &str) -> PathBuf {
+    let (path, _) = namespaced_tmp::async_impl::in_user_tmp(&format!("m-notification-{tag}")).await;
+    path
+}
+
+async fn libnotify(n: &Notify<'_>) -> zbus::Result<()> {
+    let replaces_id: u32 = match n.replace {
+        Some(tag) => tokio::fs::read_to_string(id_file(tag).await)
+            .await
+            .ok()
+            .and_then(|id| id.trim().parse().ok())
+            .unwrap_or(0),
+        None => 0,
+    };
+    let mut hints = HashMap::new();
+    hints.insert("urgency", Value::U8(if n.error { 2 } else { 1 }));
+    if let Some(img) = n.img {
+        hints.insert(
+            "image-path",
+            Value::from(img.to_string_lossy().into_owned()),
+        );
+    }
+    if let Some(tag) = n.replace {
+        // daemons like dunst replace by tag even if the id was lost
+        hints.insert("x-dunst-stack-tag", Value::from(tag));
+    }
+    let body = n
+        .content
+        .as_deref()
+        .map(|c| {
+            plain(
+                &c.replace('\t', "")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;"),
+            )
+        })
+        .unwrap_or_default();
+    let connection = zbus::Connection::session().await?;
+    let reply = connection
+        .call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                "m",
+                replaces_id,
+                "",
+                plain(&n.title.replace('\t', "")),
+                body,
+                Vec::<&str>::new(),
+                hints,
+                -1i32,
+            ),
+        )
+        .await?;
+    let id = reply.body().deserialize::<u32>()?;
+    if let Some(tag) = n.replace {
+        if let Err(e) = tokio::fs::write(id_file(tag).await, id.to_string()).await {
+            tracing::warn!(error = ?e, "failed to save the notification id");
+        }
+    }
+    Ok(())
+}
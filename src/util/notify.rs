@@ -1,16 +1,6 @@
-use std::{
-    io::{self, stdout, StdoutLock, Write},
-    path::Path,
-};
+use std::{io, path::Path};
 
-use crate::util::session_kind::SessionKind;
-use crossterm::{
-    cursor::MoveToNextLine,
-    style::{Attribute, Color, Print, SetAttribute, SetForegroundColor},
-    tty::IsTty,
-    QueueableCommand,
-};
-use tokio::process::Command;
+use crate::util::notifier;
 
 #[macro_export]
 macro_rules! notify {
@@ -18,6 +8,7 @@ macro_rules! notify {
         $($fmt:expr),*$(,)?
         $(; content: $($content:expr),*$(,)?)?
         $(; img: $img:expr)?
+        $(; replace: $replace:expr)?
         $(; force_notify: $force_notify:expr)?
     ) => {{
         $crate::util::notify::Notify::new(::std::format!($($fmt),*))
@@ -27,6 +18,9 @@ macro_rules! notify {
         $(
             .img($img)
         )*
+        $(
+            .replace($replace)
+        )*
         $(
             .force_notify($force_notify)
         )*
@@ -41,6 +35,7 @@ macro_rules! error {
         $($fmt:expr),*
         $(; content: $($content:expr),*$(,)?)?
         $(; img: $img:expr)?
+        $(; replace: $replace:expr)?
         $(; force_notify: $force_notify:expr)?
     ) => {{
         $crate::util::notify::Notify::new(::std::format!($($fmt),*))
@@ -51,6 +46,9 @@ macro_rules! error {
         $(
             .img($img)
         )*
+        $(
+            .replace($replace)
+        )*
         $(
             .force_notify($force_notify)
         )*
@@ -60,11 +58,12 @@ macro_rules! error {
 }
 
 pub struct Notify<'path> {
-    title: String,
-    error: bool,
-    content: Option<String>,
-    img: Option<&'path Path>,
-    force_notify: bool,
+    pub(super) title: String,
+    pub(super) error: bool,
+    pub(super) content: Option<String>,
+    pub(super) img: Option<&'path Path>,
+    pub(super) replace: Option<&'static str>,
+    pub(super) force_notify: bool,
 }
 
 impl<'path> Notify<'path> {
@@ -74,6 +73,7 @@ impl<'path> Notify<'path> {
             error: false,
             content: None,
             img: None,
+            replace: None,
             force_notify: false,
         }
     }
@@ -98,119 +98,14 @@ impl<'path> Notify<'path> {
         self
     }
 
+    /// Replace the last notification sent with the same `tag`, instead of showing another one.
+    pub fn replace(&mut self, tag: &'static str) -> &mut Self {
+        self.replace = Some(tag);
+        self
+    }
+
     pub async fn notify(&self) -> io::Result<()> {
-        trait BoolPaint {
-            fn paint<T: crossterm::Command>(
-                self,
-                stdout: &mut StdoutLock,
-                cmd: T,
-            ) -> io::Result<()>;
-        }
-        impl BoolPaint for bool {
-            fn paint<T: crossterm::Command>(
-                self,
-                stdout: &mut StdoutLock,
-                cmd: T,
-            ) -> io::Result<()> {
-                self.then(|| stdout.queue(cmd))
-                    .map(|_| Ok(()))
-                    .unwrap_or(Ok(()))
-            }
-        }
-        fn print(stdout: &mut StdoutLock, s: &str) -> io::Result<()> {
-            if crossterm::terminal::is_raw_mode_enabled()? {
-                for line in s.split_inclusive('\n') {
-                    if line.ends_with('\n') {
-                        stdout.queue(Print(&line[..(line.len().saturating_sub(1))]))?;
-                        stdout.queue(MoveToNextLine(1))?;
-                    } else {
-                        stdout.queue(Print(line))?;
-                    }
-                }
-            } else {
-                stdout.write_all(s.as_bytes())?;
-            }
-            Ok(())
-        }
-        match SessionKind::current().await {
-            SessionKind::Cli if !self.force_notify => {
-                let stdout = stdout();
-                let mut stdout = stdout.lock();
-                let is_tty = stdout.is_tty();
-                is_tty.paint(&mut stdout, SetAttribute(Attribute::Bold))?;
-                if self.error {
-                    is_tty.paint(&mut stdout, SetForegroundColor(Color::Red))?;
-                    stdout.queue(Print("Error: "))?;
-                    is_tty.paint(&mut stdout, SetForegroundColor(Color::Reset))?;
-                }
-                for (s, c) in triplets(&self.title) {
-                    print(&mut stdout, s)?;
-                    if let Some(x) = match c {
-                        "b" => Some(Color::Blue),
-                        "w" => Some(Color::White),
-                        "r" => Some(Color::Reset),
-                        _ => None,
-                    } {
-                        is_tty.paint(&mut stdout, SetForegroundColor(x))?;
-                    }
-                }
-                if crossterm::terminal::is_raw_mode_enabled()? {
-                    stdout.queue(MoveToNextLine(1))?;
-                } else {
-                    stdout.write_all(b"\n")?;
-                }
-                is_tty.paint(&mut stdout, SetAttribute(Attribute::Reset))?;
-                if let Some(content) = &self.content {
-                    for (s, c) in triplets(content) {
-                        print(&mut stdout, s)?;
-                        if let Some(x) = match c {
-                            "b" => Some(Color::Blue),
-                            "w" => Some(Color::White),
-                            "r" => Some(Color::Reset),
-                            _ => None,
-                        } {
-                            is_tty.paint(&mut stdout, SetForegroundColor(x))?;
-                        }
-                    }
-                    if crossterm::terminal::is_raw_mode_enabled()? {
-                        stdout.queue(MoveToNextLine(1))?;
-                    } else {
-                        stdout.write_all(b"\n")?;
-                    }
-                }
-                stdout.flush()?;
-            }
-            _ => {
-                let mut cmd = Command::new("notify-send");
-                if self.error {
-                    cmd.args(["--urgency", "critical"]);
-                }
-                if let Some(img) = self.img {
-                    cmd.arg("-i");
-                    cmd.arg(img);
-                }
-                cmd.args(["-a", "m"]);
-                cmd.arg(
-                    triplets(&self.title.replace('\t', ""))
-                        .map(|(s, _)| s)
-                        .collect::<String>(),
-                );
-                if let Some(content) = &self.content {
-                    cmd.arg(
-                        triplets(
-                            &content
-                                .replace('\t', "")
-                                .replace('<', "&lt;")
-                                .replace('>', "&gt;"),
-                        )
-                        .map(|(s, _)| s)
-                        .collect::<String>(),
-                    );
-                }
-                cmd.spawn()?.wait().await?;
-            }
-        }
-        Ok(())
+        notifier::send(self).await
     }
 }
 
@@ -0,0 +1,29 @@
+//! How commands that only show things print them, picked with `--output`.
+
+use std::{
+    io::{stdout, Write},
+    sync::OnceLock,
+};
+
+use serde::Serialize;
+
+use crate::arg_parse::OutputFormat;
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+pub fn set(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn is_json() -> bool {
+    FORMAT.get() == Some(&OutputFormat::Json)
+}
+
+/// Prints `value` as a single line of json.
+pub fn json<T: Serialize + ?Sized>(value: &T) -> anyhow::Result<()> {
+    let mut stdout = stdout().lock();
+    serde_json::to_writer(&mut stdout, value)?;
+    stdout.write_all(b"\n")?;
+    stdout.flush()?;
+    Ok(())
+}